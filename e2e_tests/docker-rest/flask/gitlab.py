@@ -102,3 +102,38 @@ def gitlab_user():
     response = make_response(open("./gitlab_api_user.json").read())
     response.headers["content-type"] = "application/json"
     return response
+
+
+# Without the /gitlab prefix, as the API URL is derived from the URL of a remote
+# when creating missing projects
+@app.route("/api/v4/user/")
+def gitlab_create_user():
+    return gitlab_user()
+
+
+@app.route("/api/v4/projects/<path:project>")
+def gitlab_project(project):
+    check_headers()
+    if project == "myuser1/existing":
+        return jsonify({"path_with_namespace": project})
+    abort(make_response(jsonify({"message": "404 Project Not Found"}), 404))
+
+
+@app.route("/api/v4/projects", methods=["POST"])
+def gitlab_create_project():
+    check_headers()
+    body = request.get_json()
+    if body.get("visibility") != "private" or "namespace_id" in body:
+        abort(500, "wrong arguments")
+    return make_response(
+        jsonify(
+            {
+                "path": body["path"],
+                "path_with_namespace": f"myuser1/{body['path']}",
+                "http_url_to_repo": f"https://example.com/myuser1/{body['path']}.git",
+                "ssh_url_to_repo": f"ssh://example.com/myuser1/{body['path']}.git",
+                "visibility": "private",
+            }
+        ),
+        201,
+    )
@@ -2,6 +2,7 @@
 
 import hashlib
 import inspect
+import json
 import os
 import os.path
 import shutil
@@ -25,8 +26,10 @@ def get_temporary_directory(dir=None):
     return tempfile.TemporaryDirectory(dir=dir)
 
 
-def grm(args, cwd=None, is_invalid=False):
-    cmd = subprocess.run([binary] + args, cwd=cwd, capture_output=True, text=True)
+def grm(args, cwd=None, is_invalid=False, input=None):
+    cmd = subprocess.run(
+        [binary] + args, cwd=cwd, capture_output=True, text=True, input=input
+    )
     if not is_invalid:
         assert "usage" not in cmd.stderr.lower()
     print(f"grmcmd: {args}")
@@ -47,6 +50,63 @@ def shell(script):
     cmd.check_returncode()
 
 
+def init_repos(root, *names):
+    """
+    Initializes a repository with an empty commit for each of the names in
+    `root`
+    """
+    for name in names:
+        shell(
+            f"""
+            git init {root}/{name}
+            cd {root}/{name}
+            git commit --allow-empty -m {name}
+        """
+        )
+
+
+def toml_options(options):
+    config = ""
+    for key, value in options.items():
+        # JSON strings and arrays of strings are valid TOML
+        if isinstance(value, bool):
+            value = str(value).lower()
+        else:
+            value = json.dumps(value)
+        config += f"{key} = {value}\n"
+    return config
+
+
+def repo_config(name, remote=None, **options):
+    """
+    The configuration of a repository, to be appended to the one of a tree.
+    `options` are set as they are. With `remote`, the repository gets that
+    path as its "origin" remote.
+    """
+    config = f'\n[[trees.repos]]\nname = "{name}"\n' + toml_options(options)
+    if remote is not None:
+        config += (
+            "\n[[trees.repos.remotes]]\n"
+            'name = "origin"\n'
+            f'url = "file://{remote}"\n'
+            'type = "file"\n'
+        )
+    return config
+
+
+def tree_config(root, *repos, **options):
+    """
+    The configuration of a tree. `repos` are names of repositories, or their
+    configuration from `repo_config()`. `options` are set as they are.
+    """
+    config = f'\n[[trees]]\nroot = "{root}"\n' + toml_options(options)
+    for repo in repos:
+        if not repo.startswith("\n"):
+            repo = repo_config(repo)
+        config += repo
+    return config
+
+
 def checksum_directory(path):
     """
     Gives a "checksum" of a directory that includes all files & directories
@@ -360,6 +420,24 @@ class RepoTree:
         del self.config
 
 
+class TempConfig:
+    """
+    A temporary configuration file with the given content. Returns its path.
+    """
+
+    def __init__(self, content):
+        self.content = content
+
+    def __enter__(self):
+        self.file = tempfile.NamedTemporaryFile()
+        with open(self.file.name, "w") as f:
+            f.write(self.content)
+        return self.file.name
+
+    def __exit__(self, exc_type, exc_val, exc_tb):
+        del self.file
+
+
 class EmptyDir:
     def __init__(self):
         pass
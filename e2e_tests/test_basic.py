@@ -1,5 +1,8 @@
 #!/usr/bin/env python3
 
+import os
+import tempfile
+
 from helpers import grm
 
 
@@ -11,3 +14,23 @@ def test_invalid_command():
 def test_help():
     cmd = grm(["--help"])
     assert "usage" in cmd.stdout.lower()
+
+
+def test_mangen():
+    with tempfile.TemporaryDirectory() as directory:
+        man_dir = os.path.join(directory, "man")
+        cmd = grm(["mangen", man_dir])
+        assert cmd.returncode == 0
+
+        pages = os.listdir(man_dir)
+        for page in (
+            "grm.1",
+            "grm-repos.1",
+            "grm-repos-sync-config.1",
+            "grm-worktree-add.1",
+        ):
+            assert page in pages
+        assert "grm-complete.1" not in pages
+
+        with open(os.path.join(man_dir, "grm-worktree-add.1")) as f:
+            assert ".SH SYNOPSIS" in f.read()
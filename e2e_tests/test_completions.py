@@ -0,0 +1,40 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+import pytest
+from helpers import TempConfig, TempGitRepositoryWorktree, funcname, grm, tree_config
+
+@pytest.mark.parametrize("shell", ["bash", "zsh", "fish", "powershell"])
+def test_completions(shell):
+    cmd = grm(["completions", shell])
+    assert cmd.returncode == 0
+    assert "worktree" in cmd.stdout
+    if shell != "powershell":
+        assert "grm complete --" in cmd.stdout
+
+
+def test_complete_repo_names():
+    with tempfile.TemporaryDirectory() as root:
+        with TempConfig(tree_config(root, "one", "two")) as config:
+            cmd = grm(["complete", "--", "repos", "path", "--config", config])
+            assert cmd.returncode == 0
+            assert cmd.stdout.splitlines() == ["one", "two"]
+
+            cmd = grm(["complete", "--", "repos", "path", "--config", config, "one"])
+            assert cmd.returncode == 0
+            assert len(cmd.stdout) == 0
+
+
+def test_complete_worktree_names():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        for name in ("first", "second", "feature/third"):
+            cmd = grm(["wt", "add", name], cwd=base_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(
+            ["complete", "--", "wt", "delete"], cwd=os.path.join(base_dir, "first")
+        )
+        assert cmd.returncode == 0
+        assert sorted(cmd.stdout.splitlines()) == ["feature/third", "first", "second"]
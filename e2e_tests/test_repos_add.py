@@ -0,0 +1,122 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+import toml
+
+from helpers import TempGitFileRemote, grm
+
+
+def test_repos_add():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        # keep me
+                        [[trees]]
+                        root = "{target}"
+                        """
+                    )
+
+                cmd = grm(["repos", "add", "--config", config.name, f"file://{remote}"])
+                assert cmd.returncode == 0
+
+                name = os.path.basename(remote)
+                assert os.path.exists(os.path.join(target, name, ".git"))
+
+                with open(config.name) as f:
+                    content = f.read()
+                assert "# keep me" in content
+
+                repos = toml.loads(content)["trees"][0]["repos"]
+                assert len(repos) == 1
+                assert repos[0]["name"] == name
+                assert repos[0]["remotes"] == [
+                    {"name": "origin", "url": f"file://{remote}", "type": "file"}
+                ]
+
+                # Adding it again fails and does not change the configuration
+                cmd = grm(["repos", "add", "--config", config.name, f"file://{remote}"])
+                assert cmd.returncode != 0
+                with open(config.name) as f:
+                    assert f.read() == content
+
+
+def test_repos_add_clone_failure():
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+                    """
+                )
+            with open(config.name) as f:
+                content = f.read()
+
+            cmd = grm(
+                [
+                    "repos",
+                    "add",
+                    "--config",
+                    config.name,
+                    "--name",
+                    "test",
+                    "file:///does/not/exist.git",
+                ]
+            )
+            assert cmd.returncode != 0
+            assert not os.path.exists(os.path.join(target, "test"))
+
+            with open(config.name) as f:
+                assert f.read() == content
+
+
+def test_repos_add_requires_tree():
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}/a"
+
+                    [[trees]]
+                    root = "{target}/b"
+                    """
+                )
+
+            cmd = grm(
+                ["repos", "add", "--config", config.name, "file:///some/repo.git"]
+            )
+            assert cmd.returncode != 0
+            assert "--tree" in cmd.stderr
+
+
+def test_repos_add_ghq_name():
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+                    """
+                )
+
+            cmd = grm(
+                [
+                    "repos",
+                    "add",
+                    "--config",
+                    config.name,
+                    "--ghq",
+                    "file:///does/not/exist.git",
+                ]
+            )
+            assert cmd.returncode != 0
+            assert "ghq" in cmd.stderr
@@ -0,0 +1,107 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+import textwrap
+
+import toml
+from helpers import TempConfig, grm, init_repos, repo_config, shell, tree_config
+
+
+def setup_repos(root):
+    shell(
+        textwrap.dedent(
+            f"""
+            export GIT_AUTHOR_DATE=2020-01-01T00:00:00
+            export GIT_COMMITTER_DATE=2020-01-01T00:00:00
+            git init {root}/old
+            cd {root}/old
+            git commit --allow-empty -m old
+            """
+        )
+    )
+    init_repos(root, "new")
+
+
+def test_repos_archive_dry_run():
+    with tempfile.TemporaryDirectory() as root:
+        setup_repos(root)
+        content = tree_config(root, "old", "new")
+        with TempConfig(content) as config:
+            cmd = grm(
+                [
+                    "repos",
+                    "archive",
+                    "--config",
+                    config,
+                    "--inactive-for",
+                    "1y",
+                    "--dry-run",
+                ]
+            )
+            assert cmd.returncode == 0
+            assert "old" in cmd.stdout
+            assert "new" not in cmd.stdout
+
+            assert os.path.exists(os.path.join(root, "old"))
+            with open(config, "r") as f:
+                assert f.read() == content
+
+
+def test_repos_archive():
+    with tempfile.TemporaryDirectory() as root:
+        setup_repos(root)
+        with TempConfig(tree_config(root, "old", "new")) as config:
+            cmd = grm(["repos", "archive", "--config", config, "--inactive-for", "1y"])
+            assert cmd.returncode == 0
+
+            assert not os.path.exists(os.path.join(root, "old"))
+            assert os.path.exists(os.path.join(root, "archive", "old"))
+            assert os.path.exists(os.path.join(root, "new"))
+
+            with open(config, "r") as f:
+                output = toml.load(f)
+            assert len(output["trees"]) == 2
+            assert [repo["name"] for repo in output["trees"][0]["repos"]] == ["new"]
+            assert output["trees"][1]["root"] == f"{root}/archive"
+            assert [repo["name"] for repo in output["trees"][1]["repos"]] == ["old"]
+
+            cmd = grm(["repos", "sync", "config", "--config", config])
+            assert cmd.returncode == 0
+            assert "unmanaged" not in cmd.stderr.lower()
+
+
+def test_repos_archive_nested_worktree_in_use():
+    with tempfile.TemporaryDirectory() as root:
+        shell(
+            textwrap.dedent(
+                f"""
+                export GIT_AUTHOR_DATE=2020-01-01T00:00:00
+                export GIT_COMMITTER_DATE=2020-01-01T00:00:00
+                git init {root}/source
+                cd {root}/source
+                git commit --allow-empty -m old
+                git clone --bare {root}/source {root}/project/.git-main-working-tree
+                """
+            )
+        )
+        project = os.path.join(root, "project")
+        cmd = grm(["wt", "add", "--no-track", "feature/new"], cwd=project)
+        assert cmd.returncode == 0
+        shell(f"cd {project}/feature/new && git commit --allow-empty -m new")
+
+        project_config = repo_config("project", worktree_setup=True)
+        with TempConfig(tree_config(root, project_config)) as config:
+            cmd = grm(
+                [
+                    "repos",
+                    "archive",
+                    "--config",
+                    config,
+                    "--inactive-for",
+                    "1y",
+                    "--dry-run",
+                ]
+            )
+            assert cmd.returncode == 0
+            assert "project" not in cmd.stdout
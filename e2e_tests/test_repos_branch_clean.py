@@ -0,0 +1,76 @@
+#!/usr/bin/env python3
+
+import tempfile
+import textwrap
+
+import git
+from helpers import TempConfig, grm, repo_config, shell, tree_config
+
+def setup_repo(root):
+    shell(
+        textwrap.dedent(
+            f"""
+            git -c init.defaultBranch=main init {root}/test
+            cd {root}/test
+            echo a > a
+            git add a
+            git commit -m a
+
+            git checkout -b merged
+            echo m > m
+            git add m
+            git commit -m m
+            git checkout main
+            git merge --no-ff merged -m merge
+
+            git checkout -b squashed
+            echo s > s
+            git add s
+            git commit -m s1
+            echo s >> s
+            git commit -am s2
+            git checkout main
+            git merge --squash squashed
+            git commit -m squashed
+
+            git checkout -b unmerged
+            echo u > u
+            git add u
+            git commit -m u
+            git checkout main
+
+            git branch release/1.0 merged
+            """
+        )
+    )
+
+
+def branches(root):
+    return sorted(head.name for head in git.Repo(f"{root}/test").heads)
+
+
+def run_branch_clean(root, args):
+    repo = repo_config("test", protected_branches=["release/*"])
+    with TempConfig(tree_config(root, repo)) as config:
+        return grm(["repos", "branch-clean", "--config", config] + args)
+
+
+def test_repos_branch_clean_dry_run():
+    with tempfile.TemporaryDirectory() as root:
+        setup_repo(root)
+        before = branches(root)
+
+        cmd = run_branch_clean(root, ["--dry-run"])
+        assert cmd.returncode == 0
+        assert "merged (merged)" in cmd.stdout
+        assert "squashed (squash-merged)" in cmd.stdout
+        assert branches(root) == before
+
+
+def test_repos_branch_clean():
+    with tempfile.TemporaryDirectory() as root:
+        setup_repo(root)
+
+        cmd = run_branch_clean(root, [])
+        assert cmd.returncode == 0
+        assert branches(root) == ["main", "release/1.0", "unmerged"]
@@ -0,0 +1,71 @@
+#!/usr/bin/env python3
+
+import json
+import os
+import tempfile
+
+import pytest
+import toml
+import yaml
+from helpers import grm
+
+config = """
+unmanaged = "warn"
+
+[[trees]]
+root = "/projects"
+
+[trees.git_config]
+"user.email" = "me@example.com"
+
+[[trees.repos]]
+name = "one"
+worktree_setup = true
+
+[[trees.repos.remotes]]
+name = "origin"
+url = "https://example.com/one.git"
+type = "https"
+
+[[trees.repos]]
+name = "two"
+"""
+
+parsers = {"toml": toml.loads, "yaml": yaml.safe_load, "json": json.loads}
+
+
+@pytest.mark.parametrize("to", ["toml", "yaml", "json"])
+def test_repos_config_convert(to):
+    with tempfile.TemporaryDirectory() as directory:
+        config_path = os.path.join(directory, "config.toml")
+        with open(config_path, "w") as f:
+            f.write(config)
+
+        cmd = grm(["repos", "config", "convert", "--config", config_path, "--to", to])
+        assert cmd.returncode == 0
+
+        output = parsers[to](cmd.stdout)
+        assert output["unmanaged"] == "warn"
+        assert [repo["name"] for repo in output["trees"][0]["repos"]] == ["one", "two"]
+        assert output["trees"][0]["git_config"] == {"user.email": "me@example.com"}
+
+        # The converted configuration can be used again
+        converted_path = os.path.join(directory, f"converted.{to}")
+        cmd = grm(
+            [
+                "repos",
+                "config",
+                "convert",
+                "--config",
+                config_path,
+                "--to",
+                to,
+                "--output",
+                converted_path,
+            ]
+        )
+        assert cmd.returncode == 0
+
+        cmd = grm(["repos", "list", "--config", converted_path])
+        assert cmd.returncode == 0
+        assert cmd.stdout.splitlines() == ["one", "two"]
@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+import git
+from helpers import grm
+
+ALTERNATE_DOMAIN = os.environ["ALTERNATE_DOMAIN"]
+
+
+def write_config(config, root, project):
+    with open(config, "w") as f:
+        f.write(
+            f"""
+            [[trees]]
+            root = "{root}"
+
+            [[trees.repos]]
+            name = "{project}"
+
+            [[trees.repos.remotes]]
+            name = "origin"
+            url = "http://{ALTERNATE_DOMAIN}:5000/myuser1/{project}.git"
+            type = "https"
+            create_remote = true
+            token_command = "echo secret-token:myauthtoken"
+            """
+        )
+
+
+def test_repos_sync_create_remote():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            write_config(config.name, root, "newproject")
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert cmd.returncode == 0
+            assert 'Created the project of remote "origin"' in cmd.stdout
+            assert "Remotes are new and empty, initializing new" in cmd.stdout
+
+            repo = git.Repo(os.path.join(root, "newproject"))
+            assert repo.remotes.origin.url.endswith("/myuser1/newproject.git")
+
+
+def test_repos_sync_create_remote_existing_project():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            write_config(config.name, root, "existing")
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert "Created the project" not in cmd.stdout
+            # The mock does not serve any repositories to clone from
+            assert "Repository failed during clone" in cmd.stderr
+
+
+def test_repos_sync_create_remote_without_token():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            write_config(config.name, root, "newproject")
+            with open(config.name) as f:
+                content = f.read()
+            with open(config.name, "w") as f:
+                f.write(
+                    "\n".join(
+                        line
+                        for line in content.splitlines()
+                        if "token_command" not in line
+                    )
+                )
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert cmd.returncode != 0
+            assert "Creating the remote project needs a token_command" in cmd.stderr
+            assert not os.path.exists(os.path.join(root, "newproject"))
@@ -0,0 +1,64 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+from helpers import TempGitFileRemote, grm, shell
+
+
+def test_repos_diff():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "diff", "--config", config.name])
+                assert cmd.returncode == 0
+                assert cmd.stdout.splitlines() == [
+                    f"--- {target} (configuration)",
+                    f"+++ {target} (on disk)",
+                    '-repo "test"',
+                    f'-  remote "origin" = "file://{remote}"',
+                ]
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                cmd = grm(["repos", "diff", "--config", config.name, "--exit-code"])
+                assert cmd.returncode == 0
+                assert len(cmd.stdout) == 0
+
+                shell(
+                    f"""
+                    cd {os.path.join(target, "test")}
+                    git remote set-url origin file:///other
+                    git remote add extra file:///extra
+                    git init --quiet {os.path.join(target, "unmanaged")}
+                """
+                )
+
+                cmd = grm(["repos", "diff", "--config", config.name, "--exit-code"])
+                assert cmd.returncode == 1
+                assert cmd.stdout.splitlines() == [
+                    f"--- {target} (configuration)",
+                    f"+++ {target} (on disk)",
+                    ' repo "test"',
+                    f'-  remote "origin" = "file://{remote}"',
+                    '+  remote "origin" = "file:///other"',
+                    '+  remote "extra" = "file:///extra"',
+                    '+repo "unmanaged"',
+                ]
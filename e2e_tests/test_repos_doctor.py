@@ -0,0 +1,86 @@
+#!/usr/bin/env python3
+
+import tempfile
+
+from helpers import TempGitFileRemote, grm, shell
+
+config_template = """
+[[trees]]
+root = "{root}"
+
+[[trees.repos]]
+name = "test"
+
+[[trees.repos.remotes]]
+name = "origin"
+url = "file://{remote}"
+type = "file"
+token_command = "{token_command}"
+"""
+
+
+def test_repos_doctor():
+    with tempfile.TemporaryDirectory() as root:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        config_template.format(
+                            root=root, remote=remote, token_command="echo token"
+                        )
+                    )
+
+                cmd = grm(["repos", "doctor", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "git: git version" in cmd.stdout
+                assert "Token command works" in cmd.stdout
+                assert f"{root}: Writable" in cmd.stdout
+                assert "No problems found" in cmd.stdout
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                # The worktree is removed without telling git. The nested one
+                # still exists and must not be reported.
+                shell(
+                    f"""
+                    cd {root}/test
+                    git worktree add ../removed
+                    rm -rf ../removed
+                    mkdir -p .git/worktrees/feature/nested
+                    mkdir ../nested
+                    echo {root}/nested/.git > .git/worktrees/feature/nested/gitdir
+                    """
+                )
+
+                cmd = grm(["repos", "doctor", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "Metadata of removed worktrees is left: removed" in cmd.stderr
+                assert f"Remove \"{root}/test/.git/worktrees/removed\"" in cmd.stdout
+                assert "1 problem(s) found" in cmd.stderr
+
+
+def test_repos_doctor_failing_token_command():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    config_template.format(
+                        root=root, remote="/nonexistent", token_command="false"
+                    )
+                )
+
+            cmd = grm(["repos", "doctor", "--config", config.name])
+            assert cmd.returncode == 1
+            assert "test: Remote" in cmd.stderr
+
+
+def test_repos_doctor_invalid_config():
+    with tempfile.NamedTemporaryFile() as config:
+        with open(config.name, "w") as f:
+            f.write("invalid")
+
+        cmd = grm(["repos", "doctor", "--config", config.name])
+        assert cmd.returncode == 1
+        # The environment is checked anyway
+        assert "git: git version" in cmd.stdout
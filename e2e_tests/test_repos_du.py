@@ -0,0 +1,27 @@
+#!/usr/bin/env python3
+
+import tempfile
+import textwrap
+
+from helpers import TempConfig, grm, shell, tree_config
+
+def test_repos_du():
+    with tempfile.TemporaryDirectory() as root:
+        shell(
+            textwrap.dedent(
+                f"""
+                git init {root}/small
+                git init {root}/large
+                head -c 2000000 /dev/urandom > {root}/large/data
+                """
+            )
+        )
+
+        with TempConfig(tree_config(root, "small", "large")) as config:
+            cmd = grm(["repos", "du", "--config", config])
+
+        assert cmd.returncode == 0
+        lines = [line for line in cmd.stdout.splitlines() if "small" in line]
+        assert len(lines) == 1
+        assert cmd.stdout.index("large") < cmd.stdout.index("small")
+        assert "1.9 MiB" in cmd.stdout
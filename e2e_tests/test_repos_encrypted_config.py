@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+from helpers import grm
+
+decrypted_config = """
+[[trees]]
+root = "/tmp/decrypted"
+
+[[trees.repos]]
+name = "decrypted"
+"""
+
+
+def fake_binary(directory, name):
+    path = os.path.join(directory, name)
+    with open(path, "w") as f:
+        f.write(f"#!/bin/sh\ncat <<'EOF'\n{decrypted_config}\nEOF\n")
+    os.chmod(path, 0o755)
+
+
+def test_repos_encrypted_config_age(monkeypatch):
+    with tempfile.TemporaryDirectory() as bindir:
+        fake_binary(bindir, "age")
+        monkeypatch.setenv("PATH", f"{bindir}:{os.environ['PATH']}")
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write("-----BEGIN AGE ENCRYPTED FILE-----\n")
+                f.write("abc\n")
+                f.write("-----END AGE ENCRYPTED FILE-----\n")
+
+            cmd = grm(["repos", "list", "--config", config.name])
+            assert cmd.returncode == 0
+            assert cmd.stdout.splitlines() == ["decrypted"]
+
+
+def test_repos_encrypted_config_sops(monkeypatch):
+    with tempfile.TemporaryDirectory() as bindir:
+        fake_binary(bindir, "sops")
+        monkeypatch.setenv("PATH", f"{bindir}:{os.environ['PATH']}")
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write("trees: ENC[AES256_GCM,data:abc]\n")
+                f.write("sops:\n")
+                f.write("  mac: ENC[AES256_GCM,data:abc]\n")
+
+            cmd = grm(["repos", "list", "--config", config.name])
+            assert cmd.returncode == 0
+            assert cmd.stdout.splitlines() == ["decrypted"]
+
+            cmd = grm(["repos", "remove", "--config", config.name, "decrypted"])
+            assert cmd.returncode != 0
+            assert "Cannot edit the encrypted configuration file" in cmd.stderr
+
+
+def test_repos_encrypted_config_missing_binary(monkeypatch):
+    with tempfile.TemporaryDirectory() as bindir:
+        monkeypatch.setenv("PATH", bindir)
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write("-----BEGIN AGE ENCRYPTED FILE-----\n")
+
+            cmd = grm(["repos", "list", "--config", config.name])
+            assert cmd.returncode != 0
+            assert '"age" is not installed' in cmd.stderr
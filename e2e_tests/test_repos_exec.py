@@ -0,0 +1,90 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+from helpers import TempConfig, grm, init_repos, repo_config, shell, tree_config
+
+
+def run_exec(root1, root2, args):
+    content = tree_config(root1, repo_config("one", tags=["work"]), "two")
+    content += tree_config(root2, repo_config("three", tags=["work"]))
+    with TempConfig(content) as config:
+        return grm(["repos", "exec", "--config", config] + args)
+
+
+def test_repos_exec():
+    with tempfile.TemporaryDirectory() as root1:
+        with tempfile.TemporaryDirectory() as root2:
+            init_repos(root1, "one", "two")
+            init_repos(root2, "three")
+
+            cmd = run_exec(root1, root2, ["--", "touch", "marker"])
+            assert cmd.returncode == 0
+            for path in ("one", "two"):
+                assert "marker" in os.listdir(os.path.join(root1, path))
+            assert "marker" in os.listdir(os.path.join(root2, "three"))
+
+
+def test_repos_exec_environment():
+    with tempfile.TemporaryDirectory() as root1:
+        with tempfile.TemporaryDirectory() as root2:
+            init_repos(root1, "one", "two")
+            init_repos(root2, "three")
+
+            cmd = run_exec(
+                root1,
+                root2,
+                ["--", "echo $GRM_REPO_NAME:$GRM_REPO_ROOT:$GRM_TREE_ROOT"],
+            )
+            assert cmd.returncode == 0
+            assert f"[one] one:{root1}/one:{root1}" in cmd.stdout
+            assert f"[three] three:{root2}/three:{root2}" in cmd.stdout
+
+
+def test_repos_exec_filter_tag():
+    with tempfile.TemporaryDirectory() as root1:
+        with tempfile.TemporaryDirectory() as root2:
+            init_repos(root1, "one", "two")
+            init_repos(root2, "three")
+
+            cmd = run_exec(root1, root2, ["--tag", "work", "--", "touch", "marker"])
+            assert cmd.returncode == 0
+            assert "marker" in os.listdir(os.path.join(root1, "one"))
+            assert "marker" not in os.listdir(os.path.join(root1, "two"))
+            assert "marker" in os.listdir(os.path.join(root2, "three"))
+
+
+def test_repos_exec_filter_tree():
+    with tempfile.TemporaryDirectory() as root1:
+        with tempfile.TemporaryDirectory() as root2:
+            init_repos(root1, "one", "two")
+            init_repos(root2, "three")
+
+            cmd = run_exec(root1, root2, ["--tree", root2, "--", "touch", "marker"])
+            assert cmd.returncode == 0
+            assert "marker" not in os.listdir(os.path.join(root1, "one"))
+            assert "marker" in os.listdir(os.path.join(root2, "three"))
+
+
+def test_repos_exec_failure():
+    with tempfile.TemporaryDirectory() as root1:
+        with tempfile.TemporaryDirectory() as root2:
+            init_repos(root1, "one", "two")
+            init_repos(root2, "three")
+
+            cmd = run_exec(
+                root1, root2, ["--jobs", "1", "--", 'test "$GRM_REPO_NAME" != two']
+            )
+            assert cmd.returncode != 0
+            assert "two: command exited with code 1" in cmd.stderr.lower()
+
+
+def test_repos_exec_missing_repo():
+    with tempfile.TemporaryDirectory() as root1:
+        with tempfile.TemporaryDirectory() as root2:
+            shell(f"git init {root1}/one")
+
+            cmd = run_exec(root1, root2, ["--", "true"])
+            assert cmd.returncode == 0
+            assert "two: repository does not exist" in cmd.stderr.lower()
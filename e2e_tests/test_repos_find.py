@@ -287,3 +287,238 @@ def test_repos_find_with_invalid_repo(configtype, default_format):
             assert set(origin.keys()) == {"name", "type", "url"}
             assert origin["type"] == "https"
             assert origin["url"] == "https://example.com/repo2.git"
+
+
+def test_repos_find_exclude_dir():
+    with tempfile.TemporaryDirectory() as tmpdir:
+        shell(
+            f"""
+            cd {tmpdir}
+            git init --quiet project/repo1
+            git init --quiet project/node_modules/dependency
+            git init --quiet go/pkg/mod/module
+            git init --quiet go/src/repo2
+        """
+        )
+
+        cmd = grm(
+            [
+                "repos",
+                "find",
+                "local",
+                tmpdir,
+                "--exclude-dir",
+                "node_modules",
+                "--exclude-dir",
+                "go/pkg",
+            ]
+        )
+        assert cmd.returncode == 0
+
+        tree = toml.loads(cmd.stdout)["trees"][0]
+        assert {repo["name"] for repo in tree["repos"]} == {
+            "project/repo1",
+            "go/src/repo2",
+        }
+
+
+def test_repos_find_non_utf8_path():
+    with tempfile.TemporaryDirectory() as tmpdir:
+        shell(f"cd {tmpdir} && git init --quiet repo1")
+        os.makedirs(os.path.join(os.fsencode(tmpdir), b"invalid\xff"))
+        shell(f"cd {tmpdir}/invalid* && git init --quiet")
+
+        cmd = grm(["repos", "find", "local", tmpdir])
+        assert cmd.returncode == 0
+        assert "Path is not valid UTF-8" in cmd.stderr
+
+        tree = toml.loads(cmd.stdout)["trees"][0]
+        assert [repo["name"] for repo in tree["repos"]] == ["repo1"]
+
+
+@pytest.mark.parametrize("max_depth", [1, 2, 3])
+def test_repos_find_max_depth(max_depth):
+    with tempfile.TemporaryDirectory() as tmpdir:
+        shell(
+            f"""
+            cd {tmpdir}
+            git init --quiet repo1
+            git init --quiet group/repo2
+            git init --quiet group/subgroup/repo3
+        """
+        )
+
+        cmd = grm(["repos", "find", "local", tmpdir, "--max-depth", str(max_depth)])
+        assert cmd.returncode == 0
+
+        tree = toml.loads(cmd.stdout)["trees"][0]
+        expected = {
+            1: {"repo1"},
+            2: {"repo1", "group/repo2"},
+            3: {"repo1", "group/repo2", "group/subgroup/repo3"},
+        }[max_depth]
+        assert {repo["name"] for repo in tree["repos"]} == expected
+
+
+def test_repos_find_merge_into():
+    with tempfile.TemporaryDirectory() as tmpdir:
+        shell(
+            f"""
+            cd {tmpdir}
+            git init --quiet repo1
+            git init --quiet repo2
+        """
+        )
+
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""# My repositories
+[[trees]]
+root = "{tmpdir}"
+
+[[trees.repos]]
+name = "repo1" # Already known
+"""
+                )
+
+            cmd = grm(["repos", "find", "local", tmpdir, "--merge-into", config.name])
+            assert cmd.returncode == 0
+            assert len(cmd.stderr) == 0
+            assert "repo2: Added to configuration" in cmd.stdout
+            assert "repo1" not in cmd.stdout
+
+            with open(config.name, "r") as f:
+                content = f.read()
+            assert content.startswith("# My repositories\n")
+            assert 'name = "repo1" # Already known\n' in content
+
+            tree = toml.loads(content)["trees"][0]
+            assert [repo["name"] for repo in tree["repos"]] == ["repo1", "repo2"]
+
+            cmd = grm(["repos", "find", "local", tmpdir, "--merge-into", config.name])
+            assert cmd.returncode == 0
+            assert "No new repositories found" in cmd.stdout
+
+            with open(config.name, "r") as f:
+                assert f.read() == content
+
+
+def test_repos_find_worktree_setup():
+    with tempfile.TemporaryDirectory() as tmpdir:
+        shell(
+            f"""
+            cd {tmpdir}
+            git init --quiet repo
+            cd repo
+            echo test > test
+            git add test
+            git commit --quiet -m "commit"
+            git branch feature
+        """
+        )
+
+        cmd = grm(["wt", "convert"], cwd=os.path.join(tmpdir, "repo"))
+        assert cmd.returncode == 0
+        cmd = grm(["wt", "add", "feature"], cwd=os.path.join(tmpdir, "repo"))
+        assert cmd.returncode == 0
+
+        shell(
+            f"""
+            cd {tmpdir}
+            git clone --quiet --bare repo/.git-main-working-tree bare.git
+            git -C bare.git worktree add --quiet ../linked feature
+        """
+        )
+
+        cmd = grm(["repos", "find", "local", tmpdir])
+        assert cmd.returncode == 0
+
+        tree = toml.loads(cmd.stdout)["trees"][0]
+        assert len(tree["repos"]) == 1
+        assert tree["repos"][0]["name"] == "repo"
+        assert tree["repos"][0]["worktree_setup"] is True
+
+        assert "linked: Worktree of another repository" in cmd.stderr
+        assert "bare.git: Bare repository with worktrees" in cmd.stderr
+
+
+@pytest.mark.parametrize("group_by_host", [True, False])
+def test_repos_find_sorted(group_by_host):
+    with tempfile.TemporaryDirectory() as tmpdir:
+        shell(
+            f"""
+            cd {tmpdir}
+            git init --quiet zeta
+            git -C zeta remote add origin https://a.example.com/zeta.git
+            git init --quiet alpha
+            git -C alpha remote add origin https://b.example.com/alpha.git
+            git init --quiet beta
+            git -C beta remote add origin git@a.example.com:beta.git
+            git init --quiet local
+        """
+        )
+
+        args = ["repos", "find", "local", tmpdir]
+        if group_by_host:
+            args += ["--group-by-host"]
+
+        cmd = grm(args)
+        assert cmd.returncode == 0
+
+        tree = toml.loads(cmd.stdout)["trees"][0]
+        names = [repo["name"] for repo in tree["repos"]]
+        if group_by_host:
+            assert names == ["beta", "zeta", "alpha", "local"]
+        else:
+            assert names == ["alpha", "beta", "local", "zeta"]
+
+        # The output is the same every time
+        assert grm(args).stdout == cmd.stdout
+
+
+def test_repos_find_parallel():
+    with tempfile.TemporaryDirectory() as tmpdir:
+        for group in range(5):
+            for repo in range(5):
+                shell(f"git init --quiet {tmpdir}/group{group}/sub/repo{repo}")
+
+        outputs = set()
+        for jobs in [1, 4, 16]:
+            cmd = grm(["repos", "find", "local", tmpdir, "--jobs", str(jobs)])
+            assert cmd.returncode == 0
+            outputs.add(cmd.stdout)
+
+        assert len(outputs) == 1
+        tree = toml.loads(outputs.pop())["trees"][0]
+        assert len(tree["repos"]) == 25
+
+
+def test_repos_find_push_url():
+    with tempfile.TemporaryDirectory() as tmpdir:
+        shell(
+            f"""
+            git init --quiet {tmpdir}/repo
+            cd {tmpdir}/repo
+            git remote add upstream https://example.com/upstream/repo.git
+            git remote set-url --push upstream git@example.com:me/repo.git
+            git remote add origin https://example.com/me/repo.git
+            git remote add mirrored https://example.com/mirrored/repo.git
+            git remote set-url --add --push mirrored git@example.com:a/repo.git
+            git remote set-url --add --push mirrored git@example.com:b/repo.git
+        """
+        )
+
+        cmd = grm(["repos", "find", "local", tmpdir])
+        assert cmd.returncode == 0
+
+        remotes = toml.loads(cmd.stdout)["trees"][0]["repos"][0]["remotes"]
+        upstream = [r for r in remotes if r["name"] == "upstream"][0]
+        assert upstream["push_url"] == "git@example.com:me/repo.git"
+        origin = [r for r in remotes if r["name"] == "origin"][0]
+        assert "push_url" not in origin
+        mirrored = [r for r in remotes if r["name"] == "mirrored"][0]
+        assert mirrored["push_url"] == [
+            "git@example.com:a/repo.git",
+            "git@example.com:b/repo.git",
+        ]
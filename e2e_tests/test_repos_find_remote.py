@@ -953,3 +953,248 @@ def test_repos_find_remote_owner(
             repo["remotes"][0]["url"] == "https://example.com/mygroup2/myproject5.git"
         )
         assert repo["remotes"][0]["type"] == "https"
+
+
+@pytest.mark.parametrize("provider", PROVIDERS)
+@pytest.mark.parametrize("use_config", [True, False])
+def test_repos_find_remote_exclude(provider, use_config):
+    if use_config:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    provider = "{provider}"
+                    token_command = "echo secret-token:myauthtoken"
+                    root = "/myroot"
+
+                    [filters]
+                    users = ["myuser1"]
+                    exclude_archived = true
+                    exclude_forks = true
+                    exclude_names = ["myproject1", "myuser1/*2"]
+                    """
+                )
+
+            cmd = grm(["repos", "find", "config", "--config", config.name])
+    else:
+        cmd = grm(
+            [
+                "repos",
+                "find",
+                "remote",
+                "--provider",
+                provider,
+                "--token-command",
+                "echo secret-token:myauthtoken",
+                "--root",
+                "/myroot",
+                "--user",
+                "myuser1",
+                "--exclude-archived",
+                "--exclude-forks",
+                "--exclude-name",
+                "myproject1",
+                "--exclude-name",
+                "myuser1/*2",
+            ]
+        )
+
+    assert cmd.returncode == 0
+    assert len(cmd.stderr) == 0
+
+    output = toml.loads(cmd.stdout)
+    names = sorted(repo["name"] for repo in output["trees"][0]["repos"])
+    assert names == ["myproject3", "myproject4", "myproject5"]
+
+
+@pytest.mark.parametrize("provider", PROVIDERS)
+@pytest.mark.parametrize("use_config", [True, False])
+@pytest.mark.parametrize(
+    "layout,strip_owner_prefix,root,name",
+    [
+        ("{host}/{owner}/{name}", None, "/myroot/example.com/myuser1", "myproject1"),
+        ("{name}", "myuser1", "/myroot", "myproject1"),
+        ("{owner}-{name}", None, "/myroot", "myuser1-myproject1"),
+    ],
+)
+def test_repos_find_remote_layout(
+    provider, use_config, layout, strip_owner_prefix, root, name
+):
+    if use_config:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                cfg = f"""
+                    provider = "{provider}"
+                    token_command = "echo secret-token:myauthtoken"
+                    root = "/myroot"
+                    layout = "{layout}"
+                    """
+                if strip_owner_prefix is not None:
+                    cfg += f'strip_owner_prefixes = ["{strip_owner_prefix}"]\n'
+                cfg += """
+                    [filters]
+                    users = ["myuser1"]
+                    """
+                f.write(cfg)
+
+            cmd = grm(["repos", "find", "config", "--config", config.name])
+    else:
+        args = [
+            "repos",
+            "find",
+            "remote",
+            "--provider",
+            provider,
+            "--token-command",
+            "echo secret-token:myauthtoken",
+            "--root",
+            "/myroot",
+            "--user",
+            "myuser1",
+            "--layout",
+            layout,
+        ]
+        if strip_owner_prefix is not None:
+            args += ["--strip-owner-prefix", strip_owner_prefix]
+        cmd = grm(args)
+
+    assert cmd.returncode == 0
+    assert len(cmd.stderr) == 0
+
+    output = toml.loads(cmd.stdout)
+    assert len(output["trees"]) == 1
+    assert output["trees"][0]["root"] == root
+    assert name in [repo["name"] for repo in output["trees"][0]["repos"]]
+
+
+@pytest.mark.parametrize("provider", PROVIDERS)
+def test_repos_find_remote_invalid_layout(provider):
+    cmd = grm(
+        [
+            "repos",
+            "find",
+            "remote",
+            "--provider",
+            provider,
+            "--token-command",
+            "echo secret-token:myauthtoken",
+            "--root",
+            "/myroot",
+            "--user",
+            "myuser1",
+            "--layout",
+            "{owner}",
+        ]
+    )
+
+    assert cmd.returncode != 0
+    assert "{name}" in cmd.stderr
+
+
+def test_repos_find_remote_several_providers():
+    with tempfile.NamedTemporaryFile() as config:
+        with open(config.name, "w") as f:
+            f.write(
+                """
+                [[providers]]
+                name = "work"
+                provider = "github"
+                token_command = "echo secret-token:myauthtoken"
+                root = "/work"
+
+                [providers.filters]
+                users = ["myuser1"]
+
+                [[providers]]
+                name = "internal"
+                provider = "gitlab"
+                token_command = "echo secret-token:myauthtoken"
+                root = "/internal"
+
+                [providers.filters]
+                users = ["myuser1"]
+                """
+            )
+
+        cmd = grm(["repos", "find", "config", "--config", config.name])
+
+    assert cmd.returncode == 0
+    assert len(cmd.stderr) == 0
+
+    output = toml.loads(cmd.stdout)
+    roots = sorted(tree["root"] for tree in output["trees"])
+    assert roots == ["/internal/myuser1", "/work/myuser1"]
+    for tree in output["trees"]:
+        assert len(tree["repos"]) == 5
+
+
+def test_repos_find_remote_several_providers_same_name():
+    with tempfile.NamedTemporaryFile() as config:
+        with open(config.name, "w") as f:
+            f.write(
+                """
+                [[providers]]
+                name = "work"
+                provider = "github"
+                token_command = "echo secret-token:myauthtoken"
+                root = "/work"
+
+                [[providers]]
+                name = "work"
+                provider = "gitlab"
+                token_command = "echo secret-token:myauthtoken"
+                root = "/internal"
+                """
+            )
+
+        cmd = grm(["repos", "find", "config", "--config", config.name])
+
+    assert cmd.returncode != 0
+    assert "several providers named" in cmd.stderr
+
+
+@pytest.mark.parametrize("provider", PROVIDERS)
+def test_repos_find_remote_merge_into(provider):
+    args = [
+        "repos",
+        "find",
+        "remote",
+        "--provider",
+        provider,
+        "--token-command",
+        "echo secret-token:myauthtoken",
+        "--root",
+        "/myroot",
+        "--user",
+        "myuser1",
+    ]
+
+    with tempfile.NamedTemporaryFile() as config:
+        with open(config.name, "w") as f:
+            f.write(
+                """# My repositories
+[[trees]]
+root = "/myroot/myuser1"
+
+[[trees.repos]]
+name = "myproject1" # Already known
+"""
+            )
+
+        cmd = grm(args + ["--merge-into", config.name])
+        assert cmd.returncode == 0
+        assert len(cmd.stderr) == 0
+        assert "myproject2: Added to configuration" in cmd.stdout
+        assert "myproject1" not in cmd.stdout
+
+        with open(config.name, "r") as f:
+            content = f.read()
+        assert content.startswith("# My repositories\n")
+        assert 'name = "myproject1" # Already known\n' in content
+
+        tree = toml.loads(content)["trees"][0]
+        assert len(tree["repos"]) == 5
+
+        cmd = grm(args + ["--merge-into", config.name])
+        assert cmd.returncode == 0
+        assert "No new repositories found" in cmd.stdout
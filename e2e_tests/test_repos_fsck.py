@@ -0,0 +1,62 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+from helpers import TempConfig, TempGitFileRemote, grm, repo_config, shell, tree_config
+
+
+def test_repos_fsck():
+    with tempfile.TemporaryDirectory() as root:
+        with TempGitFileRemote() as (remote, _):
+            with TempConfig(tree_config(root, repo_config("test", remote))) as config:
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+
+                cmd = grm(["repos", "fsck", "--config", config])
+                assert cmd.returncode == 0
+
+                shell(f"rm -rf {root}/test/.git/objects/*")
+
+                cmd = grm(["repos", "fsck", "--config", config])
+                assert cmd.returncode == 1
+                assert "corrupt" in cmd.stderr
+
+                cmd = grm(["repos", "fsck", "--config", config, "--repair"])
+                assert cmd.returncode == 0
+                assert os.path.exists(os.path.join(root, "test.corrupt"))
+
+                cmd = grm(["repos", "fsck", "--config", config])
+                assert cmd.returncode == 0
+
+                # The backup is not an unmanaged repository
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+                assert "unmanaged" not in cmd.stderr.lower()
+
+
+def test_repos_fsck_repair_referenced():
+    with tempfile.TemporaryDirectory() as root:
+        with TempGitFileRemote() as (remote, _):
+            content = tree_config(
+                root,
+                repo_config("test", remote, tags=["corrupt"]),
+                repo_config("other", remote, reference="test"),
+            )
+            with TempConfig(content) as config:
+                # The reference has to exist before cloning "other"
+                sync_args = ["repos", "sync", "config", "--config", config]
+                cmd = grm(sync_args)
+                assert cmd.returncode == 0
+                shell(f"rm -rf {root}/other")
+                cmd = grm(sync_args)
+                assert cmd.returncode == 0
+
+                shell(f"rm -rf {root}/test/.git/objects/*")
+
+                args = ["repos", "fsck", "--config", config, "--tag", "corrupt"]
+                cmd = grm(args + ["--repair"])
+                assert cmd.returncode != 0
+                assert 'Repository "other" uses its objects' in cmd.stderr
+                assert os.path.exists(os.path.join(root, "test"))
+                assert not os.path.exists(os.path.join(root, "test.corrupt"))
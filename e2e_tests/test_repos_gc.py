@@ -0,0 +1,41 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+import textwrap
+
+from helpers import TempConfig, grm, shell, tree_config
+
+def loose_objects(repo):
+    objects = os.path.join(repo, ".git", "objects")
+    return [
+        name
+        for name in os.listdir(objects)
+        if len(name) == 2 and os.listdir(os.path.join(objects, name))
+    ]
+
+
+def test_repos_gc():
+    with tempfile.TemporaryDirectory() as root:
+        shell(
+            textwrap.dedent(
+                f"""
+                git init {root}/one
+                cd {root}/one
+                for i in 1 2 3; do
+                    echo $i > file$i
+                    git add file$i
+                    git commit -m $i
+                done
+                """
+            )
+        )
+        assert loose_objects(os.path.join(root, "one"))
+
+        with TempConfig(tree_config(root, "one", "missing")) as config:
+            cmd = grm(["repos", "gc", "--config", config, "--aggressive"])
+
+        assert cmd.returncode == 0
+        assert "missing" in cmd.stderr
+        assert "Reclaimed" in cmd.stdout
+        assert not loose_objects(os.path.join(root, "one"))
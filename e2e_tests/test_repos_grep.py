@@ -0,0 +1,69 @@
+#!/usr/bin/env python3
+
+import tempfile
+import textwrap
+
+from helpers import TempConfig, grm, repo_config, shell, tree_config
+
+
+def setup_repos(root):
+    shell(
+        textwrap.dedent(
+            f"""
+            git init {root}/one
+            cd {root}/one
+            echo "needle in one" > file.txt
+            git add file.txt
+            git commit -m one
+            echo "changed" > file.txt
+            git init {root}/two
+            cd {root}/two
+            echo "Needle in two" > other.md
+            """
+        )
+    )
+
+
+def run_grep(root, args):
+    with TempConfig(tree_config(root, "one", "two")) as config:
+        return grm(["repos", "grep", "--config", config] + args)
+
+
+def test_repos_grep():
+    with tempfile.TemporaryDirectory() as root:
+        setup_repos(root)
+
+        cmd = run_grep(root, ["needle"])
+        assert cmd.returncode == 1
+        assert cmd.stdout == ""
+
+        cmd = run_grep(root, ["-i", "needle"])
+        assert cmd.returncode == 0
+        assert cmd.stdout.splitlines() == ["two:other.md:1:Needle in two"]
+
+
+def test_repos_grep_ref():
+    with tempfile.TemporaryDirectory() as root:
+        setup_repos(root)
+        shell(f"cd {root}/two && git add other.md && git commit -m two")
+
+        cmd = run_grep(root, ["--ref", "HEAD", "-i", "needle", "--", "*.txt"])
+        assert cmd.returncode == 0
+        assert cmd.stdout.splitlines() == ["one:file.txt:1:needle in one"]
+
+
+def test_repos_grep_nested_worktree():
+    with tempfile.TemporaryDirectory() as root:
+        setup_repos(root)
+        shell(f"git clone --bare {root}/one {root}/three/.git-main-working-tree")
+        cmd = grm(["wt", "add", "--no-track", "feature/x"], cwd=f"{root}/three")
+        assert cmd.returncode == 0
+        shell(f"echo 'needle in feature' > {root}/three/feature/x/new.txt")
+
+        three = repo_config("three", worktree_setup=True)
+        with TempConfig(tree_config(root, "one", "two", three)) as config:
+            cmd = grm(["repos", "grep", "--config", config, "feature"])
+        assert cmd.returncode == 0
+        assert cmd.stdout.splitlines() == [
+            "three:feature/x/new.txt:1:needle in feature"
+        ]
@@ -0,0 +1,153 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+import toml
+
+from helpers import grm, shell
+
+mrconfig = """
+[DEFAULT]
+git_gc = git gc "$@"
+
+[src/one]
+checkout = git clone 'https://example.com/one.git' 'one'
+
+[src/two]
+checkout =
+    git clone -o upstream git@example.com:user/two.git two &&
+    cd two && git submodule update --init
+
+[src/svn]
+checkout = svn checkout https://example.com/svn svn
+"""
+
+
+def test_repos_import_mr():
+    with tempfile.TemporaryDirectory() as home:
+        path = os.path.join(home, ".mrconfig")
+        with open(path, "w") as f:
+            f.write(mrconfig)
+
+        cmd = grm(["repos", "import", "--from", "mr", path])
+        assert cmd.returncode == 0
+        assert "src/svn" in cmd.stderr
+
+        output = toml.loads(cmd.stdout)
+        assert len(output["trees"]) == 1
+        tree = output["trees"][0]
+        assert tree["root"] == os.path.realpath(home)
+
+        repos = tree["repos"]
+        assert [repo["name"] for repo in repos] == ["src/one", "src/two"]
+        assert repos[0]["remotes"] == [
+            {"name": "origin", "url": "https://example.com/one.git", "type": "https"}
+        ]
+        assert repos[1]["remotes"] == [
+            {"name": "upstream", "url": "git@example.com:user/two.git", "type": "ssh"}
+        ]
+
+
+def test_repos_import_mr_merge():
+    with tempfile.TemporaryDirectory() as home:
+        path = os.path.join(home, ".mrconfig")
+        with open(path, "w") as f:
+            f.write(mrconfig)
+
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{os.path.realpath(home)}"
+
+                    [[trees.repos]]
+                    name = "src/one"
+                    """
+                )
+
+            cmd = grm(
+                ["repos", "import", "--from", "mr", path, "--merge-into", config.name]
+            )
+            assert cmd.returncode == 0
+
+            with open(config.name) as f:
+                repos = toml.load(f)["trees"][0]["repos"]
+            assert [repo["name"] for repo in repos] == ["src/one", "src/two"]
+
+
+def test_repos_import_ghq():
+    with tempfile.TemporaryDirectory() as root:
+        repo_path = os.path.join(root, "github.com", "user", "repo")
+        os.makedirs(repo_path)
+        shell(
+            f"""
+            cd {repo_path}
+            git init
+            git remote add origin https://github.com/user/repo.git
+        """
+        )
+
+        cmd = grm(["repos", "import", "--from", "ghq", root])
+        assert cmd.returncode == 0
+
+        output = toml.loads(cmd.stdout)
+        assert output["trees"][0]["root"] == root
+        repos = output["trees"][0]["repos"]
+        assert [repo["name"] for repo in repos] == ["github.com/user/repo"]
+        assert repos[0]["remotes"][0]["url"] == "https://github.com/user/repo.git"
+
+
+def test_repos_import_gita():
+    with tempfile.TemporaryDirectory() as base:
+        repo_path = os.path.join(base, "src", "repo")
+        os.makedirs(repo_path)
+        shell(
+            f"""
+            cd {repo_path}
+            git init
+            git remote add origin https://example.com/repo.git
+        """
+        )
+
+        registry = os.path.join(base, "repos.csv")
+        with open(registry, "w") as f:
+            f.write(f"{repo_path},repo,,\n{base}/missing,missing,,\n")
+
+        cmd = grm(["repos", "import", "--from", "gita", registry])
+        assert cmd.returncode == 0
+        assert "missing" in cmd.stderr
+
+        output = toml.loads(cmd.stdout)
+        assert len(output["trees"]) == 1
+        assert output["trees"][0]["root"] == os.path.join(base, "src")
+        repos = output["trees"][0]["repos"]
+        assert [repo["name"] for repo in repos] == ["repo"]
+        assert repos[0]["remotes"][0]["url"] == "https://example.com/repo.git"
+
+
+def test_repos_import_vcsh():
+    with tempfile.TemporaryDirectory() as config_home:
+        repo_d = os.path.join(config_home, "vcsh", "repo.d")
+        os.makedirs(repo_d)
+        shell(
+            f"""
+            cd {repo_d}
+            git init --bare zsh.git
+            git -C zsh.git config core.bare false
+            git -C zsh.git config core.worktree {config_home}
+            git -C zsh.git remote add origin https://example.com/zsh.git
+        """
+        )
+
+        cmd = grm(["repos", "import", "--from", "vcsh", repo_d])
+        assert cmd.returncode == 0
+        assert "vcsh clone" in cmd.stderr
+
+        output = toml.loads(cmd.stdout)
+        assert output["trees"][0]["root"] == repo_d
+        repos = output["trees"][0]["repos"]
+        assert [repo["name"] for repo in repos] == ["zsh.git"]
+        assert repos[0]["remotes"][0]["url"] == "https://example.com/zsh.git"
+        assert repos[0]["clone_mode"] == "bare"
@@ -0,0 +1,101 @@
+#!/usr/bin/env python3
+
+import csv
+import io
+import json
+import tempfile
+
+from helpers import grm
+
+config_template = """
+[[trees]]
+root = "{root}"
+
+[[trees.repos]]
+name = "one"
+tags = ["work", "rust"]
+
+[[trees.repos.remotes]]
+name = "origin"
+url = "https://example.com/one.git"
+type = "https"
+
+[[trees.repos]]
+name = "two"
+worktree_setup = true
+"""
+
+
+def test_repos_list_template():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(config_template.format(root=root))
+
+            cmd = grm(["repos", "list", "--config", config.name])
+            assert cmd.returncode == 0
+            assert cmd.stdout.splitlines() == ["one", "two"]
+
+            cmd = grm(
+                [
+                    "repos",
+                    "list",
+                    "--config",
+                    config.name,
+                    "--format",
+                    "{name} {path} {remotes[0].url} {{{tags}}}",
+                ]
+            )
+            assert cmd.returncode == 0
+            assert cmd.stdout.splitlines() == [
+                f"one {root}/one https://example.com/one.git {{work,rust}}",
+                f"two {root}/two  {{}}",
+            ]
+
+
+def test_repos_list_invalid_template():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(config_template.format(root=root))
+
+            cmd = grm(
+                ["repos", "list", "--config", config.name, "--format", "{unknown}"]
+            )
+            assert cmd.returncode != 0
+            assert len(cmd.stdout) == 0
+            assert "unknown" in cmd.stderr
+
+
+def test_repos_list_json():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(config_template.format(root=root))
+
+            cmd = grm(["repos", "list", "--config", config.name, "--format", "json"])
+            assert cmd.returncode == 0
+
+            output = json.loads(cmd.stdout)
+            assert [repo["name"] for repo in output] == ["one", "two"]
+            assert output[0]["tags"] == ["work", "rust"]
+            assert output[0]["remotes"][0]["type"] == "https"
+            assert output[1]["worktree_setup"] is True
+            assert output[1]["remotes"] == []
+
+
+def test_repos_list_csv():
+    with tempfile.TemporaryDirectory() as root:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(config_template.format(root=root))
+
+            cmd = grm(["repos", "list", "--config", config.name, "--format", "csv"])
+            assert cmd.returncode == 0
+
+            rows = list(csv.DictReader(io.StringIO(cmd.stdout)))
+            assert len(rows) == 2
+            assert rows[0]["name"] == "one"
+            assert rows[0]["tags"] == "work;rust"
+            assert rows[0]["remotes"] == "https://example.com/one.git"
+            assert rows[1]["worktree_setup"] == "true"
@@ -0,0 +1,26 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+from helpers import TempConfig, grm, tree_config
+
+def test_repos_path():
+    with tempfile.TemporaryDirectory() as root1, tempfile.TemporaryDirectory() as root2:
+        content = tree_config(root1, "one", "two") + tree_config(root2, "one")
+        with TempConfig(content) as config:
+            cmd = grm(["repos", "path", "--config", config, "two"])
+            assert cmd.returncode == 0
+            assert cmd.stdout.strip() == os.path.join(root1, "two")
+
+            cmd = grm(["repos", "path", "--config", config, "one"])
+            assert cmd.returncode != 0
+            assert "--tree" in cmd.stderr
+
+            cmd = grm(["repos", "path", "--config", config, "one", "--tree", root2])
+            assert cmd.returncode == 0
+            assert cmd.stdout.strip() == os.path.join(root2, "one")
+
+            cmd = grm(["repos", "path", "--config", config, "three"])
+            assert cmd.returncode != 0
+            assert len(cmd.stdout) == 0
@@ -0,0 +1,55 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+from helpers import grm
+
+config = """
+[[trees]]
+root = "projects"
+
+[[trees.repos]]
+name = "one"
+"""
+
+
+def test_repos_relative_root():
+    with tempfile.TemporaryDirectory() as config_dir:
+        with tempfile.TemporaryDirectory() as cwd:
+            config_path = os.path.join(config_dir, "config.toml")
+            with open(config_path, "w") as f:
+                f.write(config)
+
+            cmd = grm(["repos", "path", "--config", config_path, "one"], cwd=cwd)
+            assert cmd.returncode == 0
+            assert cmd.stdout.strip() == os.path.join(
+                os.path.realpath(config_dir), "projects", "one"
+            )
+
+            cmd = grm(
+                [
+                    "--roots-relative-to-cwd",
+                    "repos",
+                    "path",
+                    "--config",
+                    config_path,
+                    "one",
+                ],
+                cwd=cwd,
+            )
+            assert cmd.returncode == 0
+            assert cmd.stdout.strip() == os.path.join("projects", "one")
+
+
+def test_repos_relative_root_sync():
+    with tempfile.TemporaryDirectory() as config_dir:
+        with tempfile.TemporaryDirectory() as cwd:
+            config_path = os.path.join(config_dir, "config.toml")
+            with open(config_path, "w") as f:
+                f.write(config)
+
+            cmd = grm(["repos", "sync", "config", "--config", config_path], cwd=cwd)
+            assert cmd.returncode == 0
+            assert os.path.isdir(os.path.join(config_dir, "projects", "one", ".git"))
+            assert not os.path.exists(os.path.join(cwd, "projects"))
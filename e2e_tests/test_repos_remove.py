@@ -0,0 +1,149 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+import toml
+
+from helpers import TempGitFileRemote, grm, shell
+
+config_template = """
+# keep me
+[[trees]]
+root = "{root}"
+
+[[trees.repos]]
+name = "one"
+
+[[trees.repos.remotes]]
+name = "origin"
+url = "file://{remote}"
+type = "file"
+
+[[trees.repos]]
+name = "two"
+"""
+
+
+def test_repos_remove():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(config_template.format(root=target, remote=remote))
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                cmd = grm(["repos", "remove", "--config", config.name, "one"])
+                assert cmd.returncode == 0
+
+                # The files are kept
+                assert os.path.exists(os.path.join(target, "one"))
+
+                with open(config.name) as f:
+                    content = f.read()
+                assert "# keep me" in content
+                repos = toml.loads(content)["trees"][0]["repos"]
+                assert [repo["name"] for repo in repos] == ["two"]
+
+                cmd = grm(["repos", "remove", "--config", config.name, "one"])
+                assert cmd.returncode != 0
+
+
+def test_repos_remove_delete_files():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(config_template.format(root=target, remote=remote))
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                repo_path = os.path.join(target, "one")
+                args = ["repos", "remove", "--config", config.name, "--delete-files"]
+
+                shell(f"cd {repo_path} && touch new-file")
+                cmd = grm(args + ["one"])
+                assert cmd.returncode != 0
+                assert "uncommitted" in cmd.stderr
+                assert os.path.exists(repo_path)
+                with open(config.name) as f:
+                    assert "one" in f.read()
+
+                shell(f"cd {repo_path} && git add new-file && git commit -m new")
+                cmd = grm(args + ["one"])
+                assert cmd.returncode != 0
+                assert "unpushed" in cmd.stderr
+                assert os.path.exists(repo_path)
+
+                shell(f"cd {repo_path} && git push origin HEAD")
+                cmd = grm(args + ["one"])
+                assert cmd.returncode == 0
+                assert not os.path.exists(repo_path)
+                with open(config.name) as f:
+                    repos = toml.load(f)["trees"][0]["repos"]
+                assert [repo["name"] for repo in repos] == ["two"]
+
+
+def test_repos_remove_delete_files_nested_worktree():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        config_template.format(root=target, remote=remote).replace(
+                            'name = "one"', 'name = "one"\nworktree_setup = true'
+                        )
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                repo_path = os.path.join(target, "one")
+                cmd = grm(["wt", "add", "feature/foo"], cwd=repo_path)
+                assert cmd.returncode == 0
+                shell(f"touch {repo_path}/feature/foo/untracked")
+
+                args = ["repos", "remove", "--config", config.name, "--delete-files"]
+                cmd = grm(args + ["one"])
+                assert cmd.returncode != 0
+                assert "Worktree feature/foo contains uncommitted changes" in cmd.stderr
+                assert os.path.exists(os.path.join(repo_path, "feature/foo/untracked"))
+
+
+def test_repos_remove_delete_files_referenced():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(config_template.format(root=target, remote=remote))
+                    f.write(
+                        f"""
+[[trees.repos]]
+name = "three"
+reference = "one"
+
+[[trees.repos.remotes]]
+name = "origin"
+url = "file://{remote}"
+type = "file"
+"""
+                    )
+
+                # The reference has to exist before cloning "three"
+                sync_args = ["repos", "sync", "config", "--config", config.name]
+                cmd = grm(sync_args)
+                assert cmd.returncode == 0
+                shell(f"rm -rf {target}/three")
+                cmd = grm(sync_args)
+                assert cmd.returncode == 0
+
+                args = ["repos", "remove", "--config", config.name, "--delete-files"]
+                cmd = grm(args + ["one"])
+                assert cmd.returncode != 0
+                assert 'Repository "three" uses its objects' in cmd.stderr
+                assert os.path.exists(os.path.join(target, "one"))
+                with open(config.name) as f:
+                    assert "one" in f.read()
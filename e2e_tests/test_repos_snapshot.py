@@ -0,0 +1,194 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+import git
+import toml
+
+from helpers import TempGitFileRemote, grm, shell
+
+
+def write_config(config, target, remote):
+    with open(config.name, "w") as f:
+        f.write(
+            f"""
+            [[trees]]
+            root = "{target}"
+
+            [[trees.repos]]
+            name = "one"
+
+            [[trees.repos.remotes]]
+            name = "origin"
+            url = "file://{remote}"
+            type = "file"
+
+            [[trees.repos]]
+            name = "two"
+
+            [[trees.repos.remotes]]
+            name = "origin"
+            url = "file://{remote}"
+            type = "file"
+
+            [[trees.repos]]
+            name = "missing"
+            """
+        )
+
+
+def test_repos_snapshot_and_restore():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                write_config(config, target, remote)
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                one = os.path.join(target, "one")
+                two = os.path.join(target, "two")
+                missing = os.path.join(target, "missing")
+                shell(
+                    f"""
+                    rm -rf {missing}
+                    git -C {one} checkout --quiet -b feature HEAD~1
+                    git -C {two} checkout --quiet --detach HEAD~1
+                """
+                )
+
+                with git.Repo(one) as repo:
+                    one_commit = repo.head.commit.hexsha
+                with git.Repo(two) as repo:
+                    two_commit = repo.head.commit.hexsha
+
+                with tempfile.TemporaryDirectory() as lockdir:
+                    lockfile = os.path.join(lockdir, "grm.lock")
+                    cmd = grm(
+                        [
+                            "repos",
+                            "snapshot",
+                            "--config",
+                            config.name,
+                            "--output",
+                            lockfile,
+                        ]
+                    )
+                    assert cmd.returncode == 0
+                    assert "missing: Repository does not exist" in cmd.stderr
+
+                    snapshot = toml.load(lockfile)
+                    assert snapshot == {
+                        "trees": [
+                            {
+                                "root": target,
+                                "repos": [
+                                    {
+                                        "name": "one",
+                                        "branch": "feature",
+                                        "commit": one_commit,
+                                    },
+                                    {
+                                        "name": "two",
+                                        "commit": two_commit,
+                                    },
+                                ],
+                            }
+                        ]
+                    }
+
+                    shell(
+                        f"""
+                        git -C {one} checkout --quiet master
+                        git -C {one} branch --quiet -D feature
+                        git -C {two} checkout --quiet master
+                    """
+                    )
+
+                    cmd = grm(["repos", "restore", lockfile])
+                    assert cmd.returncode == 0
+
+                    with git.Repo(one) as repo:
+                        assert str(repo.active_branch) == "feature"
+                        assert repo.head.commit.hexsha == one_commit
+                    with git.Repo(two) as repo:
+                        assert repo.head.is_detached
+                        assert repo.head.commit.hexsha == two_commit
+
+                    # A second restore does not have to change anything
+                    cmd = grm(["repos", "restore", lockfile])
+                    assert cmd.returncode == 0
+                    assert "Checking out" not in cmd.stdout
+
+
+def test_repos_restore_uncommitted_changes():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                write_config(config, target, remote)
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                one = os.path.join(target, "one")
+                shell(f"git -C {one} checkout --quiet --detach HEAD~1")
+
+                with tempfile.TemporaryDirectory() as lockdir:
+                    lockfile = os.path.join(lockdir, "grm.lock")
+                    cmd = grm(
+                        [
+                            "repos",
+                            "snapshot",
+                            "--config",
+                            config.name,
+                            "--output",
+                            lockfile,
+                        ]
+                    )
+                    assert cmd.returncode == 0
+
+                    shell(
+                        f"""
+                        git -C {one} checkout --quiet master
+                        echo change > {one}/root-commit-in-remote-1
+                    """
+                    )
+
+                    cmd = grm(["repos", "restore", lockfile])
+                    assert cmd.returncode != 0
+                    assert "uncommitted changes" in cmd.stderr
+
+                    with git.Repo(one) as repo:
+                        assert str(repo.active_branch) == "master"
+
+
+def test_repos_snapshot_broken_repo():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                write_config(config, target, remote)
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                shell(f"rm -rf {target}/one/.git && mkdir {target}/one/.git")
+
+                with tempfile.TemporaryDirectory() as lockdir:
+                    lockfile = os.path.join(lockdir, "grm.lock")
+                    cmd = grm(
+                        [
+                            "repos",
+                            "snapshot",
+                            "--config",
+                            config.name,
+                            "--output",
+                            lockfile,
+                        ]
+                    )
+                    assert cmd.returncode != 0
+                    assert "one: Opening repository failed" in cmd.stderr
+
+                    snapshot = toml.load(lockfile)
+                    repos = snapshot["trees"][0]["repos"]
+                    assert [repo["name"] for repo in repos] == ["two"]
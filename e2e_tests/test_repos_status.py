@@ -1,6 +1,10 @@
 #!/usr/bin/env python3
 
-from helpers import RepoTree, grm
+import os
+import tempfile
+
+import pytest
+from helpers import RepoTree, TempGitFileRemote, TempGitRepository, grm, shell
 
 
 def test_repos_sync_worktree_clone():
@@ -9,3 +13,126 @@ def test_repos_sync_worktree_clone():
         assert cmd.returncode == 0
         for repo in repos:
             assert repo in cmd.stdout
+
+
+def test_repos_status_stashes_and_changes():
+    with TempGitRepository() as repo_dir:
+        shell(
+            f"""
+            cd {repo_dir}
+            echo change > root-commit
+            git stash
+            echo change > root-commit
+            git stash
+            touch new-file
+            """
+        )
+
+        cmd = grm(["repos", "status"], cwd=repo_dir)
+        assert cmd.returncode == 0
+        assert "Stashes: 2" in cmd.stdout
+        assert "New: 1" in cmd.stdout
+
+
+def test_repos_status_missing_remote():
+    with tempfile.TemporaryDirectory() as root:
+        with TempGitRepository(dir=root) as repo_dir:
+            name = os.path.basename(repo_dir)
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{root}"
+
+                        [[trees.repos]]
+                        name = "{name}"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file:///dev/null"
+                        type = "file"
+
+                        [[trees.repos.remotes]]
+                        name = "configured-only"
+                        url = "file:///dev/null"
+                        type = "file"
+                    """
+                    )
+
+                cmd = grm(["repos", "status", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "configured-only <!missing>" in cmd.stdout
+                assert "origin <!missing>" not in cmd.stdout
+
+
+@pytest.mark.parametrize("fetch", [True, False])
+def test_repos_status_fetch(fetch):
+    with tempfile.TemporaryDirectory() as root:
+        with TempGitFileRemote() as (remote, _head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{root}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                    """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                shell(
+                    f"""
+                    cd {root}
+                    git clone {remote} pusher
+                    cd pusher
+                    echo change > new-file
+                    git add new-file
+                    git commit -m "new commit"
+                    git push origin HEAD
+                    """
+                )
+
+                args = ["repos", "status", "--config", config.name]
+                if fetch:
+                    args.append("--fetch")
+                cmd = grm(args)
+                assert cmd.returncode == 0
+                if fetch:
+                    assert "master [-1]" in cmd.stdout
+                else:
+                    assert "master ✔" in cmd.stdout
+
+
+def test_repos_status_lfs():
+    with TempGitRepository() as repo_dir:
+        # Commit an LFS pointer file directly, so this does not require git-lfs
+        shell(
+            f"""
+            cd {repo_dir}
+            echo '*.bin filter=lfs diff=lfs merge=lfs -text' > .gitattributes
+            printf 'version https://git-lfs.github.com/spec/v1\\nsize 12345\\n' > pointer.bin
+            echo content > downloaded.bin
+            git add .
+            git commit -m "add lfs files"
+            """
+        )
+
+        cmd = grm(["repos", "status"], cwd=repo_dir)
+        assert cmd.returncode == 0
+        assert "LFS: 1/2 downloaded" in cmd.stdout
+
+        shell(f"echo content > {repo_dir}/pointer.bin")
+
+        cmd = grm(["repos", "status"], cwd=repo_dir)
+        assert cmd.returncode == 0
+        assert "LFS" not in cmd.stdout
@@ -1,20 +1,26 @@
 #!/usr/bin/env python3
 
+import json
 import os
 import re
+import shutil
 import subprocess
 import tempfile
 import textwrap
 
 import git
 import pytest
+import toml
 from helpers import (
     NonExistentPath,
+    TempConfig,
     TempGitFileRemote,
     TempGitRepository,
     checksum_directory,
     grm,
+    repo_config,
     shell,
+    tree_config,
 )
 
 templates = {
@@ -303,6 +309,97 @@ def test_repos_sync_unmanaged_repos(configtype):
                 )
 
 
+@pytest.mark.parametrize("policy", ["ignore", "warn", "error"])
+def test_repos_sync_unmanaged_policy(policy):
+    with tempfile.TemporaryDirectory() as root:
+        with TempGitRepository(dir=root):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(f'unmanaged = "{policy}"\n')
+                    f.write(templates["repo_simple"]["toml"].format(root=root))
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert os.path.exists(os.path.join(root, "test"))
+
+                if policy == "ignore":
+                    assert cmd.returncode == 0
+                    assert "unmanaged" not in cmd.stderr.lower()
+                elif policy == "warn":
+                    assert cmd.returncode == 0
+                    assert "unmanaged" in cmd.stderr.lower()
+                else:
+                    assert cmd.returncode == 1
+                    assert "unmanaged" in cmd.stderr.lower()
+
+
+def test_repos_sync_unmanaged_ignore_globs():
+    with tempfile.TemporaryDirectory() as root:
+        scratch = os.path.join(root, "scratch")
+        os.mkdir(scratch)
+        with TempGitRepository(dir=scratch):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{root}"
+                        ignore = ["scratch/*"]
+
+                        [[trees.repos]]
+                        name = "test"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "unmanaged" not in cmd.stderr.lower()
+
+                with TempGitRepository(dir=root):
+                    cmd = grm(["repos", "sync", "config", "--config", config.name])
+                    assert cmd.returncode == 0
+                    assert "unmanaged" in cmd.stderr.lower()
+                    assert "scratch" not in cmd.stderr
+
+
+def test_repos_sync_fail_fast():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "bad"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file:///nonexistent"
+                        type = "file"
+
+                        [[trees.repos]]
+                        name = "good"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(
+                    ["repos", "sync", "config", "--config", config.name, "--fail-fast"]
+                )
+                assert cmd.returncode == 2
+                assert not os.path.exists(os.path.join(target, "good"))
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 2
+                assert os.path.exists(os.path.join(target, "good"))
+
+
 @pytest.mark.parametrize("configtype", ["toml", "yaml"])
 def test_repos_sync_root_is_file(configtype):
     with tempfile.NamedTemporaryFile() as target:
@@ -644,6 +741,7 @@ def test_repos_sync_normal_change_remote_name(configtype):
 
                     cmd = grm(["repos", "sync", "config", "--config", config.name])
                     assert cmd.returncode == 0
+                    assert 'Renaming remote "origin" to "origin2"' in cmd.stdout
                     with git.Repo(git_dir) as repo:
                         # See the note in `test_repos_sync_normal_remove_remote()`
                         # about repo.remotes
@@ -654,6 +752,12 @@ def test_repos_sync_normal_change_remote_name(configtype):
                         assert len(urls) == 1
                         assert urls[0] == f"file://{remote1}"
 
+                        # The remote tracking branches are kept
+                        assert "origin2/master" in [str(ref) for ref in repo.refs]
+                        assert str(repo.active_branch.tracking_branch()) == (
+                            "origin2/master"
+                        )
+
 
 @pytest.mark.parametrize("configtype", ["toml", "yaml"])
 @pytest.mark.parametrize("init_worktree", [True, False, "default"])
@@ -828,3 +932,1655 @@ def test_repos_sync_worktree_change_to_normal(configtype):
                     assert cmd.returncode != 0
                     assert "already exists" in cmd.stderr
                     assert "using a worktree setup" in cmd.stderr
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+@pytest.mark.parametrize("use_cli_flag", [True, False])
+def test_repos_sync_shallow_clone(worktree_setup, use_cli_flag):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        worktree_setup = {str(worktree_setup).lower()}
+                        {"" if use_cli_flag else "clone_depth = 1"}
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                args = ["repos", "sync", "config", "--config", config.name]
+                if use_cli_flag:
+                    args += ["--depth", "1"]
+                cmd = grm(args)
+                assert cmd.returncode == 0
+
+                git_dir = os.path.join(target, "test")
+                if worktree_setup:
+                    git_dir = os.path.join(git_dir, ".git-main-working-tree")
+                    assert set(os.listdir(os.path.join(target, "test"))) == {
+                        ".git-main-working-tree",
+                        "master",
+                    }
+
+                with git.Repo(git_dir) as repo:
+                    assert repo.bare == worktree_setup
+                    assert os.path.exists(os.path.join(repo.git_dir, "shallow"))
+                    commit = repo.commit("origin/master")
+                    assert str(commit) == remote_head_commit_sha
+                    assert len(list(repo.iter_commits("origin/master"))) == 1
+                    assert (
+                        str(repo.branches["master"].tracking_branch())
+                        == "origin/master"
+                    )
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+def test_repos_sync_partial_clone(worktree_setup):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            shell(f"git -C {remote} config uploadpack.allowFilter true")
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        worktree_setup = {str(worktree_setup).lower()}
+                        clone_filter = "blob:none"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                git_dir = os.path.join(target, "test")
+                checkout = git_dir
+                if worktree_setup:
+                    git_dir = os.path.join(git_dir, ".git-main-working-tree")
+                    checkout = os.path.join(target, "test", "master")
+
+                with git.Repo(git_dir) as repo:
+                    assert repo.bare == worktree_setup
+                    with repo.config_reader() as reader:
+                        assert reader.get_value('remote "origin"', "promisor")
+                    commit = repo.commit("origin/master")
+                    assert str(commit) == remote_head_commit_sha
+
+                # The blobs of the checked out commit have to be there
+                with open(os.path.join(checkout, "root-commit-in-remote-2")) as f:
+                    assert f.read() == "test\n"
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+@pytest.mark.parametrize("reference", ["auto", "../first/test"])
+def test_repos_sync_reference_clone(worktree_setup, reference):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            second = repo_config(
+                "test", remote, worktree_setup=worktree_setup, reference=reference
+            )
+            content = tree_config(f"{target}/first", repo_config("test", remote))
+            content += tree_config(f"{target}/second", second)
+            with TempConfig(content) as config:
+                # The first sync clones both, so there is nothing to share yet
+                # with "auto"
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+                shutil.rmtree(os.path.join(target, "second"))
+
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+
+                git_dir = os.path.join(target, "second", "test")
+                if worktree_setup:
+                    git_dir = os.path.join(git_dir, ".git-main-working-tree")
+                else:
+                    git_dir = os.path.join(git_dir, ".git")
+
+                alternates = os.path.join(git_dir, "objects", "info", "alternates")
+                with open(alternates) as f:
+                    assert f.read().strip() == os.path.join(
+                        target, "first", "test", ".git", "objects"
+                    )
+
+                with git.Repo(git_dir) as repo:
+                    commit = repo.commit("origin/master")
+                    assert str(commit) == remote_head_commit_sha
+
+
+def test_repos_sync_reference_clone_missing():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            repo = repo_config("test", remote, reference="/nonexistent")
+            with TempConfig(tree_config(target, repo)) as config:
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+                assert os.path.exists(os.path.join(target, "test", ".git"))
+                alternates = os.path.join(
+                    target, "test", ".git", "objects", "info", "alternates"
+                )
+                assert not os.path.exists(alternates)
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+@pytest.mark.parametrize(
+    "optimize", [[], ["commit_graph"], ["repack"], ["repack", "commit_graph"]]
+)
+def test_repos_sync_optimize(worktree_setup, optimize):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            repo = repo_config("test", remote, worktree_setup=worktree_setup)
+            with TempConfig(tree_config(target, repo, optimize=optimize)) as config:
+                git_dir = os.path.join(target, "test")
+                if worktree_setup:
+                    git_dir = os.path.join(git_dir, ".git-main-working-tree")
+                else:
+                    git_dir = os.path.join(git_dir, ".git")
+                objects = os.path.join(git_dir, "objects")
+
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+                assert os.path.exists(
+                    os.path.join(objects, "info", "commit-graph")
+                ) == ("commit_graph" in optimize)
+
+                # Loose objects, like the ones a fetch leaves behind. Only
+                # reachable ones are packed.
+                shell(
+                    f"""
+                    blob=$(echo loose | git --git-dir {git_dir} hash-object -w --stdin)
+                    git --git-dir {git_dir} update-ref refs/tags/loose $blob
+                    """
+                )
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+                loose = [
+                    directory
+                    for directory in os.listdir(objects)
+                    if len(directory) == 2
+                    and os.listdir(os.path.join(objects, directory))
+                ]
+                assert (loose == []) == ("repack" in optimize)
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+def test_repos_sync_single_branch_clone(worktree_setup):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            shell(
+                f"""
+                git -C {remote} branch other
+                git -C {remote} branch unwanted
+            """
+            )
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        worktree_setup = {str(worktree_setup).lower()}
+                        single_branch = true
+                        branch = "other"
+
+                        [[trees.repos.remotes]]
+                        name = "upstream"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                git_dir = os.path.join(target, "test")
+                if worktree_setup:
+                    git_dir = os.path.join(git_dir, ".git-main-working-tree")
+                    assert set(os.listdir(os.path.join(target, "test"))) == {
+                        ".git-main-working-tree",
+                        "other",
+                    }
+
+                with git.Repo(git_dir) as repo:
+                    assert repo.bare == worktree_setup
+                    assert [str(ref) for ref in repo.remotes[0].refs] == [
+                        "upstream/other"
+                    ]
+                    assert [str(branch) for branch in repo.branches] == ["other"]
+                    assert (
+                        str(repo.branches["other"].tracking_branch())
+                        == "upstream/other"
+                    )
+                    with repo.config_reader() as reader:
+                        assert (
+                            reader.get_value('remote "upstream"', "fetch")
+                            == "+refs/heads/other:refs/remotes/upstream/other"
+                        )
+                    if not worktree_setup:
+                        assert str(repo.active_branch) == "other"
+
+
+def test_repos_sync_configured_branch():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            shell(f"git -C {remote} branch develop")
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        branch = "develop"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "configured branch" not in cmd.stderr
+
+                git_dir = os.path.join(target, "test")
+                with git.Repo(git_dir) as repo:
+                    assert str(repo.active_branch) == "develop"
+                    assert (
+                        str(repo.branches["develop"].tracking_branch())
+                        == "origin/develop"
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "configured branch" not in cmd.stderr
+
+                shell(f"git -C {git_dir} checkout -b other")
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert (
+                    'Branch "other" is checked out instead of the configured branch'
+                    ' "develop"' in cmd.stderr
+                )
+
+                with git.Repo(git_dir) as repo:
+                    assert str(repo.active_branch) == "other"
+
+
+def test_repos_sync_pinned_rev():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            shell(
+                f"""
+                cd {remote}
+                git tag v1.0
+                echo new > newfile
+                git add newfile
+                git commit -m new
+            """
+            )
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        rev = "v1.0"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(
+                    ["repos", "sync", "config", "--config", config.name, "--check"]
+                )
+                assert cmd.returncode != 0
+                assert "Repository does not exist" in cmd.stderr
+                assert not os.path.exists(os.path.join(target, "test"))
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                git_dir = os.path.join(target, "test")
+                with git.Repo(git_dir) as repo:
+                    assert repo.head.is_detached
+                    assert str(repo.head.commit) == remote_head_commit_sha
+
+                cmd = grm(
+                    ["repos", "sync", "config", "--config", config.name, "--check"]
+                )
+                assert cmd.returncode == 0
+
+                shell(f"git -C {git_dir} checkout --quiet master")
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert 'HEAD does not match the pinned revision "v1.0"' in cmd.stderr
+
+                cmd = grm(
+                    ["repos", "sync", "config", "--config", config.name, "--check"]
+                )
+                assert cmd.returncode != 0
+                assert 'HEAD does not match the pinned revision "v1.0"' in cmd.stderr
+
+                cmd = grm(["repos", "status", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "<!pinned: v1.0>" in cmd.stdout
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+@pytest.mark.parametrize("valid_paths", [True, False])
+def test_repos_sync_sparse_checkout(worktree_setup, valid_paths):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitRepository() as remote:
+            shell(
+                f"""
+                cd {remote}
+                mkdir -p docs tools/grm other
+                echo test > docs/file
+                echo test > tools/grm/file
+                echo test > other/file
+                git add .
+                git commit -m "add directories"
+            """
+            )
+            sparse_paths = ["docs/", "tools/grm/"]
+            if not valid_paths:
+                sparse_paths.append("doesnotexist/")
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        worktree_setup = {str(worktree_setup).lower()}
+                        sparse_paths = {sparse_paths}
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                if not valid_paths:
+                    assert cmd.returncode != 0
+                    assert "doesnotexist/" in cmd.stderr
+                    return
+                assert cmd.returncode == 0
+
+                checkout = os.path.join(target, "test")
+                if worktree_setup:
+                    checkout = os.path.join(checkout, "master")
+
+                assert os.path.exists(os.path.join(checkout, "docs", "file"))
+                assert os.path.exists(os.path.join(checkout, "tools", "grm", "file"))
+                assert not os.path.exists(os.path.join(checkout, "other"))
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+@pytest.mark.parametrize("submodules", ["none", "init", "recursive"])
+def test_repos_sync_submodules(monkeypatch, worktree_setup, submodules):
+    # Newer versions of git refuse to clone submodules from file:// URLs
+    monkeypatch.setenv("GIT_CONFIG_COUNT", "1")
+    monkeypatch.setenv("GIT_CONFIG_KEY_0", "protocol.file.allow")
+    monkeypatch.setenv("GIT_CONFIG_VALUE_0", "always")
+
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.TemporaryDirectory() as remotes:
+            shell(
+                f"""
+                cd {remotes}
+                for repo in nested sub super ; do
+                    git -c init.defaultBranch=master init $repo
+                    echo test > $repo/file
+                    git -C $repo add file
+                    git -C $repo commit -m init
+                done
+                git -C sub submodule add file://{remotes}/nested nested
+                git -C sub commit -m "add submodule"
+                git -C super submodule add file://{remotes}/sub sub
+                git -C super commit -m "add submodule"
+            """
+            )
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        worktree_setup = {str(worktree_setup).lower()}
+                        submodules = "{submodules}"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remotes}/super"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                checkout = os.path.join(target, "test")
+                if worktree_setup:
+                    checkout = os.path.join(checkout, "master")
+
+                assert os.path.exists(os.path.join(checkout, "file"))
+                assert os.path.exists(os.path.join(checkout, "sub", "file")) == (
+                    submodules != "none"
+                )
+                assert os.path.exists(
+                    os.path.join(checkout, "sub", "nested", "file")
+                ) == (submodules == "recursive")
+
+                # Update the submodule and check that a sync with --fetch picks
+                # up the new submodule commit
+                shell(
+                    f"""
+                    cd {remotes}
+                    echo test > sub/newfile
+                    git -C sub add newfile
+                    git -C sub commit -m "new file"
+                    git -C super/sub pull
+                    git -C super commit -am "update submodule"
+                    git -C {checkout} pull
+                """
+                )
+
+                cmd = grm(
+                    ["repos", "sync", "config", "--config", config.name, "--fetch"]
+                )
+                assert cmd.returncode == 0
+
+                assert os.path.exists(os.path.join(checkout, "sub", "newfile")) == (
+                    submodules != "none"
+                )
+
+
+def test_repos_sync_lfs_warning():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitRepository() as remote:
+            shell(
+                f"""
+                cd {remote}
+                echo '*.bin filter=lfs diff=lfs merge=lfs -text' > .gitattributes
+                git add .gitattributes
+                git commit -m "use lfs"
+            """
+            )
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "Repository uses Git LFS" in cmd.stderr
+
+
+def test_repos_sync_mirror():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            shell(
+                f"""
+                cd {remote}
+                git branch to-delete
+                git tag v1.0
+            """
+            )
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        clone_mode = "mirror"
+
+                        [[trees.repos.remotes]]
+                        name = "upstream"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                mirror = os.path.join(target, "test")
+                with git.Repo(mirror) as repo:
+                    assert repo.bare
+                    assert set(str(ref) for ref in repo.references) == {
+                        "master",
+                        "to-delete",
+                        "v1.0",
+                    }
+                    assert str(repo.commit("master")) == remote_head_commit_sha
+                    with repo.config_reader() as reader:
+                        assert reader.get_value('remote "upstream"', "mirror")
+
+                shell(
+                    f"""
+                    cd {remote}
+                    git branch -D to-delete
+                    git branch new-branch
+                """
+                )
+
+                cmd = grm(
+                    ["repos", "sync", "config", "--config", config.name, "--fetch"]
+                )
+                assert cmd.returncode == 0
+
+                with git.Repo(mirror) as repo:
+                    assert set(str(ref) for ref in repo.references) == {
+                        "master",
+                        "new-branch",
+                        "v1.0",
+                    }
+
+                cmd = grm(["repos", "status", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "Mirror" in cmd.stdout
+                assert "<!local>" not in cmd.stdout
+
+                cmd = grm(["repos", "find", "local", target])
+                assert cmd.returncode == 0
+                assert 'clone_mode = "mirror"' in cmd.stdout
+
+
+def test_repos_sync_bare():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test.git"
+                        clone_mode = "bare"
+
+                        [[trees.repos.remotes]]
+                        name = "upstream"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                bare = os.path.join(target, "test.git")
+                with git.Repo(bare) as repo:
+                    assert repo.bare
+                    assert "upstream/master" in [str(ref) for ref in repo.references]
+                    assert str(repo.commit("upstream/master")) == remote_head_commit_sha
+                    with repo.config_reader() as reader:
+                        assert not reader.has_option('remote "upstream"', "mirror")
+
+                cmd = grm(
+                    ["repos", "sync", "config", "--config", config.name, "--check"]
+                )
+                assert cmd.returncode == 0
+
+                cmd = grm(["repos", "find", "local", target])
+                assert cmd.returncode == 0
+                assert 'clone_mode = "bare"' in cmd.stdout
+
+
+def test_repos_sync_mirror_with_worktree_setup():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        worktree_setup = true
+                        clone_mode = "mirror"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode != 0
+                assert "Mirrors cannot use a worktree setup" in cmd.stderr
+                assert not os.path.exists(os.path.join(target, "test"))
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+def test_repos_sync_push_mirror(worktree_setup):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, remote_head_commit_sha):
+            with tempfile.TemporaryDirectory() as backup:
+                shell(
+                    f"""
+                    git -C {remote} branch to-delete
+                    git -C {remote} tag v1.0
+                    git init --bare {backup}
+                    git -C {remote} push {backup} master:refs/heads/stale
+                """
+                )
+                with tempfile.NamedTemporaryFile() as config:
+                    with open(config.name, "w") as f:
+                        f.write(
+                            f"""
+                            [[trees]]
+                            root = "{target}"
+
+                            [[trees.repos]]
+                            name = "test"
+                            worktree_setup = {str(worktree_setup).lower()}
+
+                            [[trees.repos.remotes]]
+                            name = "backup"
+                            url = "file://{backup}"
+                            type = "file"
+                            push_mirror = true
+
+                            [[trees.repos.remotes]]
+                            name = "origin"
+                            url = "file://{remote}"
+                            type = "file"
+                            """
+                        )
+
+                    cmd = grm(["repos", "sync", "config", "--config", config.name])
+                    assert cmd.returncode == 0
+
+                    with git.Repo(backup) as repo:
+                        assert set(str(ref) for ref in repo.references) == {
+                            "master",
+                            "to-delete",
+                            "v1.0",
+                        }
+                        assert str(repo.commit("master")) == remote_head_commit_sha
+
+                    shell(
+                        f"""
+                        git -C {remote} branch -D to-delete
+                        git -C {remote} branch new-branch
+                    """
+                    )
+
+                    cmd = grm(
+                        ["repos", "sync", "config", "--config", config.name, "--fetch"]
+                    )
+                    assert cmd.returncode == 0
+
+                    with git.Repo(backup) as repo:
+                        assert set(str(ref) for ref in repo.references) == {
+                            "master",
+                            "new-branch",
+                            "v1.0",
+                        }
+
+
+@pytest.mark.parametrize("worktree_setup", [True, False])
+def test_repos_sync_git_config(worktree_setup):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [trees.git_config]
+                        "user.email" = "tree@example.com"
+                        "pull.rebase" = true
+
+                        [[trees.repos]]
+                        name = "test"
+                        worktree_setup = {str(worktree_setup).lower()}
+
+                        [trees.repos.git_config]
+                        "user.email" = "repo@example.com"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                git_dir = os.path.join(target, "test")
+                if worktree_setup:
+                    git_dir = os.path.join(git_dir, ".git-main-working-tree")
+
+                def check_config():
+                    with git.Repo(git_dir) as repo:
+                        with repo.config_reader("repository") as reader:
+                            assert (
+                                reader.get_value("user", "email") == "repo@example.com"
+                            )
+                            assert reader.get_value("pull", "rebase") is True
+
+                check_config()
+
+                shell(f"git --git-dir {git_dir} config user.email other@example.com")
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert 'Setting git config "user.email"' in cmd.stdout
+
+                check_config()
+
+                # Nothing changes on another sync
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "Setting git config" not in cmd.stdout
+
+
+def test_repos_sync_hooks():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+                        pre_sync = ["echo tree-pre >> {target}/log"]
+                        post_sync = ["echo tree-post-$GRM_REPO_NAME >> {target}/log"]
+
+                        [[trees.repos]]
+                        name = "test"
+                        pre_sync = ["echo repo-pre >> {target}/log"]
+                        post_sync = ["touch post-sync-ran"]
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                with open(os.path.join(target, "log")) as log:
+                    assert log.read().splitlines() == [
+                        "tree-pre",
+                        "repo-pre",
+                        "tree-post-test",
+                    ]
+
+                # post_sync hooks run in the repository
+                assert os.path.exists(os.path.join(target, "test", "post-sync-ran"))
+
+
+def test_repos_sync_failing_pre_sync_hook():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        pre_sync = ["exit 3"]
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode != 0
+                assert "exited with code 3" in cmd.stderr
+                assert not os.path.exists(os.path.join(target, "test"))
+
+
+def test_repos_sync_on_clone_hook():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        on_clone = ["echo cloned >> ../log"]
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                for _ in range(2):
+                    cmd = grm(["repos", "sync", "config", "--config", config.name])
+                    assert cmd.returncode == 0
+
+                with open(os.path.join(target, "log")) as log:
+                    assert log.read().splitlines() == ["cloned"]
+
+
+def test_repos_sync_push_url():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+
+                def write_config(push_url):
+                    with open(config.name, "w") as f:
+                        f.write(
+                            f"""
+                            [[trees]]
+                            root = "{target}"
+
+                            [[trees.repos]]
+                            name = "test"
+
+                            [[trees.repos.remotes]]
+                            name = "upstream"
+                            url = "file://{remote}"
+                            type = "file"
+                            """
+                        )
+                        if push_url is not None:
+                            f.write(f"push_url = {json.dumps(push_url)}\n")
+
+                def push_urls():
+                    with git.Repo(os.path.join(target, "test")) as repo:
+                        with repo.config_reader("repository") as reader:
+                            return reader.get_values(
+                                'remote "upstream"', "pushurl", default=[]
+                            )
+
+                write_config("file:///fork")
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert push_urls() == ["file:///fork"]
+
+                # Nothing changes on another sync
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "push URLs" not in cmd.stdout
+
+                write_config(["file:///fork", "file:///mirror"])
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert push_urls() == ["file:///fork", "file:///mirror"]
+
+                write_config(None)
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "Removing push URLs" in cmd.stdout
+                assert push_urls() == []
+
+
+def test_repos_sync_refspecs():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            shell(f"git -C {remote} notes add -m 'a note' HEAD")
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        refspecs = ["+refs/notes/*:refs/notes/*"]
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                with git.Repo(os.path.join(target, "test")) as repo:
+                    with repo.config_reader("repository") as reader:
+                        assert reader.get_values('remote "origin"', "fetch") == [
+                            "+refs/heads/*:refs/remotes/origin/*",
+                            "+refs/notes/*:refs/notes/*",
+                        ]
+                    # The new clone already has the notes
+                    assert "refs/notes/commits" in [ref.path for ref in repo.refs]
+
+                # Nothing changes on another sync
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "refspec" not in cmd.stdout
+
+
+def test_repos_sync_clone_fallback():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file:///does/not/exist"
+                        type = "file"
+
+                        [[trees.repos.remotes]]
+                        name = "backup"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert 'cloned from remote "backup"' in cmd.stdout
+
+                with git.Repo(os.path.join(target, "test")) as repo:
+                    assert str(repo.head.commit) == head_commit_sha
+                    assert {str(r) for r in repo.remotes} == {"origin", "backup"}
+
+
+def test_repos_sync_primary_remote():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote1, _):
+            with TempGitFileRemote() as (remote2, remote2_head_commit_sha):
+                with tempfile.NamedTemporaryFile() as config:
+                    with open(config.name, "w") as f:
+                        f.write(
+                            f"""
+                            [[trees]]
+                            root = "{target}"
+
+                            [[trees.repos]]
+                            name = "test"
+
+                            [[trees.repos.remotes]]
+                            name = "origin"
+                            url = "file://{remote1}"
+                            type = "file"
+
+                            [[trees.repos.remotes]]
+                            name = "fork"
+                            url = "file://{remote2}"
+                            type = "file"
+                            primary = true
+                            """
+                        )
+
+                    cmd = grm(["repos", "sync", "config", "--config", config.name])
+                    assert cmd.returncode == 0
+                    assert f"file://{remote2}" in cmd.stdout.splitlines()[0]
+
+                    with git.Repo(os.path.join(target, "test")) as repo:
+                        assert str(repo.head.commit) == remote2_head_commit_sha
+                        assert str(repo.active_branch.tracking_branch()) == (
+                            "fork/master"
+                        )
+                        with repo.config_reader("repository") as reader:
+                            assert (
+                                reader.get_value("checkout", "defaultRemote") == "fork"
+                            )
+
+                    with open(config.name, "w") as f:
+                        f.write(
+                            f"""
+                            [[trees]]
+                            root = "{target}"
+
+                            [[trees.repos]]
+                            name = "test"
+
+                            [[trees.repos.remotes]]
+                            name = "origin"
+                            url = "file://{remote1}"
+                            type = "file"
+
+                            [[trees.repos.remotes]]
+                            name = "fork"
+                            url = "file://{remote2}"
+                            type = "file"
+                            """
+                        )
+
+                    cmd = grm(["repos", "sync", "config", "--config", config.name])
+                    assert cmd.returncode == 0
+                    assert "Unsetting primary remote" in cmd.stdout
+
+                    with git.Repo(os.path.join(target, "test")) as repo:
+                        with repo.config_reader("repository") as reader:
+                            assert not reader.has_option("checkout", "defaultRemote")
+
+
+def test_repos_sync_default_branch():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+                        default_branch = "master"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                with git.Repo(os.path.join(target, "test")) as repo:
+                    with repo.config_reader("repository") as reader:
+                        assert reader.get_value("grm", "defaultBranch") == "master"
+
+                cmd = grm(["repos", "find", "local", target])
+                assert cmd.returncode == 0
+                assert 'default_branch = "master"' in cmd.stdout
+
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+                assert "Unsetting default branch" in cmd.stdout
+
+                with git.Repo(os.path.join(target, "test")) as repo:
+                    with repo.config_reader("repository") as reader:
+                        assert not reader.has_option("grm", "defaultBranch")
+
+
+def test_repos_sync_multiple_primary_remotes():
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+
+                    [[trees.repos]]
+                    name = "test"
+
+                    [[trees.repos.remotes]]
+                    name = "origin"
+                    url = "file:///origin"
+                    type = "file"
+                    primary = true
+
+                    [[trees.repos.remotes]]
+                    name = "fork"
+                    url = "file:///fork"
+                    type = "file"
+                    primary = true
+                    """
+                )
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert cmd.returncode != 0
+            assert "Only one remote can be the primary one" in cmd.stderr
+            assert not os.path.exists(os.path.join(target, "test"))
+
+
+def test_repos_sync_tls_options():
+    with tempfile.TemporaryDirectory() as target:
+        git.Repo.init(os.path.join(target, "test"))
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+
+                    [[trees.repos]]
+                    name = "test"
+
+                    [[trees.repos.remotes]]
+                    name = "origin"
+                    url = "https://git.example.com/team/test.git"
+                    type = "https"
+                    ca_bundle = "/etc/ssl/example.pem"
+                    insecure_skip_verify = true
+                    """
+                )
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert cmd.returncode == 0
+            assert "Updating TLS options of remote origin" in cmd.stdout
+
+            section = 'http "https://git.example.com/"'
+            with git.Repo(os.path.join(target, "test")) as repo:
+                with repo.config_reader("repository") as reader:
+                    assert (
+                        reader.get_value(section, "sslCAInfo") == "/etc/ssl/example.pem"
+                    )
+                    assert reader.get_value(section, "sslVerify") is False
+
+            cmd = grm(["repos", "find", "local", target])
+            assert cmd.returncode == 0
+            assert 'ca_bundle = "/etc/ssl/example.pem"' in cmd.stdout
+            assert "insecure_skip_verify = true" in cmd.stdout
+
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+
+                    [[trees.repos]]
+                    name = "test"
+
+                    [[trees.repos.remotes]]
+                    name = "origin"
+                    url = "https://git.example.com/team/test.git"
+                    type = "https"
+                    """
+                )
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert cmd.returncode == 0
+            with git.Repo(os.path.join(target, "test")) as repo:
+                with repo.config_reader("repository") as reader:
+                    assert not reader.has_option(section, "sslCAInfo")
+                    assert not reader.has_option(section, "sslVerify")
+
+
+def test_repos_sync_tls_options_for_ssh_remote():
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+
+                    [[trees.repos]]
+                    name = "test"
+
+                    [[trees.repos.remotes]]
+                    name = "origin"
+                    url = "ssh://git@git.example.com/team/test.git"
+                    type = "ssh"
+                    insecure_skip_verify = true
+                    """
+                )
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert cmd.returncode != 0
+            assert "TLS options are only supported for HTTPS remotes" in cmd.stderr
+            assert not os.path.exists(os.path.join(target, "test"))
+
+
+def test_repos_sync_token_command_for_ssh_remote():
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+
+                    [[trees.repos]]
+                    name = "test"
+
+                    [[trees.repos.remotes]]
+                    name = "origin"
+                    url = "ssh://git@git.example.com/team/test.git"
+                    type = "ssh"
+                    token_command = "touch {target}/token-command-ran"
+                    """
+                )
+
+            cmd = grm(["repos", "sync", "config", "--config", config.name])
+            assert cmd.returncode != 0
+            assert "only supported for HTTPS remotes" in cmd.stderr
+            assert not os.path.exists(os.path.join(target, "test"))
+            assert not os.path.exists(os.path.join(target, "token-command-ran"))
+
+
+def test_repos_sync_check_drift():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [trees.repos.git_config]
+                        "core.autocrlf" = "input"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                check = ["repos", "sync", "config", "--config", config.name, "--check"]
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                cmd = grm(check)
+                assert cmd.returncode == 0
+
+                git_dir = os.path.join(target, "test")
+                shell(
+                    f"""
+                    cd {git_dir}
+                    git remote set-url origin file:///other
+                    git remote add extra file:///extra
+                    git config core.autocrlf false
+                """
+                )
+
+                cmd = grm(check)
+                assert cmd.returncode != 0
+                assert 'Remote "origin" points to "file:///other"' in cmd.stderr
+                assert 'Remote "extra" is not configured' in cmd.stderr
+                assert 'Git config "core.autocrlf" is not "input"' in cmd.stderr
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 0
+
+                cmd = grm(check)
+                assert cmd.returncode == 0
+
+                shell(f"git init --quiet {os.path.join(target, 'unmanaged')}")
+
+                cmd = grm(check)
+                assert cmd.returncode != 0
+                assert "unmanaged" in cmd.stderr.lower()
+
+
+def test_repos_sync_summary():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "good"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+
+                        [[trees.repos]]
+                        name = "bad"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file:///nonexistent"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(["repos", "sync", "config", "--config", config.name])
+                assert cmd.returncode == 2
+                lines = [line.replace(" ", "") for line in cmd.stdout.splitlines()]
+                assert "│Synced┆1│" in lines
+                assert "│Cloned┆1│" in lines
+                assert "│Updatedremotes┆0│" in lines
+                assert "│Failed┆1(bad)│" in lines
+                assert "│Unmanaged┆0│" in lines
+
+                cmd = grm(
+                    ["--quiet", "repos", "sync", "config", "--config", config.name]
+                )
+                assert cmd.returncode == 2
+                assert "Summary" not in cmd.stdout
+
+
+@pytest.mark.parametrize(
+    "url,remote_type,transient",
+    [
+        # Nothing listens on port 1, so the connection is refused
+        ("https://127.0.0.1:1/test.git", "https", True),
+        ("file:///does/not/exist", "file", False),
+    ],
+)
+def test_repos_sync_retries(url, remote_type, transient):
+    with tempfile.TemporaryDirectory() as target:
+        with tempfile.NamedTemporaryFile() as config:
+            with open(config.name, "w") as f:
+                f.write(
+                    f"""
+                    [[trees]]
+                    root = "{target}"
+
+                    [[trees.repos]]
+                    name = "test"
+
+                    [[trees.repos.remotes]]
+                    name = "origin"
+                    url = "{url}"
+                    type = "{remote_type}"
+                    """
+                )
+
+            cmd = grm(
+                [
+                    "repos",
+                    "sync",
+                    "config",
+                    "--config",
+                    config.name,
+                    "--retries",
+                    "2",
+                    "--retry-delay",
+                    "0",
+                ]
+            )
+            assert cmd.returncode != 0
+            if transient:
+                assert "(attempt 1/3)" in cmd.stderr
+                assert "(attempt 2/3)" in cmd.stderr
+            else:
+                # Trying again cannot create the repository
+                assert "(attempt 1/3)" not in cmd.stderr
+            assert "(attempt 3/3)" not in cmd.stderr
+
+
+@pytest.mark.parametrize("timeout", ["0", "-1", "abc"])
+def test_repos_sync_invalid_timeout(timeout):
+    with tempfile.NamedTemporaryFile() as config:
+        cmd = grm(
+            ["repos", "sync", "config", "--config", config.name, "--timeout", timeout],
+            is_invalid=True,
+        )
+        assert cmd.returncode != 0
+
+
+def test_repos_sync_with_timeout():
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, head_commit_sha):
+            with tempfile.NamedTemporaryFile() as config:
+                with open(config.name, "w") as f:
+                    f.write(
+                        f"""
+                        [[trees]]
+                        root = "{target}"
+
+                        [[trees.repos]]
+                        name = "test"
+
+                        [[trees.repos.remotes]]
+                        name = "origin"
+                        url = "file://{remote}"
+                        type = "file"
+                        """
+                    )
+
+                cmd = grm(
+                    [
+                        "repos",
+                        "sync",
+                        "config",
+                        "--config",
+                        config.name,
+                        "--timeout",
+                        "60",
+                        "--connect-timeout",
+                        "10",
+                    ]
+                )
+                assert cmd.returncode == 0
+
+                with git.Repo(os.path.join(target, "test")) as repo:
+                    assert str(repo.head.commit) == head_commit_sha
+
+
+@pytest.mark.parametrize("key", ["disabled", "skip"])
+def test_repos_sync_disabled(key):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            content = tree_config(
+                target,
+                repo_config("good", remote),
+                repo_config("broken", "/nonexistent", **{key: True}),
+            )
+            with TempConfig(content) as config:
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+                assert os.path.exists(os.path.join(target, "good"))
+                assert not os.path.exists(os.path.join(target, "broken"))
+                lines = [line.replace(" ", "") for line in cmd.stdout.splitlines()]
+                assert "│Synced┆1│" in lines
+                assert "│Skipped┆1(broken)│" in lines
+
+                cmd = grm(["repos", "status", "--config", config])
+                assert cmd.returncode == 0
+                assert "broken" not in cmd.stdout
+                assert "Skipped disabled repositories: broken" in cmd.stderr
+
+
+@pytest.mark.parametrize("hostname", ["laptop", "workstation.example.com"])
+def test_repos_sync_only_on_hosts(monkeypatch, hostname):
+    monkeypatch.setenv("GRM_HOSTNAME", hostname)
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            content = tree_config(
+                target,
+                repo_config("everywhere", remote),
+                repo_config("laptop", remote, only_on_hosts=["laptop"]),
+                repo_config("workstation", remote, only_on_hosts=["workstation"]),
+                repo_config("nowhere", remote, only_on_os=["plan9"]),
+            )
+            with TempConfig(content) as config:
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+
+                expected = {"everywhere", hostname.split(".")[0]}
+                assert set(os.listdir(target)) == expected
+
+
+def test_repos_sync_only_on_hosts_not_unmanaged(monkeypatch):
+    monkeypatch.setenv("GRM_HOSTNAME", "laptop")
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            workstation = repo_config(
+                "workstation", remote, only_on_hosts=["workstation"]
+            )
+            content = 'unmanaged = "error"\n' + tree_config(target, workstation)
+            with TempConfig(content) as config:
+                # The repository was cloned on the other host, e.g. on a
+                # shared drive
+                shell(f"git clone --quiet file://{remote} {target}/workstation")
+
+                cmd = grm(["repos", "sync", "config", "--config", config])
+                assert cmd.returncode == 0
+                assert "unmanaged" not in cmd.stderr.lower()
+
+                cmd = grm(["repos", "diff", "--config", config])
+                assert cmd.returncode == 0
+                assert "workstation" not in cmd.stdout
+
+
+@pytest.mark.parametrize("profile", ["work", "personal"])
+def test_repos_sync_profile(profile):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            content = f"""
+            [profiles.work]
+            trees = ["{target}/work"]
+
+            [profiles.personal]
+            trees = ["{target}/personal"]
+            """
+            for tree in ("work", "personal"):
+                content += tree_config(f"{target}/{tree}", repo_config("repo", remote))
+            with TempConfig(content) as config:
+                cmd = grm(
+                    [
+                        "repos",
+                        "sync",
+                        "config",
+                        "--config",
+                        config,
+                        "--profile",
+                        profile,
+                    ]
+                )
+                assert cmd.returncode == 0
+                assert os.listdir(target) == [profile]
+
+                cmd = grm(
+                    [
+                        "repos",
+                        "sync",
+                        "config",
+                        "--config",
+                        config,
+                        "--profile",
+                        "unknown",
+                    ]
+                )
+                assert cmd.returncode != 0
+                assert 'There is no profile "unknown"' in cmd.stderr
+
+
+@pytest.mark.parametrize("name", ["team/service-a", "./team//service-a/"])
+@pytest.mark.parametrize("worktree_setup", [True, False])
+def test_repos_sync_nested_name(name, worktree_setup):
+    with tempfile.TemporaryDirectory() as target:
+        with TempGitFileRemote() as (remote, _):
+            repo = repo_config(name, remote, worktree_setup=worktree_setup)
+            with TempConfig(tree_config(target, repo)) as config:
+                for _ in range(2):
+                    cmd = grm(["repos", "sync", "config", "--config", config])
+                    assert cmd.returncode == 0
+                    assert "team/service-a: OK" in cmd.stdout
+                    assert "unmanaged" not in cmd.stderr.lower()
+                    lines = [line.replace(" ", "") for line in cmd.stdout.splitlines()]
+                    assert "│Unmanaged┆0│" in lines
+                assert os.path.isdir(os.path.join(target, "team", "service-a"))
+
+                cmd = grm(["repos", "status", "--config", config])
+                assert cmd.returncode == 0
+                assert "team/service-a" in cmd.stdout
+
+                cmd = grm(["repos", "find", "local", target])
+                assert cmd.returncode == 0
+                found = toml.loads(cmd.stdout)
+                names = [repo["name"] for repo in found["trees"][0]["repos"]]
+                assert names == ["team/service-a"]
+
+
+@pytest.mark.parametrize("name", ["../escape", "/absolute", "./"])
+def test_repos_sync_invalid_nested_name(name):
+    with tempfile.TemporaryDirectory() as target:
+        with TempConfig(tree_config(target, name)) as config:
+            cmd = grm(["repos", "sync", "config", "--config", config])
+            assert cmd.returncode != 0
+            assert "Repository name" in cmd.stderr
+            assert os.listdir(target) == []
@@ -0,0 +1,78 @@
+#!/usr/bin/env python3
+
+import os
+import tempfile
+
+from helpers import grm
+
+repos_config = """
+[[trees]]
+root = "{root}"
+
+[[trees.repos]]
+name = "one"
+"""
+
+
+def test_user_config(monkeypatch):
+    with tempfile.TemporaryDirectory() as config_home:
+        with tempfile.TemporaryDirectory() as root:
+            monkeypatch.setenv("XDG_CONFIG_HOME", config_home)
+            os.mkdir(os.path.join(config_home, "grm"))
+
+            config_path = os.path.join(root, "repos.toml")
+            with open(config_path, "w") as f:
+                f.write(repos_config.format(root=root))
+
+            with open(os.path.join(config_home, "grm", "config.toml"), "w") as f:
+                f.write(f'config = "{config_path}"\njobs = 2\n')
+
+            cmd = grm(["repos", "list"], cwd=root)
+            assert cmd.returncode == 0
+            assert cmd.stdout.splitlines() == ["one"]
+
+            cmd = grm(["repos", "exec", "--help"], cwd=root)
+            assert cmd.returncode == 0
+            assert "[default: 2]" in cmd.stdout
+
+            # The command line still takes precedence
+            cmd = grm(["repos", "list", "--config", "/does/not/exist.toml"], cwd=root)
+            assert cmd.returncode != 0
+
+
+def test_user_config_invalid(monkeypatch):
+    with tempfile.TemporaryDirectory() as config_home:
+        monkeypatch.setenv("XDG_CONFIG_HOME", config_home)
+        os.mkdir(os.path.join(config_home, "grm"))
+
+        with open(os.path.join(config_home, "grm", "config.toml"), "w") as f:
+            f.write("unknown = true\n")
+
+        cmd = grm(["repos", "list"])
+        assert cmd.returncode != 0
+        assert "unknown" in cmd.stderr
+
+
+def test_grm_config_env(monkeypatch):
+    with tempfile.TemporaryDirectory() as config_home:
+        with tempfile.TemporaryDirectory() as root:
+            monkeypatch.setenv("XDG_CONFIG_HOME", config_home)
+            os.mkdir(os.path.join(config_home, "grm"))
+            with open(os.path.join(config_home, "grm", "config.toml"), "w") as f:
+                f.write('config = "/does/not/exist.toml"\n')
+
+            config_path = os.path.join(root, "repos.toml")
+            with open(config_path, "w") as f:
+                f.write(repos_config.format(root=root))
+            monkeypatch.setenv("GRM_CONFIG", config_path)
+
+            cmd = grm(["repos", "list"], cwd=root)
+            assert cmd.returncode == 0
+            assert cmd.stdout.splitlines() == ["one"]
+
+            cmd = grm(["repos", "path", "one"], cwd=root)
+            assert cmd.returncode == 0
+            assert cmd.stdout.strip() == os.path.join(root, "one")
+
+            cmd = grm(["repos", "list", "--config", "/does/not/exist.toml"], cwd=root)
+            assert cmd.returncode != 0
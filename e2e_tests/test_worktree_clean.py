@@ -25,6 +25,18 @@ def test_worktree_clean():
         assert "test" not in os.listdir(base_dir)
 
 
+def test_worktree_clean_nested_name():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "feature/test", "--track", "origin/test"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "test" in os.listdir(os.path.join(base_dir, "feature"))
+
+        cmd = grm(["wt", "clean"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "not a valid worktree directory" not in cmd.stderr
+        assert "feature" not in os.listdir(base_dir)
+
+
 def test_worktree_clean_refusal_no_tracking_branch():
     with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
         cmd = grm(["wt", "add", "test"], cwd=base_dir)
@@ -203,3 +215,63 @@ def test_worktree_clean_configured_default_branch(
             assert "test" not in os.listdir(base_dir)
         else:
             assert "test" in os.listdir(base_dir)
+
+
+def test_worktree_clean_dry_run():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "test", "--track", "origin/test"], cwd=base_dir)
+        assert cmd.returncode == 0
+        cmd = grm(["wt", "add", "unpushed"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        before = checksum_directory(base_dir)
+        cmd = grm(["wt", "clean", "--dry-run"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "would delete worktree test" in cmd.stdout.lower()
+        assert "unpushed" not in cmd.stdout.lower()
+        assert "unpushed" in cmd.stderr.lower()
+        assert "no remote tracking branch" in cmd.stderr.lower()
+
+        after = checksum_directory(base_dir)
+        assert before == after
+
+
+@pytest.mark.parametrize("answer", ["y", "n", ""])
+def test_worktree_clean_interactive(answer):
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "test", "--track", "origin/test"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "clean", "--interactive"], cwd=base_dir, input=f"{answer}\n")
+        assert cmd.returncode == 0
+        if answer == "y":
+            assert "test" not in os.listdir(base_dir)
+        else:
+            assert "test" in os.listdir(base_dir)
+            assert "skipped" in cmd.stderr.lower()
+
+
+def test_worktree_clean_older_than_keeps_recent():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "test", "--track", "origin/test"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "clean", "--older-than", "30d"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "test" in os.listdir(base_dir)
+
+
+def test_worktree_clean_older_than_removes_old():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "test", "--track", "origin/test"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "clean", "--older-than", "0s"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "test" not in os.listdir(base_dir)
+
+
+def test_worktree_clean_older_than_invalid_duration():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "clean", "--older-than", "30x"], cwd=base_dir)
+        assert cmd.returncode != 0
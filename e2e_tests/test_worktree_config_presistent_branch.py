@@ -1,6 +1,7 @@
 #!/usr/bin/env python3
 
 import os.path
+import tempfile
 
 import git
 from helpers import TempGitRepositoryWorktree, checksum_directory, funcname, grm, shell
@@ -142,3 +143,53 @@ def test_worktree_delete_branch_merged_into_persistent():
         assert cmd.returncode == 0
 
         assert "test" not in os.listdir(base_dir)
+
+
+def test_worktree_never_clean_persistent_branch_globs():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+            f.write(
+                """
+            persistent_branches = [
+                "master",
+                "release-*",
+            ]
+            """
+            )
+
+        for branch in ("release-1.0", "mybranch"):
+            cmd = grm(["wt", "add", branch, "--track", "origin/master"], cwd=base_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(["wt", "clean"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        assert "release-1.0" in os.listdir(base_dir)
+        assert "mybranch" not in os.listdir(base_dir)
+
+
+def test_worktree_never_clean_global_persistent_branches():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        with tempfile.TemporaryDirectory() as config_home:
+            os.mkdir(os.path.join(config_home, "grm"))
+            with open(os.path.join(config_home, "grm", "grm.toml"), "w") as f:
+                f.write('persistent_branches = ["mybranch"]')
+
+            cmd = grm(
+                ["wt", "add", "mybranch", "--track", "origin/master"], cwd=base_dir
+            )
+            assert cmd.returncode == 0
+
+            os.environ["XDG_CONFIG_HOME"] = config_home
+            try:
+                cmd = grm(["wt", "clean"], cwd=base_dir)
+            finally:
+                del os.environ["XDG_CONFIG_HOME"]
+            assert cmd.returncode == 0
+
+            assert "mybranch" in os.listdir(base_dir)
+
+            cmd = grm(["wt", "clean"], cwd=base_dir)
+            assert cmd.returncode == 0
+
+            assert "mybranch" not in os.listdir(base_dir)
@@ -2,6 +2,7 @@
 
 import os
 
+import git
 from helpers import (
     EmptyDir,
     NonGitDir,
@@ -10,6 +11,7 @@ from helpers import (
     checksum_directory,
     funcname,
     grm,
+    shell,
 )
 
 
@@ -30,6 +32,44 @@ def test_convert():
         assert set(files) == {".git-main-working-tree", "test"}
 
 
+def test_convert_keeps_configuration():
+    with TempGitRepository() as git_dir:
+        shell(
+            f"""
+            cd {git_dir}
+            echo 'persistent_branches = ["master"]' > grm.toml
+            git add grm.toml
+            git commit -m "grm.toml"
+            """
+        )
+
+        cmd = grm(["wt", "convert"], cwd=git_dir)
+        assert cmd.returncode == 0
+
+        files = os.listdir(git_dir)
+        assert set(files) == {".git-main-working-tree", "grm.toml"}
+
+
+def test_convert_hooks_path():
+    with TempGitRepository() as git_dir:
+        shell(
+            f"""
+            cd {git_dir}
+            printf '[worktree]\\nhooks_path = "hooks"\\n' > grm.toml
+            git add grm.toml
+            git commit -m "grm.toml"
+            """
+        )
+
+        cmd = grm(["wt", "convert"], cwd=git_dir)
+        assert cmd.returncode == 0
+
+        repo = git.Repo(os.path.join(git_dir, ".git-main-working-tree"))
+        with repo.config_reader() as config:
+            hooks_path = config.get_value("core", "hooksPath")
+        assert hooks_path == os.path.join(git_dir, "hooks")
+
+
 def test_convert_already_worktree():
     with TempGitRepositoryWorktree.get(funcname()) as (git_dir, _commit):
         before = checksum_directory(git_dir)
@@ -61,3 +101,79 @@ def test_convert_empty():
 
         after = checksum_directory(dir)
         assert before == after
+
+
+def test_convert_to_normal():
+    with TempGitRepositoryWorktree.get(funcname()) as (git_dir, _commit):
+        for name in ("test", "dir/nested"):
+            cmd = grm(["wt", "add", name], cwd=git_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(["wt", "convert", "--to-normal"], cwd=git_dir)
+        assert cmd.returncode == 0
+
+        files = set(os.listdir(git_dir))
+        assert ".git" in files
+        assert ".git-main-working-tree" not in files
+        assert "test" not in files
+        assert "dir" not in files
+        assert "root-commit-in-worktree-1" in files
+
+        repo = git.Repo(git_dir)
+        assert not repo.bare
+        assert not repo.is_dirty(untracked_files=True)
+        assert str(repo.active_branch) == "master"
+        assert {"test", "dir/nested"} <= {str(b) for b in repo.branches}
+
+
+def test_convert_to_normal_with_branch():
+    with TempGitRepositoryWorktree.get(funcname()) as (git_dir, _commit):
+        cmd = grm(["wt", "add", "test"], cwd=git_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "convert", "--to-normal", "--branch", "test"], cwd=git_dir)
+        assert cmd.returncode == 0
+
+        repo = git.Repo(git_dir)
+        assert str(repo.active_branch) == "test"
+
+
+def test_convert_to_normal_refusal_changes():
+    with TempGitRepositoryWorktree.get(funcname()) as (git_dir, _commit):
+        cmd = grm(["wt", "add", "test"], cwd=git_dir)
+        assert cmd.returncode == 0
+
+        shell(f"cd {git_dir}/test && touch changed_file")
+
+        before = checksum_directory(git_dir)
+        cmd = grm(["wt", "convert", "--to-normal"], cwd=git_dir)
+        assert cmd.returncode != 0
+        assert "test" in cmd.stderr
+
+        after = checksum_directory(git_dir)
+        assert before == after
+
+
+def test_convert_to_normal_refusal_root_files():
+    with TempGitRepositoryWorktree.get(funcname()) as (git_dir, _commit):
+        # Would be overwritten by the file of the same name in the branch
+        shell(f"cd {git_dir} && echo mine > root-commit-in-worktree-1")
+
+        before = checksum_directory(git_dir)
+        cmd = grm(["wt", "convert", "--to-normal"], cwd=git_dir)
+        assert cmd.returncode != 0
+        assert "refusing to convert" in cmd.stderr
+
+        after = checksum_directory(git_dir)
+        assert before == after
+
+
+def test_convert_to_normal_non_worktree():
+    with TempGitRepository() as git_dir:
+        before = checksum_directory(git_dir)
+
+        cmd = grm(["wt", "convert", "--to-normal"], cwd=git_dir)
+        assert cmd.returncode != 0
+
+        after = checksum_directory(git_dir)
+        assert before == after
@@ -0,0 +1,53 @@
+#!/usr/bin/env python3
+
+import os
+
+import pytest
+from helpers import TempGitRepositoryWorktree, funcname, grm
+
+
+@pytest.mark.parametrize("jobs", [1, 4])
+def test_worktree_exec(jobs):
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        for name in ("first", "dir/second"):
+            cmd = grm(["wt", "add", name], cwd=base_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(
+            ["wt", "exec", "--jobs", str(jobs), "--", "touch", "marker"],
+            cwd=base_dir,
+        )
+        assert cmd.returncode == 0
+        assert "marker" in os.listdir(os.path.join(base_dir, "first"))
+        assert "marker" in os.listdir(os.path.join(base_dir, "dir", "second"))
+
+
+def test_worktree_exec_prefixed_output():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "test"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "exec", "--", "echo out; echo err >&2"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "[test] out" in cmd.stdout
+        assert "[test] err" in cmd.stderr
+
+
+def test_worktree_exec_failure():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        for name in ("good", "bad"):
+            cmd = grm(["wt", "add", name], cwd=base_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(
+            ["wt", "exec", "--", 'test "$(basename "$PWD")" = good'], cwd=base_dir
+        )
+        assert cmd.returncode != 0
+        assert "bad: command exited with code 1" in cmd.stderr.lower()
+        assert "good:" not in cmd.stderr.lower()
+
+
+def test_worktree_exec_requires_command():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "exec"], cwd=base_dir, is_invalid=True)
+        assert cmd.returncode != 0
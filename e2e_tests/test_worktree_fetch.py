@@ -1,5 +1,6 @@
 #!/usr/bin/env python3
 
+import os
 import re
 
 import git
@@ -180,3 +181,74 @@ def test_worktree_pull(rebase, ffable, has_changes, stash):
                                 == "local-commit-in-master"
                             )
                             assert repo.commit("master~1").hexsha == remote_commit
+
+
+@pytest.mark.parametrize("all_worktrees", [True, False])
+def test_worktree_pull_inside_worktree(all_worktrees):
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _root_commit):
+        cmd = grm(["wt", "add", "master", "--track", "origin/master"], cwd=base_dir)
+        assert cmd.returncode == 0
+        cmd = grm(["wt", "add", "local", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        args = ["wt", "pull"]
+        if all_worktrees:
+            args += ["--all"]
+        cmd = grm(args, cwd=f"{base_dir}/master")
+        if all_worktrees:
+            # The worktree without a remote branch is skipped
+            assert cmd.returncode != 0
+            assert "Skipped 1 worktree(s): local" in cmd.stderr
+        else:
+            assert cmd.returncode == 0
+            assert "master: Done" in cmd.stdout
+            assert "local" not in cmd.stdout + cmd.stderr
+
+
+def test_worktree_pull_all_nested():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _root_commit):
+        cmd = grm(["wt", "add", "master", "--track", "origin/master"], cwd=base_dir)
+        assert cmd.returncode == 0
+        cmd = grm(["wt", "add", "feature/local", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "pull", "--all"], cwd=f"{base_dir}/master")
+        # The nested worktree is considered, and skipped as it has no remote
+        # branch
+        assert cmd.returncode != 0
+        assert "master: Done" in cmd.stdout
+        assert "Skipped 1 worktree(s): feature/local" in cmd.stderr
+
+
+@pytest.mark.parametrize("prune", ["flag", "config", None])
+def test_worktree_fetch_prune(prune):
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _root_commit):
+        # A remote tracking branch for a branch that is gone on the remote
+        shell(
+            f"""
+            cd {base_dir}
+            git --git-dir .git-main-working-tree update-ref refs/remotes/origin/gone master
+        """
+        )
+
+        if prune == "config":
+            with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+                f.write(
+                    """
+                [fetch]
+                prune = true
+                """
+                )
+
+        args = ["wt", "fetch"]
+        if prune == "flag":
+            args += ["--prune"]
+        cmd = grm(args, cwd=base_dir)
+        assert cmd.returncode == 0
+
+        repo = git.Repo(f"{base_dir}/.git-main-working-tree")
+        remote_branches = [str(ref) for ref in repo.remotes.origin.refs]
+        if prune is None:
+            assert "origin/gone" in remote_branches
+        else:
+            assert "origin/gone" not in remote_branches
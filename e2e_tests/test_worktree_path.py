@@ -0,0 +1,64 @@
+#!/usr/bin/env python3
+
+import os
+
+from helpers import TempGitRepositoryWorktree, funcname, grm
+
+
+def test_worktree_path():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        for name in ("first", "feature"):
+            cmd = grm(["wt", "add", name], cwd=base_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(["wt", "path"], cwd=os.path.join(base_dir, "first"))
+        assert cmd.returncode == 0
+        assert cmd.stdout.strip() == str(base_dir)
+
+        cmd = grm(["wt", "path", "first"], cwd=os.path.join(base_dir, "feature"))
+        assert cmd.returncode == 0
+        assert cmd.stdout.strip() == os.path.join(base_dir, "first")
+
+        cmd = grm(["wt", "path", "feat"], cwd=base_dir)
+        assert cmd.returncode != 0
+        assert "not found" in cmd.stderr
+
+
+def test_worktree_path_nested():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "feature/foo"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "path", "feature/foo"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert cmd.stdout.strip() == os.path.join(base_dir, "feature", "foo")
+
+
+def test_worktree_path_fuzzy():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        for name in ("first", "feature"):
+            cmd = grm(["wt", "add", name], cwd=base_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(["wt", "path", "--fuzzy", "FEAT"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert cmd.stdout.strip() == os.path.join(base_dir, "feature")
+
+        cmd = grm(["wt", "path", "--fuzzy", "f"], cwd=base_dir)
+        assert cmd.returncode != 0
+        assert "first" in cmd.stderr and "feature" in cmd.stderr
+
+
+def test_worktree_path_outside_of_worktree_setup():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "path"], cwd=os.path.dirname(base_dir))
+        assert cmd.returncode != 0
+        assert len(cmd.stdout) == 0
+
+
+def test_shell_init():
+    for shell in ("bash", "zsh", "fish"):
+        cmd = grm(["shell-init", shell])
+        assert cmd.returncode == 0
+        assert "gwt" in cmd.stdout
+        assert "grm worktree path --fuzzy" in cmd.stdout
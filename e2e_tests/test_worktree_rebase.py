@@ -268,3 +268,35 @@ def test_worktree_rebase(pull, rebase, ffable, has_changes, stash):
                         repo.commit("HEAD~3").message.strip() == "commit-in-base-local"
                     )
                     assert repo.commit("HEAD~4").message.strip() == "commit-root"
+
+
+def test_worktree_rebase_onto():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, root_commit):
+        grm(["wt", "add", "master", "--no-track"], cwd=base_dir)
+        grm(["wt", "add", "release", "--no-track"], cwd=base_dir)
+        grm(["wt", "add", "myfeatbranch", "--no-track"], cwd=base_dir)
+        shell(
+            f"""
+            cd {base_dir}/release
+            echo change > mychange-release
+            git add mychange-release
+            git commit -m "commit-in-release"
+
+            cd {base_dir}/myfeatbranch
+            echo change > mychange-feat
+            git add mychange-feat
+            git commit -m "commit-in-feat"
+        """
+        )
+
+        cmd = grm(["wt", "rebase", "--onto", "doesnotexist"], cwd=base_dir)
+        assert cmd.returncode != 0
+
+        cmd = grm(["wt", "rebase", "--onto", "release"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "master: Default branch, skipping" in cmd.stdout
+
+        repo = git.Repo(f"{base_dir}/.git-main-working-tree")
+        assert repo.commit("myfeatbranch").message.strip() == "commit-in-feat"
+        assert repo.commit("myfeatbranch~1").message.strip() == "commit-in-release"
+        assert repo.commit("master").hexsha == root_commit
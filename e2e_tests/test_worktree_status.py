@@ -1,5 +1,6 @@
 #!/usr/bin/env python3
 
+import json
 import os
 import re
 
@@ -30,6 +31,19 @@ def test_worktree_status(has_config):
         assert "test" in stdout
 
 
+def test_worktree_status_nested_name():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        for name in ("feature/foo", "plain"):
+            cmd = grm(["wt", "add", "--no-track", name], cwd=base_dir)
+            assert cmd.returncode == 0
+
+        cmd = grm(["wt", "status"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert len(cmd.stderr) == 0
+        assert "feature/foo" in cmd.stdout
+        assert "plain" in cmd.stdout
+
+
 def test_worktree_status_fail_from_subdir():
     with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
         cmd = grm(["wt", "add", "test"], cwd=base_dir)
@@ -82,3 +96,45 @@ def test_worktree_status_warn_with_non_worktree_dir():
             )
             is not None
         )
+
+
+def test_worktree_status_json():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "test", "--track", "origin/test"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        shell(
+            f"""
+            cd {base_dir}/test
+            echo change > new-file
+            git add new-file
+            git commit -m "new commit"
+            touch untracked
+            """
+        )
+
+        cmd = grm(["wt", "status", "--output", "json"], cwd=base_dir)
+        assert cmd.returncode == 0
+        statuses = json.loads(cmd.stdout)
+        assert len(statuses) == 1
+        status = statuses[0]
+        assert status["name"] == "test"
+        assert status["branch"] == "test"
+        assert status["upstream"] == "origin/test"
+        assert status["ahead"] == 1
+        assert status["behind"] == 0
+        assert status["changes"]["files_new"] == 1
+        assert status["merged"] is False
+
+
+def test_worktree_status_merged():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "fresh-branch"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "status", "--output", "json"], cwd=base_dir)
+        assert cmd.returncode == 0
+        statuses = json.loads(cmd.stdout)
+        assert statuses[0]["merged"] is True
+        assert statuses[0]["changes"] is None
+        assert statuses[0]["upstream"] is None
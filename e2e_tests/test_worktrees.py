@@ -569,6 +569,214 @@ def test_worktree_add_invalid_remote_name(
             assert len(cmd.stderr) == 0
 
 
+def test_worktree_add_with_base():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "add", "develop", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        shell(
+            f"""
+            cd {base_dir}/develop
+            touch change
+            git add change
+            git commit -m "develop"
+            """
+        )
+
+        with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+            f.write(
+                """
+            [worktree]
+            base = "develop"
+            """
+            )
+
+        cmd = grm(["wt", "add", "foo", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        develop = git.Repo(os.path.join(base_dir, "develop"))
+        repo = git.Repo(os.path.join(base_dir, "foo"))
+        assert repo.head.commit == develop.head.commit
+
+
+def test_worktree_add_with_invalid_base():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+            f.write(
+                """
+            [worktree]
+            base = "thisbranchdoesnotexist"
+            """
+            )
+
+        cmd = grm(["wt", "add", "foo"], cwd=base_dir)
+        assert cmd.returncode != 0
+        assert "thisbranchdoesnotexist" in cmd.stderr
+        assert "foo" not in os.listdir(base_dir)
+
+
+def test_worktree_add_copy_files():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+            f.write(
+                """
+            persistent_branches = ["master"]
+
+            [worktree]
+            copy_files = [".envrc", ".vscode/settings.json", "missing"]
+            """
+            )
+
+        cmd = grm(["wt", "add", "master"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        shell(
+            f"""
+            cd {base_dir}/master
+            echo "export FOO=bar" > .envrc
+            mkdir .vscode
+            echo "{{}}" > .vscode/settings.json
+            """
+        )
+
+        cmd = grm(["wt", "add", "foo", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "missing" in cmd.stderr
+
+        worktree = os.path.join(base_dir, "foo")
+        with open(os.path.join(worktree, ".envrc")) as f:
+            assert f.read() == "export FOO=bar\n"
+        assert os.path.exists(os.path.join(worktree, ".vscode", "settings.json"))
+        assert not os.path.exists(os.path.join(worktree, "missing"))
+
+
+def test_worktree_add_hooks_path():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+            f.write(
+                """
+            [worktree]
+            hooks_path = "hooks"
+            """
+            )
+
+        cmd = grm(["wt", "add", "foo", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        repo = git.Repo(os.path.join(base_dir, "foo"))
+        with repo.config_reader() as config:
+            hooks_path = config.get_value("core", "hooksPath")
+        assert hooks_path == os.path.join(base_dir, "hooks")
+
+
+def test_worktree_add_with_prefix():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+            f.write(
+                """
+            [worktree]
+            prefix = "alice/"
+            """
+            )
+
+        cmd = grm(["wt", "add", "foo", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "foo" in os.listdir(base_dir)
+
+        repo = git.Repo(os.path.join(base_dir, "foo"))
+        assert str(repo.active_branch) == "alice/foo"
+
+        # Existing branches keep their name
+        cmd = grm(["wt", "add", "master", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+        repo = git.Repo(os.path.join(base_dir, "master"))
+        assert str(repo.active_branch) == "master"
+
+        cmd = grm(["wt", "delete", "foo", "--force"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "foo" not in os.listdir(base_dir)
+
+
+def test_worktree_add_with_directory_separator():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        with open(os.path.join(base_dir, "grm.toml"), "w") as f:
+            f.write(
+                """
+            [worktree]
+            directory_separator = "-"
+            """
+            )
+
+        cmd = grm(["wt", "add", "feature/foo", "--no-track"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "feature-foo" in os.listdir(base_dir)
+        assert "feature" not in os.listdir(base_dir)
+
+        repo = git.Repo(os.path.join(base_dir, "feature-foo"))
+        assert str(repo.active_branch) == "feature/foo"
+
+        cmd = grm(["wt", "status"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "feature-foo" in cmd.stdout
+
+        cmd = grm(["wt", "delete", "feature/foo", "--force"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "feature-foo" not in os.listdir(base_dir)
+
+
+def test_worktree_add_track_auto():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        shell(
+            f"""
+            cd {base_dir}
+            git --git-dir .git-main-working-tree push origin master:autotrack
+            git --git-dir .git-main-working-tree push otherremote master:autotrack
+            git --git-dir .git-main-working-tree fetch --all
+        """
+        )
+
+        # Both remotes have the branch, the primary remote wins
+        cmd = grm(["wt", "add", "autotrack", "--track-auto"], cwd=base_dir)
+        assert cmd.returncode == 0
+        repo = git.Repo(os.path.join(base_dir, "autotrack"))
+        assert str(repo.active_branch.tracking_branch()) == "origin/autotrack"
+
+        cmd = grm(["wt", "add", "new", "--track-auto"], cwd=base_dir)
+        assert cmd.returncode == 0
+        repo = git.Repo(os.path.join(base_dir, "new"))
+        assert repo.active_branch.tracking_branch() is None
+
+        cmd = grm(["wt", "add", "other", "--track-auto", "--no-track"], cwd=base_dir)
+        assert cmd.returncode != 0
+
+
+def test_worktree_add_pull_request():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, commit):
+        shell(
+            f"""
+            cd {base_dir}
+            git --git-dir .git-main-working-tree push origin master:refs/pull/1/head
+        """
+        )
+
+        cmd = grm(["wt", "add", "--pr", "1"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "pr-1" in os.listdir(base_dir)
+
+        repo = git.Repo(os.path.join(base_dir, "pr-1"))
+        assert str(repo.active_branch) == "pr-1"
+        assert str(repo.active_branch.tracking_branch()) == "origin/pr-1"
+        assert repo.head.commit.hexsha == commit
+        assert (
+            repo.config_reader().get_value('branch "pr-1"', "merge")
+            == "refs/pull/1/head"
+        )
+
+        cmd = grm(["wt", "add", "--pr", "2"], cwd=base_dir)
+        assert cmd.returncode != 0
+        assert "pr-2" not in os.listdir(base_dir)
+
+
 def test_worktree_add_into_invalid_subdirectory():
     with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
         cmd = grm(["wt", "add", "/dir/test"], cwd=base_dir)
@@ -698,6 +906,48 @@ def test_worktree_delete_force_refusal():
         assert "test" not in os.listdir(base_dir)
 
 
+def test_worktree_delete_glob():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        for name in ("feature/one", "feature/two"):
+            cmd = grm(["wt", "add", name, "--track", "origin/test"], cwd=base_dir)
+            assert cmd.returncode == 0
+        cmd = grm(["wt", "add", "other", "--track", "origin/test"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "delete", "feature/*"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "feature" not in os.listdir(base_dir)
+        assert "other" in os.listdir(base_dir)
+        assert "feature/one" in cmd.stdout
+        assert "feature/two" in cmd.stdout
+
+
+def test_worktree_delete_glob_skips_unpushed():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(
+            ["wt", "add", "feature/pushed", "--track", "origin/test"], cwd=base_dir
+        )
+        assert cmd.returncode == 0
+        cmd = grm(["wt", "add", "feature/local"], cwd=base_dir)
+        assert cmd.returncode == 0
+
+        cmd = grm(["wt", "delete", "feature/*"], cwd=base_dir)
+        assert cmd.returncode != 0
+        assert {"local"} == set(os.listdir(os.path.join(base_dir, "feature")))
+        assert "skipped 1 worktree(s): feature/local" in cmd.stderr.lower()
+
+        cmd = grm(["wt", "delete", "feature/*", "--force"], cwd=base_dir)
+        assert cmd.returncode == 0
+        assert "feature" not in os.listdir(base_dir)
+
+
+def test_worktree_delete_glob_no_match():
+    with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
+        cmd = grm(["wt", "delete", "feature/*"], cwd=base_dir)
+        assert cmd.returncode != 0
+        assert len(cmd.stderr) != 0
+
+
 def test_worktree_add_delete_add():
     with TempGitRepositoryWorktree.get(funcname()) as (base_dir, _commit):
         cmd = grm(["wt", "add", "test", "--track", "origin/test"], cwd=base_dir)
@@ -1,4 +1,39 @@
+use std::collections::HashMap;
 use std::process;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::{Lazy, OnceCell};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_token_command_is_cached() {
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+        let counter = dir.path().join("counter");
+        let command = format!("echo x >> {0}; wc -l < {0}", counter.display());
+
+        assert_eq!(get_token_from_command(&command).unwrap().access(), "1");
+        assert_eq!(get_token_from_command(&command).unwrap().access(), "1");
+    }
+
+    #[test]
+    fn check_credentials_for() {
+        register_credentials(
+            "https://example.com/credentials.git",
+            &Credentials {
+                username: None,
+                token_command: String::from("echo token"),
+            },
+        );
+        assert_eq!(
+            credentials_for("https://example.com/credentials.git").map(|c| c.token_command),
+            Some(String::from("echo token"))
+        );
+        assert_eq!(credentials_for("https://example.com/other.git"), None);
+    }
+}
 
 #[derive(Clone)]
 pub struct AuthToken(String);
@@ -9,7 +44,53 @@ impl AuthToken {
     }
 }
 
+/// Credentials for a HTTPS remote. The token is read from the output of
+/// `token_command`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: Option<String>,
+    pub token_command: String,
+}
+
+/// The result of a token command, once it finished
+type TokenCell = Arc<OnceCell<Result<AuthToken, String>>>;
+
+/// The results of all token commands run so far, so every command is only run
+/// once per invocation
+static TOKENS: Lazy<Mutex<HashMap<String, TokenCell>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The credentials of all remotes, by their URL. They are looked up when git
+/// asks for credentials during a transfer.
+static CREDENTIALS: Lazy<Mutex<HashMap<String, Credentials>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn register_credentials(url: &str, credentials: &Credentials) {
+    CREDENTIALS
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), credentials.clone());
+}
+
+pub fn credentials_for(url: &str) -> Option<Credentials> {
+    CREDENTIALS.lock().unwrap().get(url).cloned()
+}
+
+/// Runs `command` and returns the first line of its output as the token. The
+/// result is cached, so password managers only prompt once.
 pub fn get_token_from_command(command: &str) -> Result<AuthToken, String> {
+    let cell = TOKENS
+        .lock()
+        .unwrap()
+        .entry(command.to_string())
+        .or_default()
+        .clone();
+    // Concurrent syncs of repositories using the same command wait for the
+    // first one to finish instead of running it again. Other commands are not
+    // blocked, as the global lock is already released.
+    cell.get_or_init(|| run_token_command(command)).clone()
+}
+
+fn run_token_command(command: &str) -> Result<AuthToken, String> {
     let output = process::Command::new("/usr/bin/env")
         .arg("sh")
         .arg("-c")
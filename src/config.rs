@@ -1,70 +1,936 @@
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
-use std::process;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use super::auth;
+use super::error::Error;
+use super::glob;
+use super::host;
+use super::maintenance;
+use super::net;
 use super::output::*;
 use super::path;
 use super::provider;
 use super::provider::Filter;
 use super::provider::Provider;
 use super::repo;
+use super::secrets;
 use super::tree;
 
 pub type RemoteProvider = provider::RemoteProvider;
 pub type RemoteType = repo::RemoteType;
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo_config(name: &str) -> RepoConfig {
+        RepoConfig {
+            name: name.to_string(),
+            worktree_setup: false,
+            tags: None,
+            clone_depth: None,
+            clone_filter: None,
+            branch: None,
+            single_branch: None,
+            sparse_paths: None,
+            reference: None,
+            submodules: None,
+            lfs: None,
+            clone_mode: None,
+            rev: None,
+            default_branch: None,
+            pre_sync: None,
+            post_sync: None,
+            on_clone: None,
+            git_config: None,
+            protected_branches: None,
+            disabled: None,
+            only_on_hosts: None,
+            only_on_os: None,
+            remotes: None,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_normalize_home() {
+        std::env::set_var("HOME", "/home/test");
+        let tree = |root: &str| ConfigTree {
+            root: root.to_string(),
+            pre_sync: None,
+            post_sync: None,
+            git_config: None,
+            ignore: None,
+            only_on_hosts: None,
+            only_on_os: None,
+            optimize: None,
+            repos: None,
+        };
+        let mut config = Config::from_trees(vec![tree("/home/test/projects"), tree("/home/test2")]);
+        config.normalize();
+
+        let roots: Vec<String> = config
+            .trees()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.root)
+            .collect();
+        assert_eq!(roots, vec!["~/projects", "/home/test2"]);
+    }
+
+    const CONFIG: &str = r#"# My repositories
+[[trees]]
+root = "/projects/work"
+
+[[trees.repos]]
+name = "one" # The first one
+
+# Private stuff
+[[trees]]
+root = "/projects/private"
+"#;
+
+    #[test]
+    fn check_insert_repos_into_existing_tree() {
+        let content =
+            insert_toml_repos(CONFIG, Some(0), "/projects/work", vec![repo_config("two")]).unwrap();
+
+        assert_eq!(
+            content,
+            r#"# My repositories
+[[trees]]
+root = "/projects/work"
+
+[[trees.repos]]
+name = "one" # The first one
+
+[[trees.repos]]
+name = "two"
+worktree_setup = false
+
+# Private stuff
+[[trees]]
+root = "/projects/private"
+"#
+        );
+    }
+
+    #[test]
+    fn check_insert_repos_into_last_tree() {
+        let content = insert_toml_repos(
+            CONFIG,
+            Some(1),
+            "/projects/private",
+            vec![repo_config("three")],
+        )
+        .unwrap();
+
+        assert!(content.starts_with(CONFIG));
+        assert!(content.ends_with(
+            r#"root = "/projects/private"
+
+[[trees.repos]]
+name = "three"
+worktree_setup = false
+"#
+        ));
+    }
+
+    #[test]
+    fn check_insert_repos_into_new_tree() {
+        let content =
+            insert_toml_repos(CONFIG, None, "/projects/new", vec![repo_config("four")]).unwrap();
+
+        assert!(content.starts_with(CONFIG));
+        assert!(content.ends_with(
+            r#"
+[[trees]]
+root = "/projects/new"
+
+[[trees.repos]]
+name = "four"
+worktree_setup = false
+"#
+        ));
+        let config: Config = toml::from_str(&content).unwrap();
+        assert_eq!(config.trees().unwrap().len(), 3);
+    }
+    #[test]
+    fn check_remove_repo() {
+        let content = insert_toml_repos(
+            CONFIG,
+            Some(0),
+            "/projects/work",
+            vec![repo_config("two"), repo_config("three")],
+        )
+        .unwrap();
+
+        let content = remove_toml_repo(&content, 0, 1).unwrap();
+        assert_eq!(
+            content,
+            r#"# My repositories
+[[trees]]
+root = "/projects/work"
+
+[[trees.repos]]
+name = "one" # The first one
+
+[[trees.repos]]
+name = "three"
+worktree_setup = false
+
+# Private stuff
+[[trees]]
+root = "/projects/private"
+"#
+        );
+
+        let content = remove_toml_repo(&content, 0, 1).unwrap();
+        assert_eq!(content, CONFIG);
+    }
+
+    #[test]
+    fn check_remove_repo_with_remotes() {
+        let content = r#"[[trees]]
+root = "/projects"
+
+[[trees.repos]]
+name = "one"
+
+[[trees.repos.remotes]]
+name = "origin"
+url = "https://example.com/one.git"
+type = "https"
+
+[[trees.repos]]
+name = "two"
+"#;
+
+        assert_eq!(
+            remove_toml_repo(content, 0, 0).unwrap(),
+            r#"[[trees]]
+root = "/projects"
+
+[[trees.repos]]
+name = "two"
+"#
+        );
+        assert!(remove_toml_repo(content, 0, 2).is_err());
+    }
+
+    #[test]
+    fn check_edit_inline_repos() {
+        let content = r#"[[trees]]
+root = "/projects"
+repos = [{ name = "one" }]
+
+[[trees]]
+root = "/other"
+"#;
+
+        let content =
+            insert_toml_repos(content, Some(0), "/projects", vec![repo_config("two")]).unwrap();
+        let config: Config = toml::from_str(&content).unwrap();
+        let trees = config.trees().unwrap();
+        assert_eq!(trees.len(), 2);
+        let names: Vec<String> = trees[0]
+            .repos
+            .iter()
+            .flatten()
+            .map(|repo| repo.name.clone())
+            .collect();
+        assert_eq!(names, vec!["one", "two"]);
+        assert!(trees[1].repos.is_none());
+
+        let content = remove_toml_repo(&content, 0, 0).unwrap();
+        assert_eq!(
+            content,
+            r#"[[trees]]
+root = "/projects"
+repos = [{ name = "two", worktree_setup = false }]
+
+[[trees]]
+root = "/other"
+"#
+        );
+    }
+
+    #[test]
+    fn check_user_config() {
+        let config = parse_user_config(
+            r#"
+config = "/projects/config.toml"
+color = "never"
+jobs = 8
+"#,
+            Path::new("config.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.config.as_deref(), Some("/projects/config.toml"));
+        assert_eq!(config.color.as_deref(), Some("never"));
+        assert_eq!(config.jobs, Some(8));
+        assert_eq!(config.token_command, None);
+
+        assert!(parse_user_config("jobs = 0", Path::new("config.toml")).is_err());
+        assert!(parse_user_config("color = \"blue\"", Path::new("config.toml")).is_err());
+        assert!(parse_user_config("unknown = 1", Path::new("config.toml")).is_err());
+    }
+
+    #[test]
+    fn check_convert() {
+        let config: Config = toml::from_str(
+            r#"
+unmanaged = "warn"
+
+[[trees]]
+root = "/projects"
+ignore = ["scratch/*"]
+
+[trees.git_config]
+"user.email" = "me@example.com"
+
+[[trees.repos]]
+name = "one"
+protected_branches = ["main"]
+
+[trees.repos.git_config]
+"core.autocrlf" = "input"
+
+[[trees.repos.remotes]]
+name = "origin"
+url = "https://example.com/one.git"
+type = "https"
+"#,
+        )
+        .unwrap();
+
+        let toml = config.as_toml().unwrap();
+        for converted in [
+            toml.clone(),
+            config.as_yaml().unwrap(),
+            config.as_json().unwrap(),
+        ] {
+            let config: Config = serde_yaml::from_str(&converted)
+                .or_else(|_| toml::from_str(&converted))
+                .unwrap();
+            assert_eq!(config.as_toml().unwrap(), toml);
+        }
+
+        let config: Config = toml::from_str(
+            r#"
+provider = "github"
+token_command = "echo token"
+root = "/projects"
+unmanaged = "ignore"
+
+[filters]
+owner = true
+"#,
+        )
+        .unwrap();
+        assert!(config.as_toml().is_ok());
+        assert!(!config.as_yaml().unwrap().contains("null"));
+    }
+
+    #[test]
+    fn check_providers() {
+        let providers = |content: &str| match toml::from_str::<Config>(content).unwrap() {
+            Config::ConfigProviders(providers) => providers,
+            _ => panic!("not a configuration with providers"),
+        };
+
+        let config = providers(
+            r#"
+unmanaged = "ignore"
+
+[[providers]]
+name = "work"
+provider = "github"
+token_command = "echo work"
+root = "/work"
+
+[providers.filters]
+owner = true
+
+[[providers]]
+name = "internal"
+provider = "gitlab"
+token_command = "echo internal"
+root = "/internal"
+api_url = "https://gitlab.example.com"
+"#,
+        );
+        assert_eq!(config.providers.len(), 2);
+        assert_eq!(config.unmanaged, Some(UnmanagedPolicy::Ignore));
+        assert!(config.validate().is_ok());
+
+        let provider = r#"
+[[providers]]
+provider = "github"
+token_command = "echo token"
+root = "/projects"
+"#;
+        assert!(providers(provider).validate().is_err());
+
+        let named = format!("{}name = \"personal\"\n", provider);
+        assert!(providers(&named).validate().is_ok());
+        assert!(providers(&format!("{}{}", named, named))
+            .validate()
+            .is_err());
+        assert!(providers(&format!("{}unmanaged = \"error\"\n", named))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn check_profiles() {
+        let config = || {
+            let mut config: Config = toml::from_str(
+                r#"
+[profiles.work]
+trees = ["work", "/shared"]
+
+[profiles.personal]
+trees = ["/projects"]
+
+[profiles.broken]
+trees = ["/nonexistent"]
+
+[[trees]]
+root = "work"
+
+[[trees]]
+root = "/projects"
+
+[[trees]]
+root = "/shared"
+"#,
+            )
+            .unwrap();
+            config
+                .resolve_roots(Path::new("/configs/config.toml"))
+                .unwrap();
+            match config {
+                Config::ConfigTrees(config) => config,
+                _ => panic!("not a configuration with trees"),
+            }
+        };
+        let roots = |profiles: &[&str]| {
+            let profiles: Vec<String> = profiles.iter().map(|p| p.to_string()).collect();
+            config()
+                .profile_trees(&profiles)
+                .map(|trees| trees.into_iter().map(|tree| tree.root).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            roots(&[]).unwrap(),
+            vec!["/configs/work", "/projects", "/shared"]
+        );
+        assert_eq!(roots(&["work"]).unwrap(), vec!["/configs/work", "/shared"]);
+        assert_eq!(
+            roots(&["personal", "work"]).unwrap(),
+            vec!["/configs/work", "/projects", "/shared"]
+        );
+        assert!(roots(&["unknown"]).is_err());
+        assert!(roots(&["broken"]).is_err());
+    }
+
+    #[test]
+    fn check_resolve_root() {
+        let config_path = Path::new("/configs/config.toml");
+        assert_eq!(
+            resolve_root("projects", config_path, false).unwrap(),
+            "/configs/projects"
+        );
+        assert_eq!(
+            resolve_root("../projects", config_path, false).unwrap(),
+            "/configs/../projects"
+        );
+        assert_eq!(
+            resolve_root("/projects", config_path, false).unwrap(),
+            "/projects"
+        );
+        // Kept as is, so it is still shown with "~"
+        assert_eq!(
+            resolve_root("~/projects", config_path, false).unwrap(),
+            "~/projects"
+        );
+        assert_eq!(
+            resolve_root("projects", config_path, true).unwrap(),
+            "projects"
+        );
+    }
+
+    #[test]
+    fn check_normalize_repo_name() {
+        assert_eq!(normalize_repo_name("repo").unwrap(), "repo");
+        assert_eq!(
+            normalize_repo_name("team/service-a").unwrap(),
+            "team/service-a"
+        );
+        assert_eq!(
+            normalize_repo_name("./org//team/service-b/").unwrap(),
+            "org/team/service-b"
+        );
+        assert!(normalize_repo_name("").is_err());
+        assert!(normalize_repo_name("team/").is_ok());
+        assert!(normalize_repo_name("/team/repo").is_err());
+        assert!(normalize_repo_name("team/../repo").is_err());
+        assert!(normalize_repo_name("./").is_err());
+    }
+}
+
+/// Normalizes the name of a repository. Slashes in the name put the repository
+/// into subdirectories of the tree, so empty and "." components are dropped to
+/// get the same path that is found on disk. The name must stay inside the
+/// tree.
+pub fn normalize_repo_name(name: &str) -> Result<String, String> {
+    if name.starts_with('/') {
+        return Err(format!("Repository name \"{}\" must be relative", name));
+    }
+
+    let mut components = Vec::new();
+    for component in name.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => {
+                return Err(format!(
+                    "Repository name \"{}\" must not contain \"..\"",
+                    name
+                ))
+            }
+            _ => components.push(component),
+        }
+    }
+
+    if components.is_empty() {
+        return Err(format!("Repository name \"{}\" is empty", name));
+    }
+    Ok(components.join("/"))
+}
+
+/// Normalizes the names of all repositories, see `normalize_repo_name()`
+fn normalize_repo_names(mut trees: Vec<ConfigTree>) -> Result<Vec<ConfigTree>, String> {
+    for repo in trees
+        .iter_mut()
+        .flat_map(|tree| tree.repos.iter_mut().flatten())
+    {
+        repo.name = normalize_repo_name(&repo.name)?;
+    }
+    Ok(trees)
+}
+
+/// Drops the trees and repositories that are not meant for the current
+/// machine
+fn for_current_host(trees: Vec<ConfigTree>) -> Result<Vec<ConfigTree>, String> {
+    let mut result = Vec::new();
+    for mut tree in trees {
+        if !tree.host_conditions().match_current()? {
+            print_debug(&format!(
+                "Skipping tree \"{}\", it is not used on this host",
+                tree.root
+            ));
+            continue;
+        }
+        if let Some(repos) = tree.repos.take() {
+            let mut used = Vec::new();
+            for repo in repos {
+                match repo.host_conditions().match_current()? {
+                    true => used.push(repo),
+                    false => print_debug(&format!(
+                        "{}: Skipping, it is not used on this host",
+                        repo.name
+                    )),
+                }
+            }
+            tree.repos = Some(used);
+        }
+        result.push(tree);
+    }
+    Ok(result)
+}
+
 fn worktree_setup_default() -> bool {
     false
 }
 
+static ROOTS_RELATIVE_TO_CWD: AtomicBool = AtomicBool::new(false);
+
+static PROFILES: OnceCell<Vec<String>> = OnceCell::new();
+
+/// Only uses the trees of these profiles. Can only be set once.
+pub fn set_profiles(profiles: Vec<String>) {
+    let _ = PROFILES.set(profiles);
+}
+
+fn profiles_selected() -> bool {
+    PROFILES
+        .get()
+        .map_or(false, |profiles| !profiles.is_empty())
+}
+
+/// Resolves relative tree roots against the current directory instead of the
+/// directory of the configuration file, like older versions did
+pub fn set_roots_relative_to_cwd(enabled: bool) {
+    ROOTS_RELATIVE_TO_CWD.store(enabled, Ordering::Relaxed);
+}
+
+/// Resolves a relative tree root against the directory of the configuration
+/// file at `config_path`. Other roots are returned as they are, so `~` and
+/// variables are kept for display.
+fn resolve_root(root: &str, config_path: &Path, relative_to_cwd: bool) -> Result<String, String> {
+    if relative_to_cwd || path::expand_path(Path::new(root))?.is_absolute() {
+        return Ok(root.to_string());
+    }
+
+    let config_dir = match config_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    // The directory exists, as the configuration was just read from it
+    let config_dir = match std::fs::canonicalize(&config_dir) {
+        Ok(config_dir) => config_dir,
+        Err(_) => std::env::current_dir()
+            .map_err(|error| format!("Could not get current directory: {}", error))?
+            .join(config_dir),
+    };
+
+    // Strips a leading "./" of the root, but nothing else, so symlinks are
+    // still followed the same way
+    let root = Path::new(root)
+        .strip_prefix(".")
+        .unwrap_or_else(|_| Path::new(root));
+    Ok(path::path_as_string(&config_dir.join(root)))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Config {
     ConfigTrees(ConfigTrees),
-    ConfigProvider(ConfigProvider),
+    ConfigProvider(Box<ConfigProvider>),
+    ConfigProviders(ConfigProviders),
+}
+
+/// Several providers, e.g. a work and a personal account on GitHub and an
+/// internal GitLab, that are all synced together
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigProviders {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unmanaged: Option<UnmanagedPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch: Option<repo::FetchConfig>,
+    // TOML requires tables to come after all plain values, so this has to be
+    // the last field
+    pub providers: Vec<ConfigProvider>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ConfigTrees {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unmanaged: Option<UnmanagedPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch: Option<repo::FetchConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<BTreeMap<String, ConfigProfile>>,
+    // TOML requires tables to come after all plain values, so this has to be
+    // the last field
     pub trees: Vec<ConfigTree>,
 }
 
+/// A named group of trees, so they can be synced on their own
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigProfile {
+    /// The roots of the trees
+    pub trees: Vec<String>,
+}
+
+/// What a sync does about repositories in the trees that are not part of the
+/// configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnmanagedPolicy {
+    /// Do not even look for them
+    Ignore,
+    /// Print a warning for each of them
+    Warn,
+    /// Fail the sync
+    Error,
+}
+
+impl Default for UnmanagedPolicy {
+    fn default() -> Self {
+        Self::Warn
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ConfigProviderFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub access: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub owner: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub users: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub groups: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_archived: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_forks: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_names: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_topics: Option<Vec<String>>,
+}
+
+impl ConfigProviderFilter {
+    pub fn exclusions(&self) -> Result<provider::Exclusions, String> {
+        Ok(provider::Exclusions {
+            archived: self.exclude_archived.unwrap_or(false),
+            forks: self.exclude_forks.unwrap_or(false),
+            names: self
+                .exclude_names
+                .iter()
+                .flatten()
+                .map(|name| glob::Pattern::new(name))
+                .collect::<Result<_, _>>()?,
+            topics: self.exclude_topics.clone().unwrap_or_default(),
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ConfigProvider {
+    /// Identifies the provider in messages if there are several of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
     pub provider: RemoteProvider,
     pub token_command: String,
     pub root: String,
-    pub filters: Option<ConfigProviderFilter>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub force_ssh: Option<bool>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub api_url: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_verify: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub worktree: Option<bool>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub remote_name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_owner_prefixes: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unmanaged: Option<UnmanagedPolicy>,
+
+    // TOML requires tables to come after all plain values, so these have to
+    // be the last fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filters: Option<ConfigProviderFilter>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch: Option<repo::FetchConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ConfigProvider {
+    pub fn tls(&self) -> net::TlsOptions {
+        net::TlsOptions {
+            ca_bundle: self.ca_bundle.clone(),
+            insecure_skip_verify: self.insecure_skip_verify.unwrap_or(false),
+        }
+    }
+
+    pub fn layout(&self) -> Result<provider::Layout, String> {
+        provider::Layout::new(
+            self.layout.as_deref().unwrap_or(provider::DEFAULT_LAYOUT),
+            self.strip_owner_prefixes.clone().unwrap_or_default(),
+        )
+    }
+
+    /// Resolves a relative root against the directory of the configuration
+    /// file at `config_path`
+    pub fn resolve_root(&mut self, config_path: &Path) -> Result<(), String> {
+        self.root = resolve_root(
+            &self.root,
+            config_path,
+            ROOTS_RELATIVE_TO_CWD.load(Ordering::Relaxed),
+        )?;
+        Ok(())
+    }
+
+    /// Gets the repositories from the provider, as trees below the root
+    pub fn trees(self) -> Result<Vec<ConfigTree>, Error> {
+        let token =
+            auth::get_token_from_command(&self.token_command).map_err(Error::TokenCommand)?;
+
+        let tls = self.tls();
+        let layout = self.layout().map_err(Error::Provider)?;
+        let filters = self.filters.unwrap_or(ConfigProviderFilter {
+            access: Some(false),
+            owner: Some(false),
+            users: Some(vec![]),
+            groups: Some(vec![]),
+            exclude_archived: None,
+            exclude_forks: None,
+            exclude_names: None,
+            exclude_topics: None,
+        });
+
+        let exclusions = filters.exclusions().map_err(Error::Provider)?;
+        let filter = Filter::new(
+            filters.users.unwrap_or_default(),
+            filters.groups.unwrap_or_default(),
+            filters.owner.unwrap_or(false),
+            filters.access.unwrap_or(false),
+        )
+        .with_exclusions(exclusions);
+
+        if filter.empty() {
+            print_warning(&match &self.name {
+                Some(name) => format!(
+                    "Provider \"{}\" did not specify any filters, so no repos will match",
+                    name
+                ),
+                None => String::from("You did not specify any filters, so no repos will match"),
+            });
+        }
+
+        let repos = match self.provider {
+            RemoteProvider::Github => provider::Github::new(filter, token, self.api_url, tls)
+                .and_then(|provider| {
+                    provider.get_repos(
+                        self.worktree.unwrap_or(false),
+                        self.force_ssh.unwrap_or(false),
+                        self.remote_name,
+                        &layout,
+                    )
+                })
+                .map_err(Error::Provider)?,
+            RemoteProvider::Gitlab => provider::Gitlab::new(filter, token, self.api_url, tls)
+                .and_then(|provider| {
+                    provider.get_repos(
+                        self.worktree.unwrap_or(false),
+                        self.force_ssh.unwrap_or(false),
+                        self.remote_name,
+                        &layout,
+                    )
+                })
+                .map_err(Error::Provider)?,
+        };
+
+        let mut trees = vec![];
+
+        for (namespace, namespace_repos) in repos {
+            let repos = namespace_repos
+                .into_iter()
+                .map(RepoConfig::from_repo)
+                .collect();
+            let tree = ConfigTree {
+                pre_sync: None,
+                post_sync: None,
+                git_config: None,
+                ignore: None,
+                only_on_hosts: None,
+                only_on_os: None,
+                optimize: None,
+                root: if let Some(namespace) = namespace {
+                    path::path_as_string(&Path::new(&self.root).join(namespace))
+                } else {
+                    path::path_as_string(Path::new(&self.root))
+                },
+                repos: Some(repos),
+            };
+            trees.push(tree);
+        }
+        Ok(trees)
+    }
+}
+
+impl ConfigProviders {
+    /// Checks that every provider has a unique name and does not set options
+    /// that only apply to the whole configuration
+    fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for provider in &self.providers {
+            let name = provider
+                .name
+                .as_ref()
+                .ok_or_else(|| String::from("Every provider needs a name"))?;
+            if !names.insert(name) {
+                return Err(format!("There are several providers named \"{}\"", name));
+            }
+            if provider.unmanaged.is_some() || provider.fetch.is_some() {
+                return Err(format!(
+                    "Provider \"{}\": \"unmanaged\" and \"fetch\" can only be set for all providers",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the repositories from all providers, one after the other
+    pub fn trees(self) -> Result<Vec<ConfigTree>, Error> {
+        self.validate()?;
+
+        let mut trees = Vec::new();
+        for provider in self.providers {
+            let name = provider.name.clone().unwrap_or_default();
+            trees.extend(
+                provider.trees().map_err(|error| {
+                    Error::Provider(format!("Provider \"{}\": {}", name, error))
+                })?,
+            );
+        }
+        Ok(trees)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct RemoteConfig {
     pub name: String,
     pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push_url: Option<PushUrls>,
     #[serde(rename = "type")]
     pub remote_type: RemoteType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push_mirror: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_verify: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refspecs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub create_remote: Option<bool>,
 }
 
 impl RemoteConfig {
@@ -72,7 +938,21 @@ impl RemoteConfig {
         Self {
             name: remote.name,
             url: remote.url,
+            push_url: PushUrls::from_vec(remote.push_urls),
             remote_type: remote.remote_type,
+            push_mirror: remote.push_mirror.then(|| true),
+            primary: remote.primary.then(|| true),
+            ca_bundle: remote.tls.ca_bundle,
+            insecure_skip_verify: remote.tls.insecure_skip_verify.then(|| true),
+            refspecs: (!remote.refspecs.is_empty()).then(|| remote.refspecs),
+            username: remote
+                .credentials
+                .as_ref()
+                .and_then(|credentials| credentials.username.clone()),
+            token_command: remote
+                .credentials
+                .map(|credentials| credentials.token_command),
+            create_remote: remote.create.then(|| true),
         }
     }
 
@@ -80,12 +960,70 @@ impl RemoteConfig {
         repo::Remote {
             name: self.name,
             url: self.url,
+            push_urls: self.push_url.map(PushUrls::into_vec).unwrap_or_default(),
             remote_type: self.remote_type,
+            push_mirror: self.push_mirror.unwrap_or(false),
+            primary: self.primary.unwrap_or(false),
+            tls: net::TlsOptions {
+                ca_bundle: self.ca_bundle,
+                insecure_skip_verify: self.insecure_skip_verify.unwrap_or(false),
+            },
+            refspecs: self.refspecs.unwrap_or_default(),
+            credentials: self.token_command.map(|token_command| auth::Credentials {
+                username: self.username,
+                token_command,
+            }),
+            create: self.create_remote.unwrap_or(false),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The push URLs of a remote. A single one can be given as a plain string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PushUrls {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl PushUrls {
+    pub fn from_vec(mut urls: Vec<String>) -> Option<Self> {
+        match urls.len() {
+            0 => None,
+            1 => urls.pop().map(Self::One),
+            _ => Some(Self::Many(urls)),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(url) => vec![url],
+            Self::Many(urls) => urls,
+        }
+    }
+}
+
+/// A value for a git configuration setting. git itself only knows strings,
+/// but this allows writing e.g. `pull.rebase = true` without quotes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GitConfigValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl std::fmt::Display for GitConfigValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct RepoConfig {
     pub name: String,
@@ -93,6 +1031,76 @@ pub struct RepoConfig {
     #[serde(default = "worktree_setup_default")]
     pub worktree_setup: bool,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clone_depth: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clone_filter: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_branch: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sparse_paths: Option<Vec<String>>,
+
+    /// A repository to share objects with, or "auto" to look for another
+    /// clone of the same URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submodules: Option<repo::Submodules>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lfs: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clone_mode: Option<repo::CloneMode>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+
+    /// Overrides the detection of the default branch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_sync: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_sync: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_clone: Option<Vec<String>>,
+
+    /// Globs for local branches that `grm repos branch-clean` never deletes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protected_branches: Option<Vec<String>>,
+
+    /// Keeps the repository in the configuration, but skips it when syncing
+    /// and in the status, e.g. while it is broken
+    #[serde(alias = "skip", skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<bool>,
+
+    /// Hostnames (or patterns) of the machines the repository is used on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub only_on_hosts: Option<Vec<String>>,
+
+    /// Operating systems the repository is used on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub only_on_os: Option<Vec<String>>,
+
+    // TOML requires tables to come after all plain values, so these have to
+    // be the last fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_config: Option<BTreeMap<String, GitConfigValue>>,
+
     pub remotes: Option<Vec<RemoteConfig>>,
 }
 
@@ -104,6 +1112,60 @@ impl RepoConfig {
             remotes: repo
                 .remotes
                 .map(|remotes| remotes.into_iter().map(RemoteConfig::from_remote).collect()),
+            tags: None,
+            protected_branches: None,
+            disabled: None,
+            only_on_hosts: None,
+            only_on_os: None,
+            clone_depth: repo.clone_options.depth,
+            clone_filter: repo.clone_options.filter,
+            branch: repo.clone_options.branch,
+            single_branch: repo.clone_options.single_branch.then(|| true),
+            sparse_paths: repo.clone_options.sparse_paths,
+            reference: repo
+                .clone_options
+                .reference
+                .map(|reference| reference.to_config()),
+            submodules: match repo.submodules {
+                repo::Submodules::None => None,
+                submodules => Some(submodules),
+            },
+            lfs: repo.lfs.then(|| true),
+            clone_mode: match repo.clone_mode {
+                repo::CloneMode::Normal => None,
+                clone_mode => Some(clone_mode),
+            },
+            rev: repo.rev,
+            default_branch: repo.default_branch,
+            pre_sync: (!repo.pre_sync.is_empty()).then(|| repo.pre_sync),
+            post_sync: (!repo.post_sync.is_empty()).then(|| repo.post_sync),
+            on_clone: (!repo.on_clone.is_empty()).then(|| repo.on_clone),
+            git_config: match repo.git_config.is_empty() {
+                true => None,
+                false => Some(
+                    repo.git_config
+                        .into_iter()
+                        .map(|(key, value)| (key, GitConfigValue::String(value)))
+                        .collect(),
+                ),
+            },
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags
+            .as_ref()
+            .map_or(false, |tags| tags.iter().any(|t| t == tag))
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.unwrap_or(false)
+    }
+
+    pub fn host_conditions(&self) -> host::HostConditions {
+        host::HostConditions {
+            hosts: self.only_on_hosts.clone(),
+            os: self.only_on_os.clone(),
         }
     }
 
@@ -124,6 +1186,28 @@ impl RepoConfig {
                     .map(|remote| remote.into_remote())
                     .collect()
             }),
+            clone_options: repo::CloneOptions {
+                depth: self.clone_depth,
+                filter: self.clone_filter,
+                branch: self.branch,
+                single_branch: self.single_branch.unwrap_or(false),
+                sparse_paths: self.sparse_paths,
+                reference: self.reference.map(repo::CloneReference::from_config),
+            },
+            submodules: self.submodules.unwrap_or_default(),
+            lfs: self.lfs.unwrap_or(false),
+            clone_mode: self.clone_mode.unwrap_or_default(),
+            rev: self.rev,
+            default_branch: self.default_branch,
+            git_config: self
+                .git_config
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| (key, value.to_string()))
+                .collect(),
+            pre_sync: self.pre_sync.unwrap_or_default(),
+            post_sync: self.post_sync.unwrap_or_default(),
+            on_clone: self.on_clone.unwrap_or_default(),
         }
     }
 }
@@ -134,12 +1218,20 @@ impl ConfigTrees {
     }
 
     pub fn from_vec(vec: Vec<ConfigTree>) -> Self {
-        ConfigTrees { trees: vec }
+        ConfigTrees {
+            trees: vec,
+            unmanaged: None,
+            fetch: None,
+            profiles: None,
+        }
     }
 
     pub fn from_trees(vec: Vec<tree::Tree>) -> Self {
         ConfigTrees {
             trees: vec.into_iter().map(ConfigTree::from_tree).collect(),
+            unmanaged: None,
+            fetch: None,
+            profiles: None,
         }
     }
 
@@ -147,6 +1239,44 @@ impl ConfigTrees {
         self.trees
     }
 
+    /// The trees of the selected profiles, or all trees if no profile is
+    /// selected
+    fn profile_trees(self, selected: &[String]) -> Result<Vec<ConfigTree>, String> {
+        if selected.is_empty() {
+            return Ok(self.trees);
+        }
+
+        let expand = |root: &str| {
+            path::expand_path(Path::new(root))
+                .map(|path| path::path_as_string(&path))
+                .unwrap_or_else(|_| root.to_string())
+        };
+
+        let profiles = self.profiles.unwrap_or_default();
+        let mut roots = HashSet::new();
+        for name in selected {
+            let profile = profiles
+                .get(name)
+                .ok_or_else(|| format!("There is no profile \"{}\"", name))?;
+            for root in &profile.trees {
+                let root = expand(root);
+                if !self.trees.iter().any(|tree| expand(&tree.root) == root) {
+                    return Err(format!(
+                        "Profile \"{}\" contains \"{}\", which is not a tree",
+                        name, root
+                    ));
+                }
+                roots.insert(root);
+            }
+        }
+
+        Ok(self
+            .trees
+            .into_iter()
+            .filter(|tree| roots.contains(&expand(&tree.root)))
+            .collect())
+    }
+
     pub fn trees_mut(&mut self) -> &mut Vec<ConfigTree> {
         &mut self.trees
     }
@@ -157,116 +1287,131 @@ impl ConfigTrees {
 }
 
 impl Config {
-    pub fn trees(self) -> Result<Vec<ConfigTree>, String> {
+    /// Resolves relative tree roots against the directory of the
+    /// configuration file at `config_path`
+    pub fn resolve_roots(&mut self, config_path: &Path) -> Result<(), String> {
         match self {
-            Config::ConfigTrees(config) => Ok(config.trees),
-            Config::ConfigProvider(config) => {
-                let token = match auth::get_token_from_command(&config.token_command) {
-                    Ok(token) => token,
-                    Err(error) => {
-                        print_error(&format!("Getting token from command failed: {}", error));
-                        process::exit(1);
-                    }
-                };
-
-                let filters = config.filters.unwrap_or(ConfigProviderFilter {
-                    access: Some(false),
-                    owner: Some(false),
-                    users: Some(vec![]),
-                    groups: Some(vec![]),
-                });
-
-                let filter = Filter::new(
-                    filters.users.unwrap_or_default(),
-                    filters.groups.unwrap_or_default(),
-                    filters.owner.unwrap_or(false),
-                    filters.access.unwrap_or(false),
-                );
-
-                if filter.empty() {
-                    print_warning(
-                        "The configuration does not contain any filters, so no repos will match",
-                    );
+            Config::ConfigTrees(config) => {
+                let relative_to_cwd = ROOTS_RELATIVE_TO_CWD.load(Ordering::Relaxed);
+                for tree in config.trees_mut() {
+                    tree.root = resolve_root(&tree.root, config_path, relative_to_cwd)?;
                 }
-
-                let repos = match config.provider {
-                    RemoteProvider::Github => {
-                        match provider::Github::new(filter, token, config.api_url) {
-                            Ok(provider) => provider,
-                            Err(error) => {
-                                print_error(&format!("Error: {}", error));
-                                process::exit(1);
-                            }
-                        }
-                        .get_repos(
-                            config.worktree.unwrap_or(false),
-                            config.force_ssh.unwrap_or(false),
-                            config.remote_name,
-                        )?
+                for profile in config
+                    .profiles
+                    .iter_mut()
+                    .flat_map(|profiles| profiles.values_mut())
+                {
+                    for root in &mut profile.trees {
+                        *root = resolve_root(root, config_path, relative_to_cwd)?;
                     }
-                    RemoteProvider::Gitlab => {
-                        match provider::Gitlab::new(filter, token, config.api_url) {
-                            Ok(provider) => provider,
-                            Err(error) => {
-                                print_error(&format!("Error: {}", error));
-                                process::exit(1);
-                            }
-                        }
-                        .get_repos(
-                            config.worktree.unwrap_or(false),
-                            config.force_ssh.unwrap_or(false),
-                            config.remote_name,
-                        )?
-                    }
-                };
+                }
+                Ok(())
+            }
+            Config::ConfigProvider(config) => config.resolve_root(config_path),
+            Config::ConfigProviders(config) => {
+                for provider in &mut config.providers {
+                    provider.resolve_root(config_path)?;
+                }
+                Ok(())
+            }
+        }
+    }
 
-                let mut trees = vec![];
+    /// The trees and repositories that are used on the current machine
+    pub fn trees(self) -> Result<Vec<ConfigTree>, Error> {
+        Ok(for_current_host(self.trees_on_all_hosts()?)?)
+    }
 
-                for (namespace, namespace_repos) in repos {
-                    let repos = namespace_repos
-                        .into_iter()
-                        .map(RepoConfig::from_repo)
-                        .collect();
-                    let tree = ConfigTree {
-                        root: if let Some(namespace) = namespace {
-                            path::path_as_string(&Path::new(&config.root).join(namespace))
-                        } else {
-                            path::path_as_string(Path::new(&config.root))
-                        },
-                        repos: Some(repos),
-                    };
-                    trees.push(tree);
-                }
-                Ok(trees)
+    /// Like `trees()`, but including the trees and repositories that are only
+    /// used on other machines. They are still managed, so they must not be
+    /// reported as unmanaged.
+    pub fn trees_on_all_hosts(self) -> Result<Vec<ConfigTree>, Error> {
+        match self {
+            Config::ConfigTrees(config) => {
+                let selected = PROFILES.get().map(Vec::as_slice).unwrap_or_default();
+                Ok(normalize_repo_names(config.profile_trees(selected)?)?)
             }
+            _ if profiles_selected() => Err(Error::Other(String::from(
+                "Profiles can only be used with configurations that list trees",
+            ))),
+            Config::ConfigProvider(config) => config.trees(),
+            Config::ConfigProviders(config) => config.trees(),
         }
     }
 
     pub fn from_trees(trees: Vec<ConfigTree>) -> Self {
-        Config::ConfigTrees(ConfigTrees { trees })
+        Config::ConfigTrees(ConfigTrees {
+            trees,
+            unmanaged: None,
+            fetch: None,
+            profiles: None,
+        })
+    }
+
+    pub fn unmanaged_policy(&self) -> UnmanagedPolicy {
+        match self {
+            Config::ConfigTrees(config) => config.unmanaged,
+            Config::ConfigProvider(config) => config.unmanaged,
+            Config::ConfigProviders(config) => config.unmanaged,
+        }
+        .unwrap_or_default()
+    }
+
+    /// Defaults for `--fetch`
+    pub fn fetch_config(&self) -> repo::FetchConfig {
+        match self {
+            Config::ConfigTrees(config) => config.fetch,
+            Config::ConfigProvider(config) => config.fetch,
+            Config::ConfigProviders(config) => config.fetch,
+        }
+        .unwrap_or_default()
     }
 
     pub fn normalize(&mut self) {
         if let Config::ConfigTrees(config) = self {
-            let home = path::env_home();
+            // Without a home directory, there is nothing to shorten
+            let home = match path::env_home() {
+                Ok(home) => home,
+                Err(_) => return,
+            };
             for tree in &mut config.trees_mut().iter_mut() {
-                if tree.root.starts_with(&home) {
+                // Comparing paths instead of strings takes care of different
+                // separators, e.g. on Windows
+                if let Ok(path) = Path::new(&tree.root).strip_prefix(&home) {
                     // The tilde is not handled differently, it's just a normal path component for `Path`.
                     // Therefore we can treat it like that during **output**.
-                    //
-                    // The `unwrap()` is safe here as we are testing via `starts_with()`
-                    // beforehand
-                    let mut path = tree.root.strip_prefix(&home).unwrap();
-                    if path.starts_with('/') {
-                        path = path.strip_prefix('/').unwrap();
-                    }
-
                     tree.root = Path::new("~").join(path).display().to_string();
                 }
             }
         }
     }
 
+    /// Sorts trees by their root and repositories by their name, so the output
+    /// is the same every time. With `by_host`, repositories are grouped by the
+    /// host of their first remote first.
+    pub fn sort(&mut self, by_host: bool) {
+        if let Config::ConfigTrees(config) = self {
+            config.trees_mut().sort_by(|a, b| a.root.cmp(&b.root));
+            for tree in config.trees_mut().iter_mut() {
+                if let Some(repos) = &mut tree.repos {
+                    repos.sort_by_cached_key(|repo| {
+                        let host = match by_host {
+                            true => repo
+                                .remotes
+                                .iter()
+                                .flatten()
+                                .next()
+                                .and_then(|remote| repo::remote_host(&remote.url)),
+                            false => None,
+                        };
+                        // Repositories without a host go last
+                        (host.is_none(), host, repo.name.clone())
+                    });
+                }
+            }
+        }
+    }
+
     pub fn as_toml(&self) -> Result<String, String> {
         match toml::to_string(self) {
             Ok(toml) => Ok(toml),
@@ -277,19 +1422,73 @@ impl Config {
     pub fn as_yaml(&self) -> Result<String, String> {
         serde_yaml::to_string(self).map_err(|e| e.to_string())
     }
+
+    pub fn as_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self)
+            .map(|json| json + "\n")
+            .map_err(|error| error.to_string())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ConfigTree {
     pub root: String,
+
+    /// Shell commands that are run before syncing each repository of the
+    /// tree, before the repository's own commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_sync: Option<Vec<String>>,
+
+    /// Shell commands that are run after syncing each repository of the tree,
+    /// before the repository's own commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_sync: Option<Vec<String>>,
+
+    /// Globs for repositories that are known to be unmanaged, relative to the
+    /// root. A sync does not warn about them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore: Option<Vec<String>>,
+
+    /// Hostnames (or patterns) of the machines the tree is used on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub only_on_hosts: Option<Vec<String>>,
+
+    /// Operating systems the tree is used on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub only_on_os: Option<Vec<String>>,
+
+    /// Optimizations that run in every repository of the tree after it was
+    /// synced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimize: Option<Vec<maintenance::Optimization>>,
+
+    /// git settings for all repositories of the tree. Settings of the
+    /// repositories themselves take precedence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_config: Option<BTreeMap<String, GitConfigValue>>,
+
     pub repos: Option<Vec<RepoConfig>>,
 }
 
 impl ConfigTree {
+    pub fn host_conditions(&self) -> host::HostConditions {
+        host::HostConditions {
+            hosts: self.only_on_hosts.clone(),
+            os: self.only_on_os.clone(),
+        }
+    }
+
     pub fn from_repos(root: String, repos: Vec<repo::Repo>) -> Self {
         Self {
             root,
+            pre_sync: None,
+            post_sync: None,
+            git_config: None,
+            ignore: None,
+            only_on_hosts: None,
+            only_on_os: None,
+            optimize: None,
             repos: Some(repos.into_iter().map(RepoConfig::from_repo).collect()),
         }
     }
@@ -297,17 +1496,355 @@ impl ConfigTree {
     pub fn from_tree(tree: tree::Tree) -> Self {
         Self {
             root: tree.root,
+            pre_sync: None,
+            post_sync: None,
+            git_config: None,
+            ignore: None,
+            only_on_hosts: None,
+            only_on_os: None,
+            optimize: None,
             repos: Some(tree.repos.into_iter().map(RepoConfig::from_repo).collect()),
         }
     }
 }
 
+/// Copies a table parsed from another document. Tables remember their position
+/// in the document they were parsed from, which would place them wrongly in
+/// the new one. Without a position, they are written after the table they are
+/// added to.
+fn without_position(table: &toml_edit::Table) -> toml_edit::Table {
+    let mut result = toml_edit::Table::new();
+    for (key, item) in table.iter() {
+        let item = match item {
+            toml_edit::Item::Table(table) => toml_edit::Item::Table(without_position(table)),
+            toml_edit::Item::ArrayOfTables(tables) => {
+                toml_edit::Item::ArrayOfTables(tables.iter().map(without_position).collect())
+            }
+            item => item.clone(),
+        };
+        result.insert(key, item);
+    }
+    result
+}
+
+/// Adds `tables` to an array of tables, which may also be written as an inline
+/// array, e.g. `repos = [{ name = "one" }]`
+fn push_toml_tables(
+    item: &mut toml_edit::Item,
+    key: &str,
+    tables: Vec<toml_edit::Table>,
+) -> Result<(), String> {
+    match item {
+        toml_edit::Item::None => {
+            *item = toml_edit::Item::ArrayOfTables(tables.into_iter().collect());
+        }
+        toml_edit::Item::ArrayOfTables(array) => array.extend(tables),
+        toml_edit::Item::Value(toml_edit::Value::Array(array)) => {
+            for table in tables {
+                array.push(table.into_inline_table());
+            }
+        }
+        _ => return Err(format!("\"{}\" is not a list", key)),
+    }
+    Ok(())
+}
+
+/// The tree at `tree_index` of a TOML configuration
+fn toml_tree_mut(
+    document: &mut toml_edit::Document,
+    tree_index: usize,
+) -> Option<&mut dyn toml_edit::TableLike> {
+    match document.get_mut("trees")? {
+        toml_edit::Item::ArrayOfTables(trees) => trees
+            .get_mut(tree_index)
+            .map(|tree| tree as &mut dyn toml_edit::TableLike),
+        toml_edit::Item::Value(toml_edit::Value::Array(trees)) => trees
+            .get_mut(tree_index)?
+            .as_inline_table_mut()
+            .map(|tree| tree as &mut dyn toml_edit::TableLike),
+        _ => None,
+    }
+}
+
+fn parse_toml_document(content: &str) -> Result<toml_edit::Document, String> {
+    content
+        .parse::<toml_edit::Document>()
+        .map_err(|error| format!("Error parsing configuration: {}", error))
+}
+
+/// Inserts repositories into a TOML configuration without touching the rest
+/// of it, so comments and formatting are kept. With `tree_index`, the
+/// repositories are added to that tree, otherwise a new tree is appended.
+fn insert_toml_repos(
+    content: &str,
+    tree_index: Option<usize>,
+    root: &str,
+    repos: Vec<RepoConfig>,
+) -> Result<String, String> {
+    let rendered = ConfigTrees::from_vec(vec![ConfigTree {
+        root: root.to_string(),
+        pre_sync: None,
+        post_sync: None,
+        git_config: None,
+        ignore: None,
+        only_on_hosts: None,
+        only_on_os: None,
+        optimize: None,
+        repos: Some(repos),
+    }])
+    .to_config()
+    .as_toml()?;
+
+    // unwrap() is safe, as the rendered configuration always contains exactly
+    // one tree
+    let rendered = parse_toml_document(&rendered)?;
+    let rendered_tree = rendered["trees"]
+        .as_array_of_tables()
+        .and_then(|trees| trees.get(0))
+        .unwrap();
+
+    let mut document = parse_toml_document(content)?;
+
+    match tree_index {
+        Some(tree_index) => {
+            let repos = rendered_tree["repos"]
+                .as_array_of_tables()
+                .map(|repos| repos.iter().map(without_position).collect())
+                .unwrap_or_default();
+            let tree = toml_tree_mut(&mut document, tree_index)
+                .ok_or_else(|| String::from("Tree not found in configuration"))?;
+            push_toml_tables(
+                tree.entry("repos").or_insert(toml_edit::Item::None),
+                "repos",
+                repos,
+            )?;
+        }
+        None => {
+            let mut tree = without_position(rendered_tree);
+            // Separate it from the existing trees
+            if !content.trim().is_empty() {
+                tree.decor_mut().set_prefix("\n");
+            }
+            push_toml_tables(
+                document.entry("trees").or_insert(toml_edit::Item::None),
+                "trees",
+                vec![tree],
+            )?;
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+/// Reads a configuration file for editing. Returns its content, the parsed
+/// trees and whether it is a TOML file.
+fn read_trees_file(path: &str) -> Result<(String, ConfigTrees, bool), String> {
+    let content = std::fs::read(path).map_err(|error| {
+        format!(
+            "Error reading configuration file \"{}\": {}",
+            path,
+            match error.kind() {
+                std::io::ErrorKind::NotFound => String::from("not found"),
+                _ => error.to_string(),
+            }
+        )
+    })?;
+
+    // Writing the changes back would store them unencrypted
+    if secrets::is_encrypted(&content) {
+        return Err(format!(
+            "Cannot edit the encrypted configuration file \"{}\"",
+            path
+        ));
+    }
+    let content = String::from_utf8(content)
+        .map_err(|error| format!("Error reading configuration file \"{}\": {}", path, error))?;
+
+    let (config, is_toml) = match toml::from_str::<Config>(&content) {
+        Ok(config) => (config, true),
+        Err(_) => match serde_yaml::from_str::<Config>(&content) {
+            Ok(config) => (config, false),
+            Err(error) => {
+                return Err(format!(
+                    "Error parsing configuration file \"{}\": {}",
+                    path, error
+                ))
+            }
+        },
+    };
+
+    let config = match config {
+        Config::ConfigTrees(config) => config,
+        Config::ConfigProvider(_) | Config::ConfigProviders(_) => {
+            return Err(String::from(
+                "Cannot edit a configuration that uses a provider",
+            ))
+        }
+    };
+
+    Ok((content, config, is_toml))
+}
+
+/// Adds the repositories of the given trees to the configuration file at
+/// `path`. Repositories that are already part of the configuration are
+/// skipped. Returns the names of the repositories that were added.
+///
+/// TOML files are edited in place, keeping comments and formatting. YAML
+/// files are written anew.
+pub fn merge_into_file(path: &str, trees: Vec<ConfigTree>) -> Result<Vec<String>, String> {
+    let (content, mut config, is_toml) = read_trees_file(path)?;
+
+    let mut content = content;
+    let mut added = Vec::new();
+
+    for tree in trees {
+        let root = path::expand_path(Path::new(&tree.root))?;
+
+        let existing_roots = config
+            .trees_ref()
+            .iter()
+            .map(|existing_tree| {
+                let existing_root = resolve_root(
+                    &existing_tree.root,
+                    Path::new(path),
+                    ROOTS_RELATIVE_TO_CWD.load(Ordering::Relaxed),
+                )?;
+                Ok(path::expand_path(Path::new(&existing_root))?)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let repos: Vec<RepoConfig> = tree
+            .repos
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|repo| {
+                let repo_path = root.join(&repo.name);
+                !config.trees_ref().iter().zip(&existing_roots).any(
+                    |(existing_tree, existing_root)| {
+                        existing_tree.repos.iter().flatten().any(|existing_repo| {
+                            existing_root.join(&existing_repo.name) == repo_path
+                        })
+                    },
+                )
+            })
+            .collect();
+
+        if repos.is_empty() {
+            continue;
+        }
+
+        let tree_index = existing_roots
+            .iter()
+            .position(|existing_root| existing_root == &root);
+
+        if is_toml {
+            content = insert_toml_repos(&content, tree_index, &tree.root, repos.clone())?;
+        }
+
+        added.extend(repos.iter().map(|repo| repo.name.clone()));
+
+        match tree_index {
+            Some(tree_index) => config.trees_mut()[tree_index]
+                .repos
+                .get_or_insert_with(Vec::new)
+                .extend(repos),
+            None => config.trees_mut().push(ConfigTree {
+                root: tree.root,
+                pre_sync: None,
+                post_sync: None,
+                git_config: None,
+                ignore: None,
+                only_on_hosts: None,
+                only_on_os: None,
+                optimize: None,
+                repos: Some(repos),
+            }),
+        }
+    }
+
+    if added.is_empty() {
+        return Ok(added);
+    }
+
+    if !is_toml {
+        content = config.to_config().as_yaml()?;
+    }
+
+    std::fs::write(path, content)
+        .map_err(|error| format!("Error writing configuration file \"{}\": {}", path, error))?;
+
+    Ok(added)
+}
+
+/// Removes a repository from a TOML configuration without touching the rest of
+/// it. `tree_index` and `repo_index` are the positions of the tree and the
+/// repository in the configuration.
+fn remove_toml_repo(content: &str, tree_index: usize, repo_index: usize) -> Result<String, String> {
+    let not_found = || String::from("Repository not found in configuration");
+
+    let mut document = parse_toml_document(content)?;
+    let repos = toml_tree_mut(&mut document, tree_index)
+        .and_then(|tree| tree.get_mut("repos"))
+        .ok_or_else(not_found)?;
+
+    match repos {
+        toml_edit::Item::ArrayOfTables(repos) if repo_index < repos.len() => {
+            repos.remove(repo_index);
+        }
+        toml_edit::Item::Value(toml_edit::Value::Array(repos)) if repo_index < repos.len() => {
+            let removed = repos.remove(repo_index);
+            // The first element is usually written without a leading space
+            if repo_index == 0 {
+                if let Some(first) = repos.get_mut(0) {
+                    let prefix = removed.decor().prefix().unwrap_or("").to_string();
+                    first.decor_mut().set_prefix(prefix);
+                }
+            }
+        }
+        _ => return Err(not_found()),
+    }
+
+    Ok(document.to_string())
+}
+
+/// Removes the repository at the given position from the configuration file at
+/// `path`. Like with [`merge_into_file()`], TOML files are edited in place
+/// and YAML files are written anew.
+pub fn remove_from_file(path: &str, tree_index: usize, repo_index: usize) -> Result<(), String> {
+    let (content, mut config, is_toml) = read_trees_file(path)?;
+
+    let repos = config
+        .trees_mut()
+        .get_mut(tree_index)
+        .and_then(|tree| tree.repos.as_mut())
+        .filter(|repos| repo_index < repos.len())
+        .ok_or_else(|| String::from("Repository not found in configuration"))?;
+    repos.remove(repo_index);
+
+    let content = match is_toml {
+        true => remove_toml_repo(&content, tree_index, repo_index)?,
+        false => config.to_config().as_yaml()?,
+    };
+
+    std::fs::write(path, content)
+        .map_err(|error| format!("Error writing configuration file \"{}\": {}", path, error))
+}
+
+/// Reads the configuration of repositories at `path`. Relative tree roots are
+/// resolved against the directory of the file.
+pub fn read_repos_config(path: &str) -> Result<Config, String> {
+    let mut config: Config = read_config(path)?;
+    config.resolve_roots(Path::new(path))?;
+    Ok(config)
+}
+
 pub fn read_config<'a, T>(path: &str) -> Result<T, String>
 where
     T: for<'de> serde::Deserialize<'de>,
 {
-    let content = match std::fs::read_to_string(path) {
-        Ok(s) => s,
+    let content = match std::fs::read(path) {
+        Ok(content) => secrets::decrypt(Path::new(path), content)
+            .map_err(|error| format!("Error reading configuration file \"{}\": {}", path, error))?,
         Err(e) => {
             return Err(format!(
                 "Error reading configuration file \"{}\": {}",
@@ -335,3 +1872,86 @@ where
 
     Ok(config)
 }
+
+const USER_CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Defaults for command line options, read from the configuration file of the
+/// user (`~/.config/grm/config.toml`). Options given on the command line always
+/// take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    /// The configuration file of all commands that take `--config`, with `~`
+    /// and environment variables expanded
+    pub config: Option<String>,
+    /// When to use colors, like `--color`
+    pub color: Option<String>,
+    /// How many repositories or worktrees to work on at the same time
+    pub jobs: Option<usize>,
+    /// The command to get the API token for `find remote` and `sync remote`
+    pub token_command: Option<String>,
+}
+
+/// The path of the configuration file of the user, if the system has a
+/// configuration directory
+pub fn user_config_path() -> Option<std::path::PathBuf> {
+    dirs::config_dir().map(|dir| {
+        dir.join(repo::GLOBAL_CONFIG_DIRECTORY)
+            .join(USER_CONFIG_FILE_NAME)
+    })
+}
+
+fn parse_user_config(content: &str, path: &Path) -> Result<UserConfig, String> {
+    let mut config: UserConfig = toml::from_str(content).map_err(|error| {
+        format!(
+            "Error parsing configuration file \"{}\": {}",
+            path.display(),
+            error
+        )
+    })?;
+
+    if let Some(color) = &config.color {
+        if !["auto", "always", "never"].contains(&color.as_str()) {
+            return Err(format!(
+                "Error in configuration file \"{}\": color must be one of \"auto\", \"always\" or \"never\"",
+                path.display()
+            ));
+        }
+    }
+
+    if config.jobs == Some(0) {
+        return Err(format!(
+            "Error in configuration file \"{}\": jobs must be at least 1",
+            path.display()
+        ));
+    }
+
+    config.config = config
+        .config
+        .map(|config| {
+            path::expand_path(Path::new(&config)).map(|config| path::path_as_string(&config))
+        })
+        .transpose()
+        .map_err(|error| error.to_string())?;
+
+    Ok(config)
+}
+
+/// Reads the configuration file of the user. A missing file results in an
+/// empty configuration.
+pub fn read_user_config() -> Result<UserConfig, String> {
+    let path = match user_config_path() {
+        Some(path) => path,
+        None => return Ok(UserConfig::default()),
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(content) => parse_user_config(&content, &path),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(UserConfig::default()),
+        Err(error) => Err(format!(
+            "Error reading configuration file \"{}\": {}",
+            path.display(),
+            error
+        )),
+    }
+}
@@ -0,0 +1,279 @@
+//! Differences between the configuration and the repositories on disk,
+//! printed like a unified diff: `-` lines are only in the configuration, `+`
+//! lines only on disk.
+
+use std::path::Path;
+
+use console::{Style, Term};
+
+use super::config;
+use super::glob;
+use super::maintenance;
+use super::output::*;
+use super::path;
+use super::repo;
+use super::tree;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(key: &str, line: &str) -> (String, String) {
+        (String::from(key), String::from(line))
+    }
+
+    #[test]
+    fn check_diff_attributes() {
+        let configured = vec![
+            attribute("remote \"origin\"", "remote \"origin\" = \"a\""),
+            attribute("remote \"fork\"", "remote \"fork\" = \"b\""),
+            attribute("worktree_setup", "worktree_setup = true"),
+        ];
+        let actual = vec![
+            attribute("remote \"origin\"", "remote \"origin\" = \"c\""),
+            attribute("worktree_setup", "worktree_setup = true"),
+            attribute("remote \"extra\"", "remote \"extra\" = \"d\""),
+        ];
+
+        assert_eq!(
+            diff_attributes(&configured, &actual),
+            vec![
+                Line::Removed(String::from("  remote \"origin\" = \"a\"")),
+                Line::Added(String::from("  remote \"origin\" = \"c\"")),
+                Line::Removed(String::from("  remote \"fork\" = \"b\"")),
+                Line::Context(String::from("  worktree_setup = true")),
+                Line::Added(String::from("  remote \"extra\" = \"d\"")),
+            ]
+        );
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line {
+    /// The same in the configuration and on disk
+    Context(String),
+    /// Only in the configuration
+    Removed(String),
+    /// Only on disk
+    Added(String),
+}
+
+/// The differences of a single tree
+pub struct TreeDiff {
+    /// The root as given in the configuration
+    pub root: String,
+    pub lines: Vec<Line>,
+}
+
+impl TreeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| matches!(line, Line::Context(_)))
+    }
+
+    /// Prints the differences to stdout, in red and green if colors are
+    /// enabled
+    pub fn print(&self) {
+        let term = Term::stdout();
+        let color = use_color(&term);
+        let removed = Style::new().red().force_styling(color);
+        let added = Style::new().green().force_styling(color);
+
+        println!(
+            "{}",
+            removed.apply_to(format!("--- {} (configuration)", self.root))
+        );
+        println!("{}", added.apply_to(format!("+++ {} (on disk)", self.root)));
+        for line in &self.lines {
+            match line {
+                Line::Context(line) => println!(" {}", line),
+                Line::Removed(line) => println!("{}", removed.apply_to(format!("-{}", line))),
+                Line::Added(line) => println!("{}", added.apply_to(format!("+{}", line))),
+            }
+        }
+    }
+}
+
+/// The parts of a repository that are compared, as pairs of a key and the
+/// line to show. Attributes with the same key are the same setting with
+/// possibly different values.
+fn attributes(repo: &repo::Repo) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+
+    if repo.worktree_setup {
+        attributes.push((
+            String::from("worktree_setup"),
+            String::from("worktree_setup = true"),
+        ));
+    }
+    match repo.clone_mode {
+        repo::CloneMode::Normal => (),
+        repo::CloneMode::Mirror => attributes.push((
+            String::from("clone_mode"),
+            String::from("clone_mode = \"mirror\""),
+        )),
+        repo::CloneMode::Bare => attributes.push((
+            String::from("clone_mode"),
+            String::from("clone_mode = \"bare\""),
+        )),
+    }
+
+    for remote in repo.remotes.iter().flatten() {
+        let key = format!("remote \"{}\"", remote.name);
+        attributes.push((key.clone(), format!("{} = \"{}\"", key, remote.url)));
+
+        if !remote.push_urls.is_empty() {
+            attributes.push((
+                format!("{} push_url", key),
+                format!("{} push_url = \"{}\"", key, remote.push_urls.join("\", \"")),
+            ));
+        }
+        if remote.primary {
+            attributes.push((
+                format!("{} primary", key),
+                format!("{} primary = true", key),
+            ));
+        }
+        if let Some(ca_bundle) = &remote.tls.ca_bundle {
+            attributes.push((
+                format!("{} ca_bundle", key),
+                format!("{} ca_bundle = \"{}\"", key, ca_bundle),
+            ));
+        }
+        if remote.tls.insecure_skip_verify {
+            attributes.push((
+                format!("{} insecure_skip_verify", key),
+                format!("{} insecure_skip_verify = true", key),
+            ));
+        }
+    }
+
+    attributes
+}
+
+/// Compares the attributes of a repository, in the order of the
+/// configuration. Attributes that only exist on disk come last.
+fn diff_attributes(configured: &[(String, String)], actual: &[(String, String)]) -> Vec<Line> {
+    let mut lines = Vec::new();
+
+    for (key, line) in configured {
+        match actual.iter().find(|(other, _)| other == key) {
+            Some((_, other)) if other == line => lines.push(Line::Context(format!("  {}", line))),
+            Some((_, other)) => {
+                lines.push(Line::Removed(format!("  {}", line)));
+                lines.push(Line::Added(format!("  {}", other)));
+            }
+            None => lines.push(Line::Removed(format!("  {}", line))),
+        }
+    }
+
+    for (key, line) in actual {
+        if !configured.iter().any(|(other, _)| other == key) {
+            lines.push(Line::Added(format!("  {}", line)));
+        }
+    }
+
+    lines
+}
+
+/// Compares every tree of the configuration with the repositories on disk.
+/// Only repositories that differ are part of the result, and trees without
+/// any differences are left out. Returns warnings about repositories that
+/// could not be inspected as well.
+pub fn diff_trees(config: config::Config) -> Result<(Vec<TreeDiff>, Vec<String>), String> {
+    let mut warnings = Vec::new();
+
+    // Trees may be nested, so a repository is only unmanaged if no tree
+    // contains it. Repositories of other hosts are still managed.
+    let mut configured_paths = Vec::new();
+
+    let mut trees = Vec::new();
+    for tree in config
+        .trees_on_all_hosts()
+        .map_err(|error| error.to_string())?
+    {
+        let tree_other_host = !tree.host_conditions().match_current()?;
+        let root = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root) => root,
+            // The root may only be valid on the other machines
+            Err(_) if tree_other_host => continue,
+            Err(error) => return Err(error.to_string()),
+        };
+
+        let mut repos = Vec::new();
+        for repo in tree.repos.unwrap_or_default() {
+            let other_host = tree_other_host || !repo.host_conditions().match_current()?;
+            let repo = repo.into_repo();
+            configured_paths.push(root.join(repo.fullname()));
+            if !other_host {
+                repos.push(repo);
+            }
+        }
+
+        if !tree_other_host {
+            trees.push((tree.root, root, repos, tree.ignore.unwrap_or_default()));
+        }
+    }
+
+    let mut diffs = Vec::new();
+    for (configured_root, root, repos, ignore) in trees {
+        let mut lines = Vec::new();
+
+        for repo in &repos {
+            let header = format!("repo \"{}\"", repo.fullname());
+            let configured = attributes(repo);
+
+            let repo_path = root.join(repo.fullname());
+            if !repo_path.exists() {
+                lines.push(Line::Removed(header));
+                lines.extend(diff_attributes(&configured, &[]));
+                continue;
+            }
+
+            let (actual, mut repo_warnings) = super::inspect_repo(&root, &repo_path)?;
+            warnings.append(&mut repo_warnings);
+            let actual = match actual {
+                Some(actual) => attributes(&actual),
+                None => continue,
+            };
+
+            let repo_lines = diff_attributes(&configured, &actual);
+            if repo_lines
+                .iter()
+                .any(|line| !matches!(line, Line::Context(_)))
+            {
+                lines.push(Line::Context(header));
+                lines.extend(repo_lines);
+            }
+        }
+
+        if root.exists() {
+            let ignore = glob::PatternSet::new(&ignore)?;
+            for repo_path in tree::find_repo_paths(&root)? {
+                if configured_paths.contains(&repo_path)
+                    || maintenance::is_backup(&repo_path)
+                    || tree::is_ignored(&root, &repo_path, &ignore)
+                {
+                    continue;
+                }
+                let (actual, mut repo_warnings) = super::inspect_repo(&root, &repo_path)?;
+                warnings.append(&mut repo_warnings);
+                if let Some(actual) = actual {
+                    lines.push(Line::Added(format!("repo \"{}\"", actual.fullname())));
+                    lines.extend(diff_attributes(&[], &attributes(&actual)));
+                }
+            }
+        }
+
+        let diff = TreeDiff {
+            root: configured_root,
+            lines,
+        };
+        if !diff.is_empty() {
+            diffs.push(diff);
+        }
+    }
+
+    Ok((diffs, warnings))
+}
@@ -0,0 +1,449 @@
+//! Checks of the environment GRM runs in, for `grm repos doctor`. In contrast
+//! to a sync, which stops at the first problem of a repository, all checks run
+//! and every problem comes with a hint how to fix it.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use super::auth;
+use super::config;
+use super::path;
+use super::repo;
+use super::worktree;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_dangling_worktrees() {
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+        let git_dir = dir.path().join("repo.git");
+        let worktrees = git_dir.join("worktrees");
+
+        assert!(dangling_worktrees(&git_dir).is_empty());
+
+        let existing = dir.path().join("existing");
+        std::fs::create_dir_all(&existing).unwrap();
+        std::fs::write(existing.join(".git"), "gitdir: somewhere").unwrap();
+
+        for (name, target) in [
+            ("existing", existing.join(".git")),
+            ("gone", dir.path().join("gone").join(".git")),
+            ("feature/existing", existing.join(".git")),
+            ("feature/gone", dir.path().join("gone").join(".git")),
+        ]
+        .iter()
+        {
+            std::fs::create_dir_all(worktrees.join(name)).unwrap();
+            std::fs::write(
+                worktrees.join(name).join("gitdir"),
+                format!("{}\n", target.display()),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            dangling_worktrees(&git_dir),
+            vec![String::from("feature/gone"), String::from("gone")]
+        );
+    }
+
+    #[test]
+    fn check_root() {
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+
+        let finding = check_tree_root(dir.path());
+        assert_eq!(finding.severity, Severity::Ok);
+
+        let finding = check_tree_root(&dir.path().join("new").join("tree"));
+        assert_eq!(finding.severity, Severity::Ok);
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        let finding = check_tree_root(&file);
+        assert_eq!(finding.severity, Severity::Error);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The result of a single check
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    /// What was checked, e.g. "git" or the path of a tree
+    pub subject: String,
+    pub message: String,
+    /// What to do about a problem
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(subject: &str, message: &str) -> Self {
+        Self {
+            severity: Severity::Ok,
+            subject: subject.to_string(),
+            message: message.to_string(),
+            hint: None,
+        }
+    }
+
+    fn problem(severity: Severity, subject: &str, message: &str, hint: &str) -> Self {
+        Self {
+            severity,
+            subject: subject.to_string(),
+            message: message.to_string(),
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+/// Some clone options and all worktree commands need the git command line
+/// client
+fn check_git() -> Finding {
+    match process::Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            Finding::ok("git", String::from_utf8_lossy(&output.stdout).trim())
+        }
+        Ok(output) => Finding::problem(
+            Severity::Error,
+            "git",
+            &format!(
+                "\"git --version\" failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            "Check your git installation",
+        ),
+        Err(error) => Finding::problem(
+            Severity::Error,
+            "git",
+            &format!("git cannot be run: {}", error),
+            "Install git and make sure it is in your PATH",
+        ),
+    }
+}
+
+/// Keys for SSH remotes are only taken from the SSH agent
+fn check_ssh_agent() -> Finding {
+    let subject = "ssh-agent";
+    if std::env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Finding::problem(
+            Severity::Error,
+            subject,
+            "SSH_AUTH_SOCK is not set, but SSH remotes need an SSH agent",
+            "Start ssh-agent and add your key with ssh-add",
+        );
+    }
+
+    // Exits with 1 if the agent has no keys, and with 2 if it cannot be
+    // reached
+    match process::Command::new("ssh-add").arg("-l").output() {
+        Ok(output) => match output.status.code() {
+            Some(0) => {
+                let keys = String::from_utf8_lossy(&output.stdout).lines().count();
+                Finding::ok(subject, &format!("{} key(s) available", keys))
+            }
+            Some(1) => Finding::problem(
+                Severity::Warning,
+                subject,
+                "The agent does not have any keys",
+                "Add your key with ssh-add",
+            ),
+            _ => Finding::problem(
+                Severity::Error,
+                subject,
+                &format!(
+                    "The agent cannot be reached: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                "Make sure ssh-agent is running and SSH_AUTH_SOCK points to it",
+            ),
+        },
+        Err(_) => Finding::ok(subject, "SSH_AUTH_SOCK is set, ssh-add is not available"),
+    }
+}
+
+fn check_token_command(subject: &str, command: &str) -> Finding {
+    match auth::get_token_from_command(command) {
+        Ok(_) => Finding::ok(subject, "Token command works"),
+        Err(error) => Finding::problem(
+            Severity::Error,
+            subject,
+            &error,
+            &format!("Run \"{}\" yourself and check its output", command),
+        ),
+    }
+}
+
+/// A sync creates the root if it does not exist, so only its nearest existing
+/// parent has to be writable
+fn check_tree_root(root: &Path) -> Finding {
+    let subject = path::path_as_string(root);
+
+    let existing = match root.ancestors().find(|ancestor| ancestor.exists()) {
+        Some(existing) => existing,
+        None => {
+            return Finding::problem(
+                Severity::Error,
+                &subject,
+                "Neither the root nor any of its parents exist",
+                "Check the root in the configuration",
+            )
+        }
+    };
+
+    if !existing.is_dir() {
+        return Finding::problem(
+            Severity::Error,
+            &subject,
+            &format!("\"{}\" is not a directory", path::path_as_string(existing)),
+            "Move the file away or change the root in the configuration",
+        );
+    }
+
+    let probe = existing.join(format!(".grm-doctor-{}", process::id()));
+    match std::fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            match existing == root {
+                true => Finding::ok(&subject, "Writable"),
+                false => Finding::ok(&subject, "Does not exist yet, a sync creates it"),
+            }
+        }
+        Err(error) => Finding::problem(
+            Severity::Error,
+            &subject,
+            &format!(
+                "\"{}\" is not writable: {}",
+                path::path_as_string(existing),
+                error
+            ),
+            "Fix the permissions or change the root in the configuration",
+        ),
+    }
+}
+
+/// The directory with the git data of the repository at `repo_path`
+fn git_dir(repo_path: &Path) -> PathBuf {
+    if repo::RepoHandle::detect_worktree(repo_path) {
+        repo_path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY)
+    } else if repo_path.join(".git").is_dir() {
+        repo_path.join(".git")
+    } else {
+        repo_path.to_path_buf()
+    }
+}
+
+/// Worktrees that git still knows about, but whose directory is gone. Their
+/// branches cannot be checked out anywhere else.
+///
+/// The administrative directories of worktrees with a slash in their name are
+/// nested, e.g. `worktrees/feature/foo`. A directory without a `gitdir` file is
+/// such an intermediate directory, not a worktree.
+fn dangling_worktrees(git_dir: &Path) -> Vec<String> {
+    fn collect(root: &Path, dir: &Path, dangling: &mut Vec<String>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if !path.is_dir() {
+                continue;
+            }
+            // Contains the path of the .git file in the worktree
+            match std::fs::read_to_string(path.join("gitdir")) {
+                Ok(target) => {
+                    if !Path::new(target.trim()).exists() {
+                        if let Ok(name) = path.strip_prefix(root) {
+                            dangling.push(name.to_string_lossy().to_string());
+                        }
+                    }
+                }
+                Err(_) => collect(root, &path, dangling),
+            }
+        }
+    }
+
+    let root = git_dir.join("worktrees");
+    let mut dangling = Vec::new();
+    collect(&root, &root, &mut dangling);
+    dangling.sort();
+    dangling
+}
+
+fn check_repo(name: &str, repo_path: &Path) -> Vec<Finding> {
+    let git_dir = git_dir(repo_path);
+    let mut findings = Vec::new();
+
+    // git refuses to work in repositories owned by someone else, unless
+    // they are listed in safe.directory
+    match process::Command::new("git")
+        .arg("-C")
+        .arg(&git_dir)
+        .args(["rev-parse", "--git-dir"])
+        .output()
+    {
+        Ok(output) if !output.status.success() => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            findings.push(match stderr.contains("safe.directory") {
+                true => Finding::problem(
+                    Severity::Error,
+                    name,
+                    "The repository is owned by another user",
+                    &format!(
+                        "Fix the ownership, or run \"git config --global --add safe.directory {}\"",
+                        path::path_as_string(&git_dir)
+                    ),
+                ),
+                false => Finding::problem(
+                    Severity::Error,
+                    name,
+                    &format!("git cannot open the repository: {}", stderr.trim()),
+                    "Check the repository with \"grm repos fsck\"",
+                ),
+            });
+        }
+        // A missing git is already reported
+        _ => (),
+    }
+
+    let dangling = dangling_worktrees(&git_dir);
+    if !dangling.is_empty() {
+        // Not "git worktree prune": It also removes the intermediate
+        // directories of nested worktrees, which breaks them
+        let admin_dirs: Vec<String> = dangling
+            .iter()
+            .map(|name| {
+                format!(
+                    "\"{}\"",
+                    path::path_as_string(&git_dir.join("worktrees").join(name))
+                )
+            })
+            .collect();
+        findings.push(Finding::problem(
+            Severity::Warning,
+            name,
+            &format!(
+                "Metadata of removed worktrees is left: {}",
+                dangling.join(", ")
+            ),
+            &format!("Remove {}", admin_dirs.join(", ")),
+        ));
+    }
+
+    findings
+}
+
+/// What the configuration needs from the environment
+#[derive(Default)]
+struct Requirements {
+    ssh: bool,
+    /// Descriptions and token commands
+    token_commands: Vec<(String, String)>,
+    roots: Vec<PathBuf>,
+    /// Names and paths of repositories that exist on disk
+    repos: Vec<(String, PathBuf)>,
+}
+
+fn requirements(config: config::Config) -> Result<Requirements, String> {
+    let mut requirements = Requirements::default();
+
+    let providers = match config {
+        config::Config::ConfigProvider(provider) => vec![*provider],
+        config::Config::ConfigProviders(providers) => providers.providers,
+        config => {
+            for tree in config.trees().map_err(|error| error.to_string())? {
+                let root =
+                    path::expand_path(Path::new(&tree.root)).map_err(|error| error.to_string())?;
+                for repo in tree.repos.unwrap_or_default() {
+                    for remote in repo.remotes.iter().flatten() {
+                        requirements.ssh |= remote.remote_type == repo::RemoteType::Ssh;
+                        if let Some(command) = &remote.token_command {
+                            requirements.token_commands.push((
+                                format!("{}: Remote \"{}\"", repo.name, remote.name),
+                                command.clone(),
+                            ));
+                        }
+                    }
+                    let repo_path = root.join(&repo.name);
+                    if repo_path.exists() {
+                        requirements.repos.push((repo.name, repo_path));
+                    }
+                }
+                requirements.roots.push(root);
+            }
+            return Ok(requirements);
+        }
+    };
+
+    // Asking the forges for the repositories would take long, so only what
+    // is needed for that is checked
+    for provider in providers {
+        let name = match &provider.name {
+            Some(name) => format!("Provider \"{}\"", name),
+            None => String::from("Provider"),
+        };
+        requirements.ssh |= provider.force_ssh.unwrap_or(false);
+        requirements
+            .token_commands
+            .push((name, provider.token_command.clone()));
+        requirements
+            .roots
+            .push(path::expand_path(Path::new(&provider.root)).map_err(|error| error.to_string())?);
+    }
+    Ok(requirements)
+}
+
+/// Runs all checks for the configuration at `config_path`. The checks of the
+/// environment run even if the configuration cannot be read.
+pub fn diagnose(config_path: &str) -> Vec<Finding> {
+    let mut findings = vec![check_git()];
+
+    let requirements = match config::read_repos_config(config_path).and_then(requirements) {
+        Ok(requirements) => {
+            findings.push(Finding::ok(config_path, "Configuration is valid"));
+            requirements
+        }
+        Err(error) => {
+            findings.push(Finding::problem(
+                Severity::Error,
+                config_path,
+                &error,
+                "Fix the configuration, or pass another one with --config",
+            ));
+            Requirements::default()
+        }
+    };
+
+    if requirements.ssh {
+        findings.push(check_ssh_agent());
+    }
+
+    let mut seen = HashSet::new();
+    for (subject, command) in &requirements.token_commands {
+        if seen.insert(command) {
+            findings.push(check_token_command(subject, command));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for root in &requirements.roots {
+        if seen.insert(root) {
+            findings.push(check_tree_root(root));
+        }
+    }
+
+    for (name, repo_path) in &requirements.repos {
+        findings.extend(check_repo(name, repo_path));
+    }
+
+    findings
+}
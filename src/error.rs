@@ -0,0 +1,67 @@
+//! The error type for failures that callers of the library may want to tell
+//! apart. Everything else is still reported as a plain message.
+
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The environment is not usable, e.g. because `HOME` is not set
+    Environment(String),
+    /// A path could not be expanded
+    InvalidPath {
+        path: String,
+        reason: String,
+    },
+    /// The type of a remote could not be detected from its URL
+    UnknownRemoteType(String),
+    /// The remote uses a protocol that is not supported
+    UnsupportedProtocol {
+        url: String,
+        protocol: String,
+    },
+    /// Running the command to get the token for a remote provider failed
+    TokenCommand(String),
+    /// Getting the repositories from a remote provider failed
+    Provider(String),
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Environment(message) => write!(f, "{}", message),
+            Error::InvalidPath { path, reason } => {
+                write!(f, "Unable to expand path \"{}\": {}", path, reason)
+            }
+            Error::UnknownRemoteType(url) => {
+                write!(f, "Could not detect remote type of \"{}\"", url)
+            }
+            Error::UnsupportedProtocol { url, protocol } => write!(
+                f,
+                "Remotes using {} protocol are not supported: \"{}\"",
+                protocol, url
+            ),
+            Error::TokenCommand(message) => {
+                write!(f, "Getting token from command failed: {}", message)
+            }
+            Error::Provider(message) => write!(f, "{}", message),
+            Error::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+/// Most of the library still uses plain messages as errors, this makes `?`
+/// work there
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,192 @@
+//! Running arbitrary commands in a set of directories, e.g. in all worktrees
+//! of a repository.
+
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use super::config;
+use super::output::*;
+use super::parallel;
+use super::path;
+
+pub struct Target {
+    pub name: String,
+    pub path: PathBuf,
+    pub env: Vec<(String, OsString)>,
+}
+
+/// Turns all configured repositories into targets. If `tree` is given, only
+/// repositories in the tree with that root are used, if `tag` is given, only
+/// repositories with that tag are used.
+///
+/// Repositories that do not exist locally are skipped, a warning is returned
+/// for each of them. The same goes for trees whose root cannot be expanded.
+pub fn repo_targets(
+    trees: Vec<config::ConfigTree>,
+    tree: Option<&str>,
+    tag: Option<&str>,
+) -> Result<(Vec<Target>, Vec<String>), String> {
+    let mut targets = Vec::new();
+    let mut warnings = Vec::new();
+
+    let tree_filter = tree
+        .map(|tree| path::expand_path(Path::new(tree)))
+        .transpose()?;
+
+    for tree in trees {
+        let root_path = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root_path) => root_path,
+            Err(error) => {
+                warnings.push(format!("{}, skipping", error));
+                continue;
+            }
+        };
+        if let Some(ref tree_filter) = tree_filter {
+            if &root_path != tree_filter {
+                continue;
+            }
+        }
+
+        for repo in tree.repos.unwrap_or_default() {
+            if let Some(tag) = tag {
+                if !repo.has_tag(tag) {
+                    continue;
+                }
+            }
+
+            let repo = repo.into_repo();
+            let name = repo.fullname();
+            let repo_path = root_path.join(&name);
+
+            if !repo_path.exists() {
+                warnings.push(format!("{}: Repository does not exist, skipping", name));
+                continue;
+            }
+
+            targets.push(Target {
+                env: repo_env(&name, &repo_path, &root_path),
+                name,
+                path: repo_path,
+            });
+        }
+    }
+
+    Ok((targets, warnings))
+}
+
+/// The environment that commands run for a repository get
+pub fn repo_env(name: &str, repo_path: &Path, root_path: &Path) -> Vec<(String, OsString)> {
+    vec![
+        (String::from("GRM_REPO_NAME"), OsString::from(name)),
+        (
+            String::from("GRM_REPO_ROOT"),
+            repo_path.as_os_str().to_os_string(),
+        ),
+        (
+            String::from("GRM_TREE_ROOT"),
+            root_path.as_os_str().to_os_string(),
+        ),
+    ]
+}
+
+/// A single argument is handed to the shell, so things like pipes and `&&`
+/// work. Multiple arguments are executed directly, without any shell
+/// interpretation.
+fn build_command(command: &[String]) -> Command {
+    if command.len() == 1 {
+        #[cfg(windows)]
+        let (shell, flag) = ("cmd", "/C");
+        #[cfg(not(windows))]
+        let (shell, flag) = ("sh", "-c");
+
+        let mut cmd = Command::new(shell);
+        cmd.arg(flag).arg(&command[0]);
+        cmd
+    } else {
+        let mut cmd = Command::new(&command[0]);
+        cmd.args(&command[1..]);
+        cmd
+    }
+}
+
+fn forward_output<R: Read>(prefix: &str, reader: R, stderr: bool) {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let text = String::from_utf8_lossy(&line);
+                print_command_output(prefix, text.trim_end_matches(&['\r', '\n'][..]), stderr);
+            }
+        }
+    }
+}
+
+fn run_single(target: &Target, command: &[String]) -> Result<(), String> {
+    let mut child = build_command(command)
+        .current_dir(&target.path)
+        .envs(target.env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("Failed to run command: {}", error))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .expect("stdout of child is not captured");
+    let stderr = child
+        .stderr
+        .take()
+        .expect("stderr of child is not captured");
+
+    let prefix = target.name.clone();
+    let stderr_thread = thread::spawn(move || forward_output(&prefix, stderr, true));
+    forward_output(&target.name, stdout, false);
+    stderr_thread
+        .join()
+        .map_err(|_| String::from("Reading output of command failed"))?;
+
+    let status = child
+        .wait()
+        .map_err(|error| format!("Failed to wait for command: {}", error))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        match status.code() {
+            Some(code) => Err(format!("Command exited with code {}", code)),
+            None => Err(String::from("Command was terminated by a signal")),
+        }
+    }
+}
+
+/// Runs a single shell command in the target, e.g. a hook. The output is
+/// forwarded just like for `run()`.
+pub fn run_shell(target: &Target, command: &str) -> Result<(), String> {
+    run_single(target, &[command.to_string()])
+        .map_err(|error| format!("\"{}\" failed: {}", command, error))
+}
+
+/// Runs the command in all targets, in up to `jobs` targets at the same time.
+///
+/// The output of the command is forwarded line by line, prefixed with the name
+/// of the target. Returns the result for each target, in the same order as the
+/// targets were given.
+pub fn run(
+    targets: Vec<Target>,
+    command: &[String],
+    jobs: usize,
+) -> Vec<(String, Result<(), String>)> {
+    let command = command.to_vec();
+    parallel::map(targets, jobs, move |target| {
+        let result = run_single(&target, &command);
+        (target.name, result)
+    })
+}
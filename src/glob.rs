@@ -0,0 +1,134 @@
+//! Minimal shell-style glob matching, used for matching worktree and branch
+//! names.
+//!
+//! Supported are `*` (any number of characters except `/`), `**` (any number
+//! of characters, including `/`) and `?` (exactly one character except `/`).
+//! Everything else matches literally.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, name: &str) -> bool {
+        Pattern::new(glob).unwrap().is_match(name)
+    }
+
+    #[test]
+    fn check_is_glob() {
+        assert!(is_glob("feature/*"));
+        assert!(is_glob("fix-?"));
+        assert!(!is_glob("feature/foo"));
+    }
+
+    #[test]
+    fn check_star() {
+        assert!(matches("feature/*", "feature/foo"));
+        assert!(matches("feature/*", "feature/"));
+        assert!(!matches("feature/*", "feature/foo/bar"));
+        assert!(!matches("feature/*", "other/foo"));
+        assert!(matches("*", "main"));
+        assert!(!matches("*", "feature/foo"));
+    }
+
+    #[test]
+    fn check_double_star() {
+        assert!(matches("feature/**", "feature/foo/bar"));
+        assert!(matches("**", "feature/foo"));
+    }
+
+    #[test]
+    fn check_question_mark() {
+        assert!(matches("fix-?", "fix-1"));
+        assert!(!matches("fix-?", "fix-12"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn check_literal() {
+        assert!(matches("release-1.0", "release-1.0"));
+        assert!(!matches("release-1.0", "release-100"));
+        assert!(!matches("main", "main2"));
+        assert_eq!(Pattern::new("release-1.0").unwrap().as_str(), "release-1.0");
+    }
+
+    #[test]
+    fn check_pattern_set() {
+        let patterns = PatternSet::new(&[String::from("main"), String::from("release/*")]).unwrap();
+        assert!(patterns.is_match("main"));
+        assert!(patterns.is_match("release/1.0"));
+        assert!(!patterns.is_match("feature/foo"));
+        assert!(!PatternSet::new(&[]).unwrap().is_match("main"));
+    }
+}
+
+/// Returns whether the given string contains any glob special characters
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(|c| c == '*' || c == '?')
+}
+
+/// Converts a glob into an anchored regular expression
+fn to_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    regex.push_str(".*");
+                } else {
+                    regex.push_str("[^/]*");
+                }
+            }
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// A compiled glob. Compile it once and match it against as many names as
+/// needed.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    glob: String,
+    regex: regex::Regex,
+}
+
+impl Pattern {
+    pub fn new(glob: &str) -> Result<Self, String> {
+        let regex = regex::Regex::new(&to_regex(glob))
+            .map_err(|error| format!("Invalid glob \"{}\": {}", glob, error))?;
+        Ok(Self {
+            glob: glob.to_string(),
+            regex,
+        })
+    }
+
+    /// The glob the pattern was compiled from
+    pub fn as_str(&self) -> &str {
+        &self.glob
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+/// Several globs, compiled into a single matcher
+#[derive(Debug, Clone)]
+pub struct PatternSet(regex::RegexSet);
+
+impl PatternSet {
+    pub fn new(globs: &[String]) -> Result<Self, String> {
+        regex::RegexSet::new(globs.iter().map(|glob| to_regex(glob)))
+            .map(Self)
+            .map_err(|error| format!("Invalid globs {:?}: {}", globs, error))
+    }
+
+    /// Returns whether the name matches at least one of the globs
+    pub fn is_match(&self, name: &str) -> bool {
+        self.0.is_match(name)
+    }
+}
@@ -0,0 +1,137 @@
+//! Searching the files of many repositories at once with `git grep`.
+
+use std::path::Path;
+use std::process::Command;
+
+use super::exec;
+use super::parallel;
+use super::repo;
+use super::worktree;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_format_match() {
+        assert_eq!(
+            format_match("src/main.rs:3:fn main() {", "", None),
+            "src/main.rs:3:fn main() {"
+        );
+        assert_eq!(
+            format_match("src/main.rs:3:fn main() {", "feat/", None),
+            "feat/src/main.rs:3:fn main() {"
+        );
+        assert_eq!(
+            format_match("v1.0:src/main.rs:3:fn main() {", "", Some("v1.0")),
+            "src/main.rs:3:fn main() {"
+        );
+    }
+}
+
+pub struct GrepOptions {
+    pub pattern: String,
+    /// Search the files of this revision instead of the working tree
+    pub rev: Option<String>,
+    pub ignore_case: bool,
+    /// Take the pattern literally instead of as a regular expression
+    pub fixed_strings: bool,
+    /// Only search files matching these pathspecs
+    pub pathspecs: Vec<String>,
+}
+
+/// Turns a line of `git grep -n` output into `path:line:content`, with the
+/// path relative to the repository. git prefixes the path with the revision if
+/// one was searched.
+fn format_match(line: &str, prefix: &str, rev: Option<&str>) -> String {
+    let line = match rev {
+        Some(rev) => line
+            .strip_prefix(rev)
+            .and_then(|line| line.strip_prefix(':'))
+            .unwrap_or(line),
+        None => line,
+    };
+    format!("{}{}", prefix, line)
+}
+
+/// Runs `git grep` in `dir`. `prefix` is prepended to the paths of all
+/// matches, to make them relative to the repository.
+fn grep_dir(dir: &Path, prefix: &str, options: &GrepOptions) -> Result<Vec<String>, String> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(dir)
+        .args(["grep", "-n", "-I", "--no-color"]);
+    if options.ignore_case {
+        cmd.arg("-i");
+    }
+    if options.fixed_strings {
+        cmd.arg("-F");
+    }
+    // Files that were not added yet are part of the working tree as well,
+    // only ignored ones are not
+    if options.rev.is_none() {
+        cmd.arg("--untracked");
+    }
+    cmd.arg("-e").arg(&options.pattern);
+    if let Some(rev) = &options.rev {
+        cmd.arg(rev);
+    }
+    cmd.arg("--").args(&options.pathspecs);
+
+    let output = cmd
+        .output()
+        .map_err(|error| format!("Failed to run git: {}", error))?;
+
+    // git grep exits with 1 if nothing was found
+    match output.status.code() {
+        Some(0) | Some(1) => Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| format_match(line, prefix, options.rev.as_deref()))
+            .collect()),
+        _ => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
+
+/// Searches a single repository. For a worktree setup without a revision,
+/// each worktree is searched.
+fn grep_repo(repo_path: &Path, options: &GrepOptions) -> Result<Vec<String>, String> {
+    if !repo::RepoHandle::detect_worktree(repo_path) {
+        return grep_dir(repo_path, "", options);
+    }
+
+    if options.rev.is_some() {
+        return grep_dir(
+            &repo_path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY),
+            "",
+            options,
+        );
+    }
+
+    let repo = repo::RepoHandle::open(repo_path, true)
+        .map_err(|error| format!("Error opening repository: {}", error))?;
+    let mut matches = Vec::new();
+    for worktree in repo.get_worktrees()? {
+        let name = worktree.name();
+        matches.extend(grep_dir(
+            &repo_path.join(name),
+            &format!("{}/", name),
+            options,
+        )?);
+    }
+    Ok(matches)
+}
+
+/// Searches all targets, in up to `jobs` repositories at the same time.
+/// Returns the matches in each target as `path:line:content`, in the same
+/// order as the targets were given.
+pub fn grep(
+    targets: Vec<exec::Target>,
+    options: GrepOptions,
+    jobs: usize,
+) -> Vec<(String, Result<Vec<String>, String>)> {
+    let options = std::sync::Arc::new(options);
+    parallel::map(targets, jobs, move |target| {
+        let result = grep_repo(&target.path, &options);
+        (target.name, result)
+    })
+}
@@ -1,5 +1,102 @@
 use clap::Parser;
 
+use grm::config;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn check_parse_duration() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("5min"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("6m"), Ok(Duration::from_secs(180 * 86400)));
+        assert_eq!(parse_duration("1y"), Ok(Duration::from_secs(365 * 86400)));
+    }
+
+    #[test]
+    fn check_apply_user_config() {
+        let defaults = config::UserConfig {
+            config: Some(String::from("/projects/config.toml")),
+            color: Some(String::from("never")),
+            jobs: Some(8),
+            token_command: Some(String::from("echo token")),
+        };
+        let parse = |args: &[&str]| {
+            let matches = apply_user_config(<Opts as clap::CommandFactory>::command(), &defaults)
+                .try_get_matches_from(args)
+                .unwrap();
+            <Opts as clap::FromArgMatches>::from_arg_matches(&matches).unwrap()
+        };
+
+        let opts = parse(&["grm", "repos", "exec", "--", "true"]);
+        assert!(matches!(opts.color, Color::Never));
+        match opts.subcmd {
+            SubCommand::Repos(Repos {
+                action: ReposAction::Exec(args),
+            }) => {
+                assert_eq!(args.config, "/projects/config.toml");
+                assert_eq!(args.jobs, 8);
+            }
+            _ => panic!("wrong subcommand"),
+        }
+
+        let opts = parse(&[
+            "grm", "repos", "exec", "-c", "my.toml", "-j", "2", "--", "true",
+        ]);
+        match opts.subcmd {
+            SubCommand::Repos(Repos {
+                action: ReposAction::Exec(args),
+            }) => {
+                assert_eq!(args.config, "my.toml");
+                assert_eq!(args.jobs, 2);
+            }
+            _ => panic!("wrong subcommand"),
+        }
+
+        // Without --config, status shows the repository in the current
+        // directory, so it must not get the default
+        let opts = parse(&["grm", "repos", "status"]);
+        match opts.subcmd {
+            SubCommand::Repos(Repos {
+                action: ReposAction::Status(args),
+            }) => assert!(args.config.is_none()),
+            _ => panic!("wrong subcommand"),
+        }
+
+        let opts = parse(&[
+            "grm",
+            "repos",
+            "find",
+            "remote",
+            "--provider",
+            "github",
+            "--root",
+            "/projects",
+        ]);
+        match opts.subcmd {
+            SubCommand::Repos(Repos {
+                action: ReposAction::Find(FindAction::Remote(args)),
+            }) => assert_eq!(args.token_command, "echo token"),
+            _ => panic!("wrong subcommand"),
+        }
+    }
+
+    #[test]
+    fn check_parse_invalid_duration() {
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("-3d").is_err());
+        assert!(parse_duration("3x").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("99999999999999999y").is_err());
+    }
+}
+
 #[derive(Parser)]
 #[clap(
     name = clap::crate_name!(),
@@ -12,6 +109,118 @@ use clap::Parser;
 pub struct Opts {
     #[clap(subcommand)]
     pub subcmd: SubCommand,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        help = "Print more details. Give twice to print every step"
+    )]
+    pub verbose: u8,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        help = "Only print errors"
+    )]
+    pub quiet: bool,
+
+    #[clap(
+        value_enum,
+        long,
+        global = true,
+        help = "When to use colors. \"auto\" respects NO_COLOR",
+        default_value_t = Color::Auto,
+    )]
+    pub color: Color,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Prefix every message with the current time"
+    )]
+    pub timestamps: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Also append all messages to this file"
+    )]
+    pub log_file: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Retry failed network operations (clone, fetch, forge API calls) this many times"
+    )]
+    pub retries: u32,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "SECONDS",
+        default_value_t = 1.0,
+        help = "Seconds to wait before the first retry. The delay doubles with every retry"
+    )]
+    pub retry_delay: f64,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Resolve relative tree roots against the current directory instead of the directory of the configuration file"
+    )]
+    pub roots_relative_to_cwd: bool,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        long = "profile",
+        global = true,
+        env = "GRM_PROFILE",
+        value_delimiter = ',',
+        value_name = "PROFILE",
+        help = "Only use the trees of this profile. Can be given multiple times"
+    )]
+    pub profiles: Vec<String>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Download the repository lists of forges again, instead of checking whether the cached ones are still current"
+    )]
+    pub refresh: bool,
+
+    #[clap(
+        long,
+        global = true,
+        env = "GRM_PROXY",
+        value_name = "URL",
+        help = "Proxy for clones, fetches and forge API calls. By default, the proxy is taken from the git configuration and the usual environment variables"
+    )]
+    pub proxy: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        env = "GRM_CONNECT_TIMEOUT",
+        value_name = "SECONDS",
+        help = "How long to wait for a connection to a forge"
+    )]
+    pub connect_timeout: Option<f64>,
+
+    #[clap(
+        long,
+        global = true,
+        env = "GRM_TIMEOUT",
+        value_name = "SECONDS",
+        help = "How long a single clone, fetch or forge API call may take"
+    )]
+    pub timeout: Option<f64>,
 }
 
 #[derive(Parser)]
@@ -20,6 +229,56 @@ pub enum SubCommand {
     Repos(Repos),
     #[clap(visible_alias = "wt", about = "Manage worktrees")]
     Worktree(Worktree),
+    #[clap(about = "Print shell functions to change into worktrees")]
+    ShellInit(ShellInitArgs),
+    #[clap(about = "Print the completion script for a shell")]
+    Completions(CompletionsArgs),
+    #[clap(hide = true)]
+    Complete(CompleteArgs),
+    #[clap(about = "Write man pages for grm and all its subcommands")]
+    Mangen(MangenArgs),
+}
+
+#[derive(Parser)]
+pub struct MangenArgs {
+    #[clap(help = "Directory to write the man pages to. Created if it does not exist")]
+    pub directory: String,
+}
+
+#[derive(Parser)]
+pub struct CompletionsArgs {
+    #[clap(value_enum, help = "The shell to print the completion script for")]
+    pub shell: CompletionShell,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+#[derive(Parser)]
+pub struct CompleteArgs {
+    #[clap(
+        allow_hyphen_values = true,
+        help = "The words of the command line before the one to complete"
+    )]
+    pub words: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct ShellInitArgs {
+    #[clap(value_enum, help = "The shell to print the functions for")]
+    pub shell: Shell,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
 }
 
 #[derive(Parser)]
@@ -34,8 +293,42 @@ pub enum ReposAction {
     Sync(SyncAction),
     #[clap(subcommand)]
     Find(FindAction),
+    #[clap(subcommand)]
+    Config(ConfigAction),
     #[clap(about = "Show status of configured repositories")]
-    Status(OptionalConfig),
+    Status(ReposStatusArgs),
+    #[clap(about = "Clone a repository and add it to the configuration")]
+    Add(ReposAddArgs),
+    #[clap(about = "Remove a repository from the configuration")]
+    Remove(ReposRemoveArgs),
+    #[clap(about = "List configured repositories")]
+    List(ReposListArgs),
+    #[clap(about = "Print the path of a configured repository")]
+    Path(ReposPathArgs),
+    #[clap(about = "Convert the configuration of another tool")]
+    Import(ReposImportArgs),
+    #[clap(about = "Run a command in every configured repository")]
+    Exec(ReposExecArgs),
+    #[clap(about = "Record the checked out branch and commit of all repositories")]
+    Snapshot(ReposSnapshotArgs),
+    #[clap(about = "Check out the state recorded in a snapshot")]
+    Restore(ReposRestoreArgs),
+    #[clap(about = "Show the differences between the configuration and the repositories on disk")]
+    Diff(ReposDiffArgs),
+    #[clap(about = "Run garbage collection in all configured repositories")]
+    Gc(ReposGcArgs),
+    #[clap(about = "Check the integrity of all configured repositories")]
+    Fsck(ReposFsckArgs),
+    #[clap(about = "Show the disk usage of all configured repositories")]
+    Du(ReposDuArgs),
+    #[clap(about = "Move repositories that were not used for a while into an archive")]
+    Archive(ReposArchiveArgs),
+    #[clap(about = "Search the files of all configured repositories")]
+    Grep(ReposGrepArgs),
+    #[clap(about = "Delete local branches that are merged into the default branch")]
+    BranchClean(ReposBranchCleanArgs),
+    #[clap(about = "Check the environment for common problems")]
+    Doctor(ReposDoctorArgs),
 }
 
 #[derive(Parser)]
@@ -44,7 +337,36 @@ pub enum SyncAction {
     #[clap(about = "Synchronize the repositories to the configured values")]
     Config(Config),
     #[clap(about = "Synchronize the repositories from a remote provider")]
-    Remote(SyncRemoteArgs),
+    Remote(Box<SyncRemoteArgs>),
+}
+
+#[derive(Parser)]
+#[clap(about = "Work with the configuration file")]
+pub enum ConfigAction {
+    #[clap(about = "Convert the configuration to another format")]
+    Convert(ConfigConvertArgs),
+}
+
+#[derive(Parser)]
+pub struct ConfigConvertArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(value_enum, long, help = "Format to convert the configuration to")]
+    pub to: ConvertFormat,
+
+    #[clap(
+        short,
+        long,
+        help = "Write the converted configuration to this file instead of printing it"
+    )]
+    pub output: Option<String>,
 }
 
 #[derive(Parser)]
@@ -53,7 +375,7 @@ pub enum FindAction {
     #[clap(about = "Find local repositories")]
     Local(FindLocalArgs),
     #[clap(about = "Find repositories on remote provider")]
-    Remote(FindRemoteArgs),
+    Remote(Box<FindRemoteArgs>),
     #[clap(about = "Find repositories as defined in the configuration file")]
     Config(FindConfigArgs),
 }
@@ -71,6 +393,31 @@ pub struct FindLocalArgs {
     )]
     pub exclude: Option<String>,
 
+    #[clap(
+        long,
+        help = "Do not search directories that match the given glob. Can be given multiple times",
+        name = "GLOB"
+    )]
+    pub exclude_dir: Vec<String>,
+
+    #[clap(long, help = "Search at most this many directory levels deep")]
+    pub max_depth: Option<usize>,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = super::tree::DEFAULT_SEARCH_JOBS,
+        help = "Number of directories to search at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(
+        long,
+        help = "Add new repositories to this configuration file instead of printing them",
+        name = "CONFIG"
+    )]
+    pub merge_into: Option<String>,
+
     #[clap(
         value_enum,
         short,
@@ -79,6 +426,9 @@ pub struct FindLocalArgs {
         default_value_t = ConfigFormat::Toml,
     )]
     pub format: ConfigFormat,
+
+    #[clap(long, help = "Group repositories by the host of their first remote")]
+    pub group_by_host: bool,
 }
 
 #[derive(Parser)]
@@ -86,11 +436,19 @@ pub struct FindConfigArgs {
     #[clap(
         short,
         long,
-        default_value = "./config.toml",
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
         help = "Path to the configuration file"
     )]
     pub config: String,
 
+    #[clap(
+        long,
+        help = "Add new repositories to this configuration file instead of printing them",
+        name = "CONFIG"
+    )]
+    pub merge_into: Option<String>,
+
     #[clap(
         value_enum,
         short,
@@ -99,6 +457,9 @@ pub struct FindConfigArgs {
         default_value_t = ConfigFormat::Toml,
     )]
     pub format: ConfigFormat,
+
+    #[clap(long, help = "Group repositories by the host of their first remote")]
+    pub group_by_host: bool,
 }
 
 #[derive(Parser)]
@@ -135,6 +496,40 @@ pub struct FindRemoteArgs {
     #[clap(long, help = "Get repositories that the requesting user has access to")]
     pub access: bool,
 
+    #[clap(long, help = "Skip archived repositories")]
+    pub exclude_archived: bool,
+
+    #[clap(long, help = "Skip repositories that are forks")]
+    pub exclude_forks: bool,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        long,
+        help = "Skip repositories whose name matches this pattern"
+    )]
+    pub exclude_name: Vec<String>,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        long,
+        help = "Skip repositories with this topic"
+    )]
+    pub exclude_topic: Vec<String>,
+
+    #[clap(
+        long,
+        default_value = super::provider::DEFAULT_LAYOUT,
+        help = "Where to put repositories below the root, using the placeholders {host}, {owner} and {name}"
+    )]
+    pub layout: String,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        long,
+        help = "Remove this prefix from namespaces before they are used as {owner}"
+    )]
+    pub strip_owner_prefix: Vec<String>,
+
     #[clap(long, help = "Always use SSH, even for public repositories")]
     pub force_ssh: bool,
 
@@ -144,6 +539,13 @@ pub struct FindRemoteArgs {
     #[clap(long, help = "Root of the repo tree to produce")]
     pub root: String,
 
+    #[clap(
+        long,
+        help = "Add new repositories to this configuration file instead of printing them",
+        name = "CONFIG"
+    )]
+    pub merge_into: Option<String>,
+
     #[clap(
         value_enum,
         short,
@@ -153,6 +555,9 @@ pub struct FindRemoteArgs {
     )]
     pub format: ConfigFormat,
 
+    #[clap(long, help = "Group repositories by the host of their first remote")]
+    pub group_by_host: bool,
+
     #[clap(
         long,
         help = "Use worktree setup for repositories",
@@ -165,6 +570,45 @@ pub struct FindRemoteArgs {
 
     #[clap(long, help = "Base URL for the API")]
     pub api_url: Option<String>,
+
+    #[clap(long, help = "File with the CA certificates to verify the forge with")]
+    pub ca_bundle: Option<String>,
+
+    #[clap(long, help = "Do not verify the TLS certificate of the forge")]
+    pub insecure_skip_verify: bool,
+}
+
+impl FindRemoteArgs {
+    /// The provider configuration that the arguments describe, to get the
+    /// repositories the same way as for a configuration file
+    pub fn provider_config(&self) -> config::ConfigProvider {
+        config::ConfigProvider {
+            name: None,
+            provider: self.provider.clone(),
+            token_command: self.token_command.clone(),
+            root: self.root.clone(),
+            force_ssh: Some(self.force_ssh),
+            api_url: self.api_url.clone(),
+            ca_bundle: self.ca_bundle.clone(),
+            insecure_skip_verify: Some(self.insecure_skip_verify),
+            worktree: Some(self.worktree == "true"),
+            remote_name: self.remote_name.clone(),
+            layout: Some(self.layout.clone()),
+            strip_owner_prefixes: Some(self.strip_owner_prefix.clone()),
+            unmanaged: None,
+            filters: Some(config::ConfigProviderFilter {
+                access: Some(self.access),
+                owner: Some(self.owner),
+                users: Some(self.users.clone()),
+                groups: Some(self.groups.clone()),
+                exclude_archived: Some(self.exclude_archived),
+                exclude_forks: Some(self.exclude_forks),
+                exclude_names: Some(self.exclude_name.clone()),
+                exclude_topics: Some(self.exclude_topic.clone()),
+            }),
+            fetch: None,
+        }
+    }
 }
 
 #[derive(Parser)]
@@ -173,7 +617,8 @@ pub struct Config {
     #[clap(
         short,
         long,
-        default_value = "./config.toml",
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
         help = "Path to the configuration file"
     )]
     pub config: String,
@@ -187,99 +632,716 @@ pub struct Config {
         num_args = 0..=1,
     )]
     pub init_worktree: String,
-}
 
-pub type RemoteProvider = super::provider::RemoteProvider;
+    #[clap(
+        long,
+        help = "Create shallow clones with the given number of commits, overriding the configuration"
+    )]
+    pub depth: Option<u32>,
 
-#[derive(Parser)]
-#[clap()]
-pub struct SyncRemoteArgs {
-    #[clap(value_enum, short, long, help = "Remote provider to use")]
-    pub provider: RemoteProvider,
+    #[clap(long, help = "Fetch all remotes of existing repositories")]
+    pub fetch: bool,
 
-    #[clap(short, long, help = "Name of the remote to use")]
-    pub remote_name: Option<String>,
+    #[clap(
+        long,
+        requires = "fetch",
+        help = "Remove remote tracking branches that were deleted on the remote"
+    )]
+    pub prune: bool,
+
+    #[clap(long, requires = "fetch", help = "Fetch all tags")]
+    pub tags: bool,
 
     #[clap(
-        action = clap::ArgAction::Append,
-        name = "user",
         long,
-        help = "Users to get repositories from"
+        help = "Only check whether the repositories match the configuration, without changing anything"
     )]
-    pub users: Vec<String>,
+    pub check: bool,
 
     #[clap(
-        action = clap::ArgAction::Append,
-        name = "group",
         long,
-        help = "Groups to get repositories from"
+        overrides_with = "no_wait",
+        help = "If another grm process is syncing a tree, wait for it to finish (default)"
     )]
-    pub groups: Vec<String>,
+    pub wait: bool,
 
-    #[clap(long, help = "Get repositories that belong to the requesting user")]
-    pub owner: bool,
+    #[clap(
+        long,
+        overrides_with = "wait",
+        help = "If another grm process is syncing a tree, fail instead of waiting"
+    )]
+    pub no_wait: bool,
 
-    #[clap(long, help = "Get repositories that the requesting user has access to")]
-    pub access: bool,
+    #[clap(
+        long,
+        help = "Stop at the first error instead of syncing the remaining repos"
+    )]
+    pub fail_fast: bool,
+}
 
-    #[clap(long, help = "Always use SSH, even for public repositories")]
-    pub force_ssh: bool,
+#[derive(Parser)]
+pub struct ReposAddArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
 
-    #[clap(long, help = "Command to get API token")]
-    pub token_command: String,
+    #[clap(help = "URL of the repository")]
+    pub url: String,
 
-    #[clap(long, help = "Root of the repo tree to produce")]
-    pub root: String,
+    #[clap(long, help = "Name of the repository. Taken from the URL by default")]
+    pub name: Option<String>,
 
     #[clap(
         long,
-        help = "Use worktree setup for repositories",
-        value_parser = ["true", "false"],
-        default_value = "false",
-        default_missing_value = "true",
-        num_args = 0..=1,
+        conflicts_with = "name",
+        help = "Name the repository like ghq does, i.e. <host>/<owner>/<repo>"
     )]
-    pub worktree: String,
-
-    #[clap(long, help = "Base URL for the API")]
-    pub api_url: Option<String>,
+    pub ghq: bool,
 
     #[clap(
         long,
-        help = "Check out the default worktree after clone",
-        value_parser = ["true", "false"],
-        default_value = "true",
-        default_missing_value = "true",
-        num_args = 0..=1,
+        help = "Root of the tree to add the repository to. Required if there are several trees"
     )]
-    pub init_worktree: String,
+    pub tree: Option<String>,
 }
 
 #[derive(Parser)]
-#[clap()]
-pub struct OptionalConfig {
-    #[clap(short, long, help = "Path to the configuration file")]
-    pub config: Option<String>,
-}
+pub struct ReposRemoveArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
 
-#[derive(clap::ValueEnum, Clone)]
-pub enum ConfigFormat {
-    Yaml,
-    Toml,
+    #[clap(help = "Name of the repository")]
+    pub name: String,
+
+    #[clap(
+        long,
+        help = "Root of the tree the repository is part of. Required if several trees contain it"
+    )]
+    pub tree: Option<String>,
+
+    #[clap(
+        long,
+        help = "Also delete the repository from disk. Refuses if there are uncommitted/unpushed changes"
+    )]
+    pub delete_files: bool,
 }
 
 #[derive(Parser)]
-pub struct Worktree {
-    #[clap(subcommand, name = "action")]
-    pub action: WorktreeAction,
+pub struct ReposListArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(
+        long,
+        default_value = "{name}",
+        help = "Template for each line, e.g. \"{name} {path} {remotes[0].url}\". Use \"json\" or \"csv\" for structured output"
+    )]
+    pub format: String,
 }
 
 #[derive(Parser)]
-pub enum WorktreeAction {
-    #[clap(about = "Add a new worktree")]
-    Add(WorktreeAddArgs),
-    #[clap(about = "Add an existing worktree")]
-    Delete(WorktreeDeleteArgs),
+pub struct ReposPathArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(help = "Name of the repository")]
+    pub name: String,
+
+    #[clap(
+        long,
+        help = "Root of the tree the repository is part of. Required if several trees contain it"
+    )]
+    pub tree: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ReposImportArgs {
+    #[clap(value_enum, long, help = "The tool the configuration belongs to")]
+    pub from: ImportSource,
+
+    #[clap(
+        help = "Path to the configuration to import. Defaults to ~/.mrconfig for mr, the ghq root for ghq, $XDG_CONFIG_HOME/gita/repos.csv for gita and $XDG_CONFIG_HOME/vcsh/repo.d for vcsh"
+    )]
+    pub path: Option<String>,
+
+    #[clap(
+        long,
+        help = "Add the repositories to this configuration file instead of printing them",
+        name = "CONFIG"
+    )]
+    pub merge_into: Option<String>,
+
+    #[clap(
+        value_enum,
+        short,
+        long,
+        help = "Format to produce",
+        default_value_t = ConfigFormat::Toml,
+    )]
+    pub format: ConfigFormat,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+pub enum ImportSource {
+    Mr,
+    Ghq,
+    Gita,
+    Vcsh,
+}
+
+#[derive(Parser)]
+pub struct ReposExecArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(long, help = "Only use repositories of the tree with this root")]
+    pub tree: Option<String>,
+
+    #[clap(long, help = "Only use repositories with this tag")]
+    pub tag: Option<String>,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 4,
+        help = "Number of repositories to run the command in at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(
+        required = true,
+        last = true,
+        help = "Command to run. A single argument is run through the shell"
+    )]
+    pub command: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct ReposDiffArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(long, help = "Exit with 1 if there are any differences")]
+    pub exit_code: bool,
+}
+
+#[derive(Parser)]
+pub struct ReposGcArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(long, help = "Only use repositories of the tree with this root")]
+    pub tree: Option<String>,
+
+    #[clap(long, help = "Only use repositories with this tag")]
+    pub tag: Option<String>,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 4,
+        help = "Number of repositories to collect garbage in at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(long, help = "Optimize more thoroughly, which takes much longer")]
+    pub aggressive: bool,
+
+    #[clap(
+        long,
+        help = "Register the repositories for the background maintenance of git"
+    )]
+    pub maintenance: bool,
+}
+
+#[derive(Parser)]
+pub struct ReposFsckArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(long, help = "Only use repositories of the tree with this root")]
+    pub tree: Option<String>,
+
+    #[clap(long, help = "Only use repositories with this tag")]
+    pub tag: Option<String>,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 4,
+        help = "Number of repositories to check at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(
+        long,
+        help = "Clone corrupt repositories again from their remotes, keeping the old ones"
+    )]
+    pub repair: bool,
+}
+
+#[derive(Parser)]
+pub struct ReposDuArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(long, help = "Only use repositories of the tree with this root")]
+    pub tree: Option<String>,
+
+    #[clap(long, help = "Only use repositories with this tag")]
+    pub tag: Option<String>,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 4,
+        help = "Number of repositories to measure at the same time"
+    )]
+    pub jobs: usize,
+}
+
+#[derive(Parser)]
+pub struct ReposArchiveArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(
+        long = "inactive-for",
+        value_parser = parse_duration,
+        help = "Archive repositories that were not used for the given duration (e.g. 30d, 6m, 1y)",
+        name = "DURATION"
+    )]
+    pub inactive_for: std::time::Duration,
+
+    #[clap(long, help = "Only show which repositories would be archived")]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct ReposGrepArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(long, help = "Only use repositories of the tree with this root")]
+    pub tree: Option<String>,
+
+    #[clap(long, help = "Only use repositories with this tag")]
+    pub tag: Option<String>,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 4,
+        help = "Number of repositories to search at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(
+        long = "ref",
+        help = "Search the files of this branch, tag or commit instead of the working tree"
+    )]
+    pub rev: Option<String>,
+
+    #[clap(short = 'i', long, help = "Ignore case")]
+    pub ignore_case: bool,
+
+    #[clap(
+        short = 'F',
+        long,
+        help = "Take the pattern literally instead of as a regular expression"
+    )]
+    pub fixed_strings: bool,
+
+    #[clap(help = "The pattern to search for")]
+    pub pattern: String,
+
+    #[clap(last = true, help = "Only search files matching these pathspecs")]
+    pub pathspecs: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct ReposBranchCleanArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(long, help = "Only use repositories of the tree with this root")]
+    pub tree: Option<String>,
+
+    #[clap(long, help = "Only use repositories with this tag")]
+    pub tag: Option<String>,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 4,
+        help = "Number of repositories to clean up at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(long, help = "Only show which branches would be deleted")]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct ReposDoctorArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+}
+
+#[derive(Parser)]
+pub struct ReposSnapshotArgs {
+    #[clap(
+        short,
+        long,
+        env = "GRM_CONFIG",
+        default_value = DEFAULT_CONFIG,
+        help = "Path to the configuration file"
+    )]
+    pub config: String,
+
+    #[clap(
+        short,
+        long,
+        help = "File to write the snapshot to instead of standard output"
+    )]
+    pub output: Option<String>,
+
+    #[clap(
+        value_enum,
+        short,
+        long,
+        help = "Format to produce",
+        default_value_t = ConfigFormat::Toml,
+    )]
+    pub format: ConfigFormat,
+}
+
+#[derive(Parser)]
+pub struct ReposRestoreArgs {
+    #[clap(help = "Path to the snapshot to restore")]
+    pub lockfile: String,
+}
+
+pub type RemoteProvider = super::provider::RemoteProvider;
+
+#[derive(Parser)]
+#[clap()]
+pub struct SyncRemoteArgs {
+    #[clap(value_enum, short, long, help = "Remote provider to use")]
+    pub provider: RemoteProvider,
+
+    #[clap(short, long, help = "Name of the remote to use")]
+    pub remote_name: Option<String>,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        name = "user",
+        long,
+        help = "Users to get repositories from"
+    )]
+    pub users: Vec<String>,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        name = "group",
+        long,
+        help = "Groups to get repositories from"
+    )]
+    pub groups: Vec<String>,
+
+    #[clap(long, help = "Get repositories that belong to the requesting user")]
+    pub owner: bool,
+
+    #[clap(long, help = "Get repositories that the requesting user has access to")]
+    pub access: bool,
+
+    #[clap(long, help = "Skip archived repositories")]
+    pub exclude_archived: bool,
+
+    #[clap(long, help = "Skip repositories that are forks")]
+    pub exclude_forks: bool,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        long,
+        help = "Skip repositories whose name matches this pattern"
+    )]
+    pub exclude_name: Vec<String>,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        long,
+        help = "Skip repositories with this topic"
+    )]
+    pub exclude_topic: Vec<String>,
+
+    #[clap(
+        long,
+        default_value = super::provider::DEFAULT_LAYOUT,
+        help = "Where to put repositories below the root, using the placeholders {host}, {owner} and {name}"
+    )]
+    pub layout: String,
+
+    #[clap(
+        action = clap::ArgAction::Append,
+        long,
+        help = "Remove this prefix from namespaces before they are used as {owner}"
+    )]
+    pub strip_owner_prefix: Vec<String>,
+
+    #[clap(long, help = "Always use SSH, even for public repositories")]
+    pub force_ssh: bool,
+
+    #[clap(long, help = "Command to get API token")]
+    pub token_command: String,
+
+    #[clap(long, help = "Root of the repo tree to produce")]
+    pub root: String,
+
+    #[clap(
+        long,
+        help = "Use worktree setup for repositories",
+        value_parser = ["true", "false"],
+        default_value = "false",
+        default_missing_value = "true",
+        num_args = 0..=1,
+    )]
+    pub worktree: String,
+
+    #[clap(long, help = "Base URL for the API")]
+    pub api_url: Option<String>,
+
+    #[clap(long, help = "File with the CA certificates to verify the forge with")]
+    pub ca_bundle: Option<String>,
+
+    #[clap(long, help = "Do not verify the TLS certificate of the forge")]
+    pub insecure_skip_verify: bool,
+
+    #[clap(
+        long,
+        help = "Check out the default worktree after clone",
+        value_parser = ["true", "false"],
+        default_value = "true",
+        default_missing_value = "true",
+        num_args = 0..=1,
+    )]
+    pub init_worktree: String,
+
+    #[clap(
+        long,
+        help = "Create shallow clones with the given number of commits, overriding the configuration"
+    )]
+    pub depth: Option<u32>,
+
+    #[clap(long, help = "Fetch all remotes of existing repositories")]
+    pub fetch: bool,
+
+    #[clap(
+        long,
+        requires = "fetch",
+        help = "Remove remote tracking branches that were deleted on the remote"
+    )]
+    pub prune: bool,
+
+    #[clap(long, requires = "fetch", help = "Fetch all tags")]
+    pub tags: bool,
+
+    #[clap(
+        long,
+        help = "Only check whether the repositories match the configuration, without changing anything"
+    )]
+    pub check: bool,
+
+    #[clap(
+        long,
+        overrides_with = "no_wait",
+        help = "If another grm process is syncing a tree, wait for it to finish (default)"
+    )]
+    pub wait: bool,
+
+    #[clap(
+        long,
+        overrides_with = "wait",
+        help = "If another grm process is syncing a tree, fail instead of waiting"
+    )]
+    pub no_wait: bool,
+
+    #[clap(
+        long,
+        help = "Stop at the first error instead of syncing the remaining repos"
+    )]
+    pub fail_fast: bool,
+}
+
+impl SyncRemoteArgs {
+    /// The provider configuration that the arguments describe, to get the
+    /// repositories the same way as for a configuration file
+    pub fn provider_config(&self) -> config::ConfigProvider {
+        config::ConfigProvider {
+            name: None,
+            provider: self.provider.clone(),
+            token_command: self.token_command.clone(),
+            root: self.root.clone(),
+            force_ssh: Some(self.force_ssh),
+            api_url: self.api_url.clone(),
+            ca_bundle: self.ca_bundle.clone(),
+            insecure_skip_verify: Some(self.insecure_skip_verify),
+            worktree: Some(self.worktree == "true"),
+            remote_name: self.remote_name.clone(),
+            layout: Some(self.layout.clone()),
+            strip_owner_prefixes: Some(self.strip_owner_prefix.clone()),
+            unmanaged: None,
+            filters: Some(config::ConfigProviderFilter {
+                access: Some(self.access),
+                owner: Some(self.owner),
+                users: Some(self.users.clone()),
+                groups: Some(self.groups.clone()),
+                exclude_archived: Some(self.exclude_archived),
+                exclude_forks: Some(self.exclude_forks),
+                exclude_names: Some(self.exclude_name.clone()),
+                exclude_topics: Some(self.exclude_topic.clone()),
+            }),
+            fetch: None,
+        }
+    }
+}
+
+#[derive(Parser)]
+#[clap()]
+pub struct ReposStatusArgs {
+    #[clap(short, long, help = "Path to the configuration file")]
+    pub config: Option<String>,
+
+    #[clap(long, help = "Fetch all remotes before showing the status")]
+    pub fetch: bool,
+
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 4,
+        help = "Number of repositories to fetch at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(
+        short,
+        long,
+        requires = "config",
+        conflicts_with = "fetch",
+        help = "Browse the repositories in an interactive dashboard"
+    )]
+    pub interactive: bool,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+pub enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+pub enum ConvertFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+#[derive(Parser)]
+pub struct Worktree {
+    #[clap(subcommand, name = "action")]
+    pub action: WorktreeAction,
+}
+
+#[derive(Parser)]
+pub enum WorktreeAction {
+    #[clap(about = "Add a new worktree")]
+    Add(WorktreeAddArgs),
+    #[clap(about = "Add an existing worktree")]
+    Delete(WorktreeDeleteArgs),
     #[clap(about = "Show state of existing worktrees")]
     Status(WorktreeStatusArgs),
     #[clap(about = "Convert a normal repository to a worktree setup")]
@@ -290,24 +1352,61 @@ pub enum WorktreeAction {
     Fetch(WorktreeFetchArgs),
     #[clap(about = "Fetch refs from remotes and update local branches")]
     Pull(WorktreePullArgs),
-    #[clap(about = "Rebase worktree onto default branch")]
+    #[clap(about = "Rebase worktrees onto the default branch, or another one")]
     Rebase(WorktreeRebaseArgs),
+    #[clap(about = "Run a command in every worktree")]
+    Exec(WorktreeExecArgs),
+    #[clap(about = "Print the path of a worktree")]
+    Path(WorktreePathArgs),
+}
+
+#[derive(Parser)]
+pub struct WorktreePathArgs {
+    #[clap(help = "Name of the worktree or its branch. Without it, the root is printed")]
+    pub name: Option<String>,
+
+    #[clap(
+        long,
+        help = "Also accept a part of the name, as long as only one worktree matches"
+    )]
+    pub fuzzy: bool,
 }
 
 #[derive(Parser)]
 pub struct WorktreeAddArgs {
-    #[clap(help = "Name of the worktree")]
-    pub name: String,
+    #[clap(help = "Name of the worktree", required_unless_present_any = ["pr", "mr"])]
+    pub name: Option<String>,
+
+    #[clap(
+        long = "pr",
+        help = "Check out a GitHub pull request",
+        conflicts_with_all = ["name", "mr", "track", "no_track", "track_auto"]
+    )]
+    pub pr: Option<u64>,
+
+    #[clap(
+        long = "mr",
+        help = "Check out a GitLab merge request",
+        conflicts_with_all = ["name", "track", "no_track", "track_auto"]
+    )]
+    pub mr: Option<u64>,
 
     #[clap(short = 't', long = "track", help = "Remote branch to track")]
     pub track: Option<String>,
 
     #[clap(long = "no-track", help = "Disable tracking")]
     pub no_track: bool,
+
+    #[clap(
+        long = "track-auto",
+        help = "Track the branch of the same name if it exists on a remote",
+        conflicts_with = "no_track"
+    )]
+    pub track_auto: bool,
 }
 #[derive(Parser)]
 pub struct WorktreeDeleteArgs {
-    #[clap(help = "Name of the worktree")]
+    #[clap(help = "Name of the worktree, or a glob like \"feature/*\" to delete several")]
     pub name: String,
 
     #[clap(
@@ -318,16 +1417,73 @@ pub struct WorktreeDeleteArgs {
 }
 
 #[derive(Parser)]
-pub struct WorktreeStatusArgs {}
+pub struct WorktreeStatusArgs {
+    #[clap(
+        value_enum,
+        short,
+        long,
+        help = "Output format",
+        default_value_t = StatusOutput::Table,
+    )]
+    pub output: StatusOutput,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+pub enum StatusOutput {
+    Table,
+    Json,
+}
 
 #[derive(Parser)]
-pub struct WorktreeConvertArgs {}
+pub struct WorktreeConvertArgs {
+    #[clap(
+        long = "to-normal",
+        help = "Convert a worktree setup back to a normal repository"
+    )]
+    pub to_normal: bool,
+
+    #[clap(
+        long = "branch",
+        requires = "to_normal",
+        help = "Branch to check out when converting to a normal repository (default: the default branch)"
+    )]
+    pub branch: Option<String>,
+}
 
 #[derive(Parser)]
-pub struct WorktreeCleanArgs {}
+pub struct WorktreeCleanArgs {
+    #[clap(
+        long = "dry-run",
+        help = "Only show which worktrees would be removed, and why others are kept"
+    )]
+    pub dry_run: bool,
+
+    #[clap(
+        short = 'i',
+        long = "interactive",
+        help = "Ask for confirmation before removing each worktree"
+    )]
+    pub interactive: bool,
+
+    #[clap(
+        long = "older-than",
+        value_parser = parse_duration,
+        help = "Only remove worktrees that were not used for the given duration (e.g. 12h, 30d, 2w)",
+        name = "DURATION"
+    )]
+    pub older_than: Option<std::time::Duration>,
+}
 
 #[derive(Parser)]
-pub struct WorktreeFetchArgs {}
+pub struct WorktreeFetchArgs {
+    #[clap(
+        long = "prune",
+        help = "Remove remote tracking branches that were deleted on the remote"
+    )]
+    pub prune: bool,
+    #[clap(long = "tags", help = "Fetch all tags")]
+    pub tags: bool,
+}
 
 #[derive(Parser)]
 pub struct WorktreePullArgs {
@@ -335,6 +1491,11 @@ pub struct WorktreePullArgs {
     pub rebase: bool,
     #[clap(long = "stash", help = "Stash & unstash changes before & after pull")]
     pub stash: bool,
+    #[clap(
+        long = "all",
+        help = "Update all worktrees, also when run inside of a single worktree"
+    )]
+    pub all: bool,
 }
 
 #[derive(Parser)]
@@ -345,8 +1506,129 @@ pub struct WorktreeRebaseArgs {
     pub rebase: bool,
     #[clap(long = "stash", help = "Stash & unstash changes before & after rebase")]
     pub stash: bool,
+    #[clap(
+        long = "onto",
+        help = "Branch (or any other revision) to rebase onto instead of the default branch"
+    )]
+    pub onto: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct WorktreeExecArgs {
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 1,
+        help = "Number of worktrees to run the command in at the same time"
+    )]
+    pub jobs: usize,
+
+    #[clap(
+        required = true,
+        last = true,
+        help = "Command to run. A single argument is run through the shell"
+    )]
+    pub command: Vec<String>,
+}
+
+/// Parses durations like "90s", "12h", "30d", "2w", "6m" or "1y". A month is
+/// always 30 days and a year always 365 days, as there is no need to be exact
+/// here.
+pub fn parse_duration(input: &str) -> Result<std::time::Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("Missing unit in duration \"{}\"", input))?;
+
+    let (value, unit) = input.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("Invalid duration \"{}\"", input))?;
+
+    let seconds = match unit {
+        "s" => 1,
+        "min" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        "m" => 60 * 60 * 24 * 30,
+        "y" => 60 * 60 * 24 * 365,
+        _ => {
+            return Err(format!(
+                "Invalid unit \"{}\" in duration, use one of s, min, h, d, w, m, y",
+                unit
+            ))
+        }
+    };
+
+    let seconds = value
+        .checked_mul(seconds)
+        .ok_or_else(|| format!("Duration \"{}\" is too long", input))?;
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+/// The default of all `--config` options that are required to read the
+/// configuration of repositories
+const DEFAULT_CONFIG: &str = "./config.toml";
+
+/// Replaces the defaults of the options of `cmd` and all its subcommands with
+/// the ones from the configuration of the user
+fn apply_user_config(mut cmd: clap::Command, defaults: &config::UserConfig) -> clap::Command {
+    // With `default`, the option only counts if it has that default value
+    let has_option = |cmd: &clap::Command, id: &str, default: Option<&str>| {
+        cmd.get_arguments().any(|arg| {
+            arg.get_id() == id
+                && default.map_or(true, |default| {
+                    arg.get_default_values()
+                        .iter()
+                        .any(|value| value == default)
+                })
+        })
+    };
+
+    if let Some(config) = &defaults.config {
+        // "--config" is optional for some commands, with a different meaning
+        // when missing. These do not get a default.
+        if has_option(&cmd, "config", Some(DEFAULT_CONFIG)) {
+            cmd = cmd.mut_arg("config", |arg| arg.default_value(config.clone()));
+        }
+    }
+    if let Some(jobs) = defaults.jobs {
+        if has_option(&cmd, "jobs", None) {
+            cmd = cmd.mut_arg("jobs", |arg| arg.default_value(jobs.to_string()));
+        }
+    }
+    if let Some(token_command) = &defaults.token_command {
+        if has_option(&cmd, "token_command", None) {
+            cmd = cmd.mut_arg("token_command", |arg| {
+                arg.required(false).default_value(token_command.clone())
+            });
+        }
+    }
+    if let Some(color) = &defaults.color {
+        if has_option(&cmd, "color", None) {
+            cmd = cmd.mut_arg("color", |arg| arg.default_value(color.clone()));
+        }
+    }
+
+    for subcommand in cmd.get_subcommands_mut() {
+        *subcommand = apply_user_config(std::mem::take(subcommand), defaults);
+    }
+
+    cmd
+}
+
+/// Parses the command line, using the configuration of the user for the
+/// defaults of options that are not given
+pub fn parse(defaults: &config::UserConfig) -> Opts {
+    let matches =
+        apply_user_config(<Opts as clap::CommandFactory>::command(), defaults).get_matches();
+    <Opts as clap::FromArgMatches>::from_arg_matches(&matches).unwrap_or_else(|error| error.exit())
 }
 
-pub fn parse() -> Opts {
-    Opts::parse()
+/// The command definition, for generating completions and man pages. These
+/// refer to the command by its name in some places instead of the binary, so
+/// it is named after the binary.
+pub fn command() -> clap::Command {
+    <Opts as clap::CommandFactory>::command().name("grm")
 }
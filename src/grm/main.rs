@@ -4,797 +4,1800 @@ use std::path::Path;
 use std::process;
 
 mod cmd;
+mod man;
+mod ui;
 
-use grm::auth;
 use grm::config;
+use grm::diff;
+use grm::doctor;
+use grm::exec;
 use grm::find_in_tree;
+use grm::glob;
+use grm::grep;
+use grm::import;
+use grm::list;
+use grm::maintenance;
+use grm::net;
 use grm::output::*;
 use grm::path;
 use grm::provider;
-use grm::provider::Provider;
 use grm::repo;
+use grm::retry;
+use grm::shell;
+use grm::snapshot;
 use grm::table;
 use grm::tree;
 use grm::worktree;
 
+/// The exit code if some repositories could not be synced, to tell that apart
+/// from a sync that could not even start
+const EXIT_SYNC_FAILED: i32 = 2;
+
+/// How many problems of a corrupt repository `repos fsck` shows at most
+const MAX_FSCK_PROBLEMS: usize = 10;
+
 fn main() {
-    let opts = cmd::parse();
+    let user_config = config::read_user_config().unwrap_or_else(|error| {
+        print_error(&error);
+        process::exit(1);
+    });
+    let opts = cmd::parse(&user_config);
+
+    set_level(Level::from_flags(opts.verbose, opts.quiet));
+    set_color(match opts.color {
+        cmd::Color::Auto => ColorChoice::Auto,
+        cmd::Color::Always => ColorChoice::Always,
+        cmd::Color::Never => ColorChoice::Never,
+    });
+    set_timestamps(opts.timestamps);
+    config::set_roots_relative_to_cwd(opts.roots_relative_to_cwd);
+    config::set_profiles(opts.profiles.clone());
+    provider::cache::set_refresh(opts.refresh);
+    if let Some(log_file) = &opts.log_file {
+        if let Err(error) = set_log_file(Path::new(log_file)) {
+            print_error(&error);
+            process::exit(1);
+        }
+    }
+    let retry_delay = duration_from_secs(opts.retry_delay).unwrap_or_else(|error| {
+        print_error(&format!("Invalid retry delay: {}", error));
+        process::exit(1);
+    });
+    retry::set_policy(opts.retries, retry_delay);
+
+    let seconds = |value: Option<f64>, name: &str| {
+        value.map(|value| {
+            if value == 0.0 {
+                print_error(&format!("The {} must be positive", name));
+                process::exit(1);
+            }
+            duration_from_secs(value).unwrap_or_else(|error| {
+                print_error(&format!("Invalid {}: {}", name, error));
+                process::exit(1);
+            })
+        })
+    };
+    let network_settings = net::Settings {
+        proxy: opts.proxy.filter(|proxy| !proxy.is_empty()),
+        connect_timeout: seconds(opts.connect_timeout, "connect timeout"),
+        timeout: seconds(opts.timeout, "timeout"),
+    };
+    if let Err(error) = net::set_settings(network_settings) {
+        print_error(&error);
+        process::exit(1);
+    }
 
     match opts.subcmd {
         cmd::SubCommand::Repos(repos) => match repos.action {
             cmd::ReposAction::Sync(sync) => match sync {
-                cmd::SyncAction::Config(args) => {
-                    let config = match config::read_config(&args.config) {
-                        Ok(config) => config,
-                        Err(error) => {
-                            print_error(&error);
-                            process::exit(1);
-                        }
-                    };
-                    match tree::sync_trees(config, args.init_worktree == "true") {
-                        Ok(success) => {
-                            if !success {
-                                process::exit(1)
-                            }
-                        }
-                        Err(error) => {
-                            print_error(&format!("Sync error: {}", error));
-                            process::exit(1);
-                        }
+                cmd::SyncAction::Config(args) => sync_config(args),
+                cmd::SyncAction::Remote(args) => sync_remote(args),
+            },
+            cmd::ReposAction::Config(action) => match action {
+                cmd::ConfigAction::Convert(args) => config_convert(args),
+            },
+            cmd::ReposAction::Status(args) => repos_status(args),
+            cmd::ReposAction::Add(args) => repos_add(args),
+            cmd::ReposAction::Remove(args) => repos_remove(args),
+            cmd::ReposAction::Path(args) => repos_path(args),
+            cmd::ReposAction::List(args) => repos_list(args),
+            cmd::ReposAction::Import(args) => repos_import(args),
+            cmd::ReposAction::Gc(args) => repos_gc(args),
+            cmd::ReposAction::Fsck(args) => repos_fsck(args),
+            cmd::ReposAction::Du(args) => repos_du(args),
+            cmd::ReposAction::Archive(args) => repos_archive(args),
+            cmd::ReposAction::Grep(args) => repos_grep(args),
+            cmd::ReposAction::BranchClean(args) => repos_branch_clean(args),
+            cmd::ReposAction::Doctor(args) => repos_doctor(args),
+            cmd::ReposAction::Exec(args) => repos_exec(args),
+            cmd::ReposAction::Snapshot(args) => repos_snapshot(args),
+            cmd::ReposAction::Diff(args) => repos_diff(args),
+            cmd::ReposAction::Restore(args) => repos_restore(args),
+            cmd::ReposAction::Find(find) => match find {
+                cmd::FindAction::Local(args) => find_local(args),
+                cmd::FindAction::Config(args) => find_config(args),
+                cmd::FindAction::Remote(args) => find_remote(args),
+            },
+        },
+        cmd::SubCommand::ShellInit(args) => {
+            print!(
+                "{}",
+                shell::init_script(match args.shell {
+                    cmd::Shell::Bash => shell::Shell::Bash,
+                    cmd::Shell::Zsh => shell::Shell::Zsh,
+                    cmd::Shell::Fish => shell::Shell::Fish,
+                })
+            );
+        }
+        cmd::SubCommand::Completions(args) => {
+            let (generator, script) = match args.shell {
+                cmd::CompletionShell::Bash => (
+                    clap_complete::Shell::Bash,
+                    Some(shell::completion_script(shell::Shell::Bash)),
+                ),
+                cmd::CompletionShell::Zsh => (
+                    clap_complete::Shell::Zsh,
+                    Some(shell::completion_script(shell::Shell::Zsh)),
+                ),
+                cmd::CompletionShell::Fish => (
+                    clap_complete::Shell::Fish,
+                    Some(shell::completion_script(shell::Shell::Fish)),
+                ),
+                // Only gets the static completions
+                cmd::CompletionShell::Powershell => (clap_complete::Shell::PowerShell, None),
+            };
+            clap_complete::generate(
+                generator,
+                &mut cmd::command(),
+                "grm",
+                &mut std::io::stdout(),
+            );
+            if let Some(script) = script {
+                print!("{}", script);
+            }
+        }
+        cmd::SubCommand::Complete(args) => {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            for name in shell::complete(&args.words, &cwd) {
+                println!("{}", name);
+            }
+        }
+        cmd::SubCommand::Mangen(args) => {
+            let directory = Path::new(&args.directory);
+            match man::generate(cmd::command(), directory) {
+                Ok(pages) => print_success(&format!(
+                    "Wrote {} man pages to \"{}\"",
+                    pages.len(),
+                    path::path_as_string(directory)
+                )),
+                Err(error) => {
+                    print_error(&format!("Error writing man pages: {}", error));
+                    process::exit(1);
+                }
+            }
+        }
+        cmd::SubCommand::Worktree(args) => {
+            let cwd = std::env::current_dir().unwrap_or_else(|error| {
+                print_error(&format!("Could not open current directory: {}", error));
+                process::exit(1);
+            });
+
+            let worktree_config = repo::read_worktree_root_config(&cwd).unwrap_or_else(|error| {
+                print_error(&format!("Failed to read worktree configuration: {}", error));
+                process::exit(1);
+            });
+
+            match args.action {
+                cmd::WorktreeAction::Add(args) => worktree_add(args, &cwd),
+                cmd::WorktreeAction::Delete(args) => worktree_delete(args, &cwd, &worktree_config),
+                cmd::WorktreeAction::Status(args) => worktree_status(args, &cwd),
+                cmd::WorktreeAction::Convert(args) => worktree_convert(args, &cwd),
+                cmd::WorktreeAction::Clean(args) => worktree_clean(args, &cwd, &worktree_config),
+                cmd::WorktreeAction::Path(args) => worktree_path(args, &cwd),
+                cmd::WorktreeAction::Exec(args) => worktree_exec(args, &cwd),
+                cmd::WorktreeAction::Fetch(args) => worktree_fetch(args, &cwd, &worktree_config),
+                cmd::WorktreeAction::Pull(args) => worktree_pull(args, &cwd),
+                cmd::WorktreeAction::Rebase(args) => worktree_rebase(args, &cwd, &worktree_config),
+            }
+        }
+    }
+}
+
+fn sync_config(args: cmd::Config) {
+    let config = match config::read_repos_config(&args.config) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+    // The flags can only enable what the configuration leaves
+    // disabled
+    let fetch_defaults = config.fetch_config();
+    let options = tree::SyncOptions {
+        init_worktree: args.init_worktree == "true",
+        clone_depth: args.depth,
+        fetch: args.fetch,
+        fetch_options: repo::FetchConfig {
+            prune: args.prune || fetch_defaults.prune,
+            tags: args.tags || fetch_defaults.tags,
+        },
+        check: args.check,
+        wait_for_lock: !args.no_wait,
+        fail_fast: args.fail_fast,
+    };
+    run_sync(config, &options);
+}
+
+fn sync_remote(args: Box<cmd::SyncRemoteArgs>) {
+    let trees = args.provider_config().trees().unwrap_or_else(|error| {
+        print_error(&format!("Sync error: {}", error));
+        process::exit(1);
+    });
+
+    let config = config::Config::from_trees(trees);
+
+    let options = tree::SyncOptions {
+        init_worktree: args.init_worktree == "true",
+        clone_depth: args.depth,
+        fetch: args.fetch,
+        fetch_options: repo::FetchConfig {
+            prune: args.prune,
+            tags: args.tags,
+        },
+        check: args.check,
+        wait_for_lock: !args.no_wait,
+        fail_fast: args.fail_fast,
+    };
+    run_sync(config, &options);
+}
+
+fn config_convert(args: cmd::ConfigConvertArgs) {
+    // Relative roots are kept as they are, they stay relative
+    // to the same directory if the output is written next to
+    // the original
+    let config: config::Config = match config::read_config(&args.config) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+
+    let converted = match args.to {
+        cmd::ConvertFormat::Toml => config.as_toml(),
+        cmd::ConvertFormat::Yaml => config.as_yaml(),
+        cmd::ConvertFormat::Json => config.as_json(),
+    }
+    .unwrap_or_else(|error| {
+        print_error(&format!("Failed converting configuration: {}", error));
+        process::exit(1);
+    });
+
+    match args.output {
+        Some(output) => {
+            if let Err(error) = std::fs::write(&output, converted) {
+                print_error(&format!("Error writing \"{}\": {}", output, error));
+                process::exit(1);
+            }
+            print_success(&format!("Configuration written to \"{}\"", output));
+        }
+        None => print!("{}", converted),
+    }
+}
+
+fn repos_status(args: cmd::ReposStatusArgs) {
+    match &args.config {
+        Some(config_path) => {
+            let config = match config::read_repos_config(config_path) {
+                Ok(config) => config,
+                Err(error) => {
+                    print_error(&error);
+                    process::exit(1);
+                }
+            };
+            if args.interactive {
+                if let Err(error) = ui::run(config) {
+                    print_error(&error);
+                    process::exit(1);
+                }
+                return;
+            }
+            match table::get_status_table(config, args.fetch.then(|| args.jobs)) {
+                Ok(status) => {
+                    for table in status.tables {
+                        println!("{}", table);
+                    }
+                    for error in status.errors {
+                        print_error(&format!("Error: {}", error));
+                    }
+                    if !status.skipped.is_empty() {
+                        print_warning(&format!(
+                            "Skipped disabled repositories: {}",
+                            status.skipped.join(", ")
+                        ));
                     }
                 }
-                cmd::SyncAction::Remote(args) => {
-                    let token = match auth::get_token_from_command(&args.token_command) {
-                        Ok(token) => token,
-                        Err(error) => {
-                            print_error(&format!("Getting token from command failed: {}", error));
-                            process::exit(1);
-                        }
-                    };
+                Err(error) => {
+                    print_error(&format!("Error getting status: {}", error));
+                    process::exit(1);
+                }
+            }
+        }
+        None => {
+            let dir = match std::env::current_dir() {
+                Ok(dir) => dir,
+                Err(error) => {
+                    print_error(&format!("Could not open current directory: {}", error));
+                    process::exit(1);
+                }
+            };
+
+            match table::show_single_repo_status(&dir, args.fetch) {
+                Ok((table, warnings)) => {
+                    println!("{}", table);
+                    for warning in warnings {
+                        print_warning(&warning);
+                    }
+                }
+                Err(error) => {
+                    print_error(&format!("Error getting status: {}", error));
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn repos_add(args: cmd::ReposAddArgs) {
+    let options = tree::SyncOptions {
+        init_worktree: false,
+        clone_depth: None,
+        fetch: false,
+        fetch_options: repo::FetchConfig::default(),
+        check: false,
+        wait_for_lock: true,
+        fail_fast: false,
+    };
+    let name = match args.ghq {
+        true => Some(repo::ghq_name_from_url(&args.url).unwrap_or_else(|| {
+            print_error(&format!(
+                "Cannot derive a ghq path from \"{}\", use --name",
+                args.url
+            ));
+            process::exit(1);
+        })),
+        false => args.name,
+    };
+    match tree::add_repo(
+        &args.config,
+        &args.url,
+        name.as_deref(),
+        args.tree.as_deref(),
+        &options,
+    ) {
+        Ok(name) => print_repo_success(&name, "Added to configuration"),
+        Err(error) => {
+            print_error(&format!("Error adding repository: {}", error));
+            process::exit(1);
+        }
+    }
+}
+
+fn repos_remove(args: cmd::ReposRemoveArgs) {
+    match tree::remove_repo(
+        &args.config,
+        &args.name,
+        args.tree.as_deref(),
+        args.delete_files,
+    ) {
+        Ok(deleted) => {
+            print_repo_success(&args.name, "Removed from configuration");
+            if let Some(path) = deleted {
+                print_repo_success(
+                    &args.name,
+                    &format!("Deleted \"{}\"", path::path_as_string(&path)),
+                );
+            }
+        }
+        Err(error) => {
+            print_error(&format!("Error removing repository: {}", error));
+            process::exit(1);
+        }
+    }
+}
+
+fn repos_path(args: cmd::ReposPathArgs) {
+    let config = match config::read_repos_config(&args.config) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+
+    let tree_root = args.tree.map(|tree_root| {
+        path::expand_path(Path::new(&tree_root))
+            .map(|tree_root| path::path_as_string(&tree_root))
+            .unwrap_or_else(|error| {
+                print_error(&error.to_string());
+                process::exit(1);
+            })
+    });
+
+    let trees = config.trees().unwrap_or_else(|error| {
+        print_error(&format!("Error getting repositories: {}", error));
+        process::exit(1);
+    });
+
+    let (entries, errors) = list::entries(trees);
+    for error in errors {
+        print_warning(&error);
+    }
+
+    match list::find_entry(&entries, &args.name, tree_root.as_deref()) {
+        Ok(entry) => println!("{}", entry.path),
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    }
+}
+
+fn repos_list(args: cmd::ReposListArgs) {
+    // Parse the template first, so mistakes show up before a
+    // provider is queried
+    let template = match args.format.as_str() {
+        "json" | "csv" => None,
+        format => Some(list::Template::parse(format).unwrap_or_else(|error| {
+            print_error(&format!("Invalid format: {}", error));
+            process::exit(1);
+        })),
+    };
+
+    let config = match config::read_repos_config(&args.config) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+
+    let trees = config.trees().unwrap_or_else(|error| {
+        print_error(&format!("Error getting repositories: {}", error));
+        process::exit(1);
+    });
+
+    let (entries, errors) = list::entries(trees);
+
+    match (template, args.format.as_str()) {
+        (Some(template), _) => {
+            for entry in &entries {
+                println!("{}", template.render(entry));
+            }
+        }
+        (None, "json") => match list::format_json(&entries) {
+            Ok(json) => println!("{}", json),
+            Err(error) => {
+                print_error(&format!("Error formatting repositories: {}", error));
+                process::exit(1);
+            }
+        },
+        (None, _) => print!("{}", list::format_csv(&entries)),
+    }
+
+    for error in &errors {
+        print_error(error);
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn repos_import(args: cmd::ReposImportArgs) {
+    let source = match args.from {
+        cmd::ImportSource::Mr => import::Source::Mr,
+        cmd::ImportSource::Ghq => import::Source::Ghq,
+        cmd::ImportSource::Gita => import::Source::Gita,
+        cmd::ImportSource::Vcsh => import::Source::Vcsh,
+    };
+
+    let path = match args.path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => import::default_path(&source).unwrap_or_else(|error| {
+            print_error(&format!("Error importing configuration: {}", error));
+            process::exit(1);
+        }),
+    };
+
+    let (trees, warnings) = match import::import(source, &path) {
+        Ok(result) => result,
+        Err(error) => {
+            print_error(&format!("Error importing configuration: {}", error));
+            process::exit(1);
+        }
+    };
+
+    if trees.is_empty() {
+        print_warning("No repositories found");
+    } else {
+        let mut config = config::ConfigTrees::from_trees(trees).to_config();
+        config.normalize();
+        config.sort(false);
+
+        write_found_config(config, args.merge_into, args.format);
+    }
+    for warning in warnings {
+        print_warning(&warning);
+    }
+}
+
+fn repos_gc(args: cmd::ReposGcArgs) {
+    let (_, targets) = get_repo_targets(&args.config, args.tree.as_deref(), args.tag.as_deref());
+    if targets.is_empty() {
+        print_warning("No repositories found");
+        return;
+    }
+
+    let options = maintenance::GcOptions {
+        aggressive: args.aggressive,
+        maintenance: args.maintenance,
+    };
+    let results = maintenance::gc(targets, &options, args.jobs);
+    let total = results.len();
+
+    let mut failures = 0;
+    let mut reclaimed = 0;
+    for (name, result) in results {
+        match result {
+            Ok(result) => {
+                print_repo_success(
+                    &name,
+                    &format!(
+                        "Reclaimed {} ({} -> {})",
+                        format_bytes(result.reclaimed()),
+                        format_bytes(result.before),
+                        format_bytes(result.after)
+                    ),
+                );
+                reclaimed += result.reclaimed();
+            }
+            Err(error) => {
+                print_repo_error(&name, &error);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        print_error(&format!(
+            "Garbage collection failed in {} of {} repositories",
+            failures, total
+        ));
+        process::exit(1);
+    }
+    print_success(&format!(
+        "Reclaimed {} in {} repositories",
+        format_bytes(reclaimed),
+        total
+    ));
+}
+
+fn repos_fsck(args: cmd::ReposFsckArgs) {
+    let (trees, targets) =
+        get_repo_targets(&args.config, args.tree.as_deref(), args.tag.as_deref());
+
+    if targets.is_empty() {
+        print_warning("No repositories found");
+        return;
+    }
+
+    let results = maintenance::fsck(targets, args.jobs);
+    let total = results.len();
+
+    let mut corrupt = Vec::new();
+    let mut failures = 0;
+    for (name, path, result) in results {
+        match result {
+            Ok(problems) if problems.is_empty() => {
+                print_repo_success(&name, "OK");
+            }
+            Ok(problems) => {
+                print_repo_error(&name, &format!("{} problem(s) found", problems.len()));
+                for problem in problems.iter().take(MAX_FSCK_PROBLEMS) {
+                    print_repo_error(&name, problem);
+                }
+                if problems.len() > MAX_FSCK_PROBLEMS {
+                    print_repo_error(
+                        &name,
+                        &format!("... and {} more", problems.len() - MAX_FSCK_PROBLEMS),
+                    );
+                }
+                corrupt.push(path);
+            }
+            Err(error) => {
+                print_repo_error(&name, &error);
+                failures += 1;
+            }
+        }
+    }
+
+    if corrupt.is_empty() && failures == 0 {
+        print_success(&format!("All {} repositories are intact", total));
+        return;
+    }
+
+    if args.repair && !corrupt.is_empty() {
+        let results = maintenance::repair(trees, &corrupt).unwrap_or_else(|error| {
+            print_error(&format!("Error repairing repositories: {}", error));
+            process::exit(1);
+        });
+
+        let mut repaired = 0;
+        for (name, result) in results {
+            match result {
+                Ok(backup) => {
+                    print_repo_success(
+                        &name,
+                        &format!(
+                            "Cloned again, the corrupt repository is kept at \"{}\"",
+                            backup.display()
+                        ),
+                    );
+                    repaired += 1;
+                }
+                Err(error) => print_repo_error(&name, &error),
+            }
+        }
+
+        if repaired == corrupt.len() && failures == 0 {
+            print_success(&format!("Repaired {} repositories", repaired));
+            return;
+        }
+        print_error(&format!(
+            "Repaired {} of {} corrupt repositories",
+            repaired,
+            corrupt.len()
+        ));
+        process::exit(1);
+    }
+
+    if !corrupt.is_empty() {
+        print_error(&format!(
+            "{} of {} repositories are corrupt",
+            corrupt.len(),
+            total
+        ));
+    }
+    if failures > 0 {
+        print_error(&format!(
+            "Checking failed in {} of {} repositories",
+            failures, total
+        ));
+    }
+    process::exit(1);
+}
+
+fn repos_du(args: cmd::ReposDuArgs) {
+    let (_, targets) = get_repo_targets(&args.config, args.tree.as_deref(), args.tag.as_deref());
+    if targets.is_empty() {
+        print_warning("No repositories found");
+        return;
+    }
+
+    let mut usages = Vec::new();
+    let mut failures = 0;
+    for (name, result) in maintenance::du(targets, args.jobs) {
+        match result {
+            Ok(usage) => usages.push((name, usage)),
+            Err(error) => {
+                print_repo_error(&name, &error);
+                failures += 1;
+            }
+        }
+    }
+
+    if !usages.is_empty() {
+        println!("{}", table::get_disk_usage_table(&mut usages));
+    }
+    if failures > 0 {
+        process::exit(1);
+    }
+}
+
+fn repos_archive(args: cmd::ReposArchiveArgs) {
+    let (archived, errors) =
+        match tree::archive_repos(&args.config, args.inactive_for, args.dry_run) {
+            Ok(result) => result,
+            Err(error) => {
+                print_error(&format!("Error archiving repositories: {}", error));
+                process::exit(1);
+            }
+        };
+
+    for repo in &archived {
+        let days = repo.inactive_for.as_secs() / (60 * 60 * 24);
+        match args.dry_run {
+            true => print_repo_action(
+                &repo.name,
+                &format!("Would be archived, last used {} day(s) ago", days),
+            ),
+            false => print_repo_success(
+                &repo.name,
+                &format!(
+                    "Moved to \"{}\", last used {} day(s) ago",
+                    path::path_as_string(&repo.archive_path),
+                    days
+                ),
+            ),
+        }
+    }
+    for error in &errors {
+        print_error(error);
+    }
+
+    if archived.is_empty() && errors.is_empty() {
+        print_success("No inactive repositories found");
+    } else if !args.dry_run && !archived.is_empty() {
+        print_success(&format!("Archived {} repositories", archived.len()));
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn repos_grep(args: cmd::ReposGrepArgs) {
+    let (_, targets) = get_repo_targets(&args.config, args.tree.as_deref(), args.tag.as_deref());
+    if targets.is_empty() {
+        print_warning("No repositories found");
+        return;
+    }
+
+    let options = grep::GrepOptions {
+        pattern: args.pattern,
+        rev: args.rev,
+        ignore_case: args.ignore_case,
+        fixed_strings: args.fixed_strings,
+        pathspecs: args.pathspecs,
+    };
+
+    let mut found = false;
+    let mut failures = 0;
+    for (name, result) in grep::grep(targets, options, args.jobs) {
+        match result {
+            Ok(matches) => {
+                for line in &matches {
+                    println!("{}:{}", name, line);
+                }
+                found |= !matches.is_empty();
+            }
+            Err(error) => {
+                print_repo_error(&name, &error);
+                failures += 1;
+            }
+        }
+    }
+
+    // Like grep, exit with 1 if nothing was found
+    if !found || failures > 0 {
+        process::exit(1);
+    }
+}
+
+fn repos_branch_clean(args: cmd::ReposBranchCleanArgs) {
+    let (trees, targets) =
+        get_repo_targets(&args.config, args.tree.as_deref(), args.tag.as_deref());
+    if targets.is_empty() {
+        print_warning("No repositories found");
+        return;
+    }
+
+    let results = maintenance::clean_branches(&trees, targets, args.dry_run, args.jobs);
+
+    let mut deleted = 0;
+    let mut failures = 0;
+    for (name, result) in results {
+        match result {
+            Ok(branches) => {
+                for branch in &branches {
+                    match args.dry_run {
+                        true => print_repo_action(
+                            &name,
+                            &format!("Would delete branch {} ({})", branch.name, branch.kind),
+                        ),
+                        false => print_repo_success(
+                            &name,
+                            &format!("Deleted branch {} ({})", branch.name, branch.kind),
+                        ),
+                    }
+                }
+                deleted += branches.len();
+            }
+            Err(error) => {
+                print_repo_error(&name, &error);
+                failures += 1;
+            }
+        }
+    }
+
+    if !args.dry_run {
+        print_success(&format!("Deleted {} branches", deleted));
+    }
+    if failures > 0 {
+        process::exit(1);
+    }
+}
+
+fn repos_doctor(args: cmd::ReposDoctorArgs) {
+    let findings = doctor::diagnose(&args.config);
+
+    let mut problems = 0;
+    let mut failed = false;
+    for finding in findings {
+        let message = format!("{}: {}", finding.subject, finding.message);
+        match finding.severity {
+            doctor::Severity::Ok => print_success(&message),
+            doctor::Severity::Warning => print_warning(&message),
+            doctor::Severity::Error => print_error(&message),
+        }
+        if finding.severity != doctor::Severity::Ok {
+            problems += 1;
+            failed |= finding.severity == doctor::Severity::Error;
+        }
+        if let Some(hint) = finding.hint {
+            print_repo_action(&finding.subject, &hint);
+        }
+    }
+
+    match problems {
+        0 => print_success("No problems found"),
+        _ => print_warning(&format!("{} problem(s) found", problems)),
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn repos_exec(args: cmd::ReposExecArgs) {
+    let (_, targets) = get_repo_targets(&args.config, args.tree.as_deref(), args.tag.as_deref());
+    if targets.is_empty() {
+        print_warning("No repositories found");
+        return;
+    }
+
+    let results = exec::run(targets, &args.command, args.jobs);
+    let total = results.len();
+
+    let mut failures = 0;
+    for (name, result) in results {
+        if let Err(error) = result {
+            print_repo_error(&name, &error);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        print_error(&format!(
+            "Command failed in {} of {} repositories",
+            failures, total
+        ));
+        process::exit(1);
+    }
+    print_success(&format!("Command succeeded in all {} repositories", total));
+}
+
+fn repos_snapshot(args: cmd::ReposSnapshotArgs) {
+    let config = match config::read_repos_config(&args.config) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+
+    let (snapshot, warnings, errors) = match snapshot::create_snapshot(config) {
+        Ok(result) => result,
+        Err(error) => {
+            print_error(&format!("Error creating snapshot: {}", error));
+            process::exit(1);
+        }
+    };
+    for warning in warnings {
+        print_warning(&warning);
+    }
+    for error in &errors {
+        print_error(error);
+    }
+
+    let output = match args.format {
+        cmd::ConfigFormat::Toml => snapshot.as_toml(),
+        cmd::ConfigFormat::Yaml => snapshot.as_yaml(),
+    };
+    let output = match output {
+        Ok(output) => output,
+        Err(error) => {
+            print_error(&format!("Failed converting snapshot: {}", error));
+            process::exit(1);
+        }
+    };
+
+    match args.output {
+        Some(path) => {
+            if let Err(error) = std::fs::write(&path, output) {
+                print_error(&format!(
+                    "Error writing snapshot to \"{}\": {}",
+                    path, error
+                ));
+                process::exit(1);
+            }
+            print_success(&format!("Snapshot written to \"{}\"", path));
+        }
+        None => print!("{}", output),
+    }
+
+    // The snapshot of the other repositories is still written
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn repos_diff(args: cmd::ReposDiffArgs) {
+    let config = match config::read_repos_config(&args.config) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+
+    let (diffs, warnings) = match diff::diff_trees(config) {
+        Ok(result) => result,
+        Err(error) => {
+            print_error(&format!("Error comparing repositories: {}", error));
+            process::exit(1);
+        }
+    };
+    for warning in warnings {
+        print_warning(&warning);
+    }
+
+    for diff in &diffs {
+        diff.print();
+    }
+
+    if args.exit_code && !diffs.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn repos_restore(args: cmd::ReposRestoreArgs) {
+    let snapshot: snapshot::Snapshot = match config::read_config(&args.lockfile) {
+        Ok(snapshot) => snapshot,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+
+    if !snapshot::restore_snapshot(snapshot, |name, message| print_repo_message(name, &message)) {
+        process::exit(1);
+    }
+}
+
+fn find_local(args: cmd::FindLocalArgs) {
+    let path = Path::new(&args.path);
+    if !path.exists() {
+        print_error(&format!("Path \"{}\" does not exist", path.display()));
+        process::exit(1);
+    }
+    if !path.is_dir() {
+        print_error(&format!("Path \"{}\" is not a directory", path.display()));
+        process::exit(1);
+    }
+
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(error) => {
+            print_error(&format!(
+                "Failed to canonicalize path \"{}\". This is a bug. Error message: {}",
+                &path.display(),
+                error
+            ));
+            process::exit(1);
+        }
+    };
+
+    let search_options = tree::SearchOptions {
+        exclude: args.exclude_dir,
+        max_depth: args.max_depth,
+        jobs: args.jobs,
+    };
+
+    let (found_repos, warnings) =
+        match find_in_tree(&path, args.exclude.as_deref(), &search_options) {
+            Ok((repos, warnings)) => (repos, warnings),
+            Err(error) => {
+                print_error(&error);
+                process::exit(1);
+            }
+        };
+
+    let trees = config::ConfigTrees::from_trees(vec![found_repos]);
+    if trees.trees_ref().iter().all(|t| match &t.repos {
+        None => false,
+        Some(r) => r.is_empty(),
+    }) {
+        print_warning("No repositories found");
+    } else {
+        let mut config = trees.to_config();
+
+        config.normalize();
+        config.sort(args.group_by_host);
+
+        write_found_config(config, args.merge_into, args.format);
+    }
+    for warning in warnings {
+        print_warning(&warning);
+    }
+}
+
+fn find_config(args: cmd::FindConfigArgs) {
+    let config = match config::read_repos_config(&args.config) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
+    if let config::Config::ConfigTrees(_) = config {
+        print_error(&format!(
+            "Configuration file \"{}\" does not use a provider",
+            args.config
+        ));
+        process::exit(1);
+    }
+
+    let trees = config.trees().unwrap_or_else(|error| {
+        print_error(&format!("Error: {}", error));
+        process::exit(1);
+    });
+
+    let mut config = config::Config::from_trees(trees);
+    config.sort(args.group_by_host);
+
+    write_found_config(config, args.merge_into, args.format);
+}
+
+fn find_remote(args: Box<cmd::FindRemoteArgs>) {
+    let trees = args.provider_config().trees().unwrap_or_else(|error| {
+        print_error(&format!("Error: {}", error));
+        process::exit(1);
+    });
+
+    let mut config = config::Config::from_trees(trees);
+
+    config.normalize();
+    config.sort(args.group_by_host);
+
+    write_found_config(config, args.merge_into, args.format);
+}
+
+fn worktree_add(args: cmd::WorktreeAddArgs, cwd: &Path) {
+    if args.track.is_some() && args.no_track {
+        print_warning(
+            "You are using --track and --no-track at the same time. --track will be ignored",
+        );
+    }
+    let track = match &args.track {
+        Some(branch) => {
+            let split = branch.split_once('/');
+
+            if split.is_none() || split.unwrap().0.is_empty() || split.unwrap().1.is_empty() {
+                print_error("Tracking branch needs to match the pattern <remote>/<branch_name>");
+                process::exit(1);
+            };
+
+            // unwrap() here is safe because we checked for
+            // is_none() explictily before
+            let (remote_name, remote_branch_name) = split.unwrap();
+
+            Some((remote_name, remote_branch_name))
+        }
+        None => None,
+    };
 
-                    let filter =
-                        provider::Filter::new(args.users, args.groups, args.owner, args.access);
+    let review_request = match (args.pr, args.mr) {
+        (Some(number), _) => Some(worktree::ReviewRequest::Pull(number)),
+        (None, Some(number)) => Some(worktree::ReviewRequest::Merge(number)),
+        (None, None) => None,
+    };
 
-                    if filter.empty() {
-                        print_warning("You did not specify any filters, so no repos will match");
-                    }
+    // clap makes sure that the name is given without a request
+    let (name, result) = match review_request {
+        Some(request) => (request.name(), worktree::add_review_worktree(cwd, &request)),
+        None => {
+            let name = args.name.clone().unwrap_or_default();
+            let result = worktree::add_worktree(cwd, &name, track, args.no_track, args.track_auto);
+            (name, result)
+        }
+    };
 
-                    let worktree = args.worktree == "true";
-
-                    let repos = match args.provider {
-                        cmd::RemoteProvider::Github => {
-                            match provider::Github::new(filter, token, args.api_url) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Sync error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                            .get_repos(
-                                worktree,
-                                args.force_ssh,
-                                args.remote_name,
-                            )
-                        }
-                        cmd::RemoteProvider::Gitlab => {
-                            match provider::Gitlab::new(filter, token, args.api_url) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Sync error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                            .get_repos(
-                                worktree,
-                                args.force_ssh,
-                                args.remote_name,
-                            )
-                        }
-                    };
-
-                    match repos {
-                        Ok(repos) => {
-                            let mut trees: Vec<config::ConfigTree> = vec![];
-
-                            for (namespace, repolist) in repos {
-                                let root = if let Some(namespace) = namespace {
-                                    path::path_as_string(&Path::new(&args.root).join(namespace))
-                                } else {
-                                    path::path_as_string(Path::new(&args.root))
-                                };
-
-                                let tree = config::ConfigTree::from_repos(root, repolist);
-                                trees.push(tree);
-                            }
-
-                            let config = config::Config::from_trees(trees);
-
-                            match tree::sync_trees(config, args.init_worktree == "true") {
-                                Ok(success) => {
-                                    if !success {
-                                        process::exit(1)
-                                    }
-                                }
-                                Err(error) => {
-                                    print_error(&format!("Sync error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                        }
-                        Err(error) => {
-                            print_error(&format!("Sync error: {}", error));
-                            process::exit(1);
-                        }
-                    }
+    match result {
+        Ok(warnings) => {
+            if let Some(warnings) = warnings {
+                for warning in warnings {
+                    print_warning(&warning);
                 }
-            },
-            cmd::ReposAction::Status(args) => match &args.config {
-                Some(config_path) => {
-                    let config = match config::read_config(config_path) {
-                        Ok(config) => config,
-                        Err(error) => {
-                            print_error(&error);
-                            process::exit(1);
-                        }
-                    };
-                    match table::get_status_table(config) {
-                        Ok((tables, errors)) => {
-                            for table in tables {
-                                println!("{}", table);
-                            }
-                            for error in errors {
-                                print_error(&format!("Error: {}", error));
-                            }
-                        }
-                        Err(error) => {
-                            print_error(&format!("Error getting status: {}", error));
-                            process::exit(1);
-                        }
-                    }
+            }
+            print_success(&format!("Worktree {} created", &name));
+        }
+        Err(error) => {
+            print_error(&format!("Error creating worktree: {}", error));
+            process::exit(1);
+        }
+    }
+}
+
+fn worktree_delete(
+    args: cmd::WorktreeDeleteArgs,
+    cwd: &Path,
+    worktree_config: &Option<repo::WorktreeRootConfig>,
+) {
+    let repo = repo::RepoHandle::open(cwd, true).unwrap_or_else(|error| {
+        print_error(&format!("Error opening repository: {}", error));
+        process::exit(1);
+    });
+
+    if glob::is_glob(&args.name) {
+        let names = repo
+            .find_worktrees_matching(&args.name)
+            .unwrap_or_else(|error| {
+                print_error(&format!("Error getting worktrees: {}", error));
+                process::exit(1);
+            });
+
+        if names.is_empty() {
+            print_error(&format!("No worktree matches \"{}\"", &args.name));
+            process::exit(1);
+        }
+
+        let mut removed = Vec::new();
+        let mut skipped = Vec::new();
+
+        for name in names {
+            match repo.remove_worktree(cwd, &name, Path::new(&name), args.force, worktree_config) {
+                Ok(_) => {
+                    print_success(&format!("Worktree {} deleted", &name));
+                    removed.push(name);
                 }
-                None => {
-                    let dir = match std::env::current_dir() {
-                        Ok(dir) => dir,
-                        Err(error) => {
-                            print_error(&format!("Could not open current directory: {}", error));
-                            process::exit(1);
+                Err(error) => {
+                    match error {
+                        repo::WorktreeRemoveFailureReason::Error(msg) => {
+                            print_error(&format!("{}: {}", &name, msg));
                         }
-                    };
-
-                    match table::show_single_repo_status(&dir) {
-                        Ok((table, warnings)) => {
-                            println!("{}", table);
-                            for warning in warnings {
-                                print_warning(&warning);
-                            }
+                        repo::WorktreeRemoveFailureReason::Changes(changes) => {
+                            print_warning(&format!(
+                                "{}: Changes in worktree: {}. Refusing to delete",
+                                &name, changes
+                            ));
                         }
-                        Err(error) => {
-                            print_error(&format!("Error getting status: {}", error));
-                            process::exit(1);
+                        repo::WorktreeRemoveFailureReason::NotMerged(message) => {
+                            print_warning(&format!("{}: {}", &name, message));
                         }
                     }
+                    skipped.push(name);
                 }
-            },
-            cmd::ReposAction::Find(find) => match find {
-                cmd::FindAction::Local(args) => {
-                    let path = Path::new(&args.path);
-                    if !path.exists() {
-                        print_error(&format!("Path \"{}\" does not exist", path.display()));
-                        process::exit(1);
-                    }
-                    if !path.is_dir() {
-                        print_error(&format!("Path \"{}\" is not a directory", path.display()));
+            }
+        }
+
+        if !removed.is_empty() {
+            print_success(&format!(
+                "Deleted {} worktree(s): {}",
+                removed.len(),
+                removed.join(", ")
+            ));
+        }
+        if !skipped.is_empty() {
+            print_warning(&format!(
+                "Skipped {} worktree(s): {}",
+                skipped.len(),
+                skipped.join(", ")
+            ));
+            process::exit(1);
+        }
+    } else {
+        // The worktree can also be given by the name of its
+        // branch
+        let name = repo::worktree_name(worktree_config, &args.name);
+        match repo.remove_worktree(cwd, &name, Path::new(&name), args.force, worktree_config) {
+            Ok(_) => print_success(&format!("Worktree {} deleted", &name)),
+            Err(error) => {
+                match error {
+                    repo::WorktreeRemoveFailureReason::Error(msg) => {
+                        print_error(&msg);
                         process::exit(1);
                     }
-
-                    let path = match path.canonicalize() {
-                        Ok(path) => path,
-                        Err(error) => {
-                            print_error(&format!(
-                                    "Failed to canonicalize path \"{}\". This is a bug. Error message: {}",
-                                    &path.display(),
-                                    error
-                                ));
-                            process::exit(1);
-                        }
-                    };
-
-                    let (found_repos, warnings) = match find_in_tree(&path, args.exclude.as_deref())
-                    {
-                        Ok((repos, warnings)) => (repos, warnings),
-                        Err(error) => {
-                            print_error(&error);
-                            process::exit(1);
-                        }
-                    };
-
-                    let trees = config::ConfigTrees::from_trees(vec![found_repos]);
-                    if trees.trees_ref().iter().all(|t| match &t.repos {
-                        None => false,
-                        Some(r) => r.is_empty(),
-                    }) {
-                        print_warning("No repositories found");
-                    } else {
-                        let mut config = trees.to_config();
-
-                        config.normalize();
-
-                        match args.format {
-                            cmd::ConfigFormat::Toml => {
-                                let toml = match config.as_toml() {
-                                    Ok(toml) => toml,
-                                    Err(error) => {
-                                        print_error(&format!(
-                                            "Failed converting config to TOML: {}",
-                                            &error
-                                        ));
-                                        process::exit(1);
-                                    }
-                                };
-                                print!("{}", toml);
-                            }
-                            cmd::ConfigFormat::Yaml => {
-                                let yaml = match config.as_yaml() {
-                                    Ok(yaml) => yaml,
-                                    Err(error) => {
-                                        print_error(&format!(
-                                            "Failed converting config to YAML: {}",
-                                            &error
-                                        ));
-                                        process::exit(1);
-                                    }
-                                };
-                                print!("{}", yaml);
-                            }
-                        }
+                    repo::WorktreeRemoveFailureReason::Changes(changes) => {
+                        print_warning(&format!(
+                            "Changes in worktree: {}. Refusing to delete",
+                            changes
+                        ));
                     }
-                    for warning in warnings {
-                        print_warning(&warning);
+                    repo::WorktreeRemoveFailureReason::NotMerged(message) => {
+                        print_warning(&message);
                     }
                 }
-                cmd::FindAction::Config(args) => {
-                    let config: config::ConfigProvider = match config::read_config(&args.config) {
-                        Ok(config) => config,
-                        Err(error) => {
-                            print_error(&error);
-                            process::exit(1);
-                        }
-                    };
-
-                    let token = match auth::get_token_from_command(&config.token_command) {
-                        Ok(token) => token,
-                        Err(error) => {
-                            print_error(&format!("Getting token from command failed: {}", error));
-                            process::exit(1);
-                        }
-                    };
-
-                    let filters = config.filters.unwrap_or(config::ConfigProviderFilter {
-                        access: Some(false),
-                        owner: Some(false),
-                        users: Some(vec![]),
-                        groups: Some(vec![]),
-                    });
-
-                    let filter = provider::Filter::new(
-                        filters.users.unwrap_or_default(),
-                        filters.groups.unwrap_or_default(),
-                        filters.owner.unwrap_or(false),
-                        filters.access.unwrap_or(false),
-                    );
+                process::exit(1);
+            }
+        }
+    }
+}
 
-                    if filter.empty() {
-                        print_warning("You did not specify any filters, so no repos will match");
-                    }
+fn worktree_status(args: cmd::WorktreeStatusArgs, cwd: &Path) {
+    let repo = repo::RepoHandle::open(cwd, true).unwrap_or_else(|error| {
+        print_error(&format!("Error opening repository: {}", error));
+        process::exit(1);
+    });
 
-                    let repos = match config.provider {
-                        provider::RemoteProvider::Github => {
-                            match match provider::Github::new(filter, token, config.api_url) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                            .get_repos(
-                                config.worktree.unwrap_or(false),
-                                config.force_ssh.unwrap_or(false),
-                                config.remote_name,
-                            ) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                        }
-                        provider::RemoteProvider::Gitlab => {
-                            match match provider::Gitlab::new(filter, token, config.api_url) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                            .get_repos(
-                                config.worktree.unwrap_or(false),
-                                config.force_ssh.unwrap_or(false),
-                                config.remote_name,
-                            ) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                        }
-                    };
-
-                    let mut trees = vec![];
-
-                    for (namespace, namespace_repos) in repos {
-                        let tree = config::ConfigTree {
-                            root: if let Some(namespace) = namespace {
-                                path::path_as_string(&Path::new(&config.root).join(namespace))
-                            } else {
-                                path::path_as_string(Path::new(&config.root))
-                            },
-                            repos: Some(
-                                namespace_repos
-                                    .into_iter()
-                                    .map(config::RepoConfig::from_repo)
-                                    .collect(),
-                            ),
-                        };
-                        trees.push(tree);
+    match repo.worktree_statuses(cwd) {
+        Ok((statuses, errors)) => {
+            match args.output {
+                cmd::StatusOutput::Table => {
+                    println!("{}", table::get_worktree_status_table(&statuses))
+                }
+                cmd::StatusOutput::Json => match serde_json::to_string_pretty(&statuses) {
+                    Ok(json) => println!("{}", json),
+                    Err(error) => {
+                        print_error(&format!("Error serializing status: {}", error));
+                        process::exit(1);
                     }
+                },
+            }
+            for error in errors {
+                print_error(&format!("Error: {}", error));
+            }
+        }
+        Err(error) => {
+            print_error(&format!("Error getting status: {}", error));
+            process::exit(1);
+        }
+    }
+}
 
-                    let config = config::Config::from_trees(trees);
-
-                    match args.format {
-                        cmd::ConfigFormat::Toml => {
-                            let toml = match config.as_toml() {
-                                Ok(toml) => toml,
-                                Err(error) => {
-                                    print_error(&format!(
-                                        "Failed converting config to TOML: {}",
-                                        &error
-                                    ));
-                                    process::exit(1);
-                                }
-                            };
-                            print!("{}", toml);
-                        }
-                        cmd::ConfigFormat::Yaml => {
-                            let yaml = match config.as_yaml() {
-                                Ok(yaml) => yaml,
-                                Err(error) => {
-                                    print_error(&format!(
-                                        "Failed converting config to YAML: {}",
-                                        &error
-                                    ));
-                                    process::exit(1);
-                                }
-                            };
-                            print!("{}", yaml);
-                        }
-                    }
-                }
-                cmd::FindAction::Remote(args) => {
-                    let token = match auth::get_token_from_command(&args.token_command) {
-                        Ok(token) => token,
-                        Err(error) => {
-                            print_error(&format!("Getting token from command failed: {}", error));
-                            process::exit(1);
-                        }
-                    };
+fn worktree_convert(args: cmd::WorktreeConvertArgs, cwd: &Path) {
+    // Converting works like this:
+    // * Check whether there are uncommitted/unpushed changes
+    // * Move the contents of .git dir to the worktree directory
+    // * Remove all files
+    // * Set `core.bare` to `true`
+    //
+    // Converting back (--to-normal) does the reverse:
+    // * Check that no worktree contains changes
+    // * Remove all worktrees
+    // * Move the worktree directory to .git
+    // * Set `core.bare` to `false` and check out the branch
 
-                    let filter =
-                        provider::Filter::new(args.users, args.groups, args.owner, args.access);
+    let repo = repo::RepoHandle::open(cwd, args.to_normal).unwrap_or_else(|error| {
+        if error.kind == repo::RepoErrorKind::NotFound {
+            print_error(if args.to_normal {
+                "Directory does not contain a worktree setup"
+            } else {
+                "Directory does not contain a git repository"
+            });
+        } else {
+            print_error(&format!("Opening repository failed: {}", error));
+        }
+        process::exit(1);
+    });
 
-                    if filter.empty() {
-                        print_warning("You did not specify any filters, so no repos will match");
-                    }
+    let result = if args.to_normal {
+        repo.convert_to_normal(cwd, args.branch.as_deref())
+    } else {
+        repo.convert_to_worktree(cwd)
+    };
 
-                    let worktree = args.worktree == "true";
-
-                    let repos = match args.provider {
-                        cmd::RemoteProvider::Github => {
-                            match provider::Github::new(filter, token, args.api_url) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                            .get_repos(
-                                worktree,
-                                args.force_ssh,
-                                args.remote_name,
-                            )
-                        }
-                        cmd::RemoteProvider::Gitlab => {
-                            match provider::Gitlab::new(filter, token, args.api_url) {
-                                Ok(provider) => provider,
-                                Err(error) => {
-                                    print_error(&format!("Error: {}", error));
-                                    process::exit(1);
-                                }
-                            }
-                            .get_repos(
-                                worktree,
-                                args.force_ssh,
-                                args.remote_name,
-                            )
-                        }
-                    };
+    match result {
+        Ok(_) => print_success("Conversion done"),
+        Err(reason) => {
+            match reason {
+                repo::WorktreeConversionFailureReason::Changes => {
+                    print_error("Changes found in repository, refusing to convert");
+                }
+                repo::WorktreeConversionFailureReason::Ignored => {
+                    print_error("Ignored files found in repository, refusing to convert. Run git clean -f -d -X to remove them manually.");
+                }
+                repo::WorktreeConversionFailureReason::WorktreeChanges(name) => {
+                    print_error(&format!(
+                        "Changes found in worktree {}, refusing to convert",
+                        name
+                    ));
+                }
+                repo::WorktreeConversionFailureReason::WorktreeIgnored(name) => {
+                    print_error(&format!("Ignored files found in worktree {}, refusing to convert. Run git clean -f -d -X in the worktree to remove them manually.", name));
+                }
+                repo::WorktreeConversionFailureReason::Error(error) => {
+                    print_error(&format!("Error during conversion: {}", error));
+                }
+            }
+            process::exit(1);
+        }
+    }
+}
 
-                    let repos = repos.unwrap_or_else(|error| {
-                        print_error(&format!("Error: {}", error));
-                        process::exit(1);
-                    });
-
-                    let mut trees: Vec<config::ConfigTree> = vec![];
-
-                    for (namespace, repolist) in repos {
-                        let tree = config::ConfigTree {
-                            root: if let Some(namespace) = namespace {
-                                path::path_as_string(&Path::new(&args.root).join(namespace))
-                            } else {
-                                path::path_as_string(Path::new(&args.root))
-                            },
-                            repos: Some(
-                                repolist
-                                    .into_iter()
-                                    .map(config::RepoConfig::from_repo)
-                                    .collect(),
-                            ),
-                        };
-                        trees.push(tree);
-                    }
+fn worktree_clean(
+    args: cmd::WorktreeCleanArgs,
+    cwd: &Path,
+    worktree_config: &Option<repo::WorktreeRootConfig>,
+) {
+    let repo = repo::RepoHandle::open(cwd, true).unwrap_or_else(|error| {
+        if error.kind == repo::RepoErrorKind::NotFound {
+            print_error("Directory does not contain a git repository");
+        } else {
+            print_error(&format!("Opening repository failed: {}", error));
+        }
+        process::exit(1);
+    });
 
-                    let mut config = config::Config::from_trees(trees);
-
-                    config.normalize();
-
-                    match args.format {
-                        cmd::ConfigFormat::Toml => {
-                            let toml = match config.as_toml() {
-                                Ok(toml) => toml,
-                                Err(error) => {
-                                    print_error(&format!(
-                                        "Failed converting config to TOML: {}",
-                                        &error
-                                    ));
-                                    process::exit(1);
-                                }
-                            };
-                            print!("{}", toml);
-                        }
-                        cmd::ConfigFormat::Yaml => {
-                            let yaml = match config.as_yaml() {
-                                Ok(yaml) => yaml,
-                                Err(error) => {
-                                    print_error(&format!(
-                                        "Failed converting config to YAML: {}",
-                                        &error
-                                    ));
-                                    process::exit(1);
-                                }
-                            };
-                            print!("{}", yaml);
-                        }
-                    }
-                }
-            },
-        },
-        cmd::SubCommand::Worktree(args) => {
-            let cwd = std::env::current_dir().unwrap_or_else(|error| {
-                print_error(&format!("Could not open current directory: {}", error));
+    if args.dry_run || args.interactive {
+        let candidates = repo
+            .plan_worktree_cleanup(cwd, args.older_than)
+            .unwrap_or_else(|error| {
+                print_error(&format!("Worktree cleanup failed: {}", error));
                 process::exit(1);
             });
 
-            match args.action {
-                cmd::WorktreeAction::Add(action_args) => {
-                    if action_args.track.is_some() && action_args.no_track {
-                        print_warning("You are using --track and --no-track at the same time. --track will be ignored");
+        for candidate in candidates {
+            match candidate.verdict {
+                repo::WorktreeCleanupVerdict::Remove => {
+                    if args.dry_run {
+                        print_action(&format!("Would delete worktree {}", &candidate.name));
+                        continue;
                     }
-                    let track = match &action_args.track {
-                        Some(branch) => {
-                            let split = branch.split_once('/');
-
-                            if split.is_none()
-                                || split.unwrap().0.is_empty()
-                                || split.unwrap().1.is_empty()
-                            {
-                                print_error("Tracking branch needs to match the pattern <remote>/<branch_name>");
-                                process::exit(1);
-                            };
-
-                            // unwrap() here is safe because we checked for
-                            // is_none() explictily before
-                            let (remote_name, remote_branch_name) = split.unwrap();
-
-                            Some((remote_name, remote_branch_name))
-                        }
-                        None => None,
-                    };
-
-                    match worktree::add_worktree(
-                        &cwd,
-                        &action_args.name,
-                        track,
-                        action_args.no_track,
-                    ) {
-                        Ok(warnings) => {
-                            if let Some(warnings) = warnings {
-                                for warning in warnings {
-                                    print_warning(&warning);
-                                }
-                            }
-                            print_success(&format!("Worktree {} created", &action_args.name));
-                        }
-                        Err(error) => {
-                            print_error(&format!("Error creating worktree: {}", error));
-                            process::exit(1);
-                        }
+                    if !confirm(&format!("Delete worktree {}?", &candidate.name)) {
+                        print_action(&format!("{}: Not confirmed, skipping", &candidate.name));
+                        continue;
                     }
-                }
-                cmd::WorktreeAction::Delete(action_args) => {
-                    let worktree_config = match repo::read_worktree_root_config(&cwd) {
-                        Ok(config) => config,
-                        Err(error) => {
-                            print_error(&format!(
-                                "Error getting worktree configuration: {}",
-                                error
-                            ));
-                            process::exit(1);
-                        }
-                    };
-
-                    let repo = repo::RepoHandle::open(&cwd, true).unwrap_or_else(|error| {
-                        print_error(&format!("Error opening repository: {}", error));
-                        process::exit(1);
-                    });
-
                     match repo.remove_worktree(
-                        &cwd,
-                        &action_args.name,
-                        Path::new(&action_args.name),
-                        action_args.force,
-                        &worktree_config,
+                        cwd,
+                        &candidate.name,
+                        Path::new(&candidate.name),
+                        false,
+                        worktree_config,
                     ) {
-                        Ok(_) => print_success(&format!("Worktree {} deleted", &action_args.name)),
-                        Err(error) => {
-                            match error {
-                                repo::WorktreeRemoveFailureReason::Error(msg) => {
-                                    print_error(&msg);
-                                    process::exit(1);
-                                }
-                                repo::WorktreeRemoveFailureReason::Changes(changes) => {
-                                    print_warning(&format!(
-                                        "Changes in worktree: {}. Refusing to delete",
-                                        changes
-                                    ));
-                                }
-                                repo::WorktreeRemoveFailureReason::NotMerged(message) => {
-                                    print_warning(&message);
-                                }
-                            }
+                        Ok(_) => print_success(&format!("Worktree {} deleted", &candidate.name)),
+                        Err(repo::WorktreeRemoveFailureReason::Error(error)) => {
+                            print_error(&format!("Worktree cleanup failed: {}", error));
                             process::exit(1);
                         }
-                    }
-                }
-                cmd::WorktreeAction::Status(_args) => {
-                    let repo = repo::RepoHandle::open(&cwd, true).unwrap_or_else(|error| {
-                        print_error(&format!("Error opening repository: {}", error));
-                        process::exit(1);
-                    });
-
-                    match table::get_worktree_status_table(&repo, &cwd) {
-                        Ok((table, errors)) => {
-                            println!("{}", table);
-                            for error in errors {
-                                print_error(&format!("Error: {}", error));
-                            }
-                        }
-                        Err(error) => {
-                            print_error(&format!("Error getting status: {}", error));
-                            process::exit(1);
+                        Err(repo::WorktreeRemoveFailureReason::Changes(reason))
+                        | Err(repo::WorktreeRemoveFailureReason::NotMerged(reason)) => {
+                            print_warning(&format!("{}: {}, skipping", &candidate.name, reason))
                         }
                     }
                 }
-                cmd::WorktreeAction::Convert(_args) => {
-                    // Converting works like this:
-                    // * Check whether there are uncommitted/unpushed changes
-                    // * Move the contents of .git dir to the worktree directory
-                    // * Remove all files
-                    // * Set `core.bare` to `true`
-
-                    let repo = repo::RepoHandle::open(&cwd, false).unwrap_or_else(|error| {
-                        if error.kind == repo::RepoErrorKind::NotFound {
-                            print_error("Directory does not contain a git repository");
-                        } else {
-                            print_error(&format!("Opening repository failed: {}", error));
-                        }
-                        process::exit(1);
-                    });
-
-                    match repo.convert_to_worktree(&cwd) {
-                        Ok(_) => print_success("Conversion done"),
-                        Err(reason) => {
-                            match reason {
-                                repo::WorktreeConversionFailureReason::Changes => {
-                                    print_error("Changes found in repository, refusing to convert");
-                                }
-                                repo::WorktreeConversionFailureReason::Ignored => {
-                                    print_error("Ignored files found in repository, refusing to convert. Run git clean -f -d -X to remove them manually.");
-                                }
-                                repo::WorktreeConversionFailureReason::Error(error) => {
-                                    print_error(&format!("Error during conversion: {}", error));
-                                }
-                            }
-                            process::exit(1);
-                        }
+                repo::WorktreeCleanupVerdict::Keep(reason) => {
+                    print_warning(&format!("{}: {}, skipping", &candidate.name, reason));
+                }
+                repo::WorktreeCleanupVerdict::Protected(reason) => {
+                    if args.dry_run {
+                        print_action(&format!("{}: {}, skipping", &candidate.name, reason));
                     }
                 }
-                cmd::WorktreeAction::Clean(_args) => {
-                    let repo = repo::RepoHandle::open(&cwd, true).unwrap_or_else(|error| {
-                        if error.kind == repo::RepoErrorKind::NotFound {
-                            print_error("Directory does not contain a git repository");
-                        } else {
-                            print_error(&format!("Opening repository failed: {}", error));
-                        }
-                        process::exit(1);
-                    });
+            }
+        }
+    } else {
+        match repo.cleanup_worktrees(cwd, args.older_than) {
+            Ok(messages) => {
+                for message in messages {
+                    print_message(&message);
+                }
+            }
+            Err(error) => {
+                print_error(&format!("Worktree cleanup failed: {}", error));
+                process::exit(1);
+            }
+        }
+    }
 
-                    match repo.cleanup_worktrees(&cwd) {
-                        Ok(warnings) => {
-                            for warning in warnings {
-                                print_warning(&warning);
-                            }
-                        }
-                        Err(error) => {
-                            print_error(&format!("Worktree cleanup failed: {}", error));
-                            process::exit(1);
-                        }
-                    }
+    for unmanaged_worktree in repo.find_unmanaged_worktrees(cwd).unwrap_or_else(|error| {
+        print_error(&format!("Failed finding unmanaged worktrees: {}", error));
+        process::exit(1);
+    }) {
+        print_warning(&format!(
+            "Found {}, which is not a valid worktree directory!",
+            &unmanaged_worktree
+        ));
+    }
+}
 
-                    for unmanaged_worktree in
-                        repo.find_unmanaged_worktrees(&cwd).unwrap_or_else(|error| {
-                            print_error(&format!("Failed finding unmanaged worktrees: {}", error));
-                            process::exit(1);
-                        })
-                    {
-                        print_warning(&format!(
-                            "Found {}, which is not a valid worktree directory!",
-                            &unmanaged_worktree
-                        ));
-                    }
-                }
-                cmd::WorktreeAction::Fetch(_args) => {
-                    let repo = repo::RepoHandle::open(&cwd, true).unwrap_or_else(|error| {
-                        if error.kind == repo::RepoErrorKind::NotFound {
-                            print_error("Directory does not contain a git repository");
-                        } else {
-                            print_error(&format!("Opening repository failed: {}", error));
-                        }
-                        process::exit(1);
-                    });
+fn worktree_path(args: cmd::WorktreePathArgs, cwd: &Path) {
+    // Works from anywhere inside of the worktree setup, to
+    // switch between worktrees
+    let (root, _) = worktree::find_worktree_root(cwd).unwrap_or_else(|| {
+        print_error("Directory is not part of a worktree setup");
+        process::exit(1);
+    });
 
-                    repo.fetchall().unwrap_or_else(|error| {
-                        print_error(&format!("Error fetching remotes: {}", error));
-                        process::exit(1);
-                    });
-                    print_success("Fetched from all remotes");
-                }
-                cmd::WorktreeAction::Pull(args) => {
-                    let repo = repo::RepoHandle::open(&cwd, true).unwrap_or_else(|error| {
-                        if error.kind == repo::RepoErrorKind::NotFound {
-                            print_error("Directory does not contain a git repository");
-                        } else {
-                            print_error(&format!("Opening repository failed: {}", error));
-                        }
-                        process::exit(1);
-                    });
+    let name = match args.name {
+        Some(name) => name,
+        None => {
+            println!("{}", path::path_as_string(&root));
+            return;
+        }
+    };
 
-                    repo.fetchall().unwrap_or_else(|error| {
-                        print_error(&format!("Error fetching remotes: {}", error));
-                        process::exit(1);
-                    });
+    let repo = repo::RepoHandle::open(&root, true).unwrap_or_else(|error| {
+        print_error(&format!("Opening repository failed: {}", error));
+        process::exit(1);
+    });
+    let names = repo
+        .get_worktrees()
+        .unwrap_or_else(|error| {
+            print_error(&format!("Error getting worktrees: {}", error));
+            process::exit(1);
+        })
+        .iter()
+        .map(|worktree| worktree.name().to_string())
+        .collect::<Vec<String>>();
+    let config = repo::read_worktree_root_config(&root).unwrap_or_else(|error| {
+        print_error(&format!("Failed to read worktree configuration: {}", error));
+        process::exit(1);
+    });
 
-                    let mut failures = false;
-                    for worktree in repo.get_worktrees().unwrap_or_else(|error| {
-                        print_error(&format!("Error getting worktrees: {}", error));
-                        process::exit(1);
-                    }) {
-                        if let Some(warning) = worktree
-                            .forward_branch(args.rebase, args.stash)
-                            .unwrap_or_else(|error| {
-                                print_error(&format!("Error updating worktree branch: {}", error));
-                                process::exit(1);
-                            })
-                        {
-                            print_warning(&format!("{}: {}", worktree.name(), warning));
-                            failures = true;
-                        } else {
-                            print_success(&format!("{}: Done", worktree.name()));
-                        }
-                    }
-                    if failures {
-                        process::exit(1);
-                    }
+    match worktree::match_worktree_name(&names, &name, &config, args.fuzzy) {
+        Ok(name) => println!("{}", path::path_as_string(&root.join(name))),
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    }
+}
+
+fn worktree_exec(args: cmd::WorktreeExecArgs, cwd: &Path) {
+    let repo = repo::RepoHandle::open(cwd, true).unwrap_or_else(|error| {
+        if error.kind == repo::RepoErrorKind::NotFound {
+            print_error("Directory does not contain a git repository");
+        } else {
+            print_error(&format!("Opening repository failed: {}", error));
+        }
+        process::exit(1);
+    });
+
+    let worktrees = repo.get_worktrees().unwrap_or_else(|error| {
+        print_error(&format!("Error getting worktrees: {}", error));
+        process::exit(1);
+    });
+
+    if worktrees.is_empty() {
+        print_warning("No worktrees found");
+        return;
+    }
+
+    let targets = worktrees
+        .iter()
+        .map(|worktree| exec::Target {
+            path: cwd.join(worktree.name()),
+            name: worktree.name().to_string(),
+            env: vec![],
+        })
+        .collect();
+
+    let results = exec::run(targets, &args.command, args.jobs);
+    let total = results.len();
+
+    let mut failures = 0;
+    for (name, result) in results {
+        if let Err(error) = result {
+            print_repo_error(&name, &error);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        print_error(&format!(
+            "Command failed in {} of {} worktree(s)",
+            failures, total
+        ));
+        process::exit(1);
+    }
+    print_success(&format!("Command succeeded in all {} worktree(s)", total));
+}
+
+fn worktree_fetch(
+    args: cmd::WorktreeFetchArgs,
+    cwd: &Path,
+    worktree_config: &Option<repo::WorktreeRootConfig>,
+) {
+    let repo = repo::RepoHandle::open(cwd, true).unwrap_or_else(|error| {
+        if error.kind == repo::RepoErrorKind::NotFound {
+            print_error("Directory does not contain a git repository");
+        } else {
+            print_error(&format!("Opening repository failed: {}", error));
+        }
+        process::exit(1);
+    });
+
+    let fetch_defaults = worktree_config
+        .as_ref()
+        .and_then(|config| config.fetch)
+        .unwrap_or_default();
+    repo.fetchall_with_options(repo::FetchConfig {
+        prune: args.prune || fetch_defaults.prune,
+        tags: args.tags || fetch_defaults.tags,
+    })
+    .unwrap_or_else(|error| {
+        print_error(&format!("Error fetching remotes: {}", error));
+        process::exit(1);
+    });
+    print_success("Fetched from all remotes");
+}
+
+fn worktree_pull(args: cmd::WorktreePullArgs, cwd: &Path) {
+    // Inside of a worktree, only that worktree is updated.
+    // From the root of the setup, all of them are.
+    let (root, current_worktree) = worktree::find_worktree_root(cwd).unwrap_or_else(|| {
+        print_error("Directory is not part of a worktree setup");
+        process::exit(1);
+    });
+
+    let repo = repo::RepoHandle::open(&root, true).unwrap_or_else(|error| {
+        if error.kind == repo::RepoErrorKind::NotFound {
+            print_error("Directory does not contain a git repository");
+        } else {
+            print_error(&format!("Opening repository failed: {}", error));
+        }
+        process::exit(1);
+    });
+
+    let fetch_defaults = repo::read_worktree_root_config(&root)
+        .unwrap_or_else(|error| {
+            print_error(&format!("Failed to read worktree configuration: {}", error));
+            process::exit(1);
+        })
+        .and_then(|config| config.fetch)
+        .unwrap_or_default();
+    repo.fetchall_with_options(fetch_defaults)
+        .unwrap_or_else(|error| {
+            print_error(&format!("Error fetching remotes: {}", error));
+            process::exit(1);
+        });
+
+    let worktrees = match current_worktree {
+        Some(name) if !args.all => vec![repo::Worktree::new(&name)],
+        _ => repo.get_worktrees().unwrap_or_else(|error| {
+            print_error(&format!("Error getting worktrees: {}", error));
+            process::exit(1);
+        }),
+    };
+
+    let mut failures = false;
+    let mut skipped = Vec::new();
+    for worktree in worktrees {
+        match worktree.forward_branch(&root, args.rebase, args.stash) {
+            Ok(Some(warning)) => {
+                print_warning(&format!("{}: {}", worktree.name(), warning));
+                skipped.push(worktree.name().to_string());
+            }
+            Ok(None) => print_success(&format!("{}: Done", worktree.name())),
+            Err(error) => {
+                print_error(&format!(
+                    "{}: Error updating worktree branch: {}",
+                    worktree.name(),
+                    error
+                ));
+                failures = true;
+            }
+        }
+    }
+    if !skipped.is_empty() {
+        print_warning(&format!(
+            "Skipped {} worktree(s): {}",
+            skipped.len(),
+            skipped.join(", ")
+        ));
+    }
+    if failures || !skipped.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn worktree_rebase(
+    args: cmd::WorktreeRebaseArgs,
+    cwd: &Path,
+    worktree_config: &Option<repo::WorktreeRootConfig>,
+) {
+    if args.rebase && !args.pull {
+        print_error("There is no point in using --rebase without --pull");
+        process::exit(1);
+    }
+    let repo = repo::RepoHandle::open(cwd, true).unwrap_or_else(|error| {
+        if error.kind == repo::RepoErrorKind::NotFound {
+            print_error("Directory does not contain a git repository");
+        } else {
+            print_error(&format!("Opening repository failed: {}", error));
+        }
+        process::exit(1);
+    });
+
+    let options = worktree::RebaseOptions {
+        pull: args.pull,
+        rebase: args.rebase,
+        stash: args.stash,
+        onto: args.onto.as_deref(),
+    };
+    let mut failures = false;
+    let result =
+        worktree::rebase_worktrees(&repo, cwd, worktree_config, &options, |name, outcome| {
+            match outcome {
+                worktree::RebaseOutcome::Rebased => print_success(&format!("{}: Done", name)),
+                worktree::RebaseOutcome::Skipped(reason) => {
+                    print_action(&format!("{}: {}, skipping", name, reason))
                 }
-                cmd::WorktreeAction::Rebase(args) => {
-                    if args.rebase && !args.pull {
-                        print_error("There is no point in using --rebase without --pull");
-                        process::exit(1);
-                    }
-                    let repo = repo::RepoHandle::open(&cwd, true).unwrap_or_else(|error| {
-                        if error.kind == repo::RepoErrorKind::NotFound {
-                            print_error("Directory does not contain a git repository");
-                        } else {
-                            print_error(&format!("Opening repository failed: {}", error));
-                        }
-                        process::exit(1);
-                    });
+                worktree::RebaseOutcome::Failed(warning) => {
+                    failures = true;
+                    print_warning(&format!("{}: {}", name, warning));
+                }
+            }
+        });
+    if let Err(error) = result {
+        print_error(&error);
+        process::exit(1);
+    }
+    if failures {
+        process::exit(1);
+    }
+}
 
-                    if args.pull {
-                        repo.fetchall().unwrap_or_else(|error| {
-                            print_error(&format!("Error fetching remotes: {}", error));
-                            process::exit(1);
-                        });
-                    }
+/// Like `Duration::try_from_secs_f64()`, which is not available in all
+/// supported Rust versions
+fn duration_from_secs(seconds: f64) -> Result<std::time::Duration, String> {
+    if seconds.is_nan() {
+        return Err(String::from("not a number"));
+    }
+    if seconds < 0.0 {
+        return Err(String::from("must not be negative"));
+    }
+    if seconds >= u64::MAX as f64 {
+        return Err(String::from("too large"));
+    }
+    Ok(std::time::Duration::from_secs_f64(seconds))
+}
 
-                    let config = repo::read_worktree_root_config(&cwd).unwrap_or_else(|error| {
-                        print_error(&format!("Failed to read worktree configuration: {}", error));
-                        process::exit(1);
-                    });
+/// Reads the configuration and returns its trees together with the
+/// repositories that exist locally, optionally filtered by tree and tag.
+/// Warnings about skipped repositories are printed, and any error exits the
+/// process.
+fn get_repo_targets(
+    config_path: &str,
+    tree: Option<&str>,
+    tag: Option<&str>,
+) -> (Vec<config::ConfigTree>, Vec<exec::Target>) {
+    let config = match config::read_repos_config(config_path) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        }
+    };
 
-                    let worktrees = repo.get_worktrees().unwrap_or_else(|error| {
-                        print_error(&format!("Error getting worktrees: {}", error));
-                        process::exit(1);
-                    });
-
-                    let mut failures = false;
-
-                    for worktree in &worktrees {
-                        if args.pull {
-                            if let Some(warning) = worktree
-                                .forward_branch(args.rebase, args.stash)
-                                .unwrap_or_else(|error| {
-                                    print_error(&format!(
-                                        "Error updating worktree branch: {}",
-                                        error
-                                    ));
-                                    process::exit(1);
-                                })
-                            {
-                                failures = true;
-                                print_warning(&format!("{}: {}", worktree.name(), warning));
-                            }
-                        }
-                    }
+    let trees = config.trees().unwrap_or_else(|error| {
+        print_error(&format!("Error getting repositories: {}", error));
+        process::exit(1);
+    });
 
-                    for worktree in &worktrees {
-                        if let Some(warning) = worktree
-                            .rebase_onto_default(&config, args.stash)
-                            .unwrap_or_else(|error| {
-                                print_error(&format!("Error rebasing worktree branch: {}", error));
-                                process::exit(1);
-                            })
-                        {
-                            failures = true;
-                            print_warning(&format!("{}: {}", worktree.name(), warning));
-                        } else {
-                            print_success(&format!("{}: Done", worktree.name()));
-                        }
-                    }
-                    if failures {
-                        process::exit(1);
-                    }
+    let (targets, warnings) = match exec::repo_targets(trees.clone(), tree, tag) {
+        Ok(result) => result,
+        Err(error) => {
+            print_error(&format!("Error getting repositories: {}", error));
+            process::exit(1);
+        }
+    };
+    for warning in warnings {
+        print_warning(&warning);
+    }
+
+    (trees, targets)
+}
+
+/// Adds the repositories of `config` to the configuration file `merge_into`,
+/// or prints them in the given format if there is none
+fn write_found_config(
+    config: config::Config,
+    merge_into: Option<String>,
+    format: cmd::ConfigFormat,
+) {
+    if let Some(config_path) = merge_into {
+        let trees = match config.trees() {
+            Ok(trees) => trees,
+            Err(error) => {
+                print_error(&format!("Error getting repositories: {}", error));
+                process::exit(1);
+            }
+        };
+        match config::merge_into_file(&config_path, trees) {
+            Ok(added) => {
+                for name in &added {
+                    print_repo_success(name, "Added to configuration");
                 }
+                if added.is_empty() {
+                    print_success("No new repositories found");
+                }
+            }
+            Err(error) => {
+                print_error(&format!("Error merging configuration: {}", error));
+                process::exit(1);
             }
         }
+        return;
+    }
+
+    match format {
+        cmd::ConfigFormat::Toml => {
+            let toml = match config.as_toml() {
+                Ok(toml) => toml,
+                Err(error) => {
+                    print_error(&format!("Failed converting config to TOML: {}", &error));
+                    process::exit(1);
+                }
+            };
+            print!("{}", toml);
+        }
+        cmd::ConfigFormat::Yaml => {
+            let yaml = match config.as_yaml() {
+                Ok(yaml) => yaml,
+                Err(error) => {
+                    print_error(&format!("Failed converting config to YAML: {}", &error));
+                    process::exit(1);
+                }
+            };
+            print!("{}", yaml);
+        }
+    }
+}
+
+/// Syncs all trees of the configuration, printing the outcome of every
+/// repository. Exits if anything went wrong.
+fn run_sync(config: config::Config, options: &tree::SyncOptions) {
+    let plan = match tree::SyncPlan::new(config, options) {
+        Ok(plan) => plan,
+        Err(error) => {
+            print_error(&format!("Sync error: {}", error));
+            process::exit(1);
+        }
+    };
+
+    let report = plan.execute(options, |event| match event {
+        tree::SyncEvent::Message(name, message) => print_repo_message(name, message),
+        tree::SyncEvent::Debug(message) => print_debug(message),
+        tree::SyncEvent::Synced(repo) => match &repo.result {
+            Ok(_) => print_repo_success(&repo.name, "OK"),
+            Err(error) => print_repo_error(&repo.name, error),
+        },
+    });
+
+    for error in &report.errors {
+        print_error(error);
+    }
+
+    for path in &report.unmanaged {
+        let message = format!(
+            "Found unmanaged repository: \"{}\"",
+            path::path_as_string(path)
+        );
+        match report.unmanaged_policy {
+            config::UnmanagedPolicy::Error => print_error(&message),
+            _ => print_warning(&message),
+        }
+    }
+
+    if enabled(Level::Info) {
+        println!("{}", table::get_sync_summary_table(&report));
+    }
+
+    if !report.failed().is_empty() {
+        process::exit(EXIT_SYNC_FAILED);
+    }
+    if !report.success() {
+        process::exit(1);
     }
 }
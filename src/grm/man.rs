@@ -0,0 +1,60 @@
+//! Generation of man pages from the command line definition. Like git, every
+//! subcommand gets its own page, e.g. `grm-repos-sync-config(1)`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use grm::path;
+
+/// Writes the man pages of `cmd` and all its (visible) subcommands into
+/// `directory`, creating it if necessary. Returns the paths of all pages.
+pub fn generate(mut cmd: clap::Command, directory: &Path) -> Result<Vec<PathBuf>, String> {
+    fs::create_dir_all(directory).map_err(|error| {
+        format!(
+            "Error creating \"{}\": {}",
+            path::path_as_string(directory),
+            error
+        )
+    })?;
+
+    // Propagates the global options and the version to all subcommands, so
+    // they show up in their pages as well
+    cmd.build();
+
+    let mut pages = Vec::new();
+    write_page(cmd, directory, &mut pages)?;
+    Ok(pages)
+}
+
+fn write_page(
+    cmd: clap::Command,
+    directory: &Path,
+    pages: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let name = cmd.get_name().to_string();
+
+    let mut content = Vec::new();
+    clap_mangen::Man::new(cmd.clone())
+        .render(&mut content)
+        .map_err(|error| error.to_string())?;
+
+    let page = directory.join(format!("{}.1", name));
+    fs::write(&page, content).map_err(|error| {
+        format!(
+            "Error writing \"{}\": {}",
+            path::path_as_string(&page),
+            error
+        )
+    })?;
+    pages.push(page);
+
+    for subcommand in cmd
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+    {
+        let subcommand_name = format!("{}-{}", name, subcommand.get_name());
+        write_page(subcommand.clone().name(subcommand_name), directory, pages)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,405 @@
+//! The interactive dashboard of `grm repos status --interactive`: a list of all
+//! configured repositories and their worktrees, with keys to run common
+//! actions on the selected entry.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use console::{Key, Style, Term};
+
+use grm::config;
+use grm::exec;
+use grm::output::*;
+use grm::repo;
+
+const HELP: &str = "[j/k] move  [f]etch  [p]ull  [c]lean  [s]hell  [r]efresh  [q]uit";
+
+enum RowKind {
+    Repo,
+    WorktreeSetup,
+    Worktree,
+}
+
+struct Row {
+    kind: RowKind,
+    name: String,
+    /// The directory the entry lives in. For worktrees, this is the directory
+    /// of the worktree itself.
+    path: PathBuf,
+    /// The directory of the repository the entry belongs to
+    repo_path: PathBuf,
+    /// The environment of the repository, see [`exec::repo_env()`]
+    env: Vec<(String, std::ffi::OsString)>,
+    summary: String,
+}
+
+struct Dashboard {
+    targets: Vec<exec::Target>,
+    rows: Vec<Row>,
+    selected: usize,
+    /// Index of the first row on the screen
+    offset: usize,
+    message: String,
+}
+
+fn format_changes(changes: &Option<repo::RepoChanges>) -> String {
+    match changes {
+        None => String::from("clean"),
+        Some(changes) => {
+            let mut out = Vec::new();
+            if changes.files_new > 0 {
+                out.push(format!("{} new", changes.files_new));
+            }
+            if changes.files_modified > 0 {
+                out.push(format!("{} modified", changes.files_modified));
+            }
+            if changes.files_deleted > 0 {
+                out.push(format!("{} deleted", changes.files_deleted));
+            }
+            out.join(", ")
+        }
+    }
+}
+
+fn format_ahead_behind(ahead: usize, behind: usize) -> String {
+    match (ahead, behind) {
+        (0, 0) => String::from(""),
+        (ahead, 0) => format!(" [+{}]", ahead),
+        (0, behind) => format!(" [-{}]", behind),
+        (ahead, behind) => format!(" [+{}/-{}]", ahead, behind),
+    }
+}
+
+fn repo_summary(path: &Path) -> Result<String, String> {
+    let repo_handle = repo::RepoHandle::open(path, false).map_err(|error| error.to_string())?;
+    let status = repo_handle.status(false)?;
+
+    let head = match &status.head {
+        None => return Ok(String::from("empty")),
+        Some(head) => head,
+    };
+    let tracking = status
+        .branches
+        .iter()
+        .find(|(branch_name, _)| branch_name == head)
+        .and_then(|(_, remote_branch)| remote_branch.as_ref())
+        .map(|(_, tracking_status)| match tracking_status {
+            repo::RemoteTrackingStatus::UpToDate => String::from(""),
+            repo::RemoteTrackingStatus::Ahead(d) => format_ahead_behind(*d, 0),
+            repo::RemoteTrackingStatus::Behind(d) => format_ahead_behind(0, *d),
+            repo::RemoteTrackingStatus::Diverged(d1, d2) => format_ahead_behind(*d1, *d2),
+        })
+        .unwrap_or_else(|| String::from(" <!local>"));
+
+    Ok(format!(
+        "{}{}, {}",
+        head,
+        tracking,
+        format_changes(&status.changes)
+    ))
+}
+
+/// Adds the rows for a repository: a single one for normal repositories, and
+/// one for the repository plus one per worktree for worktree setups
+fn add_rows(rows: &mut Vec<Row>, warnings: &mut Vec<String>, target: &exec::Target) {
+    if !repo::RepoHandle::detect_worktree(&target.path) {
+        rows.push(Row {
+            kind: RowKind::Repo,
+            summary: repo_summary(&target.path).unwrap_or_else(|error| error),
+            name: target.name.clone(),
+            repo_path: target.path.clone(),
+            path: target.path.clone(),
+            env: target.env.clone(),
+        });
+        return;
+    }
+
+    let statuses = repo::RepoHandle::open(&target.path, true)
+        .map_err(|error| error.to_string())
+        .and_then(|repo_handle| repo_handle.worktree_statuses(&target.path));
+
+    let (statuses, errors) = match statuses {
+        Ok(result) => result,
+        Err(error) => {
+            rows.push(Row {
+                kind: RowKind::WorktreeSetup,
+                summary: error,
+                name: target.name.clone(),
+                repo_path: target.path.clone(),
+                path: target.path.clone(),
+                env: target.env.clone(),
+            });
+            return;
+        }
+    };
+    for error in errors {
+        warnings.push(format!("{}: {}", target.name, error));
+    }
+
+    rows.push(Row {
+        kind: RowKind::WorktreeSetup,
+        summary: format!("{} worktrees", statuses.len()),
+        name: target.name.clone(),
+        repo_path: target.path.clone(),
+        path: target.path.clone(),
+        env: target.env.clone(),
+    });
+    for status in statuses {
+        rows.push(Row {
+            kind: RowKind::Worktree,
+            summary: format!(
+                "{}{}, {}",
+                status.branch,
+                match status.upstream {
+                    Some(_) => format_ahead_behind(status.ahead, status.behind),
+                    None => String::from(" <!local>"),
+                },
+                format_changes(&status.changes)
+            ),
+            path: target.path.join(&status.name),
+            name: status.name,
+            repo_path: target.path.clone(),
+            env: target.env.clone(),
+        });
+    }
+}
+
+/// Shows a message in the last line of the screen
+fn draw_message(term: &Term, message: &str) -> std::io::Result<()> {
+    let (height, width) = term.size();
+    let style = Style::new().dim().force_styling(use_color(term));
+
+    term.move_cursor_to(0, height as usize - 1)?;
+    term.clear_line()?;
+    term.write_str(
+        &style
+            .apply_to(console::truncate_str(message, width as usize, "…"))
+            .to_string(),
+    )?;
+    term.flush()
+}
+
+impl Dashboard {
+    fn new(targets: Vec<exec::Target>, warnings: Vec<String>) -> Self {
+        let mut dashboard = Self {
+            targets,
+            rows: Vec::new(),
+            selected: 0,
+            offset: 0,
+            message: String::new(),
+        };
+        dashboard.refresh();
+        if !warnings.is_empty() {
+            dashboard.message = warnings.join("; ");
+        }
+        dashboard
+    }
+
+    /// Reads the status of all repositories again
+    fn refresh(&mut self) {
+        let mut rows = Vec::new();
+        let mut warnings = Vec::new();
+
+        for target in &self.targets {
+            add_rows(&mut rows, &mut warnings, target);
+        }
+
+        self.rows = rows;
+        self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+        if !warnings.is_empty() {
+            self.message = warnings.join("; ");
+        }
+    }
+
+    fn draw(&mut self, term: &Term) -> std::io::Result<()> {
+        let (height, width) = term.size();
+        // Header, separator and message line
+        let visible = (height as usize).saturating_sub(3).max(1);
+        let width = width as usize;
+
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + visible {
+            self.offset = self.selected + 1 - visible;
+        }
+
+        let color = use_color(term);
+        let selected_style = Style::new().reverse().force_styling(color);
+
+        term.clear_screen()?;
+        term.write_line(&console::truncate_str(HELP, width, "…"))?;
+        term.write_line(&"─".repeat(width))?;
+
+        if self.rows.is_empty() {
+            term.write_line("No repositories found")?;
+        }
+
+        let name_width = self
+            .rows
+            .iter()
+            .map(|row| match row.kind {
+                RowKind::Worktree => row.name.len() + 2,
+                RowKind::Repo | RowKind::WorktreeSetup => row.name.len(),
+            })
+            .max()
+            .unwrap_or(0);
+
+        for (i, row) in self.rows.iter().enumerate().skip(self.offset).take(visible) {
+            let name = match row.kind {
+                RowKind::Worktree => format!("  {}", row.name),
+                RowKind::Repo | RowKind::WorktreeSetup => row.name.clone(),
+            };
+            let line = format!(" {:width$}  {}", name, row.summary, width = name_width);
+            let line = console::truncate_str(&line, width, "…");
+            if i == self.selected {
+                term.write_line(
+                    &selected_style
+                        .apply_to(format!("{:width$}", line))
+                        .to_string(),
+                )?;
+            } else {
+                term.write_line(&line)?;
+            }
+        }
+
+        draw_message(term, &self.message)
+    }
+
+    fn fetch(&self, row: &Row) -> Result<String, String> {
+        let repo_handle = repo::RepoHandle::open(
+            &row.repo_path,
+            matches!(row.kind, RowKind::WorktreeSetup | RowKind::Worktree),
+        )
+        .map_err(|error| error.to_string())?;
+        repo_handle.fetchall()?;
+        Ok(format!("{}: Fetched all remotes", row.name))
+    }
+
+    fn pull(&self, row: &Row) -> Result<String, String> {
+        if let RowKind::WorktreeSetup = row.kind {
+            return Err(String::from("Select a worktree to pull"));
+        }
+        let repo_handle =
+            repo::RepoHandle::open(&row.path, false).map_err(|error| error.to_string())?;
+        let branch_name = repo_handle.head_branch()?.name()?;
+        Ok(
+            match repo_handle.forward_branch(&branch_name, false, false)? {
+                Some(warning) => format!("{}: {}", row.name, warning),
+                None => format!("{}: Done", row.name),
+            },
+        )
+    }
+
+    fn clean(&self, row: &Row) -> Result<String, String> {
+        if let RowKind::Repo = row.kind {
+            return Err(String::from("Only worktree setups can be cleaned"));
+        }
+        let repo_handle =
+            repo::RepoHandle::open(&row.repo_path, true).map_err(|error| error.to_string())?;
+        let warnings = repo_handle
+            .cleanup_worktrees(&row.repo_path, None)?
+            .into_iter()
+            .filter_map(|message| match message {
+                Message::Warning(warning) => Some(warning),
+                _ => None,
+            })
+            .collect::<Vec<String>>();
+        Ok(match warnings.is_empty() {
+            true => format!("{}: Cleaned up worktrees", row.name),
+            false => format!("{}: {}", row.name, warnings.join("; ")),
+        })
+    }
+
+    /// Opens the user's shell in the directory of the entry, and returns once
+    /// it exits
+    fn shell(&self, term: &Term, row: &Row) -> Result<String, String> {
+        let shell = std::env::var_os("SHELL").unwrap_or_else(|| "sh".into());
+
+        term.clear_screen().map_err(|error| error.to_string())?;
+        term.show_cursor().map_err(|error| error.to_string())?;
+        term.write_line(&format!(
+            "Opening shell in \"{}\", exit to return",
+            row.path.display()
+        ))
+        .map_err(|error| error.to_string())?;
+
+        let status = Command::new(&shell)
+            .current_dir(&row.path)
+            .envs(row.env.iter().map(|(key, value)| (key, value)))
+            .status();
+
+        term.hide_cursor().map_err(|error| error.to_string())?;
+
+        match status {
+            Ok(_) => Ok(String::new()),
+            Err(error) => Err(format!(
+                "Error running \"{}\": {}",
+                shell.to_string_lossy(),
+                error
+            )),
+        }
+    }
+
+    fn run(&mut self, term: &Term) -> std::io::Result<()> {
+        loop {
+            self.draw(term)?;
+
+            let key = term.read_key()?;
+            let row = self.rows.get(self.selected);
+
+            let result = match (key, row) {
+                (Key::Char('q') | Key::Escape, _) => return Ok(()),
+                (Key::Char('j') | Key::ArrowDown, _) => {
+                    if self.selected + 1 < self.rows.len() {
+                        self.selected += 1;
+                    }
+                    continue;
+                }
+                (Key::Char('k') | Key::ArrowUp, _) => {
+                    self.selected = self.selected.saturating_sub(1);
+                    continue;
+                }
+                (Key::Char('r'), _) => (Ok(String::new()), Vec::new()),
+                // The actions must not print over the dashboard, so their
+                // messages are shown below it instead
+                (Key::Char('f'), Some(row)) => {
+                    draw_message(term, &format!("{}: Fetching …", row.name))?;
+                    capture(|| self.fetch(row))
+                }
+                (Key::Char('p'), Some(row)) => capture(|| self.pull(row)),
+                (Key::Char('c'), Some(row)) => capture(|| self.clean(row)),
+                (Key::Char('s'), Some(row)) => (self.shell(term, row), Vec::new()),
+                _ => continue,
+            };
+
+            let (result, captured) = result;
+            let mut message = match result {
+                Ok(message) => message,
+                Err(error) => format!("Error: {}", error),
+            };
+            if !captured.is_empty() {
+                message = format!("{} ({})", message, captured.join("; "));
+            }
+            self.message = message;
+            self.refresh();
+        }
+    }
+}
+
+pub fn run(config: config::Config) -> Result<(), String> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        return Err(String::from("Interactive mode requires a terminal"));
+    }
+
+    let (targets, warnings) = exec::repo_targets(config.trees()?, None, None)?;
+    let mut dashboard = Dashboard::new(targets, warnings);
+
+    term.hide_cursor().map_err(|error| error.to_string())?;
+    let result = dashboard.run(&term);
+    // Leave the terminal usable, even if drawing failed
+    let _ = term.clear_screen();
+    let _ = term.show_cursor();
+
+    result.map_err(|error| format!("Terminal error: {}", error))
+}
@@ -0,0 +1,119 @@
+//! Conditions on the machine that GRM runs on, so a single configuration can
+//! be shared between several machines that each only need some of its trees
+//! and repositories.
+
+use once_cell::sync::Lazy;
+
+use super::glob;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conditions(hosts: Option<&[&str]>, os: Option<&[&str]>) -> HostConditions {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        HostConditions {
+            hosts: hosts.map(strings),
+            os: os.map(strings),
+        }
+    }
+
+    #[test]
+    fn check_host_conditions() {
+        let host = Some("laptop.example.com");
+
+        assert!(conditions(None, None).matches(host, "linux").unwrap());
+        assert!(conditions(None, None).matches(None, "linux").unwrap());
+
+        let laptop = conditions(Some(&["workstation", "laptop"]), None);
+        assert!(laptop.matches(host, "linux").unwrap());
+        assert!(laptop.matches(Some("laptop"), "linux").unwrap());
+        assert!(!laptop.matches(Some("server"), "linux").unwrap());
+        // Without a hostname, it cannot be one of them
+        assert!(!laptop.matches(None, "linux").unwrap());
+
+        let pattern = conditions(Some(&["*.example.com"]), None);
+        assert!(pattern.matches(host, "linux").unwrap());
+        assert!(!pattern.matches(Some("laptop"), "linux").unwrap());
+
+        let macos = conditions(None, Some(&["macos"]));
+        assert!(macos.matches(host, "macos").unwrap());
+        assert!(!macos.matches(host, "linux").unwrap());
+
+        let both = conditions(Some(&["laptop"]), Some(&["linux"]));
+        assert!(both.matches(host, "linux").unwrap());
+        assert!(!both.matches(host, "windows").unwrap());
+        assert!(!both.matches(Some("server"), "linux").unwrap());
+    }
+}
+
+/// Overrides the hostname, e.g. for machines with generated names
+pub const HOSTNAME_ENV: &str = "GRM_HOSTNAME";
+
+fn read_hostname() -> Option<String> {
+    if let Ok(hostname) = std::env::var(HOSTNAME_ENV) {
+        return Some(hostname);
+    }
+
+    // Saves running a command on Linux
+    if let Ok(hostname) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        return Some(hostname);
+    }
+
+    std::process::Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+}
+
+static HOSTNAME: Lazy<Option<String>> = Lazy::new(|| {
+    read_hostname()
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+});
+
+/// The name of the current machine, `None` if it cannot be determined
+pub fn hostname() -> Option<&'static str> {
+    HOSTNAME.as_deref()
+}
+
+/// Where a tree or repository is synced. Without any conditions, it is synced
+/// everywhere.
+#[derive(Debug, Default)]
+pub struct HostConditions {
+    /// Patterns for hostnames, matched against the full and the short
+    /// hostname, i.e. without domain
+    pub hosts: Option<Vec<String>>,
+    /// Operating systems, like `linux`, `macos` or `windows`
+    pub os: Option<Vec<String>>,
+}
+
+impl HostConditions {
+    fn matches(&self, hostname: Option<&str>, os: &str) -> Result<bool, String> {
+        if let Some(patterns) = &self.hosts {
+            let hostname = match hostname {
+                Some(hostname) => hostname,
+                None => return Ok(false),
+            };
+            let short = hostname.split('.').next().unwrap_or(hostname);
+            let patterns = glob::PatternSet::new(patterns)?;
+            if !(patterns.is_match(hostname) || patterns.is_match(short)) {
+                return Ok(false);
+            }
+        }
+
+        if let Some(systems) = &self.os {
+            if !systems.iter().any(|system| system == os) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Whether the current machine satisfies the conditions
+    pub fn match_current(&self) -> Result<bool, String> {
+        self.matches(hostname(), std::env::consts::OS)
+    }
+}
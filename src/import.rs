@@ -0,0 +1,557 @@
+//! Converting the configuration of other repository managers into trees, so
+//! users of these tools can switch to GRM without writing a configuration by
+//! hand.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::net;
+use super::path;
+use super::repo;
+use super::tree;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_shell_words() {
+        assert_eq!(
+            shell_words(r#"git clone 'a b' "c \"d\"" e\ f"#).unwrap(),
+            vec!["git", "clone", "a b", "c \"d\"", "e f"]
+        );
+        assert_eq!(
+            shell_words("git clone x&&cd x").unwrap(),
+            vec!["git", "clone", "x", "&", "&", "cd", "x"]
+        );
+        assert!(shell_words("git clone 'x").is_err());
+    }
+
+    #[test]
+    fn check_parse_git_clone() {
+        let clone = parse_git_clone(
+            "git clone -b main --depth=1 -o upstream https://example.com/x.git dir && make",
+        )
+        .unwrap();
+        assert_eq!(clone.url, "https://example.com/x.git");
+        assert_eq!(clone.branch.as_deref(), Some("main"));
+        assert_eq!(clone.remote_name.as_deref(), Some("upstream"));
+        assert_eq!(clone.depth, Some(1));
+
+        assert!(parse_git_clone("svn checkout https://example.com/x").is_none());
+    }
+
+    #[test]
+    fn check_mr() {
+        let content = r#"
+[DEFAULT]
+git_gc = git gc "$@"
+
+# Work stuff
+[src/one]
+checkout = git clone 'git@example.com:user/one.git' 'one'
+
+[src/nested/two]
+checkout =
+    git clone https://example.com/two.git two &&
+    cd two && git submodule update --init
+update = git pull
+
+[/opt/three]
+checkout = git clone -b stable file:///srv/three.git three
+
+[src/svn]
+checkout = svn checkout https://example.com/svn svn
+
+[src/broken]
+update = git pull
+"#;
+        let (trees, warnings) = parse_mrconfig(content, Path::new("/home/me")).unwrap();
+
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[0].root, "/home/me");
+        assert_eq!(
+            trees[0]
+                .repos
+                .iter()
+                .map(|repo| repo.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["src/one", "src/nested/two"]
+        );
+        let remotes = trees[0].repos[0].remotes.as_ref().unwrap();
+        assert_eq!(remotes[0].name, "origin");
+        assert_eq!(remotes[0].url, "git@example.com:user/one.git");
+        assert_eq!(remotes[0].remote_type, repo::RemoteType::Ssh);
+
+        assert_eq!(trees[1].root, "/opt");
+        assert_eq!(trees[1].repos[0].name, "three");
+        assert_eq!(
+            trees[1].repos[0].clone_options.branch.as_deref(),
+            Some("stable")
+        );
+
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn check_gita() {
+        let content = r#"/home/me/src/one,one,,
+"/home/me/with,comma",comma,,
+"/home/me/with ""quote""",quote,,
+
+/home/me/old
+"#;
+        assert_eq!(
+            parse_gita(content),
+            vec![
+                PathBuf::from("/home/me/src/one"),
+                PathBuf::from("/home/me/with,comma"),
+                PathBuf::from("/home/me/with \"quote\""),
+                PathBuf::from("/home/me/old"),
+            ]
+        );
+    }
+}
+
+/// The tools that configurations can be imported from
+pub enum Source {
+    /// myrepos, see <https://myrepos.branchable.com/>
+    Mr,
+    /// ghq, see <https://github.com/x-motemen/ghq>
+    Ghq,
+    /// gita, see <https://github.com/nosarthur/gita>
+    Gita,
+    /// vcsh, see <https://github.com/RichiH/vcsh>
+    Vcsh,
+}
+
+/// Returns where the given tool keeps its configuration by default. For ghq,
+/// this is the root directory all repositories are cloned into, for vcsh the
+/// directory containing its bare repositories.
+pub fn default_path(source: &Source) -> Result<PathBuf, String> {
+    let home = PathBuf::from(path::env_home().map_err(|error| error.to_string())?);
+    match source {
+        Source::Mr => Ok(home.join(".mrconfig")),
+        Source::Ghq => {
+            let configured = git2::Config::open_default()
+                .and_then(|config| config.get_path("ghq.root"))
+                .ok();
+            match configured {
+                Some(root) => Ok(path::expand_path(&root).map_err(|error| error.to_string())?),
+                None => Ok(home.join("ghq")),
+            }
+        }
+        Source::Gita => Ok(config_home(&home).join("gita").join("repos.csv")),
+        Source::Vcsh => Ok(config_home(&home).join("vcsh").join("repo.d")),
+    }
+}
+
+/// `$XDG_CONFIG_HOME`, falling back to `~/.config` if it is not set
+fn config_home(home: &Path) -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home.join(".config"),
+    }
+}
+
+/// Reads the configuration of another tool from `path`. Entries that cannot be
+/// converted are skipped, a warning is returned for each of them.
+pub fn import(source: Source, path: &Path) -> Result<(Vec<tree::Tree>, Vec<String>), String> {
+    match source {
+        Source::Mr => {
+            let content = std::fs::read_to_string(path)
+                .map_err(|error| format!("Error reading \"{}\": {}", path.display(), error))?;
+            // Relative paths in an mrconfig are relative to the directory the
+            // file is in
+            let base = std::fs::canonicalize(path)
+                .map_err(|error| format!("Error resolving \"{}\": {}", path.display(), error))?;
+            let base = base.parent().unwrap_or_else(|| Path::new("/"));
+            parse_mrconfig(&content, base)
+        }
+        Source::Ghq => import_ghq(path),
+        Source::Gita => {
+            let content = std::fs::read_to_string(path)
+                .map_err(|error| format!("Error reading \"{}\": {}", path.display(), error))?;
+            import_gita(&content)
+        }
+        Source::Vcsh => import_vcsh(path),
+    }
+}
+
+/// Splits a shell command into words, resolving quotes and escapes. Unquoted
+/// `;`, `&` and `|` are returned as words of their own, so the end of a
+/// command can be detected. Anything more complex (variables, subshells, ...)
+/// is not supported.
+fn shell_words(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(String::from("Unclosed single quote")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => word.push(c),
+                            None => return Err(String::from("Unclosed double quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(String::from("Unclosed double quote")),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+            ';' | '&' | '|' => {
+                words.extend(word.take());
+                words.push(c.to_string());
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// The parts of a `git clone` command that matter for the configuration
+struct GitClone {
+    url: String,
+    branch: Option<String>,
+    remote_name: Option<String>,
+    depth: Option<u32>,
+}
+
+/// Finds the first `git clone` in a shell command and returns its arguments.
+/// `None` if there is none.
+fn parse_git_clone(command: &str) -> Option<GitClone> {
+    let words = shell_words(command).ok()?;
+    let start = words
+        .windows(2)
+        .position(|pair| pair[0] == "git" && pair[1] == "clone")?;
+
+    let mut clone = GitClone {
+        url: String::new(),
+        branch: None,
+        remote_name: None,
+        depth: None,
+    };
+
+    let mut args = words[start + 2..]
+        .iter()
+        .take_while(|word| !matches!(word.as_str(), ";" | "&" | "|"));
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || inline_value.clone().or_else(|| args.next().cloned());
+
+        match option {
+            "-b" | "--branch" => clone.branch = value(),
+            "-o" | "--origin" => clone.remote_name = value(),
+            "--depth" => clone.depth = value().and_then(|depth| depth.parse().ok()),
+            // Options that take a value, which must not be mistaken for the URL
+            "-c" | "--config" | "-u" | "--upload-pack" | "--reference" | "--template"
+            | "--separate-git-dir" | "-j" | "--jobs" | "--filter" => {
+                value();
+            }
+            option if option.starts_with('-') => {}
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    clone.url = positional.into_iter().next()?;
+    Some(clone)
+}
+
+/// Parses the content of an mrconfig. Sections are the paths of the
+/// repositories, their `checkout` command contains the URL to clone from.
+///
+/// Repositories below `base` end up in a tree with that root, all others in a
+/// tree with their parent directory as root.
+fn parse_mrconfig(content: &str, base: &Path) -> Result<(Vec<tree::Tree>, Vec<String>), String> {
+    let mut sections: Vec<(String, BTreeMap<String, String>)> = Vec::new();
+    let mut last_key: Option<String> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // Indented lines continue the value of the previous line
+        if line.starts_with(char::is_whitespace) {
+            if let (Some((_, values)), Some(key)) = (sections.last_mut(), &last_key) {
+                if let Some(value) = values.get_mut(key) {
+                    value.push(' ');
+                    value.push_str(trimmed);
+                }
+            }
+            continue;
+        }
+
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            sections.push((
+                trimmed[1..trimmed.len() - 1].trim().to_string(),
+                BTreeMap::new(),
+            ));
+            last_key = None;
+            continue;
+        }
+
+        if let (Some((key, value)), Some((_, values))) =
+            (trimmed.split_once('='), sections.last_mut())
+        {
+            let key = key.trim().to_string();
+            values.insert(key.clone(), value.trim().to_string());
+            last_key = Some(key);
+        }
+    }
+
+    let mut trees: Vec<tree::Tree> = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (section, values) in sections {
+        if section == "DEFAULT" {
+            continue;
+        }
+
+        let checkout = match values.get("checkout") {
+            Some(checkout) => checkout,
+            None => {
+                warnings.push(format!("{}: No checkout command, skipping", section));
+                continue;
+            }
+        };
+
+        let clone = match parse_git_clone(checkout) {
+            Some(clone) => clone,
+            None => {
+                warnings.push(format!("{}: Not a git repository, skipping", section));
+                continue;
+            }
+        };
+
+        let remote_type = match repo::detect_remote_type(&clone.url) {
+            Ok(remote_type) => remote_type,
+            Err(error) => {
+                warnings.push(format!("{}: {}, skipping", section, error));
+                continue;
+            }
+        };
+
+        let repo_path =
+            base.join(path::expand_path(Path::new(&section)).map_err(|error| error.to_string())?);
+
+        let (root, name) = match repo_path.strip_prefix(base) {
+            Ok(relative) => (base.to_path_buf(), path::path_as_name(relative)),
+            Err(_) => (
+                repo_path
+                    .parent()
+                    .map_or_else(|| PathBuf::from("/"), Path::to_path_buf),
+                repo_path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map(String::from),
+            ),
+        };
+        let name = match name {
+            Some(name) if !name.is_empty() => name,
+            _ => {
+                warnings.push(format!("{}: Invalid path, skipping", section));
+                continue;
+            }
+        };
+
+        let mut repo = repo::Repo::new(
+            name,
+            vec![repo::Remote {
+                name: clone.remote_name.unwrap_or_else(|| String::from("origin")),
+                url: clone.url,
+                push_urls: Vec::new(),
+                remote_type,
+                push_mirror: false,
+                primary: false,
+                tls: net::TlsOptions::default(),
+                refspecs: Vec::new(),
+                credentials: None,
+                create: false,
+            }],
+        );
+        repo.clone_options.branch = clone.branch;
+        repo.clone_options.depth = clone.depth;
+
+        let root = path::path_as_string(&root);
+        match trees.iter_mut().find(|tree| tree.root == root) {
+            Some(tree) => tree.repos.push(repo),
+            None => trees.push(tree::Tree {
+                root,
+                repos: vec![repo],
+            }),
+        }
+    }
+
+    Ok((trees, warnings))
+}
+
+/// ghq clones every repository to `<root>/<host>/<owner>/<repo>`, so the root
+/// becomes the root of the tree and the rest the name of the repository
+fn import_ghq(root: &Path) -> Result<(Vec<tree::Tree>, Vec<String>), String> {
+    if !root.is_dir() {
+        return Err(format!("\"{}\" is not a directory", root.display()));
+    }
+
+    let (tree, mut warnings) = super::find_in_tree(root, None, &tree::SearchOptions::default())?;
+
+    for repo in &tree.repos {
+        if repo.name.split('/').count() < 3 {
+            warnings.push(format!(
+                "{}: Not in the <host>/<owner>/<repo> layout of ghq",
+                repo.name
+            ));
+        }
+    }
+
+    match tree.repos.is_empty() {
+        true => Ok((Vec::new(), warnings)),
+        false => Ok((vec![tree], warnings)),
+    }
+}
+
+/// Returns the paths of the repositories in gita's `repos.csv`. Every line
+/// contains the path of a repository in the first column, followed by its
+/// name in gita and some flags, which have no equivalent in GRM.
+fn parse_gita(content: &str) -> Vec<PathBuf> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let path = match line.strip_prefix('"') {
+                // Quoted fields escape quotes by doubling them
+                Some(quoted) => {
+                    let mut path = String::new();
+                    let mut chars = quoted.chars().peekable();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '"' if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                path.push('"');
+                            }
+                            '"' => break,
+                            c => path.push(c),
+                        }
+                    }
+                    path
+                }
+                None => line.split(',').next().unwrap_or_default().to_string(),
+            };
+            match path.is_empty() {
+                true => None,
+                false => Some(PathBuf::from(path)),
+            }
+        })
+        .collect()
+}
+
+/// gita keeps a flat list of repositories anywhere on the disk, so every
+/// directory containing repositories becomes a tree of its own
+fn import_gita(content: &str) -> Result<(Vec<tree::Tree>, Vec<String>), String> {
+    let mut trees: Vec<tree::Tree> = Vec::new();
+    let mut warnings = Vec::new();
+
+    for repo_path in parse_gita(content) {
+        if !repo_path.is_dir() {
+            warnings.push(format!(
+                "{}: Directory does not exist, skipping",
+                repo_path.display()
+            ));
+            continue;
+        }
+
+        let root = repo_path
+            .parent()
+            .map_or_else(|| PathBuf::from("/"), Path::to_path_buf);
+
+        let (repo, repo_warnings) = super::inspect_repo(&root, &repo_path)?;
+        warnings.extend(repo_warnings);
+
+        if let Some(repo) = repo {
+            let root = path::path_as_string(&root);
+            match trees.iter_mut().find(|tree| tree.root == root) {
+                Some(tree) => tree.repos.push(repo),
+                None => trees.push(tree::Tree {
+                    root,
+                    repos: vec![repo],
+                }),
+            }
+        }
+    }
+
+    Ok((trees, warnings))
+}
+
+/// vcsh keeps one bare repository per `<name>.git` in its `repo.d` directory,
+/// which becomes the root of the tree
+fn import_vcsh(root: &Path) -> Result<(Vec<tree::Tree>, Vec<String>), String> {
+    let entries = std::fs::read_dir(root)
+        .map_err(|error| format!("Error reading \"{}\": {}", root.display(), error))?;
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir() && path.extension().map_or(false, |ext| ext == "git"))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+
+    let mut repos = Vec::new();
+    let mut warnings = Vec::new();
+
+    for repo_path in paths {
+        let (repo, repo_warnings) = super::inspect_repo(root, &repo_path)?;
+        warnings.extend(repo_warnings);
+        // The working directory of a vcsh repository is the home directory,
+        // so git does not consider it bare. In its own directory it is, and
+        // it uses regular remote tracking branches.
+        repos.extend(repo.map(|repo| repo::Repo {
+            clone_mode: repo::CloneMode::Bare,
+            ..repo
+        }));
+    }
+
+    if repos.is_empty() {
+        return Ok((Vec::new(), warnings));
+    }
+
+    // GRM only knows about the repositories, not about their work tree in the
+    // home directory
+    warnings.push(String::from(
+        "vcsh repositories check out into your home directory, GRM only syncs their remotes. Use \"vcsh clone\" to set them up on a new machine",
+    ));
+
+    Ok((
+        vec![tree::Tree {
+            root: path::path_as_string(root),
+            repos,
+        }],
+        warnings,
+    ))
+}
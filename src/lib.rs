@@ -2,18 +2,56 @@
 #![feature(const_option_ext)]
 #![forbid(unsafe_code)]
 
+//! GRM manages git repositories and worktrees. The `grm` binary is a thin
+//! layer over this library, so everything it does can also be done from other
+//! Rust code.
+//!
+//! The main entry points are:
+//!
+//! * [`SyncPlan`] to sync the trees of a [`Config`], resulting in a
+//!   [`SyncReport`]
+//! * [`RepoHandle::status()`] to get the [`RepoStatus`] of a repository
+//! * [`RepoHandle::worktree_statuses()`] to get the [`WorktreeStatus`] of all
+//!   worktrees of a repository
+//! * [`find_in_tree()`] to build a configuration from existing repositories
+
+use std::collections::BTreeMap;
 use std::path::Path;
 
 pub mod auth;
 pub mod config;
+pub mod diff;
+pub mod doctor;
+pub mod error;
+pub mod exec;
+pub mod glob;
+pub mod grep;
+pub mod host;
+pub mod import;
+pub mod list;
+pub mod lock;
+pub mod maintenance;
+pub mod net;
 pub mod output;
+pub mod parallel;
 pub mod path;
 pub mod provider;
 pub mod repo;
+pub mod retry;
+pub mod secrets;
+pub mod shell;
+pub mod snapshot;
 pub mod table;
 pub mod tree;
 pub mod worktree;
 
+pub use config::Config;
+pub use error::Error;
+pub use repo::{RepoChanges, RepoHandle, RepoStatus, WorktreeStatus};
+pub use tree::{
+    RepoSyncResult, SearchOptions, SyncChanges, SyncEvent, SyncOptions, SyncPlan, SyncReport,
+};
+
 /// Find all git repositories under root, recursively
 ///
 /// The bool in the return value specifies whether there is a repository
@@ -22,138 +60,250 @@ pub mod worktree;
 fn find_repos(
     root: &Path,
     exclusion_pattern: Option<&str>,
+    search_options: &tree::SearchOptions,
 ) -> Result<Option<(Vec<repo::Repo>, Vec<String>, bool)>, String> {
     let mut repos: Vec<repo::Repo> = Vec::new();
     let mut repo_in_root = false;
     let mut warnings = Vec::new();
+    let mut paths = Vec::new();
 
     let exlusion_regex: regex::Regex = regex::Regex::new(exclusion_pattern.unwrap_or(r"^$"))
         .map_err(|e| format!("invalid regex: {e}"))?;
-    for path in tree::find_repo_paths(root)? {
+    for path in tree::find_repo_paths_with_options(root, search_options)? {
         if exclusion_pattern.is_some() && exlusion_regex.is_match(&path::path_as_string(&path)) {
             warnings.push(format!("[skipped] {}", &path::path_as_string(&path)));
             continue;
         }
 
-        let is_worktree = repo::RepoHandle::detect_worktree(&path);
         if path == root {
             repo_in_root = true;
         }
 
-        match repo::RepoHandle::open(&path, is_worktree) {
+        paths.push(path);
+    }
+
+    let results = {
+        let root = root.to_path_buf();
+        parallel::map(paths, search_options.jobs, move |path| {
+            let result = inspect_repo(&root, &path);
+            (path, result)
+        })
+    };
+
+    // A single broken repository must not abort the whole search
+    for (path, result) in results {
+        match result {
+            Ok((repo, mut repo_warnings)) => {
+                repos.extend(repo);
+                warnings.append(&mut repo_warnings);
+            }
             Err(error) => {
+                warnings.push(format!("{}: {}", path::path_as_string(&path), error));
+            }
+        }
+    }
+
+    Ok(Some((repos, warnings, repo_in_root)))
+}
+
+/// Opens the repository at `path` and turns it into a configuration entry.
+/// Returns `None` if the repository cannot be used, with the reason in the
+/// warnings.
+fn inspect_repo(root: &Path, path: &Path) -> Result<(Option<repo::Repo>, Vec<String>), String> {
+    let mut warnings = Vec::new();
+
+    // The configuration can only contain valid UTF-8, so a repository with any
+    // other name could never be synced
+    if path.to_str().is_none() {
+        warnings.push(format!(
+            "[skipped] {}: Path is not valid UTF-8",
+            path::path_as_string(path)
+        ));
+        return Ok((None, warnings));
+    }
+
+    let is_worktree = repo::RepoHandle::detect_worktree(path);
+
+    match repo::RepoHandle::open(path, is_worktree) {
+        Err(error) => {
+            warnings.push(format!(
+                "Error opening repo {}{}: {}",
+                path.display(),
+                match is_worktree {
+                    true => " as worktree",
+                    false => "",
+                },
+                error
+            ));
+            Ok((None, warnings))
+        }
+        Ok(repo) => {
+            // Worktrees belong to the repository they were added to, they
+            // are not repositories of their own
+            if repo.is_linked_worktree() {
                 warnings.push(format!(
-                    "Error opening repo {}{}: {}",
-                    path.display(),
-                    match is_worktree {
-                        true => " as worktree",
-                        false => "",
-                    },
-                    error
+                    "[skipped] {}: Worktree of another repository",
+                    &path::path_as_string(path)
+                ));
+                return Ok((None, warnings));
+            }
+
+            // Outside of a worktree setup, a bare repository is expected
+            // to be a mirror or a plain bare clone. If it has worktrees, it
+            // is neither.
+            let is_bare = !is_worktree && repo.is_bare();
+            if is_bare && !repo.get_worktrees()?.is_empty() {
+                warnings.push(format!(
+                    "[skipped] {}: Bare repository with worktrees, but not using a worktree setup",
+                    &path::path_as_string(path)
                 ));
-                continue;
+                return Ok((None, warnings));
+            }
+
+            let remotes = match repo.remotes() {
+                Ok(remote) => remote,
+                Err(error) => {
+                    warnings.push(format!(
+                        "{}: Error getting remotes: {}",
+                        &path::path_as_string(path),
+                        error
+                    ));
+                    return Ok((None, warnings));
+                }
+            };
+
+            // `git clone --mirror` marks the remote, a plain bare clone
+            // keeps regular remote tracking branches
+            let mut clone_mode = repo::CloneMode::Normal;
+            if is_bare {
+                clone_mode = repo::CloneMode::Bare;
+                for remote_name in &remotes {
+                    if repo.is_mirror(remote_name)? {
+                        clone_mode = repo::CloneMode::Mirror;
+                    }
+                }
             }
-            Ok(repo) => {
-                let remotes = match repo.remotes() {
-                    Ok(remote) => remote,
-                    Err(error) => {
+
+            let primary_remote = repo.primary_remote()?;
+            let default_branch = repo.configured_default_branch()?;
+
+            let mut results: Vec<repo::Remote> = Vec::new();
+            for remote_name in remotes.iter() {
+                match repo.find_remote(remote_name)? {
+                    Some(remote) => {
+                        let name = remote.name();
+                        let url = remote.url();
+                        // Only exported if they actually differ, git itself
+                        // falls back to the fetch URL anyway
+                        let mut push_urls = repo.remote_push_urls(&name)?;
+                        let primary = primary_remote.as_deref() == Some(name.as_str());
+                        let tls = repo.tls_options(&url)?;
+                        let refspecs = repo.extra_fetch_refspecs(&name)?;
+                        if push_urls == [url.as_str()] {
+                            push_urls.clear();
+                        }
+                        let remote_type = match repo::detect_remote_type(&url) {
+                            Ok(t) => t,
+                            Err(error) => {
+                                warnings.push(format!(
+                                    "{}: {}",
+                                    &path::path_as_string(path),
+                                    error
+                                ));
+                                continue;
+                            }
+                        };
+
+                        results.push(repo::Remote {
+                            name,
+                            url,
+                            push_urls,
+                            remote_type,
+                            push_mirror: false,
+                            primary,
+                            tls,
+                            refspecs,
+                            credentials: None,
+                            create: false,
+                        });
+                    }
+                    None => {
                         warnings.push(format!(
-                            "{}: Error getting remotes: {}",
-                            &path::path_as_string(&path),
-                            error
+                            "{}: Remote {} not found",
+                            &path::path_as_string(path),
+                            remote_name
                         ));
                         continue;
                     }
                 };
+            }
+            let remotes = results;
 
-                let mut results: Vec<repo::Remote> = Vec::new();
-                for remote_name in remotes.iter() {
-                    match repo.find_remote(remote_name)? {
-                        Some(remote) => {
-                            let name = remote.name();
-                            let url = remote.url();
-                            let remote_type = match repo::detect_remote_type(&url) {
-                                Some(t) => t,
-                                None => {
-                                    warnings.push(format!(
-                                        "{}: Could not detect remote type of \"{}\"",
-                                        &path::path_as_string(&path),
-                                        &url
-                                    ));
-                                    continue;
-                                }
-                            };
-
-                            results.push(repo::Remote {
-                                name,
-                                url,
-                                remote_type,
-                            });
-                        }
+            let (namespace, name) = if path == root {
+                (
+                    None,
+                    match &root.parent() {
+                        Some(parent) => path::path_as_string(path.strip_prefix(parent).unwrap()),
                         None => {
-                            warnings.push(format!(
-                                "{}: Remote {} not found",
-                                &path::path_as_string(&path),
-                                remote_name
-                            ));
-                            continue;
+                            warnings.push(String::from("Getting name of the search root failed. Do you have a git repository in \"/\"?"));
+                            return Ok((None, warnings));
                         }
-                    };
-                }
-                let remotes = results;
-
-                let (namespace, name) = if path == root {
-                    (
-                        None,
-                        match &root.parent() {
-                            Some(parent) => {
-                                path::path_as_string(path.strip_prefix(parent).unwrap())
-                            }
-                            None => {
-                                warnings.push(String::from("Getting name of the search root failed. Do you have a git repository in \"/\"?"));
-                                continue;
-                            }
-                        },
-                    )
-                } else {
-                    let name = path.strip_prefix(root).unwrap();
-                    let namespace = name.parent().unwrap();
-                    (
-                        if namespace != Path::new("") {
-                            Some(path::path_as_string(namespace).to_string())
-                        } else {
-                            None
-                        },
-                        path::path_as_string(name),
-                    )
-                };
+                    },
+                )
+            } else {
+                // Names always use "/", so configurations can be shared
+                // between platforms. The path was checked to be valid UTF-8
+                // already, so the unwrap()s are safe.
+                let name = path.strip_prefix(root).unwrap();
+                let namespace = name.parent().unwrap();
+                (
+                    if namespace != Path::new("") {
+                        Some(path::path_as_name(namespace).unwrap())
+                    } else {
+                        None
+                    },
+                    path::path_as_name(name).unwrap(),
+                )
+            };
 
-                repos.push(repo::Repo {
+            Ok((
+                Some(repo::Repo {
                     name,
                     namespace,
                     remotes: Some(remotes),
                     worktree_setup: is_worktree,
-                });
-            }
+                    clone_options: repo::CloneOptions::default(),
+                    submodules: repo::Submodules::default(),
+                    lfs: false,
+                    clone_mode,
+                    rev: None,
+                    default_branch,
+                    git_config: BTreeMap::new(),
+                    pre_sync: Vec::new(),
+                    post_sync: Vec::new(),
+                    on_clone: Vec::new(),
+                }),
+                warnings,
+            ))
         }
     }
-    Ok(Some((repos, warnings, repo_in_root)))
 }
 
 pub fn find_in_tree(
     path: &Path,
     exclusion_pattern: Option<&str>,
+    search_options: &tree::SearchOptions,
 ) -> Result<(tree::Tree, Vec<String>), String> {
     let mut warnings = Vec::new();
 
-    let (repos, repo_in_root): (Vec<repo::Repo>, bool) = match find_repos(path, exclusion_pattern)?
-    {
-        Some((vec, mut repo_warnings, repo_in_root)) => {
-            warnings.append(&mut repo_warnings);
-            (vec, repo_in_root)
-        }
-        None => (Vec::new(), false),
-    };
+    let (repos, repo_in_root): (Vec<repo::Repo>, bool) =
+        match find_repos(path, exclusion_pattern, search_options)? {
+            Some((vec, mut repo_warnings, repo_in_root)) => {
+                warnings.append(&mut repo_warnings);
+                (vec, repo_in_root)
+            }
+            None => (Vec::new(), false),
+        };
 
     let mut root = path.to_path_buf();
     if repo_in_root {
@@ -169,7 +319,12 @@ pub fn find_in_tree(
 
     Ok((
         tree::Tree {
-            root: root.into_os_string().into_string().unwrap(),
+            root: root.into_os_string().into_string().map_err(|root| {
+                format!(
+                    "Path is not valid UTF-8: \"{}\"",
+                    Path::new(&root).display()
+                )
+            })?,
             repos,
         },
         warnings,
@@ -0,0 +1,330 @@
+//! Listing the configured repositories in a format that is easy to consume by
+//! other programs, e.g. shell scripts or fuzzy finders.
+
+use serde::Serialize;
+
+use std::path::Path;
+
+use super::config;
+use super::path;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> ListEntry {
+        ListEntry {
+            name: String::from("one"),
+            root: String::from("/projects"),
+            path: String::from("/projects/one"),
+            worktree_setup: false,
+            tags: vec![String::from("work"), String::from("rust")],
+            remotes: vec![config::RemoteConfig {
+                name: String::from("origin"),
+                url: String::from("https://example.com/one.git"),
+                push_url: None,
+                remote_type: config::RemoteType::Https,
+                push_mirror: None,
+                primary: None,
+                ca_bundle: None,
+                insecure_skip_verify: None,
+                refspecs: None,
+                username: None,
+                token_command: None,
+                create_remote: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn check_template() {
+        let template = Template::parse("{name} {root} {remotes[0].url}").unwrap();
+        assert_eq!(
+            template.render(&entry()),
+            "one /projects https://example.com/one.git"
+        );
+
+        let template = Template::parse("{{{tags}}} {remotes[1].url}|{worktree_setup}").unwrap();
+        assert_eq!(template.render(&entry()), "{work,rust} |false");
+    }
+
+    #[test]
+    fn check_template_errors() {
+        assert!(Template::parse("{unknown}").is_err());
+        assert!(Template::parse("{remotes[0].unknown}").is_err());
+        assert!(Template::parse("{name").is_err());
+        assert!(Template::parse("name}").is_err());
+    }
+
+    #[test]
+    fn check_find_entry() {
+        let mut other = entry();
+        other.root = String::from("/other");
+        other.path = String::from("/other/one");
+        let entries = vec![entry(), other];
+
+        assert!(find_entry(&entries, "one", None).is_err());
+        assert!(find_entry(&entries, "two", None).is_err());
+        assert_eq!(
+            find_entry(&entries, "one", Some("/other")).unwrap().path,
+            "/other/one"
+        );
+        assert_eq!(
+            find_entry(&entries[..1], "one", None).unwrap().path,
+            "/projects/one"
+        );
+    }
+
+    #[test]
+    fn check_csv() {
+        let mut entry = entry();
+        entry.name = String::from("with,comma \"and quotes\"");
+        assert_eq!(
+            format_csv(&[entry]),
+            "name,root,path,worktree_setup,tags,remotes\n\
+             \"with,comma \"\"and quotes\"\"\",/projects,/projects/one,false,work;rust,https://example.com/one.git\n"
+        );
+    }
+}
+
+/// A single configured repository, with everything that can be printed
+#[derive(Serialize)]
+pub struct ListEntry {
+    pub name: String,
+    /// The root of the tree, with `~` expanded
+    pub root: String,
+    pub path: String,
+    pub worktree_setup: bool,
+    pub tags: Vec<String>,
+    pub remotes: Vec<config::RemoteConfig>,
+}
+
+enum Field {
+    Name,
+    Root,
+    Path,
+    WorktreeSetup,
+    Tags,
+    RemoteName(usize),
+    RemoteUrl(usize),
+    RemoteType(usize),
+}
+
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// A format like `{name} {remotes[0].url}`. Fields are enclosed in braces,
+/// literal braces are written as `{{` and `}}`.
+pub struct Template(Vec<Segment>);
+
+impl Field {
+    fn parse(field: &str) -> Result<Self, String> {
+        let remote_regex = regex::Regex::new(r"^remotes\[(\d+)\]\.(\w+)$").unwrap();
+
+        match field {
+            "name" => Ok(Self::Name),
+            "root" => Ok(Self::Root),
+            "path" => Ok(Self::Path),
+            "worktree_setup" => Ok(Self::WorktreeSetup),
+            "tags" => Ok(Self::Tags),
+            _ => {
+                let captures = remote_regex
+                    .captures(field)
+                    .ok_or_else(|| format!("Unknown field \"{}\"", field))?;
+                let index = captures[1]
+                    .parse()
+                    .map_err(|_| format!("Invalid index in field \"{}\"", field))?;
+                match &captures[2] {
+                    "name" => Ok(Self::RemoteName(index)),
+                    "url" => Ok(Self::RemoteUrl(index)),
+                    "type" => Ok(Self::RemoteType(index)),
+                    _ => Err(format!("Unknown field \"{}\"", field)),
+                }
+            }
+        }
+    }
+
+    /// Fields that do not exist for the given entry, e.g. a remote that is
+    /// not configured, are empty
+    fn render(&self, entry: &ListEntry) -> String {
+        let remote = |index: usize| entry.remotes.get(index);
+        match self {
+            Self::Name => entry.name.clone(),
+            Self::Root => entry.root.clone(),
+            Self::Path => entry.path.clone(),
+            Self::WorktreeSetup => entry.worktree_setup.to_string(),
+            Self::Tags => entry.tags.join(","),
+            Self::RemoteName(index) => remote(*index)
+                .map(|remote| remote.name.clone())
+                .unwrap_or_default(),
+            Self::RemoteUrl(index) => remote(*index)
+                .map(|remote| remote.url.clone())
+                .unwrap_or_default(),
+            Self::RemoteType(index) => remote(*index)
+                .map(|remote| {
+                    match remote.remote_type {
+                        config::RemoteType::Ssh => "ssh",
+                        config::RemoteType::Https => "https",
+                        config::RemoteType::File => "file",
+                        config::RemoteType::Git => "git",
+                    }
+                    .to_string()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => return Err(String::from("Unclosed \"{\" in format")),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(Field::parse(field.trim())?));
+                }
+                '}' => return Err(String::from("Unmatched \"}\" in format, use \"}}\"")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self(segments))
+    }
+
+    pub fn render(&self, entry: &ListEntry) -> String {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.clone(),
+                Segment::Field(field) => field.render(entry),
+            })
+            .collect()
+    }
+}
+
+/// Quotes a CSV field if necessary, see RFC 4180
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats the entries as CSV with a header line. Tags and remote URLs are
+/// separated by `;`.
+pub fn format_csv(entries: &[ListEntry]) -> String {
+    let mut out = String::from("name,root,path,worktree_setup,tags,remotes\n");
+    for entry in entries {
+        let fields = [
+            entry.name.clone(),
+            entry.root.clone(),
+            entry.path.clone(),
+            entry.worktree_setup.to_string(),
+            entry.tags.join(";"),
+            entry
+                .remotes
+                .iter()
+                .map(|remote| remote.url.as_str())
+                .collect::<Vec<&str>>()
+                .join(";"),
+        ];
+        out.push_str(
+            &fields
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<String>>()
+                .join(","),
+        );
+        out.push('\n');
+    }
+    out
+}
+
+pub fn format_json(entries: &[ListEntry]) -> Result<String, String> {
+    serde_json::to_string_pretty(entries).map_err(|error| error.to_string())
+}
+
+/// Collects all configured repositories. Trees whose root cannot be expanded
+/// are skipped, an error is returned for each of them.
+pub fn entries(trees: Vec<config::ConfigTree>) -> (Vec<ListEntry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    for tree in trees {
+        let root = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root) => root,
+            Err(error) => {
+                errors.push(error.to_string());
+                continue;
+            }
+        };
+
+        for repo in tree.repos.unwrap_or_default() {
+            entries.push(ListEntry {
+                path: path::path_as_string(&root.join(&repo.name)),
+                root: path::path_as_string(&root),
+                name: repo.name,
+                worktree_setup: repo.worktree_setup,
+                tags: repo.tags.unwrap_or_default(),
+                remotes: repo.remotes.unwrap_or_default(),
+            });
+        }
+    }
+
+    (entries, errors)
+}
+
+/// Returns the entry of the repository `name`. With `tree_root`, only the tree
+/// with that (expanded) root is considered, which is required if several trees
+/// contain a repository with that name.
+pub fn find_entry<'a>(
+    entries: &'a [ListEntry],
+    name: &str,
+    tree_root: Option<&str>,
+) -> Result<&'a ListEntry, String> {
+    let matches = entries
+        .iter()
+        .filter(|entry| entry.name == name)
+        .filter(|entry| tree_root.map_or(true, |tree_root| entry.root == tree_root))
+        .collect::<Vec<&ListEntry>>();
+
+    match matches.len() {
+        0 => Err(format!(
+            "Repository \"{}\" is not part of the configuration",
+            name
+        )),
+        1 => Ok(matches[0]),
+        _ => Err(format!(
+            "Repository \"{}\" is part of several trees, use --tree to select one",
+            name
+        )),
+    }
+}
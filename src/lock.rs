@@ -0,0 +1,214 @@
+//! Advisory locks that keep concurrent runs of grm from working on the same
+//! tree at the same time.
+//!
+//! A lock is a file containing the process ID of its owner. Locks of processes
+//! that are no longer running are stale and taken over.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use super::output::*;
+use super::path;
+
+pub const LOCK_FILE_NAME: &str = ".grm.lock";
+
+/// How often a waiting process checks whether the lock is free again
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_lock_is_exclusive() {
+        let tmpdir = tempdir::TempDir::new("grm-test").unwrap();
+
+        let lock = Lock::acquire(tmpdir.path(), false).unwrap();
+        assert!(tmpdir.path().join(LOCK_FILE_NAME).exists());
+        assert!(Lock::acquire(tmpdir.path(), false).is_err());
+
+        drop(lock);
+        assert!(!tmpdir.path().join(LOCK_FILE_NAME).exists());
+        assert!(Lock::acquire(tmpdir.path(), false).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_stale_lock_is_taken_over() {
+        let tmpdir = tempdir::TempDir::new("grm-test").unwrap();
+
+        // Larger than the maximum PID on Linux, so it cannot be running
+        fs::write(tmpdir.path().join(LOCK_FILE_NAME), "4194305\n").unwrap();
+        assert!(Lock::acquire(tmpdir.path(), false).is_ok());
+    }
+
+    #[test]
+    fn check_new_lock_is_not_taken_over() {
+        let tmpdir = tempdir::TempDir::new("grm-test").unwrap();
+        let path = tmpdir.path().join(LOCK_FILE_NAME);
+
+        // Another process took over the stale lock first
+        let content = format!("{}\n", std::process::id());
+        fs::write(&path, &content).unwrap();
+        take_over(tmpdir.path(), &path, "4194305\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        assert_eq!(fs::read_dir(tmpdir.path()).unwrap().count(), 1);
+    }
+}
+
+/// A held lock. It is released when dropped.
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Locks the given directory. If it is locked by another process already,
+    /// waits for it to be released if `wait` is set, and fails otherwise.
+    pub fn acquire(directory: &Path, wait: bool) -> Result<Self, String> {
+        let path = directory.join(LOCK_FILE_NAME);
+        let mut waiting = false;
+
+        loop {
+            match create(directory, &path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(error) => {
+                    return Err(format!(
+                        "Error creating lock file \"{}\": {}",
+                        path.display(),
+                        error
+                    ))
+                }
+            }
+
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                // Released in the meantime
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => {
+                    return Err(format!(
+                        "Error reading lock file \"{}\": {}",
+                        path.display(),
+                        error
+                    ))
+                }
+            };
+
+            match content.trim().parse::<u32>().ok() {
+                Some(pid) if is_running(pid) => {
+                    if !wait {
+                        return Err(format!(
+                            "\"{}\" is locked by another grm process (PID {})",
+                            path::path_as_string(directory),
+                            pid
+                        ));
+                    }
+                    if !waiting {
+                        print_action(&format!(
+                            "Waiting for another grm process (PID {}) to release \"{}\"",
+                            pid,
+                            path::path_as_string(directory)
+                        ));
+                        waiting = true;
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                // The owner is gone (or the file is garbage), so nobody will
+                // ever remove the lock
+                _ => take_over(directory, &path, &content)?,
+            }
+        }
+    }
+}
+
+/// Creates the lock file at `path`. The file is written under another name
+/// first and then linked into place, so other processes never see a lock file
+/// without the process ID.
+fn create(directory: &Path, path: &Path) -> std::io::Result<()> {
+    let pid = std::process::id();
+    let tmp_path = directory.join(format!("{}.{}", LOCK_FILE_NAME, pid));
+    fs::write(&tmp_path, format!("{}\n", pid))?;
+    // Fails if the lock file exists already, just like `create_new`
+    let result = fs::hard_link(&tmp_path, path);
+    let _ = fs::remove_file(&tmp_path);
+    result
+}
+
+/// Removes the stale lock file at `path`, which contained `stale_content`
+/// when it was checked.
+///
+/// Another process may have taken over the same stale lock in the meantime
+/// and created a new lock file, which must not be removed. So the lock file
+/// is moved away atomically first, and only removed if it is still the stale
+/// one.
+fn take_over(directory: &Path, path: &Path, stale_content: &str) -> Result<(), String> {
+    let moved_path = directory.join(format!("{}.stale.{}", LOCK_FILE_NAME, std::process::id()));
+    match fs::rename(path, &moved_path) {
+        Ok(()) => {}
+        // Taken over by another process already
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => {
+            return Err(format!(
+                "Error removing stale lock file \"{}\": {}",
+                path.display(),
+                error
+            ))
+        }
+    }
+
+    let is_stale = fs::read_to_string(&moved_path)
+        .map(|content| content == stale_content)
+        .unwrap_or(false);
+    if is_stale {
+        print_warning(&format!("Removed stale lock file \"{}\"", path.display()));
+    } else {
+        // A new lock of another process, so put it back. Only if a third
+        // process locked in the meantime, this fails and both keep going.
+        let _ = fs::hard_link(&moved_path, path);
+    }
+    fs::remove_file(&moved_path).map_err(|error| {
+        format!(
+            "Error removing stale lock file \"{}\": {}",
+            moved_path.display(),
+            error
+        )
+    })
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            print_warning(&format!(
+                "Error removing lock file \"{}\": {}",
+                self.path.display(),
+                error
+            ));
+        }
+    }
+}
+
+/// Returns whether a process with the given ID is running. If this cannot be
+/// determined, the process is assumed to be running, so a lock is never taken
+/// away from a running process.
+fn is_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+
+    if cfg!(target_os = "linux") {
+        return Path::new("/proc").join(pid.to_string()).exists();
+    }
+
+    if cfg!(unix) {
+        return std::process::Command::new("kill")
+            .arg("-0")
+            .arg(pid.to_string())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map_or(true, |status| status.success());
+    }
+
+    true
+}
@@ -0,0 +1,411 @@
+//! Housekeeping for repositories that are already on disk, like garbage
+//! collection, integrity checks or finding out how much space they use.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::config;
+use super::exec;
+use super::parallel;
+use super::path;
+use super::repo;
+use super::tree;
+use super::worktree;
+
+/// The outcome of an operation for each repository, by name
+pub type RepoResults<T> = Vec<(String, Result<T, String>)>;
+
+pub struct GcOptions {
+    /// Let git spend more time to optimize the repository
+    pub aggressive: bool,
+    /// Register the repository for the background maintenance of git
+    pub maintenance: bool,
+}
+
+/// The size of the git data of a repository before and after garbage
+/// collection, in bytes
+pub struct GcResult {
+    pub before: u64,
+    pub after: u64,
+}
+
+impl GcResult {
+    pub fn reclaimed(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+fn open(repo_path: &Path) -> Result<repo::RepoHandle, String> {
+    repo::RepoHandle::open(repo_path, repo::RepoHandle::detect_worktree(repo_path))
+        .map_err(|error| format!("Opening repository failed: {}", error))
+}
+
+fn gc_repo(repo_path: &Path, aggressive: bool) -> Result<GcResult, String> {
+    let repo = open(repo_path)?;
+
+    let before = path::dir_size(repo.git_dir())?;
+    repo.gc(aggressive)?;
+    let after = path::dir_size(repo.git_dir())?;
+
+    Ok(GcResult { before, after })
+}
+
+/// Runs garbage collection in all targets, in up to `jobs` repositories at the
+/// same time. Returns the result for each target, in the same order as the
+/// targets were given.
+///
+/// Registering for maintenance writes to the global git configuration, so it
+/// happens one repository after the other.
+pub fn gc(targets: Vec<exec::Target>, options: &GcOptions, jobs: usize) -> RepoResults<GcResult> {
+    let aggressive = options.aggressive;
+    let results = parallel::map(targets, jobs, move |target| {
+        let result = gc_repo(&target.path, aggressive);
+        (target.name, target.path, result)
+    });
+
+    results
+        .into_iter()
+        .map(|(name, repo_path, result)| {
+            let result = match result {
+                Ok(result) if options.maintenance => open(&repo_path)
+                    .and_then(|repo| repo.register_maintenance())
+                    .map(|_| result),
+                result => result,
+            };
+            (name, result)
+        })
+        .collect()
+}
+
+/// An optimization that runs after syncing a repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Optimization {
+    /// Write the commit-graph
+    CommitGraph,
+    /// Pack loose objects
+    Repack,
+}
+
+/// Runs `optimizations` in the repository at `repo_path`. Packing comes first,
+/// so the commit-graph covers the new packs as well.
+pub fn optimize(repo_path: &Path, optimizations: &[Optimization]) -> Result<(), String> {
+    if optimizations.is_empty() {
+        return Ok(());
+    }
+    let repo = open(repo_path)?;
+
+    if optimizations.contains(&Optimization::Repack) {
+        repo.repack()
+            .map_err(|error| format!("Repacking failed: {}", error))?;
+    }
+    if optimizations.contains(&Optimization::CommitGraph) {
+        repo.write_commit_graph()
+            .map_err(|error| format!("Writing the commit-graph failed: {}", error))?;
+    }
+    Ok(())
+}
+
+/// The disk space used by a repository, in bytes
+pub struct DiskUsage {
+    /// The git data, i.e. objects, refs etc.
+    pub git: u64,
+    /// Everything else, i.e. the checked out files of all worktrees
+    pub worktrees: u64,
+}
+
+impl DiskUsage {
+    pub fn total(&self) -> u64 {
+        self.git + self.worktrees
+    }
+}
+
+fn du_repo(repo_path: &Path) -> Result<DiskUsage, String> {
+    let git_dir = if repo::RepoHandle::detect_worktree(repo_path) {
+        repo_path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY)
+    } else if repo_path.join(".git").exists() {
+        repo_path.join(".git")
+    } else {
+        // A bare repository, e.g. a mirror
+        repo_path.to_path_buf()
+    };
+
+    let total = path::dir_size(repo_path)?;
+    let git = path::dir_size(&git_dir)?;
+
+    Ok(DiskUsage {
+        git,
+        worktrees: total.saturating_sub(git),
+    })
+}
+
+/// Computes the disk usage of all targets, in up to `jobs` repositories at
+/// the same time. Returns the usage of each target, in the same order as the
+/// targets were given.
+pub fn du(targets: Vec<exec::Target>, jobs: usize) -> RepoResults<DiskUsage> {
+    parallel::map(targets, jobs, move |target| {
+        let result = du_repo(&target.path);
+        (target.name, result)
+    })
+}
+
+/// Runs `git fsck` for the repository at `repo_path`. Returns the problems git
+/// reports, which are empty if the repository is intact.
+///
+/// The repository is not opened with libgit2, as that might already fail for
+/// a corrupt one. Such a repository is reported as broken instead.
+fn fsck_repo(repo_path: &Path) -> Result<Vec<String>, String> {
+    let git_dir = match repo::RepoHandle::detect_worktree(repo_path) {
+        true => repo_path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY),
+        false => repo_path.to_path_buf(),
+    };
+
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(&git_dir)
+        .args(["fsck", "--full", "--no-progress", "--no-dangling"])
+        .output()
+        .map_err(|error| format!("Failed to run git: {}", error))?;
+
+    if output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let mut problems = Vec::new();
+    for stream in [&output.stdout, &output.stderr] {
+        problems.extend(
+            String::from_utf8_lossy(stream)
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+    }
+    if problems.is_empty() {
+        problems.push(format!("git fsck failed with {}", output.status));
+    }
+    Ok(problems)
+}
+
+/// Checks the integrity of all targets, in up to `jobs` repositories at the
+/// same time. Returns the problems found in each target, in the same order as
+/// the targets were given.
+pub fn fsck(
+    targets: Vec<exec::Target>,
+    jobs: usize,
+) -> Vec<(String, PathBuf, Result<Vec<String>, String>)> {
+    parallel::map(targets, jobs, move |target| {
+        let result = fsck_repo(&target.path);
+        (target.name, target.path, result)
+    })
+}
+
+/// The suffix of the backups `repair()` keeps of corrupt repositories
+const BACKUP_SUFFIX: &str = ".corrupt";
+
+/// The path a corrupt repository is moved to before it is cloned again
+fn backup_path(repo_path: &Path) -> PathBuf {
+    let mut backup = repo_path.as_os_str().to_os_string();
+    backup.push(BACKUP_SUFFIX);
+    PathBuf::from(backup)
+}
+
+/// Returns whether `path` is the backup of a corrupt repository that was
+/// repaired. Backups are not reported as unmanaged repositories.
+pub fn is_backup(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| {
+            name.len() > BACKUP_SUFFIX.len() && name.ends_with(BACKUP_SUFFIX)
+        })
+}
+
+/// Clones the repositories at `paths` again from their configured remotes. The
+/// corrupt repositories are kept next to the new clones, with a `.corrupt`
+/// suffix, so nothing that only existed locally is lost.
+///
+/// Repositories that others borrow objects from are not moved, as that would
+/// break the others.
+///
+/// Returns the path of the backup for each repository that was repaired.
+pub fn repair(
+    trees: Vec<config::ConfigTree>,
+    paths: &[PathBuf],
+) -> Result<RepoResults<PathBuf>, String> {
+    let mut results = Vec::new();
+    let config = config::ConfigTrees::from_vec(trees.clone());
+
+    let mut repair_trees = Vec::new();
+    for mut tree in trees {
+        let root = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+
+        let mut repos = Vec::new();
+        for repo_config in tree.repos.take().unwrap_or_default() {
+            let repo = repo_config.clone().into_repo();
+            let repo_path = root.join(repo.fullname());
+            if !paths.contains(&repo_path) {
+                continue;
+            }
+
+            if repo.remotes.iter().flatten().next().is_none() {
+                results.push((
+                    repo.fullname(),
+                    Err(String::from("No remotes configured, cannot clone it again")),
+                ));
+                continue;
+            }
+
+            if let Some(other) = tree::repos_referencing(&config, &repo_path).first() {
+                results.push((
+                    repo.fullname(),
+                    Err(format!(
+                        "Not repairing, repository \"{}\" uses its objects",
+                        other
+                    )),
+                ));
+                continue;
+            }
+
+            let backup = backup_path(&repo_path);
+            if backup.exists() {
+                results.push((
+                    repo.fullname(),
+                    Err(format!(
+                        "\"{}\" already exists, remove it first",
+                        backup.display()
+                    )),
+                ));
+                continue;
+            }
+            if let Err(error) = std::fs::rename(&repo_path, &backup) {
+                results.push((
+                    repo.fullname(),
+                    Err(format!("Failed to move repository aside: {}", error)),
+                ));
+                continue;
+            }
+
+            repos.push(repo_config);
+        }
+
+        if !repos.is_empty() {
+            // Hooks are meant for regular syncs, not for repairs
+            tree.pre_sync = None;
+            tree.post_sync = None;
+            tree.repos = Some(repos);
+            repair_trees.push(tree);
+        }
+    }
+
+    if repair_trees.is_empty() {
+        return Ok(results);
+    }
+
+    // Only a full clone with a checked out worktree replaces the corrupt
+    // repository, fetching is left to regular syncs
+    let options = tree::SyncOptions {
+        init_worktree: true,
+        clone_depth: None,
+        fetch: false,
+        fetch_options: repo::FetchConfig::default(),
+        check: false,
+        wait_for_lock: true,
+        fail_fast: false,
+    };
+    let plan = tree::SyncPlan::new(config::Config::from_trees(repair_trees), &options)
+        .map_err(|error| error.to_string())?;
+    let report = plan.execute(&options, |_| {});
+    if let Some(error) = report.errors.into_iter().next() {
+        return Err(error);
+    }
+    for repo in report.repos {
+        let backup = backup_path(&repo.path);
+        results.push((repo.name, repo.result.map(|_| backup)));
+    }
+
+    Ok(results)
+}
+
+/// A local branch that was deleted, or would be for a dry run
+pub struct CleanedBranch {
+    pub name: String,
+    pub kind: repo::MergeKind,
+}
+
+fn clean_branches_repo(
+    repo_path: &Path,
+    protected: &[String],
+    dry_run: bool,
+) -> Result<Vec<CleanedBranch>, String> {
+    let is_worktree = repo::RepoHandle::detect_worktree(repo_path);
+    let repo = open(repo_path)?;
+
+    // The local branches of a mirror are the ones of the remote
+    if repo.is_bare() && !is_worktree {
+        return Ok(Vec::new());
+    }
+
+    let mut protected = protected.to_vec();
+    if is_worktree {
+        protected.extend(repo::protected_branches(&repo::read_worktree_root_config(
+            repo_path,
+        )?)?);
+    }
+
+    let mut cleaned = Vec::new();
+    for (name, kind) in repo.merged_branches(&protected)? {
+        if !dry_run {
+            repo.delete_local_branch(&name)?;
+        }
+        cleaned.push(CleanedBranch { name, kind });
+    }
+    Ok(cleaned)
+}
+
+/// Deletes the local branches of all targets that are merged into the default
+/// branch, in up to `jobs` repositories at the same time. With `dry_run`, only
+/// returns which branches would be deleted.
+///
+/// `trees` are needed for the protected branches of each repository. The
+/// persistent branches of worktree setups are protected as well.
+pub fn clean_branches(
+    trees: &[config::ConfigTree],
+    targets: Vec<exec::Target>,
+    dry_run: bool,
+    jobs: usize,
+) -> RepoResults<Vec<CleanedBranch>> {
+    let mut protected_branches = Vec::new();
+    for tree in trees {
+        let root = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+        for repo in tree.repos.iter().flatten() {
+            if let Some(protected) = &repo.protected_branches {
+                let repo_path = root.join(repo.clone().into_repo().fullname());
+                protected_branches.push((repo_path, protected.clone()));
+            }
+        }
+    }
+
+    let targets = targets
+        .into_iter()
+        .map(|target| {
+            let protected = protected_branches
+                .iter()
+                .find(|(repo_path, _)| repo_path == &target.path)
+                .map(|(_, protected)| protected.clone())
+                .unwrap_or_default();
+            (target, protected)
+        })
+        .collect();
+
+    parallel::map(targets, jobs, move |(target, protected)| {
+        let result = clean_branches_repo(&target.path, &protected, dry_run);
+        (target.name, result)
+    })
+}
@@ -0,0 +1,168 @@
+//! Settings for network access, i.e. the proxy and timeouts used for clones,
+//! fetches and requests to forges.
+//!
+//! Like the retry policy, the settings are global and set once at startup.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+
+use super::path;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_url_host_prefix() {
+        assert_eq!(
+            url_host_prefix("https://git.example.com/user/repo.git"),
+            Some(String::from("https://git.example.com/"))
+        );
+        assert_eq!(
+            url_host_prefix("https://git.example.com:8443/repo"),
+            Some(String::from("https://git.example.com:8443/"))
+        );
+        assert_eq!(url_host_prefix("ssh://git@example.com/repo.git"), None);
+        assert_eq!(url_host_prefix("https://"), None);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// The proxy to use. Without one, the proxy is taken from the git
+    /// configuration (`http.proxy`) and the usual environment variables
+    /// (`https_proxy` etc.)
+    pub proxy: Option<String>,
+    /// How long to wait for a connection to a forge
+    pub connect_timeout: Option<Duration>,
+    /// How long a single clone, fetch or request to a forge may take
+    pub timeout: Option<Duration>,
+}
+
+/// TLS settings for a forge or remote host, e.g. for a self-hosted instance
+/// that uses a private certificate authority
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// A file with the certificates to verify the host with, instead of the
+    /// ones of the system
+    pub ca_bundle: Option<String>,
+    /// Do not verify the certificate of the host at all
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsOptions {
+    pub fn is_empty(&self) -> bool {
+        self.ca_bundle.is_none() && !self.insecure_skip_verify
+    }
+
+    /// The git configuration for these options, limited to URLs starting
+    /// with `url_prefix`
+    pub fn git_config(&self, url_prefix: &str) -> Vec<(String, String)> {
+        let mut config = Vec::new();
+        if let Some(ca_bundle) = &self.ca_bundle {
+            config.push((format!("http.{}.sslCAInfo", url_prefix), ca_bundle.clone()));
+        }
+        if self.insecure_skip_verify {
+            config.push((
+                format!("http.{}.sslVerify", url_prefix),
+                String::from("false"),
+            ));
+        }
+        config
+    }
+}
+
+/// The scheme and host of an HTTPS URL, e.g. `https://example.com/` for
+/// `https://example.com/user/repo.git`. TLS settings apply to the whole host,
+/// so this is used as the prefix for git's `http.<url>.*` settings.
+pub fn url_host_prefix(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("https://")?;
+    let host = rest.split('/').next().filter(|host| !host.is_empty())?;
+    Some(format!("https://{}/", host))
+}
+
+static SETTINGS: OnceCell<Settings> = OnceCell::new();
+
+pub fn set_settings(settings: Settings) -> Result<(), String> {
+    SETTINGS
+        .set(settings)
+        .map_err(|_| String::from("Network settings are already set"))
+}
+
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+/// The proxy options for libgit2. libgit2 does not use any proxy by default,
+/// so it is told to detect it like git does if none is configured.
+pub fn proxy_options() -> git2::ProxyOptions<'static> {
+    let mut options = git2::ProxyOptions::new();
+    match &settings().proxy {
+        Some(proxy) => options.url(proxy),
+        None => options.auto(),
+    };
+    options
+}
+
+/// Options for the git command line client. It already detects the proxy on
+/// its own, so only an explicitly configured one has to be passed.
+pub fn git_cli_args() -> Vec<String> {
+    match &settings().proxy {
+        Some(proxy) => vec![String::from("-c"), format!("http.proxy={}", proxy)],
+        None => Vec::new(),
+    }
+}
+
+/// The point in time at which an operation that starts now times out
+pub fn deadline() -> Option<Instant> {
+    settings().timeout.map(|timeout| Instant::now() + timeout)
+}
+
+/// Whether the given deadline has passed
+pub fn expired(deadline: Option<Instant>) -> bool {
+    deadline.map_or(false, |deadline| Instant::now() > deadline)
+}
+
+/// The error for an operation that was cancelled because of the timeout.
+/// libgit2 only reports that the operation was cancelled, or even some
+/// follow-up error, which does not help anyone.
+pub fn timeout_error() -> String {
+    format!(
+        "Timed out after {:.1}s",
+        settings().timeout.unwrap_or_default().as_secs_f64()
+    )
+}
+
+/// An HTTP client for requests to forges. It uses the environment variables
+/// for the proxy as well, unless one is configured.
+pub fn http_client(tls: &TlsOptions) -> Result<isahc::HttpClient, String> {
+    use isahc::config::{CaCertificate, Configurable, SslOption};
+
+    let settings = settings();
+    let mut builder = isahc::HttpClient::builder();
+
+    if let Some(proxy) = &settings.proxy {
+        let proxy = proxy
+            .parse()
+            .map_err(|error| format!("Invalid proxy \"{}\": {}", proxy, error))?;
+        builder = builder.proxy(Some(proxy));
+    }
+    if let Some(timeout) = settings.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = settings.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(ca_bundle) = &tls.ca_bundle {
+        let ca_bundle = path::expand_path(Path::new(ca_bundle))
+            .map_err(|error| format!("Invalid path of CA bundle \"{}\": {}", ca_bundle, error))?;
+        builder = builder.ssl_ca_certificate(CaCertificate::file(ca_bundle));
+    }
+    if tls.insecure_skip_verify {
+        builder = builder.ssl_options(SslOption::DANGER_ACCEPT_INVALID_CERTS);
+    }
+
+    builder.build().map_err(|error| error.to_string())
+}
@@ -1,18 +1,302 @@
+//! All human readable output goes through the functions in this module.
+//!
+//! Every message has a [`Level`]. Messages above the configured level are
+//! dropped, so `-v` and `--quiet` work the same everywhere. Optionally, all
+//! messages that are printed are also appended to a log file.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
 use console::{Style, Term};
+use once_cell::sync::{Lazy, OnceCell};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn check_level_from_flags() {
+        assert_eq!(Level::from_flags(0, false), Level::Info);
+        assert_eq!(Level::from_flags(1, false), Level::Debug);
+        assert_eq!(Level::from_flags(2, false), Level::Trace);
+        assert_eq!(Level::from_flags(5, false), Level::Trace);
+        assert_eq!(Level::from_flags(0, true), Level::Error);
+    }
+
+    #[test]
+    fn check_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1_048_576), "3.0 MiB");
+        assert_eq!(format_bytes(5 * 1_073_741_824), "5.0 GiB");
+    }
+}
+
+/// How important a message is. Each level includes all levels before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// Gets the level from the number of `-v` flags and `--quiet`
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Level::Error,
+            (false, 0) => Level::Info,
+            (false, 1) => Level::Debug,
+            (false, _) => Level::Trace,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warning => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// When to use colors in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only if the output is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+static LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+static COLOR: AtomicUsize = AtomicUsize::new(ColorChoice::Auto as usize);
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+static LOG_FILE: OnceCell<Mutex<File>> = OnceCell::new();
+/// The messages collected by `capture()` while it runs
+static CAPTURED: Lazy<Mutex<Option<Vec<String>>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// Returns whether messages of the given level are printed
+pub fn enabled(level: Level) -> bool {
+    level as usize <= LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn set_color(choice: ColorChoice) {
+    COLOR.store(choice as usize, Ordering::Relaxed);
+}
+
+/// Returns whether output to the given terminal gets colors. This is decided
+/// for stdout and stderr separately, so piping one of them never leaves escape
+/// codes in the output.
+pub fn use_color(term: &Term) -> bool {
+    match COLOR.load(Ordering::Relaxed) {
+        choice if choice == ColorChoice::Always as usize => true,
+        choice if choice == ColorChoice::Never as usize => false,
+        // See https://no-color.org/
+        _ => term.is_term() && std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty()),
+    }
+}
+
+/// Prefixes every message on the terminal with the current time
+pub fn set_timestamps(timestamps: bool) {
+    TIMESTAMPS.store(timestamps, Ordering::Relaxed);
+}
+
+/// Appends all printed messages to the given file, with timestamps and
+/// without colors
+pub fn set_log_file(path: &Path) -> Result<(), String> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|error| format!("Error opening log file \"{}\": {}", path.display(), error))?;
+    LOG_FILE
+        .set(Mutex::new(file))
+        .map_err(|_| String::from("Log file is already set"))
+}
+
+/// Formats seconds since the epoch as an RFC 3339 timestamp in UTC
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // Converts days since the epoch into a date of the proleptic Gregorian
+    // calendar, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+fn now() -> String {
+    format_timestamp(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0),
+    )
+}
+
+fn log(level: Level, term: Term, symbol: char, style: Style, message: &str) {
+    if !enabled(level) {
+        return;
+    }
+
+    if let Some(file) = LOG_FILE.get() {
+        // A broken log file must not stop anything
+        let _ = writeln!(
+            file.lock().unwrap(),
+            "{} {:5} {}",
+            now(),
+            level.name(),
+            message
+        );
+    }
+
+    if let Some(captured) = captured().as_mut() {
+        captured.push(message.to_string());
+        return;
+    }
+
+    let style = style.force_styling(use_color(&term));
+    let line = format!("[{}] {}", style.apply_to(symbol), message);
+
+    // Progress bars stay at the bottom, so they are removed before printing
+    // and drawn again afterwards
+    let stderr = Term::stderr();
+    let mut bars = BARS.lock().unwrap();
+    let redraw = stderr.is_term() && bars.drawn > 0;
+    if redraw {
+        bars.clear(&stderr);
+    }
+
+    match TIMESTAMPS.load(Ordering::Relaxed) {
+        true => term.write_line(&format!("{} {}", now(), line)),
+        false => term.write_line(&line),
+    }
+    .unwrap();
+
+    if redraw {
+        bars.draw(&stderr);
+    }
+}
+
+/// Runs `f` and returns the messages it printed instead of printing them,
+/// e.g. to show them somewhere else than on the terminal. Progress bars are
+/// not drawn while `f` runs.
+///
+/// Captures can be nested. If `f` panics, the capture that was active before
+/// is restored nevertheless.
+pub fn capture<T, F: FnOnce() -> T>(f: F) -> (T, Vec<String>) {
+    let guard = CaptureGuard::start();
+    let result = f();
+    (result, guard.finish())
+}
+
+/// Restores the previous capture when it is dropped, also while unwinding
+struct CaptureGuard {
+    previous: Option<Vec<String>>,
+}
+
+impl CaptureGuard {
+    fn start() -> Self {
+        let previous = captured().replace(Vec::new());
+        Self { previous }
+    }
+
+    fn finish(self) -> Vec<String> {
+        captured().take().unwrap_or_default()
+    }
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        *captured() = self.previous.take();
+    }
+}
+
+/// A panic while printing must not stop the capture from being reset
+fn captured() -> std::sync::MutexGuard<'static, Option<Vec<String>>> {
+    CAPTURED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A message that library code returns to its caller instead of printing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Action(String),
+    Success(String),
+    Warning(String),
+    Error(String),
+}
+
+/// Prints a message with the matching icon
+pub fn print_message(message: &Message) {
+    match message {
+        Message::Action(message) => print_action(message),
+        Message::Success(message) => print_success(message),
+        Message::Warning(message) => print_warning(message),
+        Message::Error(message) => print_error(message),
+    }
+}
+
+/// Prints a message about the repository `repo` with the matching icon
+pub fn print_repo_message(repo: &str, message: &Message) {
+    match message {
+        Message::Action(message) => print_repo_action(repo, message),
+        Message::Success(message) => print_repo_success(repo, message),
+        Message::Warning(message) => print_warning(&format!("{}: {}", repo, message)),
+        Message::Error(message) => print_repo_error(repo, message),
+    }
+}
 
 pub fn print_repo_error(repo: &str, message: &str) {
     print_error(&format!("{}: {}", repo, message));
 }
 
 pub fn print_error(message: &str) {
-    let stderr = Term::stderr();
-    let mut style = Style::new().red();
-    if stderr.is_term() {
-        style = style.force_styling(true);
-    }
-    stderr
-        .write_line(&format!("[{}] {}", style.apply_to('\u{2718}'), &message))
-        .unwrap();
+    log(
+        Level::Error,
+        Term::stderr(),
+        '\u{2718}',
+        Style::new().red(),
+        message,
+    );
 }
 
 pub fn print_repo_action(repo: &str, message: &str) {
@@ -20,25 +304,23 @@ pub fn print_repo_action(repo: &str, message: &str) {
 }
 
 pub fn print_action(message: &str) {
-    let stdout = Term::stdout();
-    let mut style = Style::new().yellow();
-    if stdout.is_term() {
-        style = style.force_styling(true);
-    }
-    stdout
-        .write_line(&format!("[{}] {}", style.apply_to('\u{2699}'), &message))
-        .unwrap();
+    log(
+        Level::Info,
+        Term::stdout(),
+        '\u{2699}',
+        Style::new().yellow(),
+        message,
+    );
 }
 
 pub fn print_warning(message: &str) {
-    let stderr = Term::stderr();
-    let mut style = Style::new().yellow();
-    if stderr.is_term() {
-        style = style.force_styling(true);
-    }
-    stderr
-        .write_line(&format!("[{}] {}", style.apply_to('!'), &message))
-        .unwrap();
+    log(
+        Level::Warning,
+        Term::stderr(),
+        '!',
+        Style::new().yellow(),
+        message,
+    );
 }
 
 pub fn print_repo_success(repo: &str, message: &str) {
@@ -46,13 +328,228 @@ pub fn print_repo_success(repo: &str, message: &str) {
 }
 
 pub fn print_success(message: &str) {
-    let stdout = Term::stdout();
-    let mut style = Style::new().green();
-    if stdout.is_term() {
-        style = style.force_styling(true);
-    }
+    log(
+        Level::Info,
+        Term::stdout(),
+        '\u{2714}',
+        Style::new().green(),
+        message,
+    );
+}
+
+/// Prints details that are only interesting when looking into a problem.
+/// Shown with `-v`.
+pub fn print_debug(message: &str) {
+    log(
+        Level::Debug,
+        Term::stderr(),
+        '\u{00b7}',
+        Style::new().blue(),
+        message,
+    );
+}
+
+/// Prints every single step, e.g. each git command that is run. Shown with
+/// `-vv`.
+pub fn print_trace(message: &str) {
+    log(
+        Level::Trace,
+        Term::stderr(),
+        '\u{00b7}',
+        Style::new().dim(),
+        message,
+    );
+}
 
+/// Asks a yes/no question on stdout and reads the answer from stdin. Anything
+/// except an explicit "y" or "yes" counts as "no".
+pub fn confirm(message: &str) -> bool {
+    let stdout = Term::stdout();
+    let style = Style::new().cyan().force_styling(use_color(&stdout));
     stdout
-        .write_line(&format!("[{}] {}", style.apply_to('\u{2714}'), &message))
+        .write_str(&format!("[{}] {} [y/N] ", style.apply_to('?'), &message))
         .unwrap();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Prints a single line of output of an external command, prefixed with the
+/// name of whatever the command ran for
+pub fn print_command_output(prefix: &str, line: &str, stderr: bool) {
+    let term = if stderr {
+        Term::stderr()
+    } else {
+        Term::stdout()
+    };
+    let style = Style::new().bold().force_styling(use_color(&term));
+    term.write_line(&format!(
+        "{} {}",
+        style.apply_to(format!("[{}]", prefix)),
+        line
+    ))
+    .unwrap();
+}
+
+/// How often a progress bar is redrawn at most
+const PROGRESS_REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+const PROGRESS_BAR_WIDTH: usize = 25;
+
+struct Bar {
+    id: usize,
+    line: String,
+    last_draw: Option<Instant>,
+    /// The last quarter that was logged, if stderr is not a terminal
+    logged_quarter: usize,
+}
+
+/// All progress bars that are currently shown, and how many lines they take
+/// up at the bottom of the terminal
+struct Bars {
+    next_id: usize,
+    bars: Vec<Bar>,
+    drawn: usize,
+}
+
+impl Bars {
+    fn clear(&mut self, term: &Term) {
+        if self.drawn > 0 {
+            let _ = term.clear_last_lines(self.drawn);
+            self.drawn = 0;
+        }
+    }
+
+    fn draw(&mut self, term: &Term) {
+        self.clear(term);
+        // Wrapped lines would break clearing them again later
+        let width = (term.size().1 as usize).saturating_sub(1);
+        for bar in &self.bars {
+            let _ = term.write_line(&console::truncate_str(&bar.line, width, ""));
+        }
+        self.drawn = self.bars.len();
+    }
+}
+
+static BARS: Lazy<Mutex<Bars>> = Lazy::new(|| {
+    Mutex::new(Bars {
+        next_id: 0,
+        bars: Vec::new(),
+        drawn: 0,
+    })
+});
+
+/// A progress bar for a long running operation, e.g. a clone. Bars that exist
+/// at the same time are shown below each other, and other messages are
+/// printed above them. The bar disappears when it is dropped.
+///
+/// If stderr is not a terminal, the progress is logged in quarters with
+/// `print_action()` instead, so it also shows up in logs and CI output.
+pub struct ProgressBar {
+    id: usize,
+    label: String,
+    term: Option<Term>,
+}
+
+impl ProgressBar {
+    pub fn new(label: &str) -> Self {
+        let stderr = Term::stderr();
+        let capturing = captured().is_some();
+        let term = match stderr.is_term() && enabled(Level::Info) && !capturing {
+            true => Some(stderr),
+            false => None,
+        };
+
+        let mut bars = BARS.lock().unwrap();
+        let id = bars.next_id;
+        bars.next_id += 1;
+        bars.bars.push(Bar {
+            id,
+            line: label.to_string(),
+            last_draw: None,
+            logged_quarter: 0,
+        });
+        if let Some(term) = &term {
+            bars.draw(term);
+        }
+
+        Self {
+            id,
+            label: label.to_string(),
+            term,
+        }
+    }
+
+    /// Updates the bar to `current` out of `total`, with `detail` shown
+    /// behind it
+    pub fn set(&self, current: usize, total: usize, detail: &str) {
+        let percent = match total {
+            0 => 100,
+            _ => (current * 100 / total).min(100),
+        };
+
+        let mut bars = BARS.lock().unwrap();
+        let bar = match bars.bars.iter_mut().find(|bar| bar.id == self.id) {
+            Some(bar) => bar,
+            None => return,
+        };
+
+        match &self.term {
+            Some(term) => {
+                let done = (percent * PROGRESS_BAR_WIDTH) / 100;
+                bar.line = format!(
+                    "[{}] {} [{}{}] {:>3}% {}",
+                    Style::new()
+                        .yellow()
+                        .force_styling(use_color(term))
+                        .apply_to('\u{2699}'),
+                    self.label,
+                    "#".repeat(done),
+                    "-".repeat(PROGRESS_BAR_WIDTH - done),
+                    percent,
+                    detail
+                );
+                let now = Instant::now();
+                let is_due = bar.last_draw.map_or(true, |last| {
+                    now.duration_since(last) >= PROGRESS_REDRAW_INTERVAL
+                });
+                if is_due || current == total {
+                    bar.last_draw = Some(now);
+                    bars.draw(term);
+                }
+            }
+            None => {
+                let quarter = percent / 25;
+                if quarter > bar.logged_quarter {
+                    bar.logged_quarter = quarter;
+                    // Logging needs the lock itself
+                    drop(bars);
+                    print_action(&format!("{}: {}% {}", self.label, percent, detail));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        let mut bars = BARS.lock().unwrap();
+        bars.bars.retain(|bar| bar.id != self.id);
+        if let Some(term) = &self.term {
+            bars.draw(term);
+        }
+    }
+}
+
+/// Formats a number of bytes for humans
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        1_048_576..=1_073_741_823 => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+        _ => format!("{:.1} GiB", bytes as f64 / 1_073_741_824.0),
+    }
 }
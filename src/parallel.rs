@@ -0,0 +1,73 @@
+//! A simple thread pool to process a list of items in parallel.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_map_keeps_order() {
+        let items: Vec<usize> = (0..50).collect();
+        assert_eq!(
+            map(items.clone(), 8, |i| i * 2),
+            items.iter().map(|i| i * 2).collect::<Vec<usize>>()
+        );
+    }
+
+    #[test]
+    fn check_map_single_job() {
+        assert_eq!(map(vec![3, 1, 2], 1, |i| i + 1), vec![4, 2, 3]);
+    }
+
+    #[test]
+    fn check_map_empty() {
+        assert_eq!(map(Vec::<usize>::new(), 4, |i| i), Vec::<usize>::new());
+    }
+}
+
+/// Applies `f` to all items, using up to `jobs` threads at the same time.
+///
+/// The results are returned in the same order as the items, regardless of the
+/// order in which they were processed.
+pub fn map<T, R, F>(items: Vec<T>, jobs: usize, f: F) -> Vec<R>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+{
+    let count = items.len();
+    let queue = Arc::new(Mutex::new(items.into_iter().enumerate()));
+    let results = Arc::new(Mutex::new(Vec::with_capacity(count)));
+    let f = Arc::new(f);
+
+    let workers: Vec<_> = (0..jobs.clamp(1, count.max(1)))
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let results = Arc::clone(&results);
+            let f = Arc::clone(&f);
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                match next {
+                    None => break,
+                    Some((i, item)) => {
+                        let result = f(item);
+                        results.lock().unwrap().push((i, result));
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().expect("Worker thread panicked");
+    }
+
+    let mut results = Arc::try_unwrap(results)
+        .unwrap_or_else(|_| panic!("Worker threads are still running"))
+        .into_inner()
+        .unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -1,7 +1,6 @@
 use std::path::{Path, PathBuf};
-use std::process;
 
-use super::output::*;
+use super::error::Error;
 
 #[cfg(test)]
 mod tests {
@@ -15,7 +14,7 @@ mod tests {
     fn check_expand_tilde() {
         setup();
         assert_eq!(
-            expand_path(Path::new("~/file")),
+            expand_path(Path::new("~/file")).unwrap(),
             Path::new("/home/test/file")
         );
     }
@@ -24,57 +23,274 @@ mod tests {
     fn check_expand_invalid_tilde() {
         setup();
         assert_eq!(
-            expand_path(Path::new("/home/~/file")),
+            expand_path(Path::new("/home/~/file")).unwrap(),
             Path::new("/home/~/file")
         );
     }
 
+    #[test]
+    fn check_dir_size() {
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+        std::fs::write(dir.path().join("a"), [0; 100]).unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub").join("b"), [0; 23]).unwrap();
+        assert_eq!(dir_size(dir.path()).unwrap(), 123);
+    }
+
+    #[test]
+    fn check_copy_recursive() {
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+        let from = dir.path().join("from");
+        std::fs::create_dir_all(from.join("sub")).unwrap();
+        std::fs::write(from.join("a"), "a").unwrap();
+        std::fs::write(from.join("sub").join("b"), "b").unwrap();
+
+        let to = dir.path().join("to").join("nested");
+        copy_recursive(&from, &to).unwrap();
+        assert_eq!(std::fs::read_to_string(to.join("a")).unwrap(), "a");
+        assert_eq!(
+            std::fs::read_to_string(to.join("sub").join("b")).unwrap(),
+            "b"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_copy_recursive_symlinks() {
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+        let from = dir.path().join("from");
+        std::fs::create_dir_all(from.join("sub")).unwrap();
+        // Following this would copy forever
+        std::os::unix::fs::symlink("..", from.join("sub").join("loop")).unwrap();
+        std::os::unix::fs::symlink("missing", from.join("dangling")).unwrap();
+
+        let to = dir.path().join("to");
+        copy_recursive(&from, &to).unwrap();
+        assert_eq!(
+            std::fs::read_link(to.join("sub").join("loop")).unwrap(),
+            Path::new("..")
+        );
+        assert_eq!(
+            std::fs::read_link(to.join("dangling")).unwrap(),
+            Path::new("missing")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        setup();
+        let path = Path::new(OsStr::from_bytes(b"/tmp/\xffrepo"));
+        assert_eq!(path_as_string(path), "/tmp/\u{fffd}repo");
+        assert_eq!(expand_path(path).unwrap(), path);
+    }
+
+    #[test]
+    fn check_path_as_name() {
+        assert_eq!(
+            path_as_name(Path::new("group/repo")),
+            Some(String::from("group/repo"))
+        );
+        assert_eq!(path_as_name(Path::new("repo")), Some(String::from("repo")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn check_windows_paths() {
+        assert_eq!(
+            path_as_name(Path::new("group\\repo")),
+            Some(String::from("group/repo"))
+        );
+        assert_eq!(
+            expand_path(Path::new("D:\\projects")).unwrap(),
+            Path::new("D:\\projects")
+        );
+        assert_eq!(
+            expand_path(Path::new("~\\projects")).unwrap(),
+            Path::new(&env_home().unwrap()).join("projects")
+        );
+    }
+
+    #[test]
+    fn check_expand_without_home() {
+        let no_home = || Err(Error::Environment(String::from("no home")));
+        assert_eq!(
+            expand_path_with_home(Path::new("/srv/repos"), no_home).unwrap(),
+            Path::new("/srv/repos")
+        );
+        assert!(expand_path_with_home(Path::new("~/repos"), no_home).is_err());
+        assert!(expand_path_with_home(Path::new("$HOME/repos"), no_home).is_err());
+    }
+
     #[test]
     fn check_expand_home() {
         setup();
         assert_eq!(
-            expand_path(Path::new("$HOME/file")),
+            expand_path(Path::new("$HOME/file")).unwrap(),
             Path::new("/home/test/file")
         );
         assert_eq!(
-            expand_path(Path::new("${HOME}/file")),
+            expand_path(Path::new("${HOME}/file")).unwrap(),
             Path::new("/home/test/file")
         );
     }
 }
 
+/// Converts a path into a string for showing it to humans. Parts that are not
+/// valid UTF-8 are replaced, so the result cannot be used to find the path
+/// again.
 pub fn path_as_string(path: &Path) -> String {
-    path.to_path_buf().into_os_string().into_string().unwrap()
+    path.to_string_lossy().to_string()
 }
 
-pub fn env_home() -> String {
-    match std::env::var("HOME") {
-        Ok(path) => path,
-        Err(e) => {
-            print_error(&format!("Unable to read HOME: {}", e));
-            process::exit(1);
-        }
-    }
+/// Returns the home directory of the current user. On Unix, this is `$HOME`.
+/// On Windows, it is the user's profile directory, e.g. `C:\Users\name`.
+pub fn env_home() -> Result<String, Error> {
+    dirs::home_dir()
+        .ok_or_else(|| Error::Environment(String::from("Unable to find the home directory")))?
+        .into_os_string()
+        .into_string()
+        .map_err(|_| Error::Environment(String::from("Home directory is not valid UTF-8")))
+}
+
+/// Converts a relative path into a repository name, which always uses `/` as
+/// separator, regardless of the platform. Returns `None` if the path is not
+/// valid UTF-8.
+pub fn path_as_name(path: &Path) -> Option<String> {
+    let components = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<&str>>>()?;
+    Some(components.join("/"))
+}
+
+pub fn expand_path(path: &Path) -> Result<PathBuf, Error> {
+    expand_path_with_home(path, env_home)
 }
 
-pub fn expand_path(path: &Path) -> PathBuf {
-    let expanded_path = match shellexpand::full_with_context(
-        &path_as_string(path),
-        || Some(env_home()),
-        |name| -> Result<Option<String>, &'static str> {
+/// Expands `path`, getting the home directory from `home` only if the path
+/// refers to it. So paths without "~" and `$HOME` work without a home
+/// directory.
+fn expand_path_with_home<F>(path: &Path, home: F) -> Result<PathBuf, Error>
+where
+    F: Fn() -> Result<String, Error>,
+{
+    // Variables and "~" can only be expanded in valid UTF-8, anything else is
+    // used as is
+    let path_str = match path.to_str() {
+        Some(path_str) => path_str,
+        None => return Ok(path.to_path_buf()),
+    };
+
+    // The closure for "~" cannot return an error, so it is kept until the
+    // expansion is done
+    let home_error = std::cell::RefCell::new(None);
+    let expanded = shellexpand::full_with_context(
+        path_str,
+        || match home() {
+            Ok(home) => Some(home),
+            Err(error) => {
+                *home_error.borrow_mut() = Some(error);
+                None
+            }
+        },
+        |name| -> Result<Option<String>, String> {
             match name {
-                "HOME" => Ok(Some(env_home())),
+                "HOME" => home().map(Some).map_err(|error| error.to_string()),
                 _ => Ok(None),
             }
         },
-    ) {
+    );
+    if let Some(error) = home_error.into_inner() {
+        return Err(error);
+    }
+
+    let expanded_path = match expanded {
         Ok(std::borrow::Cow::Borrowed(path)) => path.to_owned(),
         Ok(std::borrow::Cow::Owned(path)) => path,
         Err(e) => {
-            print_error(&format!("Unable to expand root: {}", e));
-            process::exit(1);
+            return Err(Error::InvalidPath {
+                path: path_as_string(path),
+                reason: e.to_string(),
+            });
         }
     };
 
-    Path::new(&expanded_path).to_path_buf()
+    Ok(Path::new(&expanded_path).to_path_buf())
+}
+
+/// The total size of all files below `path`, in bytes. Symlinks are not
+/// followed.
+pub fn dir_size(path: &Path) -> Result<u64, String> {
+    let metadata = std::fs::symlink_metadata(path)
+        .map_err(|error| format!("Failed to read \"{}\": {}", path.display(), error))?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)
+        .map_err(|error| format!("Failed to read \"{}\": {}", path.display(), error))?
+    {
+        let entry = entry.map_err(|error| error.to_string())?;
+        size += dir_size(&entry.path())?;
+    }
+    Ok(size)
+}
+
+/// Copies a file or a whole directory to `to`, creating missing parent
+/// directories. Symlinks are copied as symlinks, so they cannot make this
+/// loop forever.
+pub fn copy_recursive(from: &Path, to: &Path) -> Result<(), String> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|error| format!("Failed to create \"{}\": {}", parent.display(), error))?;
+    }
+
+    let file_type = std::fs::symlink_metadata(from)
+        .map_err(|error| format!("Failed to read \"{}\": {}", from.display(), error))?
+        .file_type();
+
+    if file_type.is_symlink() {
+        copy_symlink(from, to)?;
+    } else if file_type.is_dir() {
+        std::fs::create_dir_all(to)
+            .map_err(|error| format!("Failed to create \"{}\": {}", to.display(), error))?;
+        for entry in std::fs::read_dir(from)
+            .map_err(|error| format!("Failed to read \"{}\": {}", from.display(), error))?
+        {
+            let entry = entry.map_err(|error| error.to_string())?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        std::fs::copy(from, to).map_err(|error| {
+            format!(
+                "Failed to copy \"{}\" to \"{}\": {}",
+                from.display(),
+                to.display(),
+                error
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Creates a symlink at `to` with the same target as the one at `from`
+fn copy_symlink(from: &Path, to: &Path) -> Result<(), String> {
+    let target = std::fs::read_link(from)
+        .map_err(|error| format!("Failed to read \"{}\": {}", from.display(), error))?;
+
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(&target, to);
+    // Windows needs to know whether the target is a directory
+    #[cfg(windows)]
+    let result = match from.is_dir() {
+        true => std::os::windows::fs::symlink_dir(&target, to),
+        false => std::os::windows::fs::symlink_file(&target, to),
+    };
+
+    result.map_err(|error| format!("Failed to create \"{}\": {}", to.display(), error))
 }
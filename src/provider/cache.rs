@@ -0,0 +1,186 @@
+//! On-disk cache for the repository listings of forges. Every page is stored
+//! with its `ETag` and `Last-Modified` headers, so the next request for it can
+//! be conditional. If nothing changed, the forge only answers with "304 Not
+//! Modified", which is quick and does not count against the rate limit of
+//! GitHub.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::print_debug;
+use super::repo;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_roundtrip() {
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+        let token = "secret";
+        let other_token = "other";
+        let uri = "https://api.github.com/user/repos?per_page=100";
+
+        assert!(load_from(dir.path(), uri, token).is_none());
+
+        let response = CachedResponse {
+            etag: Some(String::from("\"abc\"")),
+            last_modified: None,
+            link: Some(String::from("<https://api.github.com/page2>; rel=\"next\"")),
+            body: String::from("[]"),
+        };
+        store_in(dir.path(), uri, token, &response).unwrap();
+
+        assert_eq!(load_from(dir.path(), uri, token), Some(response));
+        // Another token may see other repositories
+        assert!(load_from(dir.path(), uri, other_token).is_none());
+    }
+
+    #[test]
+    fn check_cache_file_is_stable() {
+        // The name must not change between builds, or the cache is lost
+        assert_eq!(
+            cache_file(Path::new("/cache"), "https://example.com/repos", "token"),
+            Path::new(
+                "/cache/085cc919421e9f2f988fa0186d40c6e97f52f04eb5612480e5c9d651244a4670.json"
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir::TempDir::new("grm-test").unwrap();
+        let directory = dir.path().join("api");
+        let uri = "https://api.github.com/user/repos";
+        let response = CachedResponse {
+            etag: Some(String::from("\"abc\"")),
+            last_modified: None,
+            link: None,
+            body: String::from("[]"),
+        };
+        store_in(&directory, uri, "secret", &response).unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&directory), 0o700);
+        assert_eq!(mode(&cache_file(&directory, uri, "secret")), 0o600);
+    }
+}
+
+const CACHE_DIRECTORY: &str = "api";
+
+static REFRESH: AtomicBool = AtomicBool::new(false);
+
+/// Ignores the cache, so all listings are downloaded again. They are still
+/// stored for the next run.
+pub fn set_refresh(refresh: bool) {
+    REFRESH.store(refresh, Ordering::Relaxed);
+}
+
+/// A successful response of a forge
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The `Link` header, pointing to the next page
+    pub link: Option<String>,
+    pub body: String,
+}
+
+fn cache_directory() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| {
+        dir.join(repo::GLOBAL_CONFIG_DIRECTORY)
+            .join(CACHE_DIRECTORY)
+    })
+}
+
+/// The file for `uri`. The token is part of the key, as different users see
+/// different repositories. Only its hash is stored. SHA-256 gives the same
+/// name with every build, unlike the hashers of the standard library.
+fn cache_file(directory: &Path, uri: &str, token: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    // The length keeps "a" + "bc" and "ab" + "c" apart
+    hasher.update((uri.len() as u64).to_le_bytes());
+    hasher.update(uri);
+    hasher.update(token);
+    directory.join(format!("{:x}.json", hasher.finalize()))
+}
+
+fn load_from(directory: &Path, uri: &str, token: &str) -> Option<CachedResponse> {
+    let content = std::fs::read_to_string(cache_file(directory, uri, token)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn store_in(
+    directory: &Path,
+    uri: &str,
+    token: &str,
+    response: &CachedResponse,
+) -> Result<(), String> {
+    create_private_dir(directory).map_err(|error| error.to_string())?;
+    let content = serde_json::to_string(response).map_err(|error| error.to_string())?;
+    create_private_file(&cache_file(directory, uri, token))
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|error| error.to_string())
+}
+
+/// Creates `directory` so only the current user can read it, as the responses
+/// list private repositories
+fn create_private_dir(directory: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+        builder.mode(0o700).create(directory)?;
+        // The directory may be left over from before its mode was set
+        std::fs::set_permissions(directory, std::fs::Permissions::from_mode(0o700))
+    }
+    #[cfg(not(unix))]
+    builder.create(directory)
+}
+
+/// Creates or truncates `path` so only the current user can read it
+fn create_private_file(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// The cached response for `uri`, unless the cache is refreshed
+pub fn load(uri: &str, token: &str) -> Option<CachedResponse> {
+    if REFRESH.load(Ordering::Relaxed) {
+        return None;
+    }
+    load_from(&cache_directory()?, uri, token)
+}
+
+/// Stores `response` for `uri`. Responses without `ETag` and `Last-Modified`
+/// cannot be checked for changes, so they are not stored. Failing to store a
+/// response is not an error, it is just slower next time.
+pub fn store(uri: &str, token: &str, response: &CachedResponse) {
+    if response.etag.is_none() && response.last_modified.is_none() {
+        return;
+    }
+    if let Some(directory) = cache_directory() {
+        if let Err(error) = store_in(&directory, uri, token, response) {
+            print_debug(&format!(
+                "Caching the response of {} failed: {}",
+                uri, error
+            ));
+        }
+    }
+}
@@ -2,10 +2,12 @@ use serde::Deserialize;
 
 use super::auth;
 use super::escape;
+use super::net;
 use super::ApiErrorResponse;
 use super::Filter;
 use super::JsonError;
 use super::Project;
+use super::ProjectCreation;
 use super::Provider;
 
 const ACCEPT_HEADER_JSON: &str = "application/vnd.github.v3+json";
@@ -19,6 +21,12 @@ pub struct GithubProject {
     pub clone_url: String,
     pub ssh_url: String,
     pub private: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub fork: bool,
+    #[serde(default)]
+    pub topics: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -51,6 +59,18 @@ impl Project for GithubProject {
     fn private(&self) -> bool {
         self.private
     }
+
+    fn archived(&self) -> bool {
+        self.archived
+    }
+
+    fn fork(&self) -> bool {
+        self.fork
+    }
+
+    fn topics(&self) -> Vec<String> {
+        self.topics.clone()
+    }
 }
 
 #[derive(Deserialize)]
@@ -67,6 +87,7 @@ impl JsonError for GithubApiErrorResponse {
 pub struct Github {
     filter: Filter,
     secret_token: auth::AuthToken,
+    tls: net::TlsOptions,
 }
 
 impl Provider for Github {
@@ -77,6 +98,7 @@ impl Provider for Github {
         filter: Filter,
         secret_token: auth::AuthToken,
         api_url_override: Option<String>,
+        tls: net::TlsOptions,
     ) -> Result<Self, String> {
         if api_url_override.is_some() {
             return Err("API URL overriding is not supported for Github".to_string());
@@ -84,6 +106,7 @@ impl Provider for Github {
         Ok(Self {
             filter,
             secret_token,
+            tls,
         })
     }
 
@@ -95,6 +118,10 @@ impl Provider for Github {
         &self.secret_token
     }
 
+    fn tls(&self) -> &net::TlsOptions {
+        &self.tls
+    }
+
     fn auth_header_key() -> &'static str {
         "token"
     }
@@ -133,8 +160,49 @@ impl Provider for Github {
             &format!("{GITHUB_API_BASEURL}/user"),
             Self::auth_header_key(),
             self.secret_token(),
+            self.tls(),
             Some(ACCEPT_HEADER_JSON),
         )?
         .username)
     }
 }
+
+impl ProjectCreation for Github {
+    fn project_exists(&self, namespace: &str, name: &str) -> Result<bool, String> {
+        super::exists::<GithubApiErrorResponse>(
+            &format!(
+                "{GITHUB_API_BASEURL}/repos/{}/{}",
+                escape(namespace),
+                escape(name)
+            ),
+            Self::auth_header_key(),
+            self.secret_token(),
+            self.tls(),
+            Some(ACCEPT_HEADER_JSON),
+        )
+        .map_err(super::api_error)
+    }
+
+    fn create_project(&self, namespace: &str, name: &str) -> Result<(), String> {
+        // Repositories of the user and of organizations are created differently
+        let uri = if namespace == self.get_current_user().map_err(super::api_error)? {
+            format!("{GITHUB_API_BASEURL}/user/repos")
+        } else {
+            format!("{GITHUB_API_BASEURL}/orgs/{}/repos", escape(namespace))
+        };
+
+        super::post::<GithubProject, GithubApiErrorResponse>(
+            &uri,
+            Self::auth_header_key(),
+            self.secret_token(),
+            self.tls(),
+            Some(ACCEPT_HEADER_JSON),
+            &serde_json::json!({
+                "name": name,
+                "private": true,
+            }),
+        )
+        .map_err(super::api_error)?;
+        Ok(())
+    }
+}
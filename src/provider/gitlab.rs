@@ -2,10 +2,12 @@ use serde::Deserialize;
 
 use super::auth;
 use super::escape;
+use super::net;
 use super::ApiErrorResponse;
 use super::Filter;
 use super::JsonError;
 use super::Project;
+use super::ProjectCreation;
 use super::Provider;
 
 const ACCEPT_HEADER_JSON: &str = "application/json";
@@ -27,6 +29,13 @@ pub struct GitlabProject {
     pub http_url_to_repo: String,
     pub ssh_url_to_repo: String,
     pub visibility: GitlabVisibility,
+    #[serde(default)]
+    pub archived: bool,
+    /// Only present for forks
+    #[serde(default)]
+    pub forked_from_project: Option<serde::de::IgnoredAny>,
+    #[serde(default)]
+    pub topics: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -34,6 +43,11 @@ struct GitlabUser {
     pub username: String,
 }
 
+#[derive(Deserialize)]
+struct GitlabNamespace {
+    pub id: u64,
+}
+
 impl Project for GitlabProject {
     fn name(&self) -> String {
         self.name.clone()
@@ -58,6 +72,18 @@ impl Project for GitlabProject {
     fn private(&self) -> bool {
         !matches!(self.visibility, GitlabVisibility::Public)
     }
+
+    fn archived(&self) -> bool {
+        self.archived
+    }
+
+    fn fork(&self) -> bool {
+        self.forked_from_project.is_some()
+    }
+
+    fn topics(&self) -> Vec<String> {
+        self.topics.clone()
+    }
 }
 
 #[derive(Deserialize)]
@@ -76,6 +102,7 @@ pub struct Gitlab {
     filter: Filter,
     secret_token: auth::AuthToken,
     api_url_override: Option<String>,
+    tls: net::TlsOptions,
 }
 
 impl Gitlab {
@@ -96,11 +123,13 @@ impl Provider for Gitlab {
         filter: Filter,
         secret_token: auth::AuthToken,
         api_url_override: Option<String>,
+        tls: net::TlsOptions,
     ) -> Result<Self, String> {
         Ok(Self {
             filter,
             secret_token,
             api_url_override,
+            tls,
         })
     }
 
@@ -112,6 +141,10 @@ impl Provider for Gitlab {
         &self.secret_token
     }
 
+    fn tls(&self) -> &net::TlsOptions {
+        &self.tls
+    }
+
     fn auth_header_key() -> &'static str {
         "bearer"
     }
@@ -154,8 +187,58 @@ impl Provider for Gitlab {
             &format!("{}/api/v4/user", self.api_url()),
             Self::auth_header_key(),
             self.secret_token(),
+            self.tls(),
             Some(ACCEPT_HEADER_JSON),
         )?
         .username)
     }
 }
+
+impl ProjectCreation for Gitlab {
+    fn project_exists(&self, namespace: &str, name: &str) -> Result<bool, String> {
+        super::exists::<GitlabApiErrorResponse>(
+            &format!(
+                "{}/api/v4/projects/{}",
+                self.api_url(),
+                escape(&format!("{}/{}", namespace, name))
+            ),
+            Self::auth_header_key(),
+            self.secret_token(),
+            self.tls(),
+            Some(ACCEPT_HEADER_JSON),
+        )
+        .map_err(super::api_error)
+    }
+
+    fn create_project(&self, namespace: &str, name: &str) -> Result<(), String> {
+        let mut body = serde_json::json!({
+            "name": name,
+            "path": name,
+            "visibility": "private",
+        });
+
+        // Without a namespace, the project is created for the current user
+        if namespace != self.get_current_user().map_err(super::api_error)? {
+            let namespace = super::call::<GitlabNamespace, GitlabApiErrorResponse>(
+                &format!("{}/api/v4/namespaces/{}", self.api_url(), escape(namespace)),
+                Self::auth_header_key(),
+                self.secret_token(),
+                self.tls(),
+                Some(ACCEPT_HEADER_JSON),
+            )
+            .map_err(super::api_error)?;
+            body["namespace_id"] = serde_json::json!(namespace.id);
+        }
+
+        super::post::<GitlabProject, GitlabApiErrorResponse>(
+            &format!("{}/api/v4/projects", self.api_url()),
+            Self::auth_header_key(),
+            self.secret_token(),
+            self.tls(),
+            Some(ACCEPT_HEADER_JSON),
+            &body,
+        )
+        .map_err(super::api_error)?;
+        Ok(())
+    }
+}
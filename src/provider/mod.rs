@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 // Required to use the `json()` method from the trait
 use isahc::ReadResponseExt;
 
+pub mod cache;
 pub mod github;
 pub mod gitlab;
 
@@ -10,12 +11,204 @@ pub use github::Github;
 pub use gitlab::Gitlab;
 
 use super::auth;
+use super::glob;
+use super::net;
+use super::output::*;
 use super::repo;
+use super::retry;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const DEFAULT_REMOTE_NAME: &str = "origin";
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_parse_project_url() {
+        let project = |base: &str, namespace: &str, name: &str| {
+            Some((base.to_string(), namespace.to_string(), name.to_string()))
+        };
+        assert_eq!(
+            parse_project_url("git@github.com:me/project.git"),
+            project("https://github.com", "me", "project")
+        );
+        assert_eq!(
+            parse_project_url("ssh://git@gitlab.example.com:2222/group/sub/project.git"),
+            project("https://gitlab.example.com", "group/sub", "project")
+        );
+        assert_eq!(
+            parse_project_url("https://gitlab.example.com:8443/group/project"),
+            project("https://gitlab.example.com:8443", "group", "project")
+        );
+        assert_eq!(
+            parse_project_url("http://localhost/me/project.git"),
+            project("http://localhost", "me", "project")
+        );
+        assert_eq!(parse_project_url("https://github.com/project.git"), None);
+        assert_eq!(parse_project_url("file:///srv/git/me/project.git"), None);
+        assert_eq!(parse_project_url("/srv/git/me/project.git"), None);
+    }
+
+    #[test]
+    fn check_with_page_size() {
+        assert_eq!(
+            with_page_size("https://api.github.com/user/repos"),
+            "https://api.github.com/user/repos?per_page=100"
+        );
+        assert_eq!(
+            with_page_size("https://api.github.com/orgs/org/repos?type=all"),
+            "https://api.github.com/orgs/org/repos?type=all&per_page=100"
+        );
+    }
+
+    #[test]
+    fn check_rate_limit_wait() {
+        use isahc::http::{HeaderMap, StatusCode};
+
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        assert_eq!(rate_limit_wait(StatusCode::OK, &headers(&[]), now), None);
+        // Missing permissions
+        assert_eq!(
+            rate_limit_wait(
+                StatusCode::FORBIDDEN,
+                &headers(&[("x-ratelimit-remaining", "10")]),
+                now
+            ),
+            None
+        );
+        assert_eq!(
+            rate_limit_wait(
+                StatusCode::FORBIDDEN,
+                &headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1060")
+                ]),
+                now
+            ),
+            Some(Duration::from_secs(61))
+        );
+        assert_eq!(
+            rate_limit_wait(
+                StatusCode::TOO_MANY_REQUESTS,
+                &headers(&[("retry-after", "30"), ("ratelimit-reset", "1060")]),
+                now
+            ),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            rate_limit_wait(
+                StatusCode::TOO_MANY_REQUESTS,
+                &headers(&[("ratelimit-reset", "900")]),
+                now
+            ),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &headers(&[]), now),
+            Some(DEFAULT_RATE_LIMIT_WAIT)
+        );
+    }
+
+    #[test]
+    fn check_exclusions() {
+        let project =
+            |full_name: &str, archived: bool, fork: bool, topics: &[&str]| github::GithubProject {
+                name: full_name.rsplit('/').next().unwrap().to_string(),
+                full_name: full_name.to_string(),
+                clone_url: String::new(),
+                ssh_url: String::new(),
+                private: false,
+                archived,
+                fork,
+                topics: topics.iter().map(|topic| topic.to_string()).collect(),
+            };
+        let exclusions = Exclusions {
+            archived: true,
+            forks: false,
+            names: vec![
+                glob::Pattern::new("legacy-*").unwrap(),
+                glob::Pattern::new("other/*").unwrap(),
+            ],
+            topics: vec![String::from("deprecated")],
+        };
+
+        let excludes = |project| exclusions.excludes(&project);
+        assert!(!excludes(project("org/app", false, true, &["rust"])));
+        assert!(excludes(project("org/app", true, false, &[])));
+        assert!(excludes(project("org/legacy-app", false, false, &[])));
+        assert!(excludes(project("other/app", false, false, &[])));
+        assert!(excludes(project("org/app", false, false, &["deprecated"])));
+        assert!(!Exclusions::default().excludes(&project(
+            "org/legacy-app",
+            true,
+            true,
+            &["deprecated"]
+        )));
+    }
+    #[test]
+    fn check_layout() {
+        let layout = |template: &str, prefixes: &[&str]| {
+            Layout::new(
+                template,
+                prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+            )
+            .unwrap()
+        };
+
+        let default = Layout::default();
+        assert_eq!(
+            default.path("github.com", Some("org"), "app").unwrap(),
+            "org/app"
+        );
+        assert_eq!(default.path("github.com", None, "app").unwrap(), "app");
+
+        let ghq = layout("{host}/{owner}/{name}", &[]);
+        assert_eq!(
+            ghq.path("gitlab.com", Some("company/team"), "app").unwrap(),
+            "gitlab.com/company/team/app"
+        );
+
+        let teams = layout("{owner}/{name}", &["company"]);
+        assert_eq!(
+            teams
+                .path("gitlab.com", Some("company/team"), "app")
+                .unwrap(),
+            "team/app"
+        );
+        assert_eq!(
+            teams.path("gitlab.com", Some("company"), "app").unwrap(),
+            "app"
+        );
+        // Only whole components are stripped
+        assert_eq!(
+            teams.path("gitlab.com", Some("company-b"), "app").unwrap(),
+            "company-b/app"
+        );
+
+        let flat = layout("{owner}-{name}", &[]);
+        assert_eq!(
+            flat.path("github.com", Some("org"), "app").unwrap(),
+            "org-app"
+        );
+
+        assert!(Layout::new("{owner}", vec![]).is_err());
+        assert!(Layout::new("{owner}/{nam}", vec![]).is_err());
+        assert!(Layout::new("/{name}", vec![]).is_err());
+        assert!(Layout::new("../{name}", vec![]).is_err());
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, clap::ValueEnum, Clone)]
 pub enum RemoteProvider {
     #[serde(alias = "github", alias = "GitHub")]
@@ -31,36 +224,171 @@ enum ProjectResponse<T, U> {
     Failure(U),
 }
 
+/// How many projects are requested per page. This is the maximum of both
+/// GitHub and GitLab, the default is much lower.
+const PAGE_SIZE: usize = 100;
+
+/// Adds the page size to the query of `uri`
+fn with_page_size(uri: &str) -> String {
+    let separator = match uri.contains('?') {
+        true => '&',
+        false => '?',
+    };
+    format!("{}{}per_page={}", uri, separator, PAGE_SIZE)
+}
+
 pub fn escape(s: &str) -> String {
     url_escape::encode_component(s).to_string()
 }
 
+/// The base URL of the forge, the namespace and the name of the project a
+/// remote URL points to, e.g. `("https://github.com", "me", "project")` for
+/// `git@github.com:me/project.git`
+pub fn parse_project_url(url: &str) -> Option<(String, String, String)> {
+    let web = url
+        .strip_prefix("https://")
+        .map(|rest| ("https", rest))
+        .or_else(|| url.strip_prefix("http://").map(|rest| ("http", rest)));
+
+    let (base, path) = if let Some((scheme, rest)) = web {
+        let (host, path) = rest.split_once('/')?;
+        (format!("{}://{}", scheme, host), path)
+    } else {
+        // The SSH port has nothing to do with the one of the web interface
+        let (host, path) = match url.strip_prefix("ssh://") {
+            Some(rest) => {
+                let (host, path) = rest.split_once('/')?;
+                (host.split(':').next()?, path)
+            }
+            None => url.split_once(':')?,
+        };
+        if host.contains('/') || path.starts_with('/') {
+            return None;
+        }
+        let host = host.rsplit('@').next()?;
+        (format!("https://{}", host), path)
+    };
+
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let (namespace, name) = path.rsplit_once('/')?;
+    if base.ends_with("://") || namespace.is_empty() || name.is_empty() {
+        return None;
+    }
+    Some((base, namespace.to_string(), name.to_string()))
+}
+
+/// Creates the project that `remote` points to on its forge, if it does not
+/// exist yet. Returns whether it was created.
+///
+/// GitHub is used for remotes on github.com, every other host is assumed to be
+/// a GitLab instance.
+pub fn create_missing_project(remote: &repo::Remote) -> Result<bool, String> {
+    let (base, namespace, name) = parse_project_url(&remote.url)
+        .ok_or_else(|| format!("Cannot tell the project that \"{}\" points to", remote.url))?;
+
+    let token_command = match &remote.credentials {
+        Some(credentials) => &credentials.token_command,
+        None => {
+            return Err(String::from(
+                "Creating the remote project needs a token_command",
+            ))
+        }
+    };
+    let token = auth::get_token_from_command(token_command)?;
+    let filter = Filter::new(vec![], vec![], false, false);
+
+    let create = |provider: &dyn ProjectCreation| -> Result<bool, String> {
+        if provider.project_exists(&namespace, &name)? {
+            return Ok(false);
+        }
+        provider.create_project(&namespace, &name)?;
+        Ok(true)
+    };
+
+    if base == "https://github.com" {
+        create(&Github::new(filter, token, None, remote.tls.clone())?)
+    } else {
+        let api_url = match base.as_str() {
+            "https://gitlab.com" => None,
+            _ => Some(base),
+        };
+        create(&Gitlab::new(filter, token, api_url, remote.tls.clone())?)
+    }
+}
+
+/// Checking for and creating single projects, to bootstrap remotes that do not
+/// exist yet
+pub trait ProjectCreation {
+    /// Whether the project `namespace/name` exists
+    fn project_exists(&self, namespace: &str, name: &str) -> Result<bool, String>;
+
+    /// Creates the private project `namespace/name`. The namespace is either
+    /// the current user or a group (organization) that can hold projects.
+    fn create_project(&self, namespace: &str, name: &str) -> Result<(), String>;
+}
+
+fn api_error<U>(error: ApiErrorResponse<U>) -> String
+where
+    U: JsonError,
+{
+    match error {
+        ApiErrorResponse::Json(x) => x.to_string(),
+        ApiErrorResponse::String(s) => s,
+    }
+}
+
 pub trait Project {
     fn into_repo_config(
         self,
         provider_name: &str,
         worktree_setup: bool,
         force_ssh: bool,
+        tls: &net::TlsOptions,
     ) -> repo::Repo
     where
         Self: Sized,
     {
+        let use_ssh = force_ssh || self.private();
         repo::Repo {
             name: self.name(),
             namespace: self.namespace(),
             worktree_setup,
+            clone_options: repo::CloneOptions::default(),
+            submodules: repo::Submodules::default(),
+            lfs: false,
+            clone_mode: repo::CloneMode::default(),
+            rev: None,
+            default_branch: None,
+            git_config: BTreeMap::new(),
+            pre_sync: Vec::new(),
+            post_sync: Vec::new(),
+            on_clone: Vec::new(),
             remotes: Some(vec![repo::Remote {
                 name: String::from(provider_name),
-                url: if force_ssh || self.private() {
+                url: if use_ssh {
                     self.ssh_url()
                 } else {
                     self.http_url()
                 },
-                remote_type: if force_ssh || self.private() {
+                push_urls: Vec::new(),
+                remote_type: if use_ssh {
                     repo::RemoteType::Ssh
                 } else {
                     repo::RemoteType::Https
                 },
+                push_mirror: false,
+                primary: false,
+                // The TLS options of the forge apply to clones via HTTPS as
+                // well
+                tls: if use_ssh {
+                    net::TlsOptions::default()
+                } else {
+                    tls.clone()
+                },
+                refspecs: Vec::new(),
+                credentials: None,
+                create: false,
             }]),
         }
     }
@@ -70,6 +398,9 @@ pub trait Project {
     fn ssh_url(&self) -> String;
     fn http_url(&self) -> String;
     fn private(&self) -> bool;
+    fn archived(&self) -> bool;
+    fn fork(&self) -> bool;
+    fn topics(&self) -> Vec<String>;
 }
 
 #[derive(Clone)]
@@ -78,6 +409,46 @@ pub struct Filter {
     groups: Vec<String>,
     owner: bool,
     access: bool,
+    exclusions: Exclusions,
+}
+
+/// Repositories to skip, even though they match the filter
+#[derive(Clone, Default)]
+pub struct Exclusions {
+    pub archived: bool,
+    pub forks: bool,
+    /// Patterns for the names of repositories. If a pattern contains a `/`,
+    /// it is matched against the name including the namespace.
+    pub names: Vec<glob::Pattern>,
+    pub topics: Vec<String>,
+}
+
+impl Exclusions {
+    fn excludes(&self, project: &impl Project) -> bool {
+        if (self.archived && project.archived()) || (self.forks && project.fork()) {
+            return true;
+        }
+
+        let name = project.name();
+        let full_name = match project.namespace() {
+            Some(namespace) => format!("{}/{}", namespace, name),
+            None => name.clone(),
+        };
+        for pattern in &self.names {
+            let candidate = match pattern.as_str().contains('/') {
+                true => &full_name,
+                false => &name,
+            };
+            if pattern.is_match(candidate) {
+                return true;
+            }
+        }
+
+        project
+            .topics()
+            .iter()
+            .any(|topic| self.topics.contains(topic))
+    }
 }
 
 impl Filter {
@@ -87,14 +458,125 @@ impl Filter {
             groups,
             owner,
             access,
+            exclusions: Exclusions::default(),
         }
     }
 
+    pub fn with_exclusions(mut self, exclusions: Exclusions) -> Self {
+        self.exclusions = exclusions;
+        self
+    }
+
     pub fn empty(&self) -> bool {
         self.users.is_empty() && self.groups.is_empty() && !self.owner && !self.access
     }
 }
 
+/// The default layout, putting every repository into a directory named after
+/// its namespace
+pub const DEFAULT_LAYOUT: &str = "{owner}/{name}";
+
+const LAYOUT_PLACEHOLDERS: [&str; 3] = ["{host}", "{owner}", "{name}"];
+
+/// Where the repositories of a forge end up below the root, e.g.
+/// `{host}/{owner}/{name}` for the layout of ghq
+#[derive(Clone, Debug)]
+pub struct Layout {
+    template: String,
+    /// Namespace prefixes that are removed before `{owner}` is replaced, e.g.
+    /// `company` to get `team/repo` instead of `company/team/repo`
+    strip_owner_prefixes: Vec<String>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            template: String::from(DEFAULT_LAYOUT),
+            strip_owner_prefixes: Vec::new(),
+        }
+    }
+}
+
+impl Layout {
+    pub fn new(template: &str, strip_owner_prefixes: Vec<String>) -> Result<Self, String> {
+        let mut rest = template.to_string();
+        for placeholder in LAYOUT_PLACEHOLDERS.iter() {
+            rest = rest.replace(placeholder, "");
+        }
+        if rest.contains('{') || rest.contains('}') {
+            return Err(format!(
+                "Invalid layout \"{}\", only {} can be used as placeholders",
+                template,
+                LAYOUT_PLACEHOLDERS.join(", ")
+            ));
+        }
+        if !template.contains("{name}") {
+            return Err(format!(
+                "Invalid layout \"{}\", it has to contain {{name}}",
+                template
+            ));
+        }
+        if template.starts_with('/')
+            || template
+                .split('/')
+                .any(|component| component == ".." || component == ".")
+        {
+            return Err(format!(
+                "Invalid layout \"{}\", it has to be a relative path below the root",
+                template
+            ));
+        }
+
+        Ok(Layout {
+            template: template.to_string(),
+            strip_owner_prefixes: strip_owner_prefixes
+                .into_iter()
+                .map(|prefix| prefix.trim_matches('/').to_string())
+                .collect(),
+        })
+    }
+
+    fn strip_owner_prefix<'a>(&self, owner: &'a str) -> &'a str {
+        for prefix in &self.strip_owner_prefixes {
+            if owner == prefix {
+                return "";
+            }
+            if let Some(rest) = owner
+                .strip_prefix(prefix.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                return rest;
+            }
+        }
+        owner
+    }
+
+    /// The path of a repository relative to the root. Components that end up
+    /// empty, e.g. for repositories without namespace, are dropped.
+    pub fn path(&self, host: &str, owner: Option<&str>, name: &str) -> Result<String, String> {
+        let owner = self.strip_owner_prefix(owner.unwrap_or(""));
+
+        let path = self
+            .template
+            .replace("{host}", host)
+            .replace("{owner}", owner)
+            .replace("{name}", name);
+
+        let components: Vec<&str> = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect();
+        if components
+            .iter()
+            .any(|component| *component == ".." || *component == ".")
+        {
+            return Err(format!("Invalid path \"{}\" for repository {}", path, name));
+        }
+
+        Ok(components.join("/"))
+    }
+}
+
 pub enum ApiErrorResponse<T>
 where
     T: JsonError,
@@ -124,12 +606,14 @@ pub trait Provider {
         filter: Filter,
         secret_token: auth::AuthToken,
         api_url_override: Option<String>,
+        tls: net::TlsOptions,
     ) -> Result<Self, String>
     where
         Self: Sized;
 
     fn filter(&self) -> &Filter;
     fn secret_token(&self) -> &auth::AuthToken;
+    fn tls(&self) -> &net::TlsOptions;
     fn auth_header_key() -> &'static str;
 
     fn get_user_projects(
@@ -163,52 +647,102 @@ pub trait Provider {
     ) -> Result<Vec<Self::Project>, ApiErrorResponse<Self::Error>> {
         let mut results = vec![];
 
-        let client = isahc::HttpClient::new().map_err(|error| error.to_string())?;
+        let client = net::http_client(self.tls())?;
+        let progress = ProgressBar::new("Listing repositories");
 
-        let request = isahc::Request::builder()
-            .uri(uri)
-            .method("GET")
-            .header("accept", accept_header.unwrap_or("application/json"))
-            .header(
-                "authorization",
-                format!(
-                    "{} {}",
-                    Self::auth_header_key(),
-                    &self.secret_token().access()
-                ),
-            )
-            .body(())
-            .map_err(|error| error.to_string())?;
+        let mut next_uri = Some(with_page_size(uri));
+        let mut page = 0;
+        while let Some(uri) = next_uri.take() {
+            page += 1;
+            print_debug(&format!("GET {}", uri));
 
-        let mut response = client
-            .send(request)
-            .map_err(|error| ApiErrorResponse::String(error.to_string()))?;
+            let cached = cache::load(&uri, self.secret_token().access());
 
-        if !response.status().is_success() {
-            let r: Self::Error = response
-                .json()
-                .map_err(|error| format!("Failed deserializing error response: {}", error))?;
-            return Err(ApiErrorResponse::Json(r));
-        }
+            let mut response = send(&client, &uri, || {
+                let mut request = isahc::Request::builder()
+                    .uri(&uri)
+                    .method("GET")
+                    .header("accept", accept_header.unwrap_or("application/json"))
+                    .header(
+                        "authorization",
+                        format!(
+                            "{} {}",
+                            Self::auth_header_key(),
+                            &self.secret_token().access()
+                        ),
+                    );
+                if let Some(cached) = &cached {
+                    if let Some(etag) = &cached.etag {
+                        request = request.header("if-none-match", etag);
+                    }
+                    if let Some(last_modified) = &cached.last_modified {
+                        request = request.header("if-modified-since", last_modified);
+                    }
+                }
+                request.body(())
+            })
+            .map_err(ApiErrorResponse::String)?;
 
-        let result: Vec<Self::Project> = response
-            .json()
-            .map_err(|error| format!("Failed deserializing response: {}", error))?;
+            let response = match cached {
+                Some(cached) if response.status() == isahc::http::StatusCode::NOT_MODIFIED => {
+                    print_debug(&format!("{} did not change, using cached response", uri));
+                    cached
+                }
+                _ => {
+                    if !response.status().is_success() {
+                        let r: Self::Error = response.json().map_err(|error| {
+                            format!("Failed deserializing error response: {}", error)
+                        })?;
+                        return Err(ApiErrorResponse::Json(r));
+                    }
 
-        results.extend(result);
+                    let header = |name: &str| {
+                        response
+                            .headers()
+                            .get(name)
+                            .and_then(|value| value.to_str().ok())
+                            .map(|value| value.to_string())
+                    };
+                    let (etag, last_modified, link) =
+                        (header("etag"), header("last-modified"), header("link"));
+                    let fresh = cache::CachedResponse {
+                        etag,
+                        last_modified,
+                        link,
+                        body: response
+                            .text()
+                            .map_err(|error| format!("Failed reading response: {}", error))?,
+                    };
+                    cache::store(&uri, self.secret_token().access(), &fresh);
+                    fresh
+                }
+            };
 
-        if let Some(link_header) = response.headers().get("link") {
-            let link_header = link_header.to_str().map_err(|error| error.to_string())?;
+            let result: Vec<Self::Project> = serde_json::from_str(&response.body)
+                .map_err(|error| format!("Failed deserializing response: {}", error))?;
 
-            let link_header =
-                parse_link_header::parse(link_header).map_err(|error| error.to_string())?;
+            results.extend(result);
 
-            let next_page = link_header.get(&Some(String::from("next")));
+            // Without a "last" link, this is the last page
+            let mut last_page = page;
+            if let Some(link_header) = &response.link {
+                let link_header =
+                    parse_link_header::parse(link_header).map_err(|error| error.to_string())?;
 
-            if let Some(page) = next_page {
-                let following_repos = self.call_list(&page.raw_uri, accept_header)?;
-                results.extend(following_repos);
+                next_uri = link_header
+                    .get(&Some(String::from("next")))
+                    .map(|link| link.raw_uri.clone());
+
+                if let Some(last) = link_header
+                    .get(&Some(String::from("last")))
+                    .and_then(|link| link.queries.get("page"))
+                    .and_then(|last| last.parse().ok())
+                {
+                    last_page = last;
+                }
             }
+
+            progress.set(page, last_page, &format!("{} repositories", results.len()));
         }
 
         Ok(results)
@@ -219,6 +753,7 @@ pub trait Provider {
         worktree_setup: bool,
         force_ssh: bool,
         remote_name: Option<String>,
+        layout: &Layout,
     ) -> Result<HashMap<Option<String>, Vec<repo::Repo>>, String> {
         let mut repos = vec![];
 
@@ -300,55 +835,299 @@ pub trait Provider {
             }
         }
 
+        let mut included = Vec::new();
+        for repo in repos {
+            if self.filter().exclusions.excludes(&repo) {
+                print_debug(&format!("Skipping excluded repository {}", repo.name()));
+            } else {
+                included.push(repo);
+            }
+        }
+        let repos = included;
+
         let mut ret: HashMap<Option<String>, Vec<repo::Repo>> = HashMap::new();
+        let mut paths: HashMap<String, String> = HashMap::new();
 
         let remote_name = remote_name.unwrap_or_else(|| DEFAULT_REMOTE_NAME.to_string());
 
         for repo in repos {
             let namespace = repo.namespace();
+            let full_name = match &namespace {
+                Some(namespace) => format!("{}/{}", namespace, repo.name()),
+                None => repo.name(),
+            };
 
-            let mut repo = repo.into_repo_config(&remote_name, worktree_setup, force_ssh);
+            let mut repo =
+                repo.into_repo_config(&remote_name, worktree_setup, force_ssh, self.tls());
 
-            // Namespace is already part of the hashmap key. I'm not too happy
-            // about the data exchange format here.
+            let host = repo
+                .remotes
+                .iter()
+                .flatten()
+                .find_map(|remote| repo::remote_host(&remote.url))
+                .unwrap_or_default();
+            let path = layout.path(&host, namespace.as_deref(), &repo.name)?;
+
+            if let Some(other) = paths.insert(path.clone(), full_name.clone()) {
+                return Err(format!(
+                    "Repositories {} and {} would both end up in \"{}\", adapt the layout",
+                    other, full_name, path
+                ));
+            }
+
+            // The directory is part of the hashmap key, the last component of
+            // the path is the name of the repository. I'm not too happy about
+            // the data exchange format here.
+            let (directory, name) = match path.rsplit_once('/') {
+                Some((directory, name)) => (Some(directory.to_string()), name.to_string()),
+                None => (None, path),
+            };
+            repo.name = name;
             repo.remove_namespace();
 
-            ret.entry(namespace).or_insert(vec![]).push(repo);
+            ret.entry(directory).or_insert(vec![]).push(repo);
         }
 
         Ok(ret)
     }
 }
 
+/// How long to wait for a rate limit to reset at most. GitHub resets its
+/// limits every hour.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// How long to wait if a rate limit was hit, but the response does not tell
+/// when it resets
+const DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// How long to wait before the next request, if `status` and `headers` of a
+/// response say that a rate limit was hit. GitHub responds with "403
+/// Forbidden" for those as well, so the headers have to tell them apart from
+/// missing permissions.
+fn rate_limit_wait(
+    status: isahc::http::StatusCode,
+    headers: &isahc::http::HeaderMap,
+    now: SystemTime,
+) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    let limited = status == isahc::http::StatusCode::TOO_MANY_REQUESTS
+        || (status == isahc::http::StatusCode::FORBIDDEN
+            && (header("x-ratelimit-remaining") == Some(0) || header("retry-after").is_some()));
+    if !limited {
+        return None;
+    }
+
+    if let Some(seconds) = header("retry-after") {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    // The time of the reset, in seconds since the epoch. One second is added,
+    // as the clocks of client and server are hardly ever in sync.
+    match header("x-ratelimit-reset").or_else(|| header("ratelimit-reset")) {
+        Some(reset) => {
+            let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            Some(Duration::from_secs(reset.saturating_sub(now) + 1))
+        }
+        None => Some(DEFAULT_RATE_LIMIT_WAIT),
+    }
+}
+
+/// A request that failed, and whether trying again might help
+struct SendError {
+    message: String,
+    transient: bool,
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Sends the request built by `request`, retrying on connection errors and
+/// server errors. Other errors, like a missing authorization, are returned
+/// as responses right away, as retrying cannot fix them.
+///
+/// If a rate limit is hit, this waits until it resets. That does not count as
+/// a retry.
+fn send<F>(
+    client: &isahc::HttpClient,
+    uri: &str,
+    request: F,
+) -> Result<isahc::Response<isahc::Body>, String>
+where
+    F: Fn() -> Result<isahc::Request<()>, isahc::http::Error>,
+{
+    let request = &request;
+    loop {
+        let response = retry::run(
+            &format!("GET {}", uri),
+            |error: &SendError| error.transient,
+            || {
+                let request = request().map_err(|error| SendError {
+                    message: error.to_string(),
+                    transient: false,
+                })?;
+                let response = client.send(request).map_err(|error| SendError {
+                    // Includes failed TLS handshakes, unlike is_tls(), which
+                    // is about certificates that will not become valid
+                    transient: error.is_network() || error.is_timeout(),
+                    message: error.to_string(),
+                })?;
+
+                let status = response.status();
+                if status.is_server_error() {
+                    return Err(SendError {
+                        message: format!("Server responded with \"{}\"", status),
+                        transient: true,
+                    });
+                }
+                Ok(response)
+            },
+        )
+        .map_err(|error| error.message)?;
+
+        match rate_limit_wait(response.status(), response.headers(), SystemTime::now()) {
+            None => return Ok(response),
+            Some(wait) if wait > MAX_RATE_LIMIT_WAIT => {
+                return Err(format!(
+                    "Rate limit exceeded, it resets in {} minutes",
+                    wait.as_secs() / 60
+                ))
+            }
+            Some(wait) => {
+                print_warning(&format!(
+                    "Rate limit exceeded, waiting {}s for it to reset",
+                    wait.as_secs()
+                ));
+                std::thread::sleep(wait);
+            }
+        }
+    }
+}
+
 fn call<T, U>(
     uri: &str,
     auth_header_key: &str,
     secret_token: &auth::AuthToken,
+    tls: &net::TlsOptions,
+    accept_header: Option<&str>,
+) -> Result<T, ApiErrorResponse<U>>
+where
+    T: serde::de::DeserializeOwned,
+    U: serde::de::DeserializeOwned + JsonError,
+{
+    print_debug(&format!("GET {}", uri));
+    let client = net::http_client(tls)?;
+
+    let mut response = send(&client, uri, || {
+        isahc::Request::builder()
+            .uri(uri)
+            .header("accept", accept_header.unwrap_or("application/json"))
+            .header(
+                "authorization",
+                format!("{} {}", &auth_header_key, &secret_token.access()),
+            )
+            .body(())
+    })
+    .map_err(ApiErrorResponse::String)?;
+
+    let success = response.status().is_success();
+
+    if !success {
+        let response: U = response
+            .json()
+            .map_err(|error| format!("Failed deserializing error response: {}", error))?;
+
+        return Err(ApiErrorResponse::Json(response));
+    }
+
+    let response: T = response
+        .json()
+        .map_err(|error| format!("Failed deserializing response: {}", error))?;
+
+    Ok(response)
+}
+
+/// Whether `uri` exists, i.e. does not respond with "404 Not Found"
+fn exists<U>(
+    uri: &str,
+    auth_header_key: &str,
+    secret_token: &auth::AuthToken,
+    tls: &net::TlsOptions,
     accept_header: Option<&str>,
+) -> Result<bool, ApiErrorResponse<U>>
+where
+    U: serde::de::DeserializeOwned + JsonError,
+{
+    print_debug(&format!("GET {}", uri));
+    let client = net::http_client(tls)?;
+
+    let mut response = send(&client, uri, || {
+        isahc::Request::builder()
+            .uri(uri)
+            .header("accept", accept_header.unwrap_or("application/json"))
+            .header(
+                "authorization",
+                format!("{} {}", &auth_header_key, &secret_token.access()),
+            )
+            .body(())
+    })
+    .map_err(ApiErrorResponse::String)?;
+
+    if response.status() == isahc::http::StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+
+    if !response.status().is_success() {
+        let response: U = response
+            .json()
+            .map_err(|error| format!("Failed deserializing error response: {}", error))?;
+
+        return Err(ApiErrorResponse::Json(response));
+    }
+
+    Ok(true)
+}
+
+/// Sends `body` as JSON to `uri`. Unlike GET requests, this is not retried, as
+/// the request may have had an effect even when it failed.
+fn post<T, U>(
+    uri: &str,
+    auth_header_key: &str,
+    secret_token: &auth::AuthToken,
+    tls: &net::TlsOptions,
+    accept_header: Option<&str>,
+    body: &serde_json::Value,
 ) -> Result<T, ApiErrorResponse<U>>
 where
     T: serde::de::DeserializeOwned,
     U: serde::de::DeserializeOwned + JsonError,
 {
-    let client = isahc::HttpClient::new().map_err(|error| error.to_string())?;
+    print_debug(&format!("POST {}", uri));
+    let client = net::http_client(tls)?;
 
     let request = isahc::Request::builder()
         .uri(uri)
+        .method("POST")
         .header("accept", accept_header.unwrap_or("application/json"))
+        .header("content-type", "application/json")
         .header(
             "authorization",
             format!("{} {}", &auth_header_key, &secret_token.access()),
         )
-        .body(())
-        .map_err(|error| ApiErrorResponse::String(error.to_string()))?;
+        .body(body.to_string())
+        .map_err(|error| error.to_string())?;
 
-    let mut response = client
-        .send(request)
-        .map_err(|error| ApiErrorResponse::String(error.to_string()))?;
+    let mut response = client.send(request).map_err(|error| error.to_string())?;
 
-    let success = response.status().is_success();
-
-    if !success {
+    if !response.status().is_success() {
         let response: U = response
             .json()
             .map_err(|error| format!("Failed deserializing error response: {}", error))?;
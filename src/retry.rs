@@ -0,0 +1,144 @@
+//! Retrying network operations, so a short hiccup of DNS, TLS or the remote
+//! host does not fail a whole repository.
+//!
+//! The policy is global, like the output settings, as network operations
+//! happen deep down in many different places. It is set once at startup.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::output::*;
+
+/// The delay is doubled after every attempt, but never exceeds this
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_delays() {
+        assert_eq!(
+            delays(4, Duration::from_secs(10)).collect::<Vec<Duration>>(),
+            vec![
+                Duration::from_secs(10),
+                Duration::from_secs(20),
+                Duration::from_secs(40),
+                Duration::from_secs(60),
+            ]
+        );
+        assert_eq!(delays(0, Duration::from_secs(1)).count(), 0);
+        // Computed lazily, so a huge number of retries does not allocate
+        assert_eq!(
+            delays(u32::MAX, Duration::from_secs(1)).nth(100),
+            Some(MAX_DELAY)
+        );
+    }
+
+    #[test]
+    fn check_only_transient_errors_are_retried() {
+        let delays = || vec![Duration::ZERO; 3].into_iter();
+
+        let mut attempts = 0;
+        let result: Result<(), String> = run_with_delays(
+            "test",
+            delays(),
+            |error| error == "transient",
+            || {
+                attempts += 1;
+                Err(String::from("permanent"))
+            },
+        );
+        assert_eq!(result, Err(String::from("permanent")));
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let result: Result<(), String> = run_with_delays(
+            "test",
+            delays(),
+            |error| error == "transient",
+            || {
+                attempts += 1;
+                Err(String::from("transient"))
+            },
+        );
+        assert_eq!(result, Err(String::from("transient")));
+        assert_eq!(attempts, 4);
+    }
+}
+
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+static INITIAL_DELAY_MS: AtomicU64 = AtomicU64::new(1000);
+
+/// Sets how often failed network operations are retried, and how long to wait
+/// before the first retry
+pub fn set_policy(retries: u32, initial_delay: Duration) {
+    RETRIES.store(retries, Ordering::Relaxed);
+    INITIAL_DELAY_MS.store(
+        u64::try_from(initial_delay.as_millis()).unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+}
+
+/// The delays before each retry, doubling every time
+fn delays(retries: u32, initial_delay: Duration) -> impl ExactSizeIterator<Item = Duration> {
+    let mut delay = initial_delay;
+    (0..retries).map(move |_| {
+        let current = std::cmp::min(delay, MAX_DELAY);
+        delay = current * 2;
+        current
+    })
+}
+
+/// Runs `operation`, retrying it according to the policy if it fails with an
+/// error that `is_transient` accepts, e.g. a connection that was reset. Other
+/// errors are returned right away, as trying again cannot fix them. `what`
+/// describes the operation in the messages about failed attempts.
+pub fn run<T, E, P, F>(what: &str, is_transient: P, operation: F) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    P: FnMut(&E) -> bool,
+    F: FnMut() -> Result<T, E>,
+{
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let delays = delays(
+        retries,
+        Duration::from_millis(INITIAL_DELAY_MS.load(Ordering::Relaxed)),
+    );
+    run_with_delays(what, delays, is_transient, operation)
+}
+
+fn run_with_delays<T, E, D, P, F>(
+    what: &str,
+    delays: D,
+    mut is_transient: P,
+    mut operation: F,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    D: ExactSizeIterator<Item = Duration>,
+    P: FnMut(&E) -> bool,
+    F: FnMut() -> Result<T, E>,
+{
+    let attempts = delays.len() + 1;
+
+    for (attempt, delay) in delays.enumerate() {
+        match operation() {
+            Ok(result) => return Ok(result),
+            Err(error) if !is_transient(&error) => return Err(error),
+            Err(error) => {
+                print_warning(&format!(
+                    "{} failed (attempt {}/{}): {}. Retrying in {:.1}s",
+                    what,
+                    attempt + 1,
+                    attempts,
+                    error,
+                    delay.as_secs_f64()
+                ));
+                std::thread::sleep(delay);
+            }
+        }
+    }
+
+    operation()
+}
@@ -0,0 +1,135 @@
+//! Decryption of configuration files that are encrypted with
+//! [age](https://age-encryption.org) or [SOPS](https://github.com/getsops/sops),
+//! so they can be committed to a dotfiles repository without leaking secrets.
+//!
+//! Both are optional: The `age` and `sops` binaries are only needed once an
+//! encrypted file is actually read.
+
+use std::path::{Path, PathBuf};
+use std::process;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_encryption() {
+        assert_eq!(
+            encryption(b"-----BEGIN AGE ENCRYPTED FILE-----\nabc\n"),
+            Some(Encryption::Age)
+        );
+        assert_eq!(
+            encryption(b"age-encryption.org/v1\n-> X25519 abc\n"),
+            Some(Encryption::Age)
+        );
+        assert_eq!(
+            encryption(b"trees:\n- root: ENC[AES256_GCM,data:abc]\nsops:\n  mac: ENC[abc]\n"),
+            Some(Encryption::Sops(SopsFormat::Yaml))
+        );
+        assert_eq!(
+            encryption(b"{\"trees\": [], \"sops\": {\"mac\": \"ENC[abc]\"}}"),
+            Some(Encryption::Sops(SopsFormat::Json))
+        );
+        assert_eq!(encryption(b"[[trees]]\nroot = \"~/projects\"\n"), None);
+        assert_eq!(encryption(b"trees:\n- root: ~/sops\n"), None);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SopsFormat {
+    Yaml,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Encryption {
+    Age,
+    Sops(SopsFormat),
+}
+
+fn encryption(content: &[u8]) -> Option<Encryption> {
+    if content.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+        || content.starts_with(b"age-encryption.org/")
+    {
+        return Some(Encryption::Age);
+    }
+
+    // SOPS keeps its metadata (keys and MAC) next to the encrypted values, in
+    // a top-level "sops" key. It does not support TOML.
+    let content = std::str::from_utf8(content).ok()?;
+    let format = match content.trim_start().starts_with('{') {
+        true => SopsFormat::Json,
+        false => SopsFormat::Yaml,
+    };
+    match serde_yaml::from_str::<serde_yaml::Value>(content) {
+        Ok(serde_yaml::Value::Mapping(mapping)) => mapping
+            .get("sops")
+            .filter(|sops| sops.get("mac").is_some())
+            .map(|_| Encryption::Sops(format)),
+        _ => None,
+    }
+}
+
+/// Whether `content` is encrypted with age or SOPS
+pub fn is_encrypted(content: &[u8]) -> bool {
+    encryption(content).is_some()
+}
+
+/// The identity file for age. This is the same one SOPS uses, so a single key
+/// works for both.
+fn age_identity() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("SOPS_AGE_KEY_FILE") {
+        return Some(PathBuf::from(path));
+    }
+    dirs::config_dir()
+        .map(|dir| dir.join("sops").join("age").join("keys.txt"))
+        .filter(|path| path.exists())
+}
+
+fn run(command: &mut process::Command, name: &str) -> Result<String, String> {
+    let output = command.output().map_err(|error| match error.kind() {
+        std::io::ErrorKind::NotFound => {
+            format!("The file is encrypted, but \"{}\" is not installed", name)
+        }
+        _ => format!("Failed to run {}: {}", name, error),
+    })?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Decryption with {} failed: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    String::from_utf8(output.stdout).map_err(|error| error.to_string())
+}
+
+/// Returns `content` of the file at `path` as a string, decrypting it first if
+/// it is encrypted with age or SOPS
+pub fn decrypt(path: &Path, content: Vec<u8>) -> Result<String, String> {
+    match encryption(&content) {
+        None => String::from_utf8(content).map_err(|error| error.to_string()),
+        Some(Encryption::Age) => {
+            let mut command = process::Command::new("age");
+            command.arg("--decrypt");
+            if let Some(identity) = age_identity() {
+                command.arg("--identity").arg(identity);
+            }
+            run(command.arg("--").arg(path), "age")
+        }
+        Some(Encryption::Sops(format)) => {
+            let format = match format {
+                SopsFormat::Yaml => "yaml",
+                SopsFormat::Json => "json",
+            };
+            run(
+                process::Command::new("sops")
+                    .arg("--decrypt")
+                    .args(["--input-type", format, "--output-type", format])
+                    .arg(path),
+                "sops",
+            )
+        }
+    }
+}
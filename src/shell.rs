@@ -0,0 +1,272 @@
+//! Integration into interactive shells. A program cannot change the working
+//! directory of the shell that runs it, so this is done by shell functions
+//! that call grm. The same goes for completions that depend on the state of
+//! the configuration or the current directory.
+
+use std::path::Path;
+
+use super::config;
+use super::list;
+use super::repo;
+use super::worktree;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_init_script() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = init_script(shell);
+            assert!(script.contains("gwt"));
+            assert!(script.contains("grm worktree path --fuzzy"));
+        }
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn check_completion_target() {
+        assert!(matches!(
+            completion_target(&words(&["repos", "path"])),
+            CompletionTarget::Repos(None)
+        ));
+        assert!(matches!(
+            completion_target(&words(&["-v", "repos", "remove", "--config", "my.toml"])),
+            CompletionTarget::Repos(Some(config)) if config == "my.toml"
+        ));
+        assert!(matches!(
+            completion_target(&words(&["repos", "path", "--tree", "/projects", "-c=my.toml"])),
+            CompletionTarget::Repos(Some(config)) if config == "my.toml"
+        ));
+        assert!(matches!(
+            completion_target(&words(&["--color", "never", "wt", "delete"])),
+            CompletionTarget::Worktrees
+        ));
+        assert!(matches!(
+            completion_target(&words(&["worktree", "path", "--fuzzy"])),
+            CompletionTarget::Worktrees
+        ));
+
+        assert!(matches!(
+            completion_target(&words(&["repos", "path", "name"])),
+            CompletionTarget::Nothing
+        ));
+        assert!(matches!(
+            completion_target(&words(&["repos", "path", "--tree"])),
+            CompletionTarget::Nothing
+        ));
+        assert!(matches!(
+            completion_target(&words(&["wt", "add"])),
+            CompletionTarget::Nothing
+        ));
+        assert!(matches!(
+            completion_target(&words(&["repos"])),
+            CompletionTarget::Nothing
+        ));
+    }
+
+    #[test]
+    fn check_completion_script() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            assert!(completion_script(shell).contains("grm complete --"));
+        }
+    }
+}
+
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+const POSIX_INIT: &str = r#"gwt() {
+    local dir
+    dir="$(grm worktree path --fuzzy "$@")" && cd "$dir"
+}
+"#;
+
+const FISH_INIT: &str = r#"function gwt
+    set -l dir (grm worktree path --fuzzy $argv)
+    and cd $dir
+end
+"#;
+
+/// The script that defines the `gwt` function, which changes into a worktree
+/// of the current worktree setup by its name. Without a name, it changes into
+/// the root of the worktree setup.
+pub fn init_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash | Shell::Zsh => POSIX_INIT,
+        Shell::Fish => FISH_INIT,
+    }
+}
+
+const DEFAULT_CONFIG: &str = "./config.toml";
+
+/// Options of the commands that complete names which take a value. Their
+/// values must not be mistaken for positional arguments.
+const VALUE_OPTIONS: &[&str] = &[
+    "-c",
+    "--config",
+    "--tree",
+    "--color",
+    "--log-file",
+    "--retries",
+    "--retry-delay",
+    "--proxy",
+    "--connect-timeout",
+    "--timeout",
+];
+
+/// What the word following a partial command line can be completed with
+enum CompletionTarget {
+    /// Names of the repositories in the given configuration file, or the
+    /// default one
+    Repos(Option<String>),
+    /// Names of the worktrees of the current worktree setup
+    Worktrees,
+    Nothing,
+}
+
+/// Finds out what the next word after `words` (without the program name) can
+/// be completed with
+fn completion_target(words: &[String]) -> CompletionTarget {
+    let mut positionals = Vec::new();
+    let mut config = None;
+
+    let mut words = words.iter();
+    let mut expects_value = false;
+    while let Some(word) = words.next() {
+        if let Some((option, value)) = word.split_once('=').filter(|_| word.starts_with('-')) {
+            if option == "-c" || option == "--config" {
+                config = Some(value.to_string());
+            }
+            continue;
+        }
+        if VALUE_OPTIONS.contains(&word.as_str()) {
+            match words.next() {
+                Some(value) => {
+                    if word == "-c" || word == "--config" {
+                        config = Some(value.clone());
+                    }
+                }
+                None => expects_value = true,
+            }
+            continue;
+        }
+        if !word.starts_with('-') {
+            positionals.push(word.as_str());
+        }
+    }
+
+    if expects_value {
+        return CompletionTarget::Nothing;
+    }
+
+    match positionals[..] {
+        ["repos", "path" | "remove"] => CompletionTarget::Repos(config),
+        ["worktree" | "wt", "delete" | "path"] => CompletionTarget::Worktrees,
+        _ => CompletionTarget::Nothing,
+    }
+}
+
+/// Returns the names the next word after `words` (without the program name)
+/// can be completed with. Errors are ignored, as there is nothing sensible to
+/// show for them while completing.
+pub fn complete(words: &[String], cwd: &Path) -> Vec<String> {
+    match completion_target(words) {
+        CompletionTarget::Repos(config) => {
+            // The same order as for the command itself: the command line, the
+            // environment and then the configuration of the user
+            let config = config
+                .or_else(|| std::env::var("GRM_CONFIG").ok())
+                .or_else(|| {
+                    config::read_user_config()
+                        .ok()
+                        .and_then(|config| config.config)
+                })
+                .unwrap_or_else(|| String::from(DEFAULT_CONFIG));
+            // Provider configurations are skipped, querying a forge would
+            // make completion way too slow
+            let trees = match config::read_repos_config(&config) {
+                Ok(config::Config::ConfigTrees(config)) => config.trees(),
+                _ => return Vec::new(),
+            };
+            // Several trees can contain repositories with the same name
+            let mut names = list::entries(trees)
+                .0
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<String>>();
+            names.sort();
+            names.dedup();
+            names
+        }
+        CompletionTarget::Worktrees => {
+            let root = match worktree::find_worktree_root(cwd) {
+                Some((root, _)) => root,
+                None => return Vec::new(),
+            };
+            repo::RepoHandle::open(&root, true)
+                .ok()
+                .and_then(|repo| repo.get_worktrees().ok())
+                .unwrap_or_default()
+                .iter()
+                .map(|worktree| worktree.name().to_string())
+                .collect()
+        }
+        CompletionTarget::Nothing => Vec::new(),
+    }
+}
+
+const BASH_COMPLETION: &str = r#"
+_grm_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" names
+    if [[ "$cur" != -* ]]; then
+        names="$(grm complete -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null)"
+    fi
+    if [[ -n "$names" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$names" -- "$cur"))
+        return 0
+    fi
+    _grm "$@"
+}
+complete -F _grm_dynamic -o bashdefault -o default grm
+"#;
+
+const ZSH_COMPLETION: &str = r#"
+_grm_dynamic() {
+    local -a names
+    if [[ "${words[CURRENT]}" != -* ]]; then
+        names=(${(f)"$(grm complete -- ${words[2,CURRENT-1]} 2>/dev/null)"})
+    fi
+    if (( ${#names} )); then
+        compadd -a names
+    else
+        _grm "$@"
+    fi
+}
+compdef _grm_dynamic grm
+"#;
+
+const FISH_COMPLETION: &str = r#"
+function __grm_complete_names
+    set -l words (commandline -opc)
+    grm complete -- $words[2..-1] 2>/dev/null
+end
+complete -c grm -a '(__grm_complete_names)'
+"#;
+
+/// The script that has to be added to the static completions generated by
+/// clap, to complete the names of repositories and worktrees
+pub fn completion_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH_COMPLETION,
+        Shell::Zsh => ZSH_COMPLETION,
+        Shell::Fish => FISH_COMPLETION,
+    }
+}
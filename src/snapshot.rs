@@ -0,0 +1,298 @@
+//! Snapshots of the checked out state of all configured repositories, so that
+//! exactly this state can be restored later, e.g. on a different machine.
+
+use serde::{Deserialize, Serialize};
+
+use std::path::Path;
+
+use super::config;
+use super::output::*;
+use super::path;
+use super::repo;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_snapshot_roundtrip() {
+        let snapshot = Snapshot {
+            trees: vec![SnapshotTree {
+                root: String::from("~/projects"),
+                repos: vec![
+                    SnapshotRepo {
+                        name: String::from("one"),
+                        branch: Some(String::from("main")),
+                        commit: String::from("a3b9cf4a3b9cf4a3b9cf4a3b9cf4a3b9cf4a3b9c"),
+                    },
+                    SnapshotRepo {
+                        name: String::from("two"),
+                        branch: None,
+                        commit: String::from("0123456789abcdef0123456789abcdef01234567"),
+                    },
+                ],
+            }],
+        };
+
+        let toml = snapshot.as_toml().unwrap();
+        assert!(!toml.contains("branch = \"\""));
+
+        let parsed: Snapshot = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.trees[0].root, "~/projects");
+        assert_eq!(parsed.trees[0].repos[0].branch.as_deref(), Some("main"));
+        assert_eq!(parsed.trees[0].repos[1].branch, None);
+        assert_eq!(
+            parsed.trees[0].repos[1].commit,
+            "0123456789abcdef0123456789abcdef01234567"
+        );
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    pub trees: Vec<SnapshotTree>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotTree {
+    pub root: String,
+    pub repos: Vec<SnapshotRepo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotRepo {
+    pub name: String,
+    /// The checked out branch. `None` if HEAD was detached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    pub commit: String,
+}
+
+impl Snapshot {
+    pub fn as_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|error| error.to_string())
+    }
+
+    pub fn as_yaml(&self) -> Result<String, String> {
+        serde_yaml::to_string(self).map_err(|error| error.to_string())
+    }
+}
+
+/// Records the checked out branch and commit of all configured repositories.
+///
+/// Repositories that cannot be part of the snapshot, e.g. because they do not
+/// exist locally, are skipped, a warning is returned for each of them.
+/// Repositories that fail are left out as well, with an error for each of
+/// them, so the snapshot still contains all the others.
+pub fn create_snapshot(
+    config: config::Config,
+) -> Result<(Snapshot, Vec<String>, Vec<String>), String> {
+    let mut trees = Vec::new();
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+
+    for tree in config.trees()? {
+        let root_path = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root_path) => root_path,
+            Err(error) => {
+                errors.push(format!("{}: {}", tree.root, error));
+                continue;
+            }
+        };
+        let mut repos = Vec::new();
+
+        for repo in tree.repos.unwrap_or_default() {
+            let repo = repo.into_repo();
+            let name = repo.fullname();
+            let repo_path = root_path.join(&name);
+
+            if repo.worktree_setup {
+                warnings.push(format!(
+                    "{}: Worktree setups cannot be snapshotted, skipping",
+                    name
+                ));
+                continue;
+            }
+
+            if !repo_path.exists() {
+                warnings.push(format!("{}: Repository does not exist, skipping", name));
+                continue;
+            }
+
+            let repo_handle = match repo::RepoHandle::open(&repo_path, false) {
+                Ok(repo_handle) => repo_handle,
+                Err(error) => {
+                    errors.push(format!("{}: Opening repository failed: {}", name, error));
+                    continue;
+                }
+            };
+
+            if repo_handle.is_bare() {
+                warnings.push(format!(
+                    "{}: Repository does not have a working directory, skipping",
+                    name
+                ));
+                continue;
+            }
+
+            match repo_handle.is_empty() {
+                Ok(true) => {
+                    warnings.push(format!("{}: Repository is empty, skipping", name));
+                    continue;
+                }
+                Ok(false) => (),
+                Err(error) => {
+                    errors.push(format!("{}: {}", name, error));
+                    continue;
+                }
+            }
+
+            match head_state(&repo_handle) {
+                Ok((branch, commit)) => repos.push(SnapshotRepo {
+                    name,
+                    branch,
+                    commit,
+                }),
+                Err(error) => errors.push(format!("{}: {}", name, error)),
+            }
+        }
+
+        if !repos.is_empty() {
+            trees.push(SnapshotTree {
+                root: tree.root,
+                repos,
+            });
+        }
+    }
+
+    Ok((Snapshot { trees }, warnings, errors))
+}
+
+/// The checked out branch, `None` if HEAD is detached, and commit
+fn head_state(repo_handle: &repo::RepoHandle) -> Result<(Option<String>, String), String> {
+    let branch = repo_handle
+        .head_branch()
+        .ok()
+        .map(|branch| branch.name())
+        .transpose()?;
+
+    Ok((branch, repo_handle.head_commit_id()?))
+}
+
+/// Checks out the branches and commits recorded in the snapshot. `on_message`
+/// is called with the name of the repository (or the root of the tree) for
+/// everything that happens. Returns whether all repositories were restored
+/// successfully.
+pub fn restore_snapshot<F>(snapshot: Snapshot, mut on_message: F) -> bool
+where
+    F: FnMut(&str, Message),
+{
+    let mut success = true;
+
+    for tree in snapshot.trees {
+        let root_path = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root_path) => root_path,
+            Err(error) => {
+                on_message(&tree.root, Message::Error(error.to_string()));
+                success = false;
+                continue;
+            }
+        };
+
+        for repo in tree.repos {
+            let result = restore_repo(&root_path.join(&repo.name), &repo, &mut |message| {
+                on_message(&repo.name, message)
+            });
+            match result {
+                Ok(()) => on_message(&repo.name, Message::Success(String::from("OK"))),
+                Err(error) => {
+                    on_message(&repo.name, Message::Error(error));
+                    success = false;
+                }
+            }
+        }
+    }
+
+    success
+}
+
+fn restore_repo(
+    repo_path: &Path,
+    repo: &SnapshotRepo,
+    notify: &mut dyn FnMut(Message),
+) -> Result<(), String> {
+    if !repo_path.exists() {
+        return Err(String::from("Repository does not exist. Run sync?"));
+    }
+
+    let repo_handle = repo::RepoHandle::open(repo_path, false)
+        .map_err(|error| format!("Opening repository failed: {}", error))?;
+
+    if !repo_handle.has_rev(&repo.commit) {
+        notify(Message::Action(format!(
+            "Commit {} not found, fetching remotes",
+            &repo.commit
+        )));
+        repo_handle.fetchall()?;
+        if !repo_handle.has_rev(&repo.commit) {
+            return Err(format!("Commit {} not found", &repo.commit));
+        }
+    }
+
+    let head_branch = repo_handle
+        .head_branch()
+        .ok()
+        .map(|branch| branch.name())
+        .transpose()?;
+
+    if !repo_handle.is_empty()?
+        && repo_handle.head_matches_rev(&repo.commit)?
+        && head_branch == repo.branch
+    {
+        return Ok(());
+    }
+
+    if repo_handle.status(false)?.changes.is_some() {
+        return Err(String::from(
+            "Repository contains uncommitted changes, not touching it",
+        ));
+    }
+
+    match &repo.branch {
+        Some(branch) => match repo_handle.find_local_branch(branch) {
+            Ok(local_branch) => {
+                if local_branch.commit()?.id().hex_string() == repo.commit {
+                    notify(Message::Action(format!(
+                        "Checking out branch \"{}\"",
+                        branch
+                    )));
+                    repo_handle.checkout_branch(branch)?;
+                } else {
+                    // Moving the local branch could lose commits, so leave it
+                    // alone
+                    notify(Message::Warning(format!(
+                        "Branch \"{}\" does not point to commit {}, checking out the commit instead",
+                        branch, &repo.commit
+                    )));
+                    repo_handle.checkout_rev(&repo.commit)?;
+                }
+            }
+            Err(_) => {
+                notify(Message::Action(format!("Creating branch \"{}\"", branch)));
+                repo_handle.checkout_new_branch(branch, &repo.commit)?;
+            }
+        },
+        None => {
+            notify(Message::Action(format!(
+                "Checking out commit {}",
+                &repo.commit
+            )));
+            repo_handle.checkout_rev(&repo.commit)?;
+        }
+    }
+
+    Ok(())
+}
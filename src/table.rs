@@ -1,10 +1,16 @@
 use super::config;
+use super::maintenance;
+use super::output::*;
+use super::parallel;
 use super::path;
 use super::repo;
+use super::tree;
 
 use comfy_table::{Cell, Table};
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 fn add_table_header(table: &mut Table) {
     table
@@ -20,13 +26,44 @@ fn add_table_header(table: &mut Table) {
         ]);
 }
 
+fn format_remote_tracking_status(status: &repo::RemoteTrackingStatus) -> String {
+    match status {
+        repo::RemoteTrackingStatus::UpToDate => String::from(" \u{2714}"),
+        repo::RemoteTrackingStatus::Ahead(d) => format!(" [+{}]", &d),
+        repo::RemoteTrackingStatus::Behind(d) => format!(" [-{}]", &d),
+        repo::RemoteTrackingStatus::Diverged(d1, d2) => format!(" [+{}/-{}]", &d1, &d2),
+    }
+}
+
+/// `configured_remotes` are the names of the remotes as given in the
+/// configuration. Remotes that are configured but missing in the repository
+/// are marked as such. Without a configuration, this is `None`. The same goes
+/// for `pinned_rev`, the revision that HEAD is expected to point to.
 fn add_repo_status(
     table: &mut Table,
     repo_name: &str,
     repo_handle: &repo::RepoHandle,
     is_worktree: bool,
+    configured_remotes: Option<&[String]>,
+    pinned_rev: Option<&str>,
 ) -> Result<(), String> {
-    let repo_status = repo_handle.status(is_worktree)?;
+    // Mirrors are bare, so there is no working directory to look at, just like
+    // for worktree setups
+    let is_mirror = !is_worktree && repo_handle.is_bare();
+    let repo_status = repo_handle.status(is_worktree || is_mirror)?;
+
+    let mut remotes = repo_status
+        .remotes
+        .iter()
+        .map(|r| format!("{}\n", r))
+        .collect::<String>();
+    if let Some(configured_remotes) = configured_remotes {
+        for remote in configured_remotes {
+            if !repo_status.remotes.contains(remote) {
+                remotes.push_str(&format!("{} <!missing>\n", remote));
+            }
+        }
+    }
 
     table.add_row(vec![
         repo_name,
@@ -34,25 +71,40 @@ fn add_repo_status(
             true => "\u{2714}",
             false => "",
         },
-        &match is_worktree {
-            true => String::from(""),
-            false => match repo_status.changes {
-                Some(changes) => {
-                    let mut out = Vec::new();
-                    if changes.files_new > 0 {
-                        out.push(format!("New: {}\n", changes.files_new))
-                    }
-                    if changes.files_modified > 0 {
-                        out.push(format!("Modified: {}\n", changes.files_modified))
-                    }
-                    if changes.files_deleted > 0 {
-                        out.push(format!("Deleted: {}\n", changes.files_deleted))
+        &format!(
+            "{}{}{}",
+            match (is_worktree, is_mirror) {
+                (true, _) => String::from(""),
+                (false, true) => String::from("Mirror"),
+                (false, false) => match repo_status.changes {
+                    Some(changes) => {
+                        let mut out = Vec::new();
+                        if changes.files_new > 0 {
+                            out.push(format!("New: {}\n", changes.files_new))
+                        }
+                        if changes.files_modified > 0 {
+                            out.push(format!("Modified: {}\n", changes.files_modified))
+                        }
+                        if changes.files_deleted > 0 {
+                            out.push(format!("Deleted: {}\n", changes.files_deleted))
+                        }
+                        out.into_iter().collect::<String>().trim().to_string()
                     }
-                    out.into_iter().collect::<String>().trim().to_string()
-                }
-                None => String::from("\u{2714}"),
+                    None => String::from("\u{2714}"),
+                },
             },
-        },
+            match repo_status.stashes {
+                0 => String::from(""),
+                stashes => format!("\nStashes: {}", stashes),
+            },
+            match repo_status.lfs {
+                Some(lfs) if lfs.downloaded < lfs.files => {
+                    format!("\nLFS: {}/{} downloaded", lfs.downloaded, lfs.files)
+                }
+                Some(_) | None => String::from(""),
+            }
+        )
+        .trim(),
         repo_status
             .branches
             .iter()
@@ -61,19 +113,14 @@ fn add_repo_status(
                     "branch: {}{}\n",
                     &branch_name,
                     &match remote_branch {
+                        // In a mirror, all branches are local copies of the remote ones
+                        None if is_mirror => String::from(""),
                         None => String::from(" <!local>"),
                         Some((remote_branch_name, remote_tracking_status)) => {
                             format!(
                                 " <{}>{}",
                                 remote_branch_name,
-                                &match remote_tracking_status {
-                                    repo::RemoteTrackingStatus::UpToDate =>
-                                        String::from(" \u{2714}"),
-                                    repo::RemoteTrackingStatus::Ahead(d) => format!(" [+{}]", &d),
-                                    repo::RemoteTrackingStatus::Behind(d) => format!(" [-{}]", &d),
-                                    repo::RemoteTrackingStatus::Diverged(d1, d2) =>
-                                        format!(" [+{}/-{}]", &d1, &d2),
-                                }
+                                format_remote_tracking_status(remote_tracking_status)
                             )
                         }
                     }
@@ -81,80 +128,229 @@ fn add_repo_status(
             })
             .collect::<String>()
             .trim(),
-        &match is_worktree {
+        &match is_worktree || is_mirror {
             true => String::from(""),
             false => match repo_status.head {
-                Some(head) => head,
+                Some(head) => {
+                    let tracking_status = repo_status
+                        .branches
+                        .iter()
+                        .find(|(branch_name, _)| branch_name == &head)
+                        .and_then(|(_, remote_branch)| remote_branch.as_ref())
+                        .map(|(_, status)| format_remote_tracking_status(status))
+                        .unwrap_or_default();
+                    let pin_status = match pinned_rev {
+                        Some(rev) if !repo_handle.head_matches_rev(rev).unwrap_or(false) => {
+                            format!("\n<!pinned: {}>", rev)
+                        }
+                        Some(_) | None => String::from(""),
+                    };
+                    format!("{}{}{}", head, tracking_status, pin_status)
+                }
                 None => String::from("Empty"),
             },
         },
-        repo_status
-            .remotes
-            .iter()
-            .map(|r| format!("{}\n", r))
-            .collect::<String>()
-            .trim(),
+        remotes.trim(),
     ]);
 
     Ok(())
 }
 
+/// A summary of what a sync did, so it does not get lost in the output of
+/// hundreds of repositories
+pub fn get_sync_summary_table(report: &tree::SyncReport) -> impl std::fmt::Display {
+    let mut table = Table::new();
+    table
+        .load_preset(comfy_table::presets::UTF8_FULL)
+        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+        .set_header(vec![Cell::new("Summary"), Cell::new("Repos")]);
+
+    let failed = report.failed();
+    table.add_row(vec![
+        String::from("Synced"),
+        (report.repos.len() - failed.len()).to_string(),
+    ]);
+    table.add_row(vec![
+        String::from("Cloned"),
+        report.count(|changes| changes.cloned).to_string(),
+    ]);
+    table.add_row(vec![
+        String::from("Updated remotes"),
+        report.count(|changes| changes.remotes_updated).to_string(),
+    ]);
+    table.add_row(vec![
+        String::from("Failed"),
+        match failed.is_empty() {
+            true => String::from("0"),
+            false => format!(
+                "{} ({})",
+                failed.len(),
+                failed
+                    .iter()
+                    .map(|repo| repo.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ),
+        },
+    ]);
+    table.add_row(vec![
+        String::from("Skipped"),
+        match report.skipped.is_empty() {
+            true => String::from("0"),
+            false => format!("{} ({})", report.skipped.len(), report.skipped.join(", ")),
+        },
+    ]);
+    table.add_row(vec![
+        String::from("Unmanaged"),
+        report.unmanaged.len().to_string(),
+    ]);
+    table
+}
+
 // Don't return table, return a type that implements Display(?)
-pub fn get_worktree_status_table(
-    repo: &repo::RepoHandle,
-    directory: &Path,
-) -> Result<(impl std::fmt::Display, Vec<String>), String> {
-    let worktrees = repo.get_worktrees()?;
+/// A table of the disk usage of repositories, with the largest ones first and
+/// the sum of all of them at the end
+pub fn get_disk_usage_table(
+    usages: &mut [(String, maintenance::DiskUsage)],
+) -> impl std::fmt::Display {
+    usages.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.total()));
+
     let mut table = Table::new();
+    table
+        .load_preset(comfy_table::presets::UTF8_FULL)
+        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+        .set_header(vec![
+            Cell::new("Repo"),
+            Cell::new("Git"),
+            Cell::new("Worktrees"),
+            Cell::new("Total"),
+        ]);
 
-    let mut errors = Vec::new();
+    for (name, usage) in usages.iter() {
+        table.add_row(vec![
+            name.clone(),
+            format_bytes(usage.git),
+            format_bytes(usage.worktrees),
+            format_bytes(usage.total()),
+        ]);
+    }
 
-    add_worktree_table_header(&mut table);
-    for worktree in &worktrees {
-        let worktree_dir = &directory.join(worktree.name());
-        if worktree_dir.exists() {
-            let repo = match repo::RepoHandle::open(worktree_dir, false) {
-                Ok(repo) => repo,
-                Err(error) => {
-                    errors.push(format!(
-                        "Failed opening repo of worktree {}: {}",
-                        &worktree.name(),
-                        &error
-                    ));
-                    continue;
-                }
-            };
-            if let Err(error) = add_worktree_status(&mut table, worktree, &repo) {
-                errors.push(error);
-            }
-        } else {
-            errors.push(format!(
-                "Worktree {} does not have a directory",
-                &worktree.name()
-            ));
-        }
+    if usages.len() > 1 {
+        let git = usages.iter().map(|(_, usage)| usage.git).sum();
+        let worktrees = usages.iter().map(|(_, usage)| usage.worktrees).sum();
+        table.add_row(vec![
+            String::from("Total"),
+            format_bytes(git),
+            format_bytes(worktrees),
+            format_bytes(git + worktrees),
+        ]);
     }
-    for worktree in repo::RepoHandle::find_unmanaged_worktrees(repo, directory)? {
-        errors.push(format!(
-            "Found {}, which is not a valid worktree directory!",
-            &worktree
-        ));
+
+    table
+}
+
+pub fn get_worktree_status_table(statuses: &[repo::WorktreeStatus]) -> impl std::fmt::Display {
+    let mut table = Table::new();
+
+    add_worktree_table_header(&mut table);
+    for status in statuses {
+        add_worktree_status(&mut table, status);
     }
-    Ok((table, errors))
+    table
+}
+
+/// Fetches all remotes of the given repositories, `jobs` repositories at a
+/// time. Returns an error message for each repository that failed.
+fn fetch_repos(repos: Vec<(String, PathBuf, bool)>, jobs: usize) -> Vec<String> {
+    let total = repos.len();
+    let done = Arc::new(AtomicUsize::new(0));
+
+    let progress = Arc::new(ProgressBar::new("Fetching remotes"));
+    progress.set(0, total, &format!("0/{} repositories", total));
+    let results = {
+        let progress = Arc::clone(&progress);
+        parallel::map(repos, jobs, move |(name, path, worktree_setup)| {
+            let result = repo::RepoHandle::open(&path, worktree_setup)
+                .map_err(|error| format!("Opening repository failed: {}", error))
+                .and_then(|repo| repo.fetchall());
+
+            let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+            progress.set(done, total, &format!("{}/{} repositories", done, total));
+
+            result.map_err(|error| format!("{}: Fetching failed: {}", name, error))
+        })
+    };
+    drop(progress);
+
+    results
+        .into_iter()
+        .filter_map(|result| result.err())
+        .collect()
+}
+
+/// The status of all trees of a configuration
+pub struct StatusTables {
+    pub tables: Vec<Table>,
+    pub errors: Vec<String>,
+    /// Repositories that are disabled in the configuration
+    pub skipped: Vec<String>,
 }
 
-pub fn get_status_table(config: config::Config) -> Result<(Vec<Table>, Vec<String>), String> {
+/// If `fetch_jobs` is set, all remotes of all repositories are fetched first,
+/// using that many repositories in parallel.
+pub fn get_status_table(
+    config: config::Config,
+    fetch_jobs: Option<usize>,
+) -> Result<StatusTables, String> {
     let mut errors = Vec::new();
     let mut tables = Vec::new();
-    for tree in config.trees()? {
+    let mut skipped = Vec::new();
+
+    let trees = config.trees()?;
+
+    if let Some(jobs) = fetch_jobs {
+        let mut repos = Vec::new();
+        for tree in &trees {
+            let root_path = match path::expand_path(Path::new(&tree.root)) {
+                Ok(root_path) => root_path,
+                // Reported below, when getting the status
+                Err(_) => continue,
+            };
+            for repo in tree
+                .repos
+                .iter()
+                .flatten()
+                .filter(|repo| !repo.is_disabled())
+            {
+                let repo_path = root_path.join(&repo.name);
+                if repo_path.exists() {
+                    repos.push((repo.name.clone(), repo_path, repo.worktree_setup));
+                }
+            }
+        }
+        errors.extend(fetch_repos(repos, jobs));
+    }
+
+    for tree in trees {
         let repos = tree.repos.unwrap_or_default();
 
-        let root_path = path::expand_path(Path::new(&tree.root));
+        let root_path = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root_path) => root_path,
+            Err(error) => {
+                errors.push(error.to_string());
+                continue;
+            }
+        };
 
         let mut table = Table::new();
         add_table_header(&mut table);
 
         for repo in &repos {
+            if repo.is_disabled() {
+                skipped.push(repo.name.clone());
+                continue;
+            }
+
             let repo_path = root_path.join(&repo.name);
 
             if !repo_path.exists() {
@@ -185,9 +381,19 @@ pub fn get_status_table(config: config::Config) -> Result<(Vec<Table>, Vec<Strin
                 }
             };
 
-            if let Err(err) =
-                add_repo_status(&mut table, &repo.name, &repo_handle, repo.worktree_setup)
-            {
+            let configured_remotes = repo
+                .remotes
+                .as_ref()
+                .map(|remotes| remotes.iter().map(|r| r.name.clone()).collect::<Vec<_>>());
+
+            if let Err(err) = add_repo_status(
+                &mut table,
+                &repo.name,
+                &repo_handle,
+                repo.worktree_setup,
+                configured_remotes.as_deref(),
+                repo.rev.as_deref(),
+            ) {
                 errors.push(format!("{}: Couldn't add repo status: {}", &repo.name, err));
             }
         }
@@ -195,7 +401,11 @@ pub fn get_status_table(config: config::Config) -> Result<(Vec<Table>, Vec<Strin
         tables.push(table);
     }
 
-    Ok((tables, errors))
+    Ok(StatusTables {
+        tables,
+        errors,
+        skipped,
+    })
 }
 
 fn add_worktree_table_header(table: &mut Table) {
@@ -207,47 +417,28 @@ fn add_worktree_table_header(table: &mut Table) {
             Cell::new("Status"),
             Cell::new("Branch"),
             Cell::new("Remote branch"),
+            Cell::new("Merged"),
         ]);
 }
 
-fn add_worktree_status(
-    table: &mut Table,
-    worktree: &repo::Worktree,
-    repo: &repo::RepoHandle,
-) -> Result<(), String> {
-    let repo_status = repo.status(false)?;
-
-    let local_branch = repo
-        .head_branch()
-        .map_err(|error| format!("Failed getting head branch: {}", error))?;
-
-    let upstream_output = match local_branch.upstream() {
-        Ok(remote_branch) => {
-            let remote_branch_name = remote_branch
-                .name()
-                .map_err(|error| format!("Failed getting name of remote branch: {}", error))?;
-
-            let (ahead, behind) = repo
-                .graph_ahead_behind(&local_branch, &remote_branch)
-                .map_err(|error| format!("Failed computing branch deviation: {}", error))?;
-
-            format!(
-                "{}{}\n",
-                &remote_branch_name,
-                &match (ahead, behind) {
-                    (0, 0) => String::from(""),
-                    (d, 0) => format!(" [+{}]", &d),
-                    (0, d) => format!(" [-{}]", &d),
-                    (d1, d2) => format!(" [+{}/-{}]", &d1, &d2),
-                },
-            )
-        }
-        Err(_) => String::from(""),
+fn add_worktree_status(table: &mut Table, status: &repo::WorktreeStatus) {
+    let upstream_output = match &status.upstream {
+        Some(remote_branch_name) => format!(
+            "{}{}",
+            &remote_branch_name,
+            &match (status.ahead, status.behind) {
+                (0, 0) => String::from(""),
+                (d, 0) => format!(" [+{}]", &d),
+                (0, d) => format!(" [-{}]", &d),
+                (d1, d2) => format!(" [+{}/-{}]", &d1, &d2),
+            },
+        ),
+        None => String::from(""),
     };
 
     table.add_row(vec![
-        worktree.name(),
-        &match repo_status.changes {
+        status.name.as_str(),
+        &match &status.changes {
             Some(changes) => {
                 let mut out = Vec::new();
                 if changes.files_new > 0 {
@@ -263,17 +454,19 @@ fn add_worktree_status(
             }
             None => String::from("\u{2714}"),
         },
-        &local_branch
-            .name()
-            .map_err(|error| format!("Failed getting name of branch: {}", error))?,
+        &status.branch,
         &upstream_output,
+        match status.merged {
+            Some(true) => "\u{2714}",
+            Some(false) => "",
+            None => "?",
+        },
     ]);
-
-    Ok(())
 }
 
 pub fn show_single_repo_status(
     path: &Path,
+    fetch: bool,
 ) -> Result<(impl std::fmt::Display, Vec<String>), String> {
     let mut table = Table::new();
     let mut warnings = Vec::new();
@@ -311,7 +504,23 @@ pub fn show_single_repo_status(
         },
     };
 
-    add_repo_status(&mut table, &repo_name, &repo_handle.unwrap(), is_worktree)?;
+    let repo_handle = repo_handle.unwrap();
+
+    if fetch {
+        let result = repo_handle.fetchall();
+        if let Err(error) = result {
+            warnings.push(format!("Fetching failed: {}", error));
+        }
+    }
+
+    add_repo_status(
+        &mut table,
+        &repo_name,
+        &repo_handle,
+        is_worktree,
+        None,
+        None,
+    )?;
 
     Ok((table, warnings))
 }
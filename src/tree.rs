@@ -1,149 +1,1196 @@
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use super::auth;
 use super::config;
+use super::error::Error;
+use super::exec;
+use super::glob;
+use super::lock;
+use super::maintenance;
+use super::net;
 use super::output::*;
+use super::parallel;
 use super::path;
+use super::provider;
 use super::repo;
+use super::retry;
 use super::worktree;
 
+/// The directory in the root of a tree that `archive_repos()` moves inactive
+/// repositories to
+pub const ARCHIVE_DIRECTORY: &str = "archive";
+
 pub struct Tree {
     pub root: String,
     pub repos: Vec<repo::Repo>,
 }
 
-pub fn find_unmanaged_repos(
-    root_path: &Path,
-    managed_repos: &[repo::Repo],
-) -> Result<Vec<PathBuf>, String> {
-    let mut unmanaged_repos = Vec::new();
+/// Settings for a sync that are given on the command line instead of in the
+/// configuration
+pub struct SyncOptions {
+    /// Check out the default branch into a worktree after cloning a repository
+    /// with a worktree setup
+    pub init_worktree: bool,
+    /// Overrides the clone depth of all repositories, if given
+    pub clone_depth: Option<u32>,
+    /// Fetch all remotes of repositories that already exist
+    pub fetch: bool,
+    /// How to fetch, only used together with `fetch`
+    pub fetch_options: repo::FetchConfig,
+    /// Only check whether the repositories match the configuration, without
+    /// changing anything
+    pub check: bool,
+    /// If another process is syncing a tree, wait for it to finish instead of
+    /// failing
+    pub wait_for_lock: bool,
+    /// Stop at the first error instead of syncing the remaining repositories
+    pub fail_fast: bool,
+}
+
+/// A repository that is part of a sync, with the directory it lives in
+pub struct PlannedRepo {
+    pub path: PathBuf,
+    pub repo: repo::Repo,
+    /// Disabled in the configuration, so it is not synced. It is still
+    /// managed, so it is not reported as unmanaged either.
+    pub disabled: bool,
+    /// Only used on other machines, so it is not synced. Like a disabled
+    /// repository, it is still managed.
+    pub other_host: bool,
+    /// Optimizations that run after the repository was synced
+    pub optimize: Vec<maintenance::Optimization>,
+}
+
+/// A tree that is part of a sync
+pub struct PlannedTree {
+    pub root: PathBuf,
+    pub repos: Vec<PlannedRepo>,
+    /// Globs for unmanaged repositories that are not reported
+    pub ignore: Vec<String>,
+    /// Only used on other machines. Its repositories are still managed, so
+    /// they are not reported as unmanaged in other trees.
+    pub other_host: bool,
+}
 
-    for repo_path in find_repo_paths(root_path)? {
-        if !managed_repos
+/// Everything a sync is going to do, resolved from the configuration. Creating
+/// a plan does not touch any repository, so it can be inspected first.
+pub struct SyncPlan {
+    pub trees: Vec<PlannedTree>,
+    /// Trees that cannot be synced, e.g. because their root cannot be expanded
+    pub errors: Vec<String>,
+    pub unmanaged_policy: config::UnmanagedPolicy,
+}
+
+/// What syncing a single repository changed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncChanges {
+    /// The repository did not exist and was cloned
+    pub cloned: bool,
+    /// Remotes of an existing repository were added, removed or changed
+    pub remotes_updated: bool,
+}
+
+/// The outcome of syncing a single repository
+pub struct RepoSyncResult {
+    pub name: String,
+    pub path: PathBuf,
+    pub result: Result<SyncChanges, String>,
+}
+
+/// What happens during a sync, passed to the callback of
+/// [`SyncPlan::execute()`]
+pub enum SyncEvent<'a> {
+    /// Something about the repository with the given name that is being
+    /// synced, e.g. a remote that is updated
+    Message(&'a str, &'a Message),
+    /// Details that are only interesting when debugging, e.g. a repository
+    /// that is skipped
+    Debug(&'a str),
+    /// A repository is done
+    Synced(&'a RepoSyncResult),
+}
+
+/// The outcome of a whole sync
+pub struct SyncReport {
+    pub repos: Vec<RepoSyncResult>,
+    /// Repositories found in the trees that are not part of the configuration
+    pub unmanaged: Vec<PathBuf>,
+    pub unmanaged_policy: config::UnmanagedPolicy,
+    /// Errors that are not tied to a single repository
+    pub errors: Vec<String>,
+    /// Repositories that were skipped because they are disabled
+    pub skipped: Vec<String>,
+}
+
+impl SyncReport {
+    /// Returns whether all repositories were synced without errors
+    pub fn success(&self) -> bool {
+        self.errors.is_empty()
+            && self.repos.iter().all(|repo| repo.result.is_ok())
+            && (self.unmanaged_policy != config::UnmanagedPolicy::Error
+                || self.unmanaged.is_empty())
+    }
+
+    /// The repositories that could not be synced
+    pub fn failed(&self) -> Vec<&RepoSyncResult> {
+        self.repos
             .iter()
-            .any(|r| Path::new(root_path).join(r.fullname()) == repo_path)
-        {
-            unmanaged_repos.push(repo_path);
-        }
+            .filter(|repo| repo.result.is_err())
+            .collect()
+    }
+
+    /// The number of synced repositories for which `predicate` is true
+    pub fn count<F>(&self, predicate: F) -> usize
+    where
+        F: Fn(&SyncChanges) -> bool,
+    {
+        self.repos
+            .iter()
+            .filter(|repo| matches!(&repo.result, Ok(changes) if predicate(changes)))
+            .count()
     }
-    Ok(unmanaged_repos)
 }
 
-pub fn sync_trees(config: config::Config, init_worktree: bool) -> Result<bool, String> {
-    let mut failures = false;
+impl SyncPlan {
+    pub fn new(config: config::Config, options: &SyncOptions) -> Result<Self, Error> {
+        let mut trees = Vec::new();
+        let mut errors = Vec::new();
+        let unmanaged_policy = config.unmanaged_policy();
 
-    let mut unmanaged_repos_absolute_paths = vec![];
-    let mut managed_repos_absolute_paths = vec![];
+        for tree in config.trees_on_all_hosts()? {
+            let tree_other_host = match tree.host_conditions().match_current() {
+                Ok(matches) => !matches,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
 
-    let trees = config.trees()?;
+            let root = match path::expand_path(Path::new(&tree.root)) {
+                Ok(root) => root,
+                // The root may only be valid on the other machines
+                Err(_) if tree_other_host => continue,
+                Err(error) => {
+                    errors.push(error.to_string());
+                    continue;
+                }
+            };
 
-    for tree in trees {
-        let repos: Vec<repo::Repo> = tree
-            .repos
-            .unwrap_or_default()
-            .into_iter()
-            .map(|repo| repo.into_repo())
-            .collect();
+            let mut configs = Vec::new();
+            for repo in tree.repos.unwrap_or_default() {
+                let other_host = match repo.host_conditions().match_current() {
+                    Ok(matches) => tree_other_host || !matches,
+                    Err(error) => {
+                        errors.push(format!("{}: {}", repo.name, error));
+                        continue;
+                    }
+                };
+                configs.push((repo.is_disabled(), other_host, repo.into_repo()));
+            }
 
-        let root_path = path::expand_path(Path::new(&tree.root));
+            let ignore = tree.ignore.unwrap_or_default();
+            let mut repos: Vec<PlannedRepo> = configs
+                .into_iter()
+                .map(|(disabled, other_host, mut repo)| {
+                    if options.clone_depth.is_some() {
+                        repo.clone_options.depth = options.clone_depth;
+                    }
+                    for (key, value) in tree.git_config.iter().flatten() {
+                        repo.git_config
+                            .entry(key.clone())
+                            .or_insert_with(|| value.to_string());
+                    }
+                    // Hooks of the tree run before the ones of the repository
+                    repo.pre_sync = tree
+                        .pre_sync
+                        .iter()
+                        .flatten()
+                        .cloned()
+                        .chain(repo.pre_sync)
+                        .collect();
+                    repo.post_sync = tree
+                        .post_sync
+                        .iter()
+                        .flatten()
+                        .cloned()
+                        .chain(repo.post_sync)
+                        .collect();
+                    PlannedRepo {
+                        path: root.join(repo.fullname()),
+                        repo,
+                        disabled,
+                        other_host,
+                        optimize: tree.optimize.clone().unwrap_or_default(),
+                    }
+                })
+                .collect();
 
-        for repo in &repos {
-            managed_repos_absolute_paths.push(root_path.join(repo.fullname()));
-            match sync_repo(&root_path, repo, init_worktree) {
-                Ok(_) => print_repo_success(&repo.name, "OK"),
-                Err(error) => {
-                    print_repo_error(&repo.name, &error);
-                    failures = true;
+            // Relative references are relative to the tree
+            for planned in &mut repos {
+                if let Some(repo::CloneReference::Path(reference)) =
+                    &mut planned.repo.clone_options.reference
+                {
+                    match path::expand_path(reference) {
+                        Ok(expanded) => *reference = root.join(expanded),
+                        Err(error) => {
+                            errors.push(format!("{}: {}", planned.repo.fullname(), error))
+                        }
+                    }
                 }
             }
+
+            trees.push(PlannedTree {
+                root,
+                repos,
+                ignore,
+                other_host: tree_other_host,
+            });
+        }
+
+        resolve_auto_references(&mut trees);
+
+        Ok(Self {
+            trees,
+            errors,
+            unmanaged_policy,
+        })
+    }
+
+    /// Syncs all repositories of the plan. `on_event` is called for every
+    /// message about a repository and after each repository, so progress can
+    /// be shown while the sync is running. Apart from the progress bars of
+    /// clones and fetches, nothing is printed. With `fail_fast`, the sync
+    /// stops at the first error.
+    pub fn execute<F>(&self, options: &SyncOptions, mut on_event: F) -> SyncReport
+    where
+        F: FnMut(SyncEvent),
+    {
+        let mut report = SyncReport {
+            repos: Vec::new(),
+            unmanaged: Vec::new(),
+            // A check is meant to find any drift between the configuration
+            // and the trees, so unmanaged repositories fail it unless they
+            // are ignored explicitly
+            unmanaged_policy: match (options.check, self.unmanaged_policy) {
+                (true, config::UnmanagedPolicy::Warn) => config::UnmanagedPolicy::Error,
+                (_, policy) => policy,
+            },
+            errors: self.errors.clone(),
+            skipped: Vec::new(),
+        };
+
+        if options.fail_fast && !report.errors.is_empty() {
+            return report;
         }
 
-        match find_unmanaged_repos(&root_path, &repos) {
-            Ok(repos) => {
-                for path in repos.into_iter() {
-                    if !unmanaged_repos_absolute_paths.contains(&path) {
-                        unmanaged_repos_absolute_paths.push(path);
+        'trees: for tree in &self.trees {
+            if tree.other_host {
+                on_event(SyncEvent::Debug(&format!(
+                    "Skipping tree \"{}\", it is not used on this host",
+                    tree.root.display()
+                )));
+                continue;
+            }
+
+            // Checking does not change anything, so it does not need a lock
+            let _lock = match options.check {
+                true => None,
+                false => match create_root_and_lock(&tree.root, options.wait_for_lock) {
+                    Ok(lock) => Some(lock),
+                    Err(error) => {
+                        report.errors.push(error);
+                        match options.fail_fast {
+                            true => break,
+                            false => continue,
+                        }
                     }
+                },
+            };
+
+            for planned in &tree.repos {
+                if planned.disabled {
+                    let name = planned.repo.fullname();
+                    on_event(SyncEvent::Debug(&format!("{}: Disabled, skipping", name)));
+                    report.skipped.push(name);
+                    continue;
+                }
+                if planned.other_host {
+                    on_event(SyncEvent::Debug(&format!(
+                        "{}: Skipping, it is not used on this host",
+                        planned.repo.fullname()
+                    )));
+                    continue;
+                }
+                on_event(SyncEvent::Debug(&format!(
+                    "{}: Syncing into \"{}\"",
+                    planned.repo.fullname(),
+                    planned.path.display()
+                )));
+                let name = planned.repo.fullname();
+                let result = match options.check {
+                    true => check_repo(&tree.root, &planned.repo).map(|()| SyncChanges::default()),
+                    false => sync_repo_with_hooks(&tree.root, planned, options, &mut |message| {
+                        on_event(SyncEvent::Message(&name, &message))
+                    }),
+                };
+                let result = RepoSyncResult {
+                    name,
+                    path: planned.path.clone(),
+                    result,
+                };
+                on_event(SyncEvent::Synced(&result));
+                let failed = result.result.is_err();
+                report.repos.push(result);
+                if failed && options.fail_fast {
+                    break 'trees;
                 }
             }
-            Err(error) => {
-                print_error(&format!("Error getting unmanaged repos: {}", error));
-                failures = true;
+
+            // Nothing was cloned in check mode, so the root may not exist yet
+            if self.unmanaged_policy == config::UnmanagedPolicy::Ignore
+                || (options.check && !tree.root.exists())
+            {
+                continue;
+            }
+
+            let ignore = match glob::PatternSet::new(&tree.ignore) {
+                Ok(ignore) => ignore,
+                Err(error) => {
+                    report.errors.push(error);
+                    continue;
+                }
+            };
+            match find_repo_paths(&tree.root) {
+                Ok(paths) => {
+                    for path in paths {
+                        if maintenance::is_backup(&path) || is_ignored(&tree.root, &path, &ignore) {
+                            continue;
+                        }
+                        if !report.unmanaged.contains(&path) {
+                            report.unmanaged.push(path);
+                        }
+                    }
+                }
+                Err(error) => report
+                    .errors
+                    .push(format!("Error getting unmanaged repos: {}", error)),
             }
         }
+
+        // A repository is only unmanaged if no tree manages it, trees may be
+        // nested
+        report.unmanaged.retain(|path| {
+            !self
+                .trees
+                .iter()
+                .flat_map(|tree| tree.repos.iter())
+                .any(|planned| &planned.path == path)
+        });
+
+        report
     }
+}
 
-    for unmanaged_repo_absolute_path in &unmanaged_repos_absolute_paths {
-        if managed_repos_absolute_paths
+/// Replaces "auto" references with an existing clone of the same URL in any
+/// of the trees. Without one, the repository is cloned on its own.
+fn resolve_auto_references(trees: &mut [PlannedTree]) {
+    let remote_urls = |repo: &repo::Repo| -> Vec<String> {
+        repo.remotes
             .iter()
-            .any(|managed_repo_absolute_path| {
-                managed_repo_absolute_path == unmanaged_repo_absolute_path
-            })
-        {
+            .flatten()
+            .map(|remote| remote.url.clone())
+            .collect()
+    };
+
+    let clones: Vec<(PathBuf, Vec<String>)> = trees
+        .iter()
+        .flat_map(|tree| tree.repos.iter())
+        .map(|planned| {
+            let git_dir = match planned.repo.worktree_setup {
+                true => planned.path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY),
+                false => planned.path.clone(),
+            };
+            (git_dir, remote_urls(&planned.repo))
+        })
+        .filter(|(git_dir, _)| git_dir.exists())
+        .collect();
+
+    for planned in trees.iter_mut().flat_map(|tree| tree.repos.iter_mut()) {
+        if planned.repo.clone_options.reference != Some(repo::CloneReference::Auto) {
             continue;
         }
-        print_warning(&format!(
-            "Found unmanaged repository: \"{}\"",
-            path::path_as_string(unmanaged_repo_absolute_path)
+        let urls = remote_urls(&planned.repo);
+        let found = clones.iter().find(|(git_dir, clone_urls)| {
+            !git_dir.starts_with(&planned.path) && clone_urls.iter().any(|url| urls.contains(url))
+        });
+        planned.repo.clone_options.reference = match found {
+            Some((git_dir, _)) => {
+                print_debug(&format!(
+                    "{}: Sharing objects with \"{}\"",
+                    planned.repo.fullname(),
+                    git_dir.display()
+                ));
+                Some(repo::CloneReference::Path(git_dir.clone()))
+            }
+            None => None,
+        };
+    }
+}
+
+/// Names of the repositories of `config` that borrow objects from the
+/// repository at `repo_path`, because they were cloned with a `reference` to
+/// it. Deleting or moving the repository would break them.
+pub(crate) fn repos_referencing(config: &config::ConfigTrees, repo_path: &Path) -> Vec<String> {
+    let target = match fs::canonicalize(repo_path) {
+        Ok(target) => target,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut names = Vec::new();
+    for tree in config.trees_ref() {
+        let root = match path::expand_path(Path::new(&tree.root)) {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+        for repo in tree.repos.iter().flatten() {
+            let name = repo.clone().into_repo().fullname();
+            let path = root.join(&name);
+            if path.starts_with(repo_path) {
+                continue;
+            }
+
+            // Normal clones, worktree setups and mirrors
+            let uses_target = [
+                path.join(".git"),
+                path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY),
+                path,
+            ]
+            .iter()
+            .map(|git_dir| git_dir.join("objects"))
+            .filter_map(|objects| {
+                let alternates =
+                    fs::read_to_string(objects.join("info").join("alternates")).ok()?;
+                Some((objects, alternates))
+            })
+            .any(|(objects, alternates)| {
+                // Relative entries are relative to the objects directory
+                alternates.lines().any(|line| {
+                    fs::canonicalize(objects.join(line.trim()))
+                        .map(|alternate| alternate.starts_with(&target))
+                        .unwrap_or(false)
+                })
+            });
+            if uses_target {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Clones the repository at `url` into a tree and adds it to the configuration
+/// file at `config_path`. The configuration is only changed if cloning
+/// succeeded. Returns the name of the repository.
+///
+/// Without `name`, the name is taken from the URL. Without `tree_root`, the
+/// configuration has to contain exactly one tree. A root that is not part of
+/// the configuration yet adds a new tree.
+pub fn add_repo(
+    config_path: &str,
+    url: &str,
+    name: Option<&str>,
+    tree_root: Option<&str>,
+    options: &SyncOptions,
+) -> Result<String, String> {
+    let config = match config::read_repos_config(config_path)? {
+        config::Config::ConfigTrees(config) => config,
+        config::Config::ConfigProvider(_) | config::Config::ConfigProviders(_) => {
+            return Err(String::from(
+                "Cannot add repositories to a configuration that uses a provider",
+            ))
+        }
+    };
+
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => repo::repo_name_from_url(url).ok_or_else(|| {
+            format!(
+                "Could not get the repository name from \"{}\", use --name",
+                url
+            )
+        })?,
+    };
+    let remote_type = repo::detect_remote_type(url).map_err(|error| error.to_string())?;
+
+    let mut trees = config.trees();
+    let tree_index = match tree_root {
+        Some(tree_root) => {
+            let root = path::expand_path(Path::new(tree_root))?;
+            let mut tree_index = None;
+            for (i, tree) in trees.iter().enumerate() {
+                if path::expand_path(Path::new(&tree.root))? == root {
+                    tree_index = Some(i);
+                    break;
+                }
+            }
+            tree_index
+        }
+        None => match trees.len() {
+            0 => return Err(String::from("No tree configured, use --tree")),
+            1 => Some(0),
+            _ => {
+                return Err(String::from(
+                    "Multiple trees configured, use --tree to select one",
+                ))
+            }
+        },
+    };
+
+    let mut tree = match tree_index {
+        Some(tree_index) => trees.swap_remove(tree_index),
+        // unwrap() is safe, as there is always a tree root if no existing
+        // tree was found
+        None => config::ConfigTree::from_repos(tree_root.unwrap().to_string(), Vec::new()),
+    };
+
+    if tree.repos.iter().flatten().any(|repo| repo.name == name) {
+        return Err(format!(
+            "Repository \"{}\" is already part of the configuration",
+            name
         ));
     }
 
-    Ok(!failures)
+    let repo = config::RepoConfig::from_repo(repo::Repo::new(
+        name.clone(),
+        vec![repo::Remote {
+            name: String::from("origin"),
+            url: url.to_string(),
+            push_urls: Vec::new(),
+            remote_type,
+            push_mirror: false,
+            primary: false,
+            tls: net::TlsOptions::default(),
+            refspecs: Vec::new(),
+            credentials: None,
+            create: false,
+        }],
+    ));
+
+    // Only the new repository is synced, but with the settings of its tree
+    let root = tree.root.clone();
+    tree.repos = Some(vec![repo.clone()]);
+
+    let plan = SyncPlan::new(config::Config::from_trees(vec![tree]), options)
+        .map_err(|error| error.to_string())?;
+    let report = plan.execute(options, |_| {});
+    if let Some(error) = report.errors.into_iter().next() {
+        return Err(error);
+    }
+    for result in report.repos {
+        result.result?;
+    }
+
+    config::merge_into_file(
+        config_path,
+        vec![config::ConfigTree {
+            root,
+            pre_sync: None,
+            post_sync: None,
+            git_config: None,
+            ignore: None,
+            only_on_hosts: None,
+            only_on_os: None,
+            optimize: None,
+            repos: Some(vec![repo]),
+        }],
+    )?;
+
+    Ok(name)
+}
+
+/// Removes the repository `name` from the configuration file at `config_path`.
+/// With `tree_root`, only the tree with that root is considered, which is
+/// required if several trees contain a repository with that name.
+///
+/// If `delete_files` is set, the repository is deleted from disk as well, but
+/// only if it does not contain any uncommitted or unpushed changes. Otherwise,
+/// nothing is changed. Returns the path of the deleted repository, if any.
+pub fn remove_repo(
+    config_path: &str,
+    name: &str,
+    tree_root: Option<&str>,
+    delete_files: bool,
+) -> Result<Option<PathBuf>, String> {
+    let config = match config::read_repos_config(config_path)? {
+        config::Config::ConfigTrees(config) => config,
+        config::Config::ConfigProvider(_) | config::Config::ConfigProviders(_) => {
+            return Err(String::from(
+                "Cannot remove repositories from a configuration that uses a provider",
+            ))
+        }
+    };
+
+    let tree_root = tree_root
+        .map(|tree_root| path::expand_path(Path::new(tree_root)))
+        .transpose()?;
+
+    let mut matches = Vec::new();
+    for (tree_index, tree) in config.trees_ref().iter().enumerate() {
+        let root = path::expand_path(Path::new(&tree.root))?;
+        if let Some(ref tree_root) = tree_root {
+            if &root != tree_root {
+                continue;
+            }
+        }
+        for (repo_index, repo) in tree.repos.iter().flatten().enumerate() {
+            if repo.name == name {
+                matches.push((tree_index, repo_index, root.clone(), repo.worktree_setup));
+            }
+        }
+    }
+
+    let (tree_index, repo_index, root, worktree_setup) = match matches.len() {
+        0 => {
+            return Err(format!(
+                "Repository \"{}\" is not part of the configuration",
+                name
+            ))
+        }
+        1 => matches.remove(0),
+        _ => {
+            return Err(format!(
+                "Repository \"{}\" is part of several trees, use --tree to select one",
+                name
+            ))
+        }
+    };
+
+    let repo_path = root.join(name);
+    let delete_files = delete_files && repo_path.exists();
+
+    // Keeps a sync from working on the repository while it is deleted
+    let _lock = match delete_files {
+        true => {
+            let lock = lock::Lock::acquire(&root, true)?;
+            let repo_handle = repo::RepoHandle::open(&repo_path, worktree_setup)
+                .map_err(|error| format!("Error opening repository: {}", error))?;
+            repo_handle
+                .check_removable(&repo_path, worktree_setup)
+                .map_err(|reason| format!("Not deleting \"{}\": {}", name, reason))?;
+            if let Some(other) = repos_referencing(&config, &repo_path).first() {
+                return Err(format!(
+                    "Not deleting \"{}\": Repository \"{}\" uses its objects",
+                    name, other
+                ));
+            }
+            Some(lock)
+        }
+        false => None,
+    };
+
+    config::remove_from_file(config_path, tree_index, repo_index)?;
+
+    if !delete_files {
+        return Ok(None);
+    }
+
+    fs::remove_dir_all(&repo_path).map_err(|error| {
+        format!(
+            "Error deleting \"{}\": {}",
+            path::path_as_string(&repo_path),
+            error
+        )
+    })?;
+
+    Ok(Some(repo_path))
+}
+
+/// A repository that was moved into the archive of its tree, or would be for
+/// a dry run
+pub struct ArchivedRepo {
+    pub name: String,
+    pub path: PathBuf,
+    pub archive_path: PathBuf,
+    /// Time since the repository was last used
+    pub inactive_for: std::time::Duration,
+}
+
+/// Moves all repositories of the configuration file at `config_path` that were
+/// not used for at least `inactive_for` into the `archive` directory of their
+/// tree, and moves them into a tree for that directory in the configuration as
+/// well. With `dry_run`, only returns which repositories would be archived.
+///
+/// Problems with single repositories do not abort, but are returned as a list
+/// of errors instead.
+pub fn archive_repos(
+    config_path: &str,
+    inactive_for: std::time::Duration,
+    dry_run: bool,
+) -> Result<(Vec<ArchivedRepo>, Vec<String>), String> {
+    let config = match config::read_repos_config(config_path)? {
+        config::Config::ConfigTrees(config) => config,
+        config::Config::ConfigProvider(_) | config::Config::ConfigProviders(_) => {
+            return Err(String::from(
+                "Cannot archive repositories of a configuration that uses a provider",
+            ))
+        }
+    };
+
+    let mut archived = Vec::new();
+    let mut errors = Vec::new();
+    // The repositories to move in the configuration, by tree
+    let mut moves = Vec::new();
+
+    for (tree_index, tree) in config.trees_ref().iter().enumerate() {
+        let root = path::expand_path(Path::new(&tree.root))?;
+        // Repositories that are already archived stay where they are
+        if root.file_name() == Some(std::ffi::OsStr::new(ARCHIVE_DIRECTORY)) {
+            continue;
+        }
+        let archive_root = root.join(ARCHIVE_DIRECTORY);
+
+        let mut candidates = Vec::new();
+        for (repo_index, repo_config) in tree.repos.iter().flatten().enumerate() {
+            let repo = repo_config.clone().into_repo();
+            let repo_path = root.join(repo.fullname());
+            if !repo_path.exists() {
+                continue;
+            }
+
+            let age = match repo::RepoHandle::repo_inactive_for(&repo_path, repo.worktree_setup) {
+                Ok(age) => age,
+                Err(error) => {
+                    errors.push(format!("{}: {}", repo.fullname(), error));
+                    continue;
+                }
+            };
+            if age < inactive_for {
+                continue;
+            }
+            if let Some(other) = repos_referencing(&config, &repo_path).first() {
+                errors.push(format!(
+                    "{}: Not archiving, repository \"{}\" uses its objects",
+                    repo.fullname(),
+                    other
+                ));
+                continue;
+            }
+
+            let archive_path = archive_root.join(repo.fullname());
+            if archive_path.exists() {
+                errors.push(format!(
+                    "{}: \"{}\" already exists",
+                    repo.fullname(),
+                    archive_path.display()
+                ));
+                continue;
+            }
+
+            candidates.push((
+                repo_index,
+                repo_config.clone(),
+                ArchivedRepo {
+                    name: repo.fullname(),
+                    path: repo_path,
+                    archive_path,
+                    inactive_for: age,
+                },
+            ));
+        }
+
+        if candidates.is_empty() {
+            continue;
+        }
+        if dry_run {
+            archived.extend(candidates.into_iter().map(|(_, _, repo)| repo));
+            continue;
+        }
+
+        // Keeps a sync from working on the repositories while they are moved
+        let _lock = lock::Lock::acquire(&root, true)?;
+
+        let mut moved = Vec::new();
+        for (repo_index, repo_config, repo) in candidates {
+            if let Err(error) = move_into_archive(&repo) {
+                errors.push(format!("{}: {}", repo.name, error));
+                continue;
+            }
+
+            // The repository was moved, so the configuration has to follow
+            // even if its worktrees cannot be fixed
+            if repo_config.worktree_setup {
+                if let Err(error) = repo::RepoHandle::open(&repo.archive_path, true)
+                    .map_err(|error| format!("Error opening repository: {}", error))
+                    .and_then(|handle| handle.relocate_worktrees(&repo.path, &repo.archive_path))
+                {
+                    errors.push(format!(
+                        "{}: Error reconnecting worktrees: {}",
+                        repo.name, error
+                    ));
+                }
+            }
+
+            moved.push((repo_index, repo_config));
+            archived.push(repo);
+        }
+
+        if !moved.is_empty() {
+            moves.push((tree_index, tree.root.clone(), moved));
+        }
+    }
+
+    for (tree_index, tree_root, moved) in moves {
+        // Removing a repository shifts the ones after it, so start at the end
+        for (repo_index, _) in moved.iter().rev() {
+            config::remove_from_file(config_path, tree_index, *repo_index)?;
+        }
+        config::merge_into_file(
+            config_path,
+            vec![config::ConfigTree {
+                root: format!("{}/{}", tree_root.trim_end_matches('/'), ARCHIVE_DIRECTORY),
+                pre_sync: None,
+                post_sync: None,
+                git_config: None,
+                ignore: None,
+                only_on_hosts: None,
+                only_on_os: None,
+                optimize: None,
+                repos: Some(moved.into_iter().map(|(_, repo)| repo).collect()),
+            }],
+        )?;
+    }
+
+    Ok((archived, errors))
+}
+
+fn move_into_archive(repo: &ArchivedRepo) -> Result<(), String> {
+    if let Some(parent) = repo.archive_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|error| format!("Error creating \"{}\": {}", parent.display(), error))?;
+    }
+    fs::rename(&repo.path, &repo.archive_path).map_err(|error| {
+        format!(
+            "Error moving repository to \"{}\": {}",
+            repo.archive_path.display(),
+            error
+        )
+    })
+}
+
+/// Syncs the repository, running its `pre_sync` hooks in the root of the tree
+/// before and its `post_sync` hooks in the repository afterwards. If the
+/// repository did not exist before, its `on_clone` hooks run right after the
+/// sync. A failing hook stops the sync of the repository.
+fn sync_repo_with_hooks(
+    root_path: &Path,
+    planned: &PlannedRepo,
+    options: &SyncOptions,
+    notify: &mut dyn FnMut(Message),
+) -> Result<SyncChanges, String> {
+    let repo = &planned.repo;
+    let target = |path: &Path| exec::Target {
+        name: repo.fullname(),
+        path: path.to_path_buf(),
+        env: exec::repo_env(&repo.fullname(), &planned.path, root_path),
+    };
+
+    for hook in &repo.pre_sync {
+        notify(Message::Action(format!(
+            "Running pre_sync hook \"{}\"",
+            hook
+        )));
+        exec::run_shell(&target(root_path), hook)?;
+    }
+
+    let is_new = !planned.path.exists();
+    let changes = sync_repo(root_path, repo, options, notify)?;
+
+    // Slow repositories are annoying, but not worth failing the sync for
+    if let Err(error) = maintenance::optimize(&planned.path, &planned.optimize) {
+        notify(Message::Error(error));
+    }
+
+    if is_new {
+        for hook in &repo.on_clone {
+            notify(Message::Action(format!(
+                "Running on_clone hook \"{}\"",
+                hook
+            )));
+            exec::run_shell(&target(&planned.path), hook)?;
+        }
+    }
+
+    for hook in &repo.post_sync {
+        notify(Message::Action(format!(
+            "Running post_sync hook \"{}\"",
+            hook
+        )));
+        exec::run_shell(&target(&planned.path), hook)?;
+    }
+
+    Ok(changes)
+}
+
+/// Returns whether the repository at `path` matches one of the `ignore` globs
+/// of the tree at `root`, so it is not reported as unmanaged
+pub fn is_ignored(root: &Path, path: &Path, ignore: &glob::PatternSet) -> bool {
+    path.strip_prefix(root)
+        .ok()
+        .and_then(path::path_as_name)
+        .map_or(false, |name| ignore.is_match(&name))
+}
+
+/// Locks the root of a tree, creating it first if necessary
+fn create_root_and_lock(root: &Path, wait: bool) -> Result<lock::Lock, String> {
+    fs::create_dir_all(root).map_err(|error| {
+        format!(
+            "Error creating tree root \"{}\": {}",
+            path::path_as_string(root),
+            error
+        )
+    })?;
+    lock::Lock::acquire(root, wait)
+}
+
+/// How many directories are searched at the same time by default
+pub const DEFAULT_SEARCH_JOBS: usize = 8;
+
+/// Restricts which directories are searched for repositories. Excluded
+/// directories are not even descended into.
+#[derive(Clone)]
+pub struct SearchOptions {
+    /// Globs of directories to skip. They are matched against both the path
+    /// relative to the search root and the name of the directory, so
+    /// `node_modules` skips these directories everywhere.
+    pub exclude: Vec<String>,
+    /// How many directory levels below the search root are searched at most
+    pub max_depth: Option<usize>,
+    /// How many directories are searched at the same time
+    pub jobs: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            max_depth: None,
+            jobs: DEFAULT_SEARCH_JOBS,
+        }
+    }
+}
+
+/// Returns whether the directory at `relative_path` matches one of the
+/// `exclude` globs of `SearchOptions`
+fn is_excluded(exclude: &glob::PatternSet, relative_path: &Path) -> bool {
+    let relative_path = path::path_as_string(relative_path);
+    let name = relative_path.rsplit('/').next().unwrap_or_default();
+    exclude.is_match(&relative_path) || exclude.is_match(name)
 }
 
 /// Finds repositories recursively, returning their path
 pub fn find_repo_paths(path: &Path) -> Result<Vec<PathBuf>, String> {
+    find_repo_paths_with_options(path, &SearchOptions::default())
+}
+
+/// The directory tree is searched level by level, with all directories of a
+/// level being searched in parallel. The paths are returned sorted, so the
+/// result does not depend on the order in which the directories were read.
+pub fn find_repo_paths_with_options(
+    path: &Path,
+    options: &SearchOptions,
+) -> Result<Vec<PathBuf>, String> {
+    let root = Arc::new(path.to_path_buf());
+    let exclude = Arc::new(glob::PatternSet::new(&options.exclude)?);
+    let options = Arc::new(options.clone());
+
     let mut repos = Vec::new();
+    let mut directories = vec![path.to_path_buf()];
+    let mut depth = 0;
+
+    while !directories.is_empty() {
+        let results = {
+            let root = Arc::clone(&root);
+            let exclude = Arc::clone(&exclude);
+            let options = Arc::clone(&options);
+            parallel::map(directories, options.jobs, move |directory| {
+                search_directory(&root, &directory, depth, &options, &exclude)
+            })
+        };
 
+        directories = Vec::new();
+        for result in results {
+            let (repo, subdirectories) = result?;
+            repos.extend(repo);
+            directories.extend(subdirectories);
+        }
+        depth += 1;
+    }
+
+    repos.sort();
+    Ok(repos)
+}
+
+/// Returns the directory if it is a repository. Otherwise, returns the
+/// subdirectories that have to be searched next.
+fn search_directory(
+    root: &Path,
+    path: &Path,
+    depth: usize,
+    options: &SearchOptions,
+    exclude: &glob::PatternSet,
+) -> Result<(Option<PathBuf>, Vec<PathBuf>), String> {
     let git_dir = path.join(".git");
     let git_worktree = path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY);
 
-    if git_dir.exists() || git_worktree.exists() {
-        repos.push(path.to_path_buf());
-    } else {
-        match fs::read_dir(path) {
-            Ok(contents) => {
-                for content in contents {
-                    match content {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            if path.is_symlink() {
+    if git_dir.exists() || git_worktree.exists() || is_bare_repo(path) {
+        return Ok((Some(path.to_path_buf()), Vec::new()));
+    }
+
+    if options
+        .max_depth
+        .map_or(false, |max_depth| depth >= max_depth)
+    {
+        return Ok((None, Vec::new()));
+    }
+
+    let mut subdirectories = Vec::new();
+
+    match fs::read_dir(path) {
+        Ok(contents) => {
+            for content in contents {
+                match content {
+                    Ok(entry) => {
+                        let path = entry.path();
+                        if path.is_symlink() {
+                            continue;
+                        }
+                        if path.is_dir() {
+                            // unwrap() is safe, as we only ever descend below
+                            // the root
+                            if is_excluded(exclude, path.strip_prefix(root).unwrap()) {
                                 continue;
                             }
-                            if path.is_dir() {
-                                match find_repo_paths(&path) {
-                                    Ok(ref mut r) => repos.append(r),
-                                    Err(error) => return Err(error),
-                                }
-                            }
+                            subdirectories.push(path);
                         }
-                        Err(e) => {
-                            return Err(format!("Error accessing directory: {}", e));
-                        }
-                    };
-                }
-            }
-            Err(e) => {
-                return Err(format!(
-                    "Failed to open \"{}\": {}",
-                    &path.display(),
-                    match e.kind() {
-                        std::io::ErrorKind::NotADirectory =>
-                            String::from("directory expected, but path is not a directory"),
-                        std::io::ErrorKind::NotFound => String::from("not found"),
-                        _ => format!("{:?}", e.kind()),
                     }
-                ));
+                    Err(e) => {
+                        return Err(format!("Error accessing directory: {}", e));
+                    }
+                };
             }
-        };
-    }
+        }
+        Err(e) => {
+            return Err(format!(
+                "Failed to open \"{}\": {}",
+                &path.display(),
+                match e.kind() {
+                    std::io::ErrorKind::NotADirectory =>
+                        String::from("directory expected, but path is not a directory"),
+                    std::io::ErrorKind::NotFound => String::from("not found"),
+                    _ => format!("{:?}", e.kind()),
+                }
+            ));
+        }
+    };
 
-    Ok(repos)
+    Ok((None, subdirectories))
 }
 
-fn sync_repo(root_path: &Path, repo: &repo::Repo, init_worktree: bool) -> Result<(), String> {
+fn sync_repo(
+    root_path: &Path,
+    repo: &repo::Repo,
+    options: &SyncOptions,
+    notify: &mut dyn FnMut(Message),
+) -> Result<SyncChanges, String> {
     let repo_path = root_path.join(&repo.fullname());
     let actual_git_directory = get_actual_git_directory(&repo_path, repo.worktree_setup);
+    let is_mirror = repo.clone_mode == repo::CloneMode::Mirror;
+    let is_bare = repo.clone_mode != repo::CloneMode::Normal;
+
+    if is_bare && repo.worktree_setup {
+        return Err(String::from(match is_mirror {
+            true => "Mirrors cannot use a worktree setup",
+            false => "Bare repositories cannot use a worktree setup",
+        }));
+    }
+
+    if repo.rev.is_some() && (is_bare || repo.worktree_setup) {
+        return Err(String::from(
+            "Revisions can only be pinned for repositories with a working directory",
+        ));
+    }
+
+    let primary_remotes = repo
+        .remotes
+        .iter()
+        .flatten()
+        .filter(|remote| remote.primary)
+        .collect::<Vec<&repo::Remote>>();
+    if primary_remotes.len() > 1 {
+        return Err(String::from("Only one remote can be the primary one"));
+    }
+    if primary_remotes.iter().any(|remote| remote.push_mirror) {
+        return Err(String::from("Push mirrors cannot be the primary remote"));
+    }
+
+    // The TLS options are configured for the whole host, so they cannot differ
+    // between remotes on the same one
+    let mut tls_hosts: Vec<(String, &net::TlsOptions)> = Vec::new();
+    for remote in repo.remotes.iter().flatten() {
+        match net::url_host_prefix(&remote.url) {
+            Some(host) => match tls_hosts.iter().find(|(other, _)| other == &host) {
+                Some((_, tls)) if *tls != &remote.tls => {
+                    return Err(format!(
+                        "Remotes on host \"{}\" have different TLS options",
+                        host
+                    ));
+                }
+                Some(_) => (),
+                None => tls_hosts.push((host, &remote.tls)),
+            },
+            None if !remote.tls.is_empty() => {
+                return Err(format!(
+                    "Remote \"{}\": TLS options are only supported for HTTPS remotes",
+                    remote.name
+                ));
+            }
+            None => (),
+        }
+    }
+
+    for remote in repo.remotes.iter().flatten() {
+        if let Some(credentials) = &remote.credentials {
+            // For other remotes, the token is only used to create the project
+            if remote.remote_type != repo::RemoteType::Https && !remote.create {
+                return Err(format!(
+                    "Remote \"{}\": A token command is only supported for HTTPS remotes",
+                    remote.name
+                ));
+            }
+            auth::register_credentials(&remote.url, credentials);
+        }
+    }
+
+    // Remotes whose project was just created on the forge. They are empty, so
+    // they cannot be cloned from, but get the default branch pushed instead.
+    let mut created_remotes = Vec::new();
+    for remote in repo.remotes.iter().flatten().filter(|remote| remote.create) {
+        let created = provider::create_missing_project(remote).map_err(|error| {
+            format!(
+                "Creating the project of remote \"{}\" failed: {}",
+                remote.name, error
+            )
+        })?;
+        if created {
+            notify(Message::Success(format!(
+                "Created the project of remote \"{}\"",
+                remote.name
+            )));
+            created_remotes.push(remote.name.as_str());
+        }
+    }
 
     let mut newly_created = false;
 
@@ -181,14 +1228,27 @@ fn sync_repo(root_path: &Path, repo: &repo::Repo, init_worktree: bool) -> Result
                 "Repo already exists, but is not using a worktree setup",
             ));
         };
-    } else if matches!(&repo.remotes, None) || repo.remotes.as_ref().unwrap().is_empty() {
-        print_repo_action(
-            &repo.name,
-            "Repository does not have remotes configured, initializing new",
-        );
+    } else if repo
+        .remotes
+        .iter()
+        .flatten()
+        .all(|remote| created_remotes.contains(&remote.name.as_str()))
+    {
+        if is_bare {
+            return Err(String::from(match is_mirror {
+                true => "Mirrors need a remote to clone from",
+                false => "Bare repositories need a remote to clone from",
+            }));
+        }
+        notify(Message::Action(String::from(
+            match created_remotes.is_empty() {
+                true => "Repository does not have remotes configured, initializing new",
+                false => "Remotes are new and empty, initializing new",
+            },
+        )));
         match repo::RepoHandle::init(&repo_path, repo.worktree_setup) {
             Ok(r) => {
-                print_repo_success(&repo.name, "Repository created");
+                notify(Message::Success(String::from("Repository created")));
                 Some(r)
             }
             Err(e) => {
@@ -196,17 +1256,93 @@ fn sync_repo(root_path: &Path, repo: &repo::Repo, init_worktree: bool) -> Result
             }
         };
     } else {
-        let first = repo.remotes.as_ref().unwrap().first().unwrap();
+        // Push mirrors are only pushed to, so clone from a different remote if
+        // possible. If cloning fails, the next remote is tried, starting with
+        // the primary one.
+        let remotes = repo.remotes.as_ref().unwrap();
+        let mut candidates = remotes
+            .iter()
+            .filter(|remote| {
+                !remote.push_mirror && !created_remotes.contains(&remote.name.as_str())
+            })
+            .collect::<Vec<&repo::Remote>>();
+        candidates.sort_by_key(|remote| !remote.primary);
+        if candidates.is_empty() {
+            // Not all remotes were just created, otherwise there would be
+            // nothing to clone
+            candidates.push(
+                remotes
+                    .iter()
+                    .find(|remote| !created_remotes.contains(&remote.name.as_str()))
+                    .unwrap(),
+            );
+        }
 
-        match repo::clone_repo(first, &repo_path, repo.worktree_setup) {
-            Ok(_) => {
-                print_repo_success(&repo.name, "Repository successfully cloned");
-            }
-            Err(e) => {
-                return Err(format!("Repository failed during clone: {}", e));
-            }
+        let clone_target = match repo.worktree_setup {
+            false => repo_path.clone(),
+            true => repo_path.join(worktree::GIT_MAIN_WORKTREE_DIRECTORY),
         };
 
+        let mut errors = Vec::new();
+        for remote in &candidates {
+            notify(Message::Action(format!(
+                "Cloning {}into \"{}\" from \"{}\"",
+                match is_mirror {
+                    true => "mirror ",
+                    false => "",
+                },
+                clone_target.display(),
+                &remote.url
+            )));
+            let result = retry::run(
+                &format!(
+                    "{}: Cloning from remote \"{}\"",
+                    repo.fullname(),
+                    remote.name
+                ),
+                |error| repo::is_transient_error(&ToString::to_string(error)),
+                || match is_mirror {
+                    true => repo::clone_mirror(remote, &repo_path),
+                    false => repo::clone_repo(
+                        remote,
+                        &repo_path,
+                        repo.worktree_setup,
+                        is_bare,
+                        &repo.clone_options,
+                    ),
+                },
+            );
+
+            match result {
+                Ok(_) => {
+                    notify(Message::Success(match errors.is_empty() {
+                        true => String::from("Repository successfully cloned"),
+                        false => format!(
+                            "Repository successfully cloned from remote \"{}\"",
+                            remote.name
+                        ),
+                    }));
+                    break;
+                }
+                Err(e) => {
+                    if candidates.len() > 1 {
+                        notify(Message::Error(format!(
+                            "Cloning from remote \"{}\" failed: {}",
+                            remote.name, e
+                        )));
+                    }
+                    errors.push(e.to_string());
+                }
+            }
+        }
+
+        if errors.len() == candidates.len() {
+            return Err(match errors.len() {
+                1 => format!("Repository failed during clone: {}", errors[0]),
+                _ => String::from("Repository failed during clone from all remotes"),
+            });
+        }
+
         newly_created = true;
     }
 
@@ -223,18 +1359,52 @@ fn sync_repo(root_path: &Path, repo: &repo::Repo, init_worktree: bool) -> Result
         }
     };
 
-    if newly_created && repo.worktree_setup && init_worktree {
-        match repo_handle.default_branch() {
-            Ok(branch) => {
-                worktree::add_worktree(&repo_path, &branch.name()?, None, false)?;
+    if !repo.worktree_setup {
+        if let Some(mismatch) = clone_mode_mismatch(&repo_handle, &repo_path, repo.clone_mode) {
+            return Err(format!("Repo already exists, but {}", mismatch));
+        }
+    }
+
+    if newly_created && repo.worktree_setup && options.init_worktree {
+        let branch_name = match &repo.clone_options.branch {
+            Some(branch) => Ok(branch.clone()),
+            None => repo_handle
+                .default_branch()
+                .and_then(|branch| branch.name()),
+        };
+        match branch_name {
+            Ok(branch_name) => {
+                worktree::add_worktree(&repo_path, &branch_name, None, false, false)?;
+
+                if let Some(sparse_paths) = &repo.clone_options.sparse_paths {
+                    repo::RepoHandle::open(&repo_path.join(&branch_name), false)
+                        .map_err(|error| format!("Opening worktree failed: {}", error))?
+                        .set_sparse_checkout(sparse_paths)?;
+                }
             }
-            Err(_error) => print_repo_error(
-                &repo.name,
+            Err(_error) => notify(Message::Error(String::from(
                 "Could not determine default branch, skipping worktree initializtion",
-            ),
+            ))),
+        }
+    }
+    if newly_created && !repo.worktree_setup && !is_bare {
+        if let Some(sparse_paths) = &repo.clone_options.sparse_paths {
+            repo_handle.set_sparse_checkout(sparse_paths)?;
+        }
+
+        if let Some(rev) = &repo.rev {
+            notify(Message::Action(format!(
+                "Checking out revision \"{}\"",
+                rev
+            )));
+            repo_handle.checkout_rev(rev)?;
         }
     }
+
+    let mut remotes_updated = false;
     if let Some(remotes) = &repo.remotes {
+        remotes_updated = rename_remotes(&repo_handle, remotes, notify)?;
+
         let current_remotes: Vec<String> = repo_handle
             .remotes()
             .map_err(|error| format!("Repository failed during getting the remotes: {}", error))?;
@@ -247,23 +1417,22 @@ fn sync_repo(root_path: &Path, repo: &repo::Repo, init_worktree: bool) -> Result
                     let current_url = current_remote.url();
 
                     if remote.url != current_url {
-                        print_repo_action(
-                            &repo.name,
-                            &format!("Updating remote {} to \"{}\"", &remote.name, &remote.url),
-                        );
+                        remotes_updated = true;
+                        notify(Message::Action(format!(
+                            "Updating remote {} to \"{}\"",
+                            &remote.name, &remote.url
+                        )));
                         if let Err(e) = repo_handle.remote_set_url(&remote.name, &remote.url) {
                             return Err(format!("Repository failed during setting of the remote URL for remote \"{}\": {}", &remote.name, e));
                         };
                     }
                 }
                 None => {
-                    print_repo_action(
-                        &repo.name,
-                        &format!(
-                            "Setting up new remote \"{}\" to \"{}\"",
-                            &remote.name, &remote.url
-                        ),
-                    );
+                    remotes_updated = true;
+                    notify(Message::Action(format!(
+                        "Setting up new remote \"{}\" to \"{}\"",
+                        &remote.name, &remote.url
+                    )));
                     if let Err(e) = repo_handle.new_remote(&remote.name, &remote.url) {
                         return Err(format!(
                             "Repository failed during setting the remotes: {}",
@@ -272,14 +1441,77 @@ fn sync_repo(root_path: &Path, repo: &repo::Repo, init_worktree: bool) -> Result
                     }
                 }
             }
+
+            if remote.push_urls != repo_handle.remote_push_urls(&remote.name)? {
+                remotes_updated = true;
+                notify(Message::Action(match remote.push_urls.is_empty() {
+                    true => format!("Removing push URLs of remote {}", &remote.name),
+                    false => format!(
+                        "Setting push URLs of remote {} to \"{}\"",
+                        &remote.name,
+                        remote.push_urls.join("\", \"")
+                    ),
+                }));
+                repo_handle
+                    .remote_set_push_urls(&remote.name, &remote.push_urls)
+                    .map_err(|error| {
+                        format!(
+                            "Repository failed during setting of the push URLs for remote \"{}\": {}",
+                            &remote.name, error
+                        )
+                    })?;
+            }
+
+            // Refspecs are only added, git sets up the default one itself and
+            // others may have been added by hand
+            let current_refspecs = repo_handle.extra_fetch_refspecs(&remote.name)?;
+            for refspec in &remote.refspecs {
+                if !current_refspecs.contains(refspec) {
+                    remotes_updated = true;
+                    notify(Message::Action(format!(
+                        "Adding fetch refspec \"{}\" to remote {}",
+                        refspec, &remote.name
+                    )));
+                    repo_handle
+                        .add_fetch_refspec(&remote.name, refspec)
+                        .map_err(|error| {
+                            format!(
+                                "Repository failed during adding fetch refspec \"{}\" to remote \"{}\": {}",
+                                refspec, &remote.name, error
+                            )
+                        })?;
+                    // A fresh clone should not have to wait for the next
+                    // fetch to get these refs
+                    if newly_created {
+                        repo_handle.fetch(&remote.name)?;
+                    }
+                }
+            }
+
+            if remote.tls != repo_handle.tls_options(&remote.url)? {
+                remotes_updated = true;
+                notify(Message::Action(format!(
+                    "Updating TLS options of remote {}",
+                    &remote.name
+                )));
+                repo_handle
+                    .set_tls_options(&remote.url, &remote.tls)
+                    .map_err(|error| {
+                        format!(
+                            "Repository failed during setting of the TLS options for remote \"{}\": {}",
+                            &remote.name, error
+                        )
+                    })?;
+            }
         }
 
         for current_remote in &current_remotes {
             if !remotes.iter().any(|r| &r.name == current_remote) {
-                print_repo_action(
-                    &repo.name,
-                    &format!("Deleting remote \"{}\"", &current_remote,),
-                );
+                remotes_updated = true;
+                notify(Message::Action(format!(
+                    "Deleting remote \"{}\"",
+                    &current_remote,
+                )));
                 if let Err(e) = repo_handle.remote_delete(current_remote) {
                     return Err(format!(
                         "Repository failed during deleting remote \"{}\": {}",
@@ -289,9 +1521,446 @@ fn sync_repo(root_path: &Path, repo: &repo::Repo, init_worktree: bool) -> Result
             }
         }
     }
+
+    match primary_remotes.first() {
+        Some(primary) => {
+            if repo_handle.set_primary_remote(&primary.name)? {
+                notify(Message::Action(format!(
+                    "Setting primary remote to \"{}\"",
+                    primary.name
+                )));
+            }
+        }
+        None => {
+            // Unless it is set directly, not by marking a remote as primary
+            let set_directly = repo
+                .git_config
+                .keys()
+                .any(|key| key.eq_ignore_ascii_case(repo::GIT_CONFIG_PRIMARY_REMOTE));
+            if !set_directly && repo_handle.unset_primary_remote()? {
+                notify(Message::Action(String::from("Unsetting primary remote")));
+            }
+        }
+    }
+
+    match &repo.default_branch {
+        Some(default_branch) => {
+            if repo_handle.set_default_branch(default_branch)? {
+                notify(Message::Action(format!(
+                    "Setting default branch to \"{}\"",
+                    default_branch
+                )));
+            }
+        }
+        None => {
+            if repo_handle.unset_default_branch()? {
+                notify(Message::Action(String::from("Unsetting default branch")));
+            }
+        }
+    }
+
+    for (key, value) in &repo.git_config {
+        if repo_handle.set_local_config(key, value)? {
+            notify(Message::Action(format!(
+                "Setting git config \"{}\" to \"{}\"",
+                key, value
+            )));
+        }
+    }
+
+    // Push mirrors get everything pushed with the other push mirrors below
+    if !is_mirror {
+        for remote in
+            repo.remotes.iter().flatten().filter(|remote| {
+                !remote.push_mirror && created_remotes.contains(&remote.name.as_str())
+            })
+        {
+            push_to_created_remote(&repo_handle, &remote.name, notify)?;
+        }
+    }
+
+    if options.fetch && !newly_created {
+        let push_mirrors = repo
+            .remotes
+            .iter()
+            .flatten()
+            .filter(|remote| remote.push_mirror)
+            .map(|remote| remote.name.as_str())
+            .collect::<Vec<&str>>();
+
+        for remote in repo_handle.remotes()? {
+            // Push mirrors are only pushed to
+            if push_mirrors.contains(&remote.as_str()) {
+                continue;
+            }
+            // Branches that were deleted have to be removed from the push mirrors
+            // as well, so they must not linger as remote tracking branches
+            let prune = options.fetch_options.prune
+                || !push_mirrors.is_empty()
+                || repo_handle.is_mirror(&remote)?;
+            repo_handle
+                .fetch_with_options(
+                    &remote,
+                    repo::FetchConfig {
+                        prune,
+                        ..options.fetch_options
+                    },
+                )
+                .map_err(|error| {
+                    format!("Fetching from remote \"{}\" failed: {}", remote, error)
+                })?;
+        }
+    }
+
+    // Done after fetching, so a pinned revision that was just pushed to the
+    // remote can be found
+    if !newly_created && !repo.worktree_setup && !is_bare {
+        match (&repo.rev, &repo.clone_options.branch) {
+            (Some(rev), _) => {
+                if !repo_handle.head_matches_rev(rev)? {
+                    notify(Message::Warning(format!(
+                        "HEAD does not match the pinned revision \"{}\"",
+                        rev
+                    )));
+                }
+            }
+            (None, Some(branch)) => check_branch(&repo_handle, branch, notify)?,
+            (None, None) => (),
+        }
+    }
+
+    if newly_created || options.fetch {
+        push_mirrors(&repo_handle, repo, notify)?;
+    }
+
+    if newly_created && !is_bare && !repo.lfs && repo_handle.uses_lfs()? {
+        notify(Message::Warning(String::from(
+            "Repository uses Git LFS, set \"lfs = true\" to download LFS objects",
+        )));
+    }
+
+    if (newly_created || options.fetch) && !is_bare {
+        update_checkouts(&repo_path, &repo_handle, repo)?;
+    }
+
+    Ok(SyncChanges {
+        cloned: newly_created,
+        // Remotes that are added right after cloning are not an update
+        remotes_updated: remotes_updated && !newly_created,
+    })
+}
+
+/// Describes how the layout of the repository at `repo_path` differs from
+/// the one its clone mode expects. Repositories of vcsh are not bare
+/// according to git, as their working directory is the home directory, but
+/// they do not have one in their own directory.
+fn clone_mode_mismatch(
+    repo_handle: &repo::RepoHandle,
+    repo_path: &Path,
+    clone_mode: repo::CloneMode,
+) -> Option<&'static str> {
+    match clone_mode {
+        repo::CloneMode::Normal if repo_handle.is_bare() => Some("is a mirror"),
+        repo::CloneMode::Mirror if !repo_handle.is_bare() => Some("is not a mirror"),
+        repo::CloneMode::Bare if !repo_handle.is_bare() && repo_path.join(".git").exists() => {
+            Some("is not bare")
+        }
+        _ => None,
+    }
+}
+
+/// Checks whether a repository matches its configuration, without changing
+/// anything
+fn check_repo(root_path: &Path, repo: &repo::Repo) -> Result<(), String> {
+    let repo_path = root_path.join(repo.fullname());
+
+    if !repo_path.exists() {
+        return Err(String::from("Repository does not exist"));
+    }
+
+    let repo_handle = repo::RepoHandle::open(&repo_path, repo.worktree_setup)
+        .map_err(|error| format!("Opening repository failed: {}", error))?;
+
+    if !repo.worktree_setup {
+        if let Some(mismatch) = clone_mode_mismatch(&repo_handle, &repo_path, repo.clone_mode) {
+            return Err(format!("Repository {}", mismatch));
+        }
+    }
+
+    // Everything that a sync would change is collected, so a single run shows
+    // all of it
+    let mut differences = Vec::new();
+
+    if let Some(rev) = &repo.rev {
+        if !repo_handle.head_matches_rev(rev)? {
+            differences.push(format!(
+                "HEAD does not match the pinned revision \"{}\"",
+                rev
+            ));
+        }
+    }
+
+    if let Some(remotes) = &repo.remotes {
+        for remote in remotes {
+            let current = match repo_handle.find_remote(&remote.name)? {
+                Some(current) => current,
+                None => {
+                    differences.push(format!("Remote \"{}\" is missing", remote.name));
+                    continue;
+                }
+            };
+
+            if current.url() != remote.url {
+                differences.push(format!(
+                    "Remote \"{}\" points to \"{}\" instead of \"{}\"",
+                    remote.name,
+                    current.url(),
+                    remote.url
+                ));
+            }
+            if repo_handle.remote_push_urls(&remote.name)? != remote.push_urls {
+                differences.push(format!(
+                    "Push URLs of remote \"{}\" do not match",
+                    remote.name
+                ));
+            }
+            let current_refspecs = repo_handle.extra_fetch_refspecs(&remote.name)?;
+            for refspec in &remote.refspecs {
+                if !current_refspecs.contains(refspec) {
+                    differences.push(format!(
+                        "Remote \"{}\" is missing the fetch refspec \"{}\"",
+                        remote.name, refspec
+                    ));
+                }
+            }
+            if repo_handle.tls_options(&remote.url)? != remote.tls {
+                differences.push(format!(
+                    "TLS options of remote \"{}\" do not match",
+                    remote.name
+                ));
+            }
+            if remote.primary && repo_handle.primary_remote()?.as_ref() != Some(&remote.name) {
+                differences.push(format!(
+                    "Remote \"{}\" is not the primary remote",
+                    remote.name
+                ));
+            }
+        }
+
+        for name in repo_handle.remotes()? {
+            if !remotes.iter().any(|remote| remote.name == name) {
+                differences.push(format!("Remote \"{}\" is not configured", name));
+            }
+        }
+    }
+
+    for (key, value) in &repo.git_config {
+        if repo_handle.local_config(key)?.as_ref() != Some(value) {
+            differences.push(format!("Git config \"{}\" is not \"{}\"", key, value));
+        }
+    }
+
+    match differences.is_empty() {
+        true => Ok(()),
+        false => Err(differences.join(", ")),
+    }
+}
+
+/// Warns if the checked out branch is not the configured one. The user may
+/// switch branches for a reason, so this does not change anything.
+fn check_branch(
+    repo_handle: &repo::RepoHandle,
+    branch: &str,
+    notify: &mut dyn FnMut(Message),
+) -> Result<(), String> {
+    // Empty repositories do not have anything checked out yet
+    if repo_handle.is_empty()? {
+        return Ok(());
+    }
+
+    match repo_handle.head_branch() {
+        Ok(head) => {
+            let head = head.name()?;
+            if head != branch {
+                notify(Message::Warning(format!(
+                    "Branch \"{}\" is checked out instead of the configured branch \"{}\"",
+                    head, branch
+                )));
+            }
+        }
+        Err(_) => notify(Message::Warning(format!(
+            "No branch is checked out instead of the configured branch \"{}\"",
+            branch
+        ))),
+    }
+
+    Ok(())
+}
+
+/// Renames remotes that only got a new name in the configuration, instead of
+/// deleting and adding them, which would lose their remote tracking branches.
+/// A remote counts as renamed if its name is gone from the configuration, and
+/// exactly one new remote has the same URL. Returns whether any remote was
+/// renamed.
+fn rename_remotes(
+    repo_handle: &repo::RepoHandle,
+    remotes: &[repo::Remote],
+    notify: &mut dyn FnMut(Message),
+) -> Result<bool, String> {
+    let current_remotes = repo_handle
+        .remotes()
+        .map_err(|error| format!("Repository failed during getting the remotes: {}", error))?;
+
+    let mut removed = Vec::new();
+    for name in &current_remotes {
+        if !remotes.iter().any(|remote| &remote.name == name) {
+            if let Some(remote) = repo_handle.find_remote(name)? {
+                removed.push((name.clone(), remote.url()));
+            }
+        }
+    }
+
+    let added = remotes
+        .iter()
+        .filter(|remote| !current_remotes.contains(&remote.name))
+        .collect::<Vec<&repo::Remote>>();
+
+    let mut renamed = false;
+    for (old_name, url) in &removed {
+        let candidates = added
+            .iter()
+            .filter(|remote| &remote.url == url)
+            .collect::<Vec<_>>();
+        let same_url = removed.iter().filter(|(_, other)| other == url).count();
+
+        if let ([new], 1) = (candidates.as_slice(), same_url) {
+            notify(Message::Action(format!(
+                "Renaming remote \"{}\" to \"{}\"",
+                old_name, new.name
+            )));
+            let problems = repo_handle
+                .remote_rename(old_name, &new.name)
+                .map_err(|error| {
+                    format!(
+                        "Repository failed during renaming of remote \"{}\": {}",
+                        old_name, error
+                    )
+                })?;
+            for problem in problems {
+                notify(Message::Warning(format!(
+                    "Refspec \"{}\" of remote \"{}\" could not be renamed",
+                    problem, new.name
+                )));
+            }
+            renamed = true;
+        }
+    }
+
+    Ok(renamed)
+}
+
+/// Pushes the default branch to a remote whose project was just created, and
+/// makes it the upstream of the branch
+fn push_to_created_remote(
+    repo_handle: &repo::RepoHandle,
+    remote_name: &str,
+    notify: &mut dyn FnMut(Message),
+) -> Result<(), String> {
+    let branch = match repo_handle.default_branch() {
+        Ok(branch) => branch.name()?,
+        Err(_) => {
+            notify(Message::Action(format!(
+                "No commits yet, nothing to push to remote \"{}\"",
+                remote_name
+            )));
+            return Ok(());
+        }
+    };
+
+    notify(Message::Action(format!(
+        "Pushing branch \"{}\" to remote \"{}\"",
+        branch, remote_name
+    )));
+    repo_handle
+        .push_branch(remote_name, &branch)
+        .map_err(|error| format!("Pushing to remote \"{}\" failed: {}", remote_name, error))
+}
+
+/// Pushes all branches and tags to the remotes that are configured as push
+/// mirrors
+fn push_mirrors(
+    repo_handle: &repo::RepoHandle,
+    repo: &repo::Repo,
+    notify: &mut dyn FnMut(Message),
+) -> Result<(), String> {
+    let remotes = match &repo.remotes {
+        Some(remotes) => remotes,
+        None => return Ok(()),
+    };
+
+    let mut push_mirrors = remotes
+        .iter()
+        .filter(|remote| remote.push_mirror)
+        .peekable();
+    if push_mirrors.peek().is_none() {
+        return Ok(());
+    }
+
+    let source = remotes
+        .iter()
+        .find(|remote| !remote.push_mirror)
+        .ok_or_else(|| String::from("Push mirrors need another remote to push from"))?;
+
+    for push_mirror in push_mirrors {
+        notify(Message::Action(format!(
+            "Pushing to push mirror \"{}\"",
+            &push_mirror.name
+        )));
+        repo_handle.push_mirror(&source.name, &push_mirror.name)?;
+    }
+
+    Ok(())
+}
+
+/// Updates the submodules and LFS objects of the repository. For worktree
+/// setups, this is done for every worktree.
+fn update_checkouts(
+    repo_path: &Path,
+    repo_handle: &repo::RepoHandle,
+    repo: &repo::Repo,
+) -> Result<(), String> {
+    if repo.submodules == repo::Submodules::None && !repo.lfs {
+        return Ok(());
+    }
+
+    if repo.worktree_setup {
+        for worktree in repo_handle.get_worktrees()? {
+            let worktree = worktree.name();
+            let checkout = repo::RepoHandle::open(&repo_path.join(worktree), false)
+                .map_err(|error| format!("Opening worktree {} failed: {}", worktree, error))?;
+            update_checkout(&checkout, repo)
+                .map_err(|error| format!("Worktree {}: {}", worktree, error))?;
+        }
+        Ok(())
+    } else {
+        update_checkout(repo_handle, repo)
+    }
+}
+
+fn update_checkout(checkout: &repo::RepoHandle, repo: &repo::Repo) -> Result<(), String> {
+    checkout.update_submodules(repo.submodules)?;
+    if repo.lfs {
+        checkout.lfs_pull()?;
+    }
     Ok(())
 }
 
+/// Detects bare repositories, e.g. mirrors, by the files that every git
+/// directory contains
+fn is_bare_repo(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
 fn get_actual_git_directory(path: &Path, is_worktree: bool) -> PathBuf {
     match is_worktree {
         false => path.to_path_buf(),
@@ -11,7 +11,8 @@
 //! workflow will be quite straightforward.
 //!
 //! * The name of the worktree (and therefore the path) is **always** the same
-//!   as the name of the branch.
+//!   as the name of the branch. The only exception is `worktree.prefix`, which
+//!   is prepended to the names of new branches, but not to the worktree.
 //! * Never modify existing local branches
 //! * Only modify tracking branches for existing local branches if explicitly
 //!   requested
@@ -207,9 +208,10 @@
 //! * Does the local branch track the correct remote branch?
 //! * Does that remote branch also exist?
 use std::cell::RefCell;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 // use super::output::*;
+use super::path;
 use super::repo;
 
 pub const GIT_MAIN_WORKTREE_DIRECTORY: &str = ".git-main-working-tree";
@@ -218,14 +220,35 @@ pub const GIT_MAIN_WORKTREE_DIRECTORY: &str = ".git-main-working-tree";
 mod tests {
     use super::*;
 
+    #[test]
+    fn check_match_worktree_name() {
+        let names = vec![
+            String::from("main"),
+            String::from("feature-login"),
+            String::from("feature-logout"),
+        ];
+        assert_eq!(
+            match_worktree_name(&names, "main", &None, false),
+            Ok(String::from("main"))
+        );
+        assert!(match_worktree_name(&names, "login", &None, false).is_err());
+        assert_eq!(
+            match_worktree_name(&names, "LOGIN", &None, true),
+            Ok(String::from("feature-login"))
+        );
+        // Ambiguous
+        assert!(match_worktree_name(&names, "log", &None, true).is_err());
+        assert!(match_worktree_name(&names, "nothing", &None, true).is_err());
+    }
+
     #[test]
     fn invalid_worktree_names() {
-        assert!(add_worktree(Path::new("/tmp/"), "/leadingslash", None, false).is_err());
-        assert!(add_worktree(Path::new("/tmp/"), "trailingslash/", None, false).is_err());
-        assert!(add_worktree(Path::new("/tmp/"), "//", None, false).is_err());
-        assert!(add_worktree(Path::new("/tmp/"), "test//test", None, false).is_err());
-        assert!(add_worktree(Path::new("/tmp/"), "test test", None, false).is_err());
-        assert!(add_worktree(Path::new("/tmp/"), "test\ttest", None, false).is_err());
+        assert!(add_worktree(Path::new("/tmp/"), "/leadingslash", None, false, false).is_err());
+        assert!(add_worktree(Path::new("/tmp/"), "trailingslash/", None, false, false).is_err());
+        assert!(add_worktree(Path::new("/tmp/"), "//", None, false, false).is_err());
+        assert!(add_worktree(Path::new("/tmp/"), "test//test", None, false, false).is_err());
+        assert!(add_worktree(Path::new("/tmp/"), "test test", None, false, false).is_err());
+        assert!(add_worktree(Path::new("/tmp/"), "test\ttest", None, false, false).is_err());
     }
 }
 
@@ -361,7 +384,9 @@ impl<'a> Worktree<'a, WithLocalTargetSelected<'a>> {
 }
 
 impl<'a> Worktree<'a, WithRemoteTrackingBranch<'a>> {
-    fn create(self, directory: &Path) -> Result<Option<Vec<String>>, String> {
+    /// Creates the worktree `name`. Its directory is named the same, the
+    /// branch may differ.
+    fn create(self, directory: &Path, name: &str) -> Result<Option<Vec<String>>, String> {
         let mut warnings: Vec<String> = vec![];
 
         let mut branch = if let Some(branch) = self.extra.local_branch {
@@ -453,8 +478,8 @@ impl<'a> Worktree<'a, WithRemoteTrackingBranch<'a>> {
 
         // We have to create subdirectories first, otherwise adding the worktree
         // will fail
-        if self.extra.local_branch_name.contains('/') {
-            let path = Path::new(&self.extra.local_branch_name);
+        if name.contains('/') {
+            let path = Path::new(name);
             if let Some(base) = path.parent() {
                 // This is a workaround of a bug in libgit2 (?)
                 //
@@ -524,11 +549,8 @@ impl<'a> Worktree<'a, WithRemoteTrackingBranch<'a>> {
             }
         }
 
-        self.repo.new_worktree(
-            &self.extra.local_branch_name,
-            &directory.join(&self.extra.local_branch_name),
-            &branch,
-        )?;
+        self.repo
+            .new_worktree(name, &directory.join(name), &branch)?;
 
         Ok(if warnings.is_empty() {
             None
@@ -538,6 +560,66 @@ impl<'a> Worktree<'a, WithRemoteTrackingBranch<'a>> {
     }
 }
 
+/// Finds the worktree setup that `path` is part of. Returns the root of the
+/// setup and, if `path` is inside one of its worktrees, the name of that
+/// worktree.
+pub fn find_worktree_root(path: &Path) -> Option<(PathBuf, Option<String>)> {
+    let mut worktree_dir = None;
+    for directory in path.ancestors() {
+        if repo::RepoHandle::detect_worktree(directory) {
+            let name = worktree_dir.and_then(|worktree_dir: &Path| {
+                worktree_dir
+                    .strip_prefix(directory)
+                    .ok()
+                    .map(path::path_as_string)
+            });
+            return Some((directory.to_path_buf(), name));
+        }
+        // Worktrees have a `.git` file that points to the main repository
+        if worktree_dir.is_none() && directory.join(".git").is_file() {
+            worktree_dir = Some(directory);
+        }
+    }
+    None
+}
+
+/// Finds the worktree among `names` that `query` refers to: The worktree of
+/// that name, or the one of the branch of that name. With `fuzzy`, it is
+/// enough for the query to be part of exactly one worktree name, ignoring case.
+pub fn match_worktree_name(
+    names: &[String],
+    query: &str,
+    config: &Option<repo::WorktreeRootConfig>,
+    fuzzy: bool,
+) -> Result<String, String> {
+    let name = repo::worktree_name(config, query);
+    if names.contains(&name) {
+        return Ok(name);
+    }
+    if !fuzzy {
+        return Err(format!("Worktree {} not found", query));
+    }
+
+    let query = query.to_lowercase();
+    let matching = names
+        .iter()
+        .filter(|name| name.to_lowercase().contains(&query))
+        .collect::<Vec<&String>>();
+    match matching.len() {
+        0 => Err(format!("No worktree matches {}", query)),
+        1 => Ok(matching[0].clone()),
+        _ => Err(format!(
+            "{} matches several worktrees: {}",
+            query,
+            matching
+                .iter()
+                .map(|name| name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        )),
+    }
+}
+
 /// A branch name must never start or end with a slash, and it cannot have two
 /// consecutive slashes
 fn validate_worktree_name(name: &str) -> Result<(), String> {
@@ -565,6 +647,45 @@ fn validate_worktree_name(name: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Copies the given files from `source` into the new worktree at
+/// `destination`. Files that are missing or already exist in the new worktree
+/// are skipped, and returned as warnings.
+fn copy_worktree_files(source: &Path, destination: &Path, files: &[String]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for file in files {
+        let relative = Path::new(file);
+        if !relative
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            warnings.push(format!(
+                "Not copying {}: Only plain relative paths are allowed",
+                file
+            ));
+            continue;
+        }
+
+        let from = source.join(relative);
+        let to = destination.join(relative);
+        // Symlinks are copied as they are, even if their target is missing
+        if std::fs::symlink_metadata(&from).is_err() {
+            warnings.push(format!(
+                "Not copying {}: Not found in {}",
+                file,
+                path::path_as_string(source)
+            ));
+        } else if std::fs::symlink_metadata(&to).is_ok() {
+            warnings.push(format!(
+                "Not copying {}: Already exists in the new worktree",
+                file
+            ));
+        } else if let Err(error) = path::copy_recursive(&from, &to) {
+            warnings.push(format!("Not copying {}: {}", file, error));
+        }
+    }
+    warnings
+}
+
 // TECHDEBT
 //
 // Instead of opening the repo & reading configuration inside the function, it
@@ -574,6 +695,7 @@ pub fn add_worktree(
     name: &str,
     track: Option<(&str, &str)>,
     no_track: bool,
+    track_auto: bool,
 ) -> Result<Option<Vec<String>>, String> {
     let mut warnings: Vec<String> = vec![];
 
@@ -590,18 +712,105 @@ pub fn add_worktree(
 
     let config = repo::read_worktree_root_config(directory)?;
 
-    if repo.find_worktree(name).is_ok() {
-        return Err(format!("Worktree {} already exists", &name));
+    // `name` is the branch, without any prefix
+    let worktree_name = repo::worktree_name(&config, name);
+
+    if repo.find_worktree(&worktree_name).is_ok() {
+        return Err(format!("Worktree {} already exists", &worktree_name));
     }
 
+    let base = config
+        .as_ref()
+        .and_then(|config| config.worktree.as_ref())
+        .and_then(|worktree| worktree.base.clone());
+    let copy_files = config
+        .as_ref()
+        .and_then(|config| config.worktree.as_ref())
+        .and_then(|worktree| worktree.copy_files.clone())
+        .unwrap_or_default();
+    // The files are copied from the worktree of the default branch
+    let copy_source = if copy_files.is_empty() {
+        None
+    } else {
+        Some(repo::worktree_name(
+            &config,
+            &match config.as_ref().and_then(|config| config.default_branch()) {
+                Some(branch) => branch.to_string(),
+                None => repo.default_branch()?.name()?,
+            },
+        ))
+    };
+    let hooks_path = config
+        .as_ref()
+        .and_then(|config| config.worktree.as_ref())
+        .and_then(|worktree| worktree.hooks_path.clone());
+    let branch_prefix = config
+        .as_ref()
+        .and_then(|config| config.worktree.as_ref())
+        .and_then(|worktree| worktree.prefix.clone());
     let track_config = config.and_then(|config| config.track);
     let prefix = track_config
         .as_ref()
         .and_then(|track| track.default_remote_prefix.as_ref());
     let enable_tracking = track_config.as_ref().map_or(false, |track| track.default);
-    let default_remote = track_config
-        .as_ref()
-        .map(|track| track.default_remote.clone());
+    // Without a configured default remote, the primary remote of the
+    // repository is used
+    let default_remote = match track_config.as_ref() {
+        Some(track) => Some(track.default_remote.clone()),
+        None => repo.primary_remote()?,
+    };
+
+    // The prefix only applies to new branches. Existing ones, like the default
+    // branch, keep their name.
+    let branch_name = match branch_prefix {
+        Some(ref branch_prefix)
+            if !name.starts_with(branch_prefix.as_str())
+                && repo.find_local_branch(name).is_err()
+                && !remotes
+                    .iter()
+                    .any(|remote| repo.find_remote_branch(remote, name).is_ok()) =>
+        {
+            format!("{}{}", branch_prefix, name)
+        }
+        _ => name.to_string(),
+    };
+    let branch_name = branch_name.as_str();
+
+    // With automatic tracking, the branch of the same name on a remote is
+    // tracked as if given with `--track`. If several remotes have it, the
+    // default remote wins.
+    let auto_track_remote = if !no_track
+        && track.is_none()
+        && (track_auto || track_config.as_ref().map_or(false, |track| track.auto))
+    {
+        let matching = remotes
+            .iter()
+            .filter(|remote| repo.find_remote_branch(remote, branch_name).is_ok())
+            .collect::<Vec<&String>>();
+        match matching.len() {
+            0 => None,
+            1 => Some(matching[0].clone()),
+            _ => match default_remote {
+                Some(ref default_remote) if matching.contains(&default_remote) => {
+                    Some(default_remote.clone())
+                }
+                _ => {
+                    warnings.push(format!(
+                        "Branch {} exists on multiple remotes and none of them is the default remote, not tracking any of them",
+                        branch_name
+                    ));
+                    None
+                }
+            },
+        }
+    } else {
+        None
+    };
+    let track = track.or_else(|| {
+        auto_track_remote
+            .as_deref()
+            .map(|remote| (remote, branch_name))
+    });
 
     // Note that we have to define all variables that borrow from `repo`
     // *first*, otherwise we'll receive "borrowed value does not live long
@@ -613,9 +822,14 @@ pub fn add_worktree(
     //
     // So if we define `commit` *after* the respective worktrees, it will be dropped first while
     // still being borrowed by `Worktree`.
-    let default_branch_head = repo.default_branch()?.commit_owned()?;
+    let base_head = match base {
+        Some(ref base) => repo
+            .find_rev_commit(base)
+            .map_err(|error| format!("Invalid base for new worktrees: {}", error))?,
+        None => repo.default_branch()?.commit_owned()?,
+    };
 
-    let worktree = Worktree::<Init>::new(&repo).set_local_branch_name(name);
+    let worktree = Worktree::<Init>::new(&repo).set_local_branch_name(branch_name);
 
     let get_remote_head = |remote_name: &str,
                            remote_branch_name: &str|
@@ -633,22 +847,22 @@ pub fn add_worktree(
         if let Ok(remote_branch) = repo.find_remote_branch(remote_name, remote_branch_name) {
             worktree.select_commit(Some(Box::new(remote_branch.commit_owned()?)))
         } else {
-            worktree.select_commit(Some(Box::new(default_branch_head)))
+            worktree.select_commit(Some(Box::new(base_head)))
         }
     } else {
         match remotes.len() {
-            0 => worktree.select_commit(Some(Box::new(default_branch_head))),
+            0 => worktree.select_commit(Some(Box::new(base_head))),
             1 => {
                 let remote_name = &remotes[0];
                 let commit: Option<Box<repo::Commit>> = ({
                     if let Some(prefix) = prefix {
-                        get_remote_head(remote_name, &format!("{prefix}/{name}"))?
+                        get_remote_head(remote_name, &format!("{prefix}/{branch_name}"))?
                     } else {
                         None
                     }
                 })
-                .or(get_remote_head(remote_name, name)?)
-                .or_else(|| Some(Box::new(default_branch_head)));
+                .or(get_remote_head(remote_name, branch_name)?)
+                .or_else(|| Some(Box::new(base_head)));
 
                 worktree.select_commit(commit)
             }
@@ -656,7 +870,7 @@ pub fn add_worktree(
                 let commit = if let Some(ref default_remote) = default_remote {
                     if let Some(ref prefix) = prefix {
                         if let Ok(remote_branch) = repo
-                            .find_remote_branch(default_remote, &format!("{prefix}/{name}"))
+                            .find_remote_branch(default_remote, &format!("{prefix}/{branch_name}"))
                         {
                             Some(Box::new(remote_branch.commit_owned()?))
                         } else {
@@ -667,7 +881,7 @@ pub fn add_worktree(
                     }
                     .or({
                         if let Ok(remote_branch) =
-                            repo.find_remote_branch(default_remote, name)
+                            repo.find_remote_branch(default_remote, branch_name)
                         {
                             Some(Box::new(remote_branch.commit_owned()?))
                         } else {
@@ -683,7 +897,7 @@ pub fn add_worktree(
                             if let Some(ref prefix) = prefix {
                                 if let Ok(remote_branch) = repo.find_remote_branch(
                                     remote_name,
-                                    &format!("{prefix}/{name}"),
+                                    &format!("{prefix}/{branch_name}"),
                                 ) {
                                     Some(Box::new(remote_branch.commit_owned()?))
                                 } else {
@@ -695,7 +909,7 @@ pub fn add_worktree(
                         })
                         .or({
                             if let Ok(remote_branch) =
-                                repo.find_remote_branch(remote_name, name)
+                                repo.find_remote_branch(remote_name, branch_name)
                             {
                                 Some(Box::new(remote_branch.commit_owned()?))
                             } else {
@@ -716,7 +930,7 @@ pub fn add_worktree(
                     // remotes return None for the branch, we do *not* abort, we
                     // continue!
                     if commits.is_empty() {
-                        Some(Box::new(default_branch_head))
+                        Some(Box::new(base_head))
                     } else if commits.len() == 1 {
                         Some(commits.swap_remove(0))
                     } else if commits.windows(2).any(|window| {
@@ -733,7 +947,7 @@ pub fn add_worktree(
                             // never, as it's such a rare edge case.
                             "Branch exists on multiple remotes, but they deviate. Selecting default branch instead".to_string()
                         );
-                        Some(Box::new(default_branch_head))
+                        Some(Box::new(base_head))
                     } else {
                         Some(commits.swap_remove(0))
                     }
@@ -755,12 +969,14 @@ pub fn add_worktree(
     } else {
         match remotes.len() {
             0 => worktree.set_remote_tracking_branch(None, prefix.map(|s| s.as_str())),
-            1 => worktree
-                .set_remote_tracking_branch(Some((&remotes[0], name)), prefix.map(|s| s.as_str())),
+            1 => worktree.set_remote_tracking_branch(
+                Some((&remotes[0], branch_name)),
+                prefix.map(|s| s.as_str()),
+            ),
             _ => {
                 if let Some(default_remote) = default_remote {
                     worktree.set_remote_tracking_branch(
-                        Some((&default_remote, name)),
+                        Some((&default_remote, branch_name)),
                         prefix.map(|s| s.as_str()),
                     )
                 } else {
@@ -770,7 +986,28 @@ pub fn add_worktree(
         }
     };
 
-    worktree.create(directory)?;
+    if let Some(hooks_path) = hooks_path {
+        repo.set_hooks_path(directory, &hooks_path)?;
+    }
+
+    worktree.create(directory, &worktree_name)?;
+
+    if let Some(copy_source) = copy_source {
+        if copy_source != worktree_name {
+            if directory.join(&copy_source).is_dir() {
+                warnings.extend(copy_worktree_files(
+                    &directory.join(&copy_source),
+                    &directory.join(&worktree_name),
+                    &copy_files,
+                ));
+            } else {
+                warnings.push(format!(
+                    "Worktree {} not found, not copying any files",
+                    copy_source
+                ));
+            }
+        }
+    }
 
     Ok(if warnings.is_empty() {
         None
@@ -778,3 +1015,177 @@ pub fn add_worktree(
         Some(warnings)
     })
 }
+
+pub struct RebaseOptions<'a> {
+    /// Update the branches from their remotes before rebasing
+    pub pull: bool,
+    /// Rebase instead of fast-forwarding when updating from the remotes
+    pub rebase: bool,
+    /// Stash uncommitted changes during the rebase
+    pub stash: bool,
+    /// The revision to rebase onto instead of the default branch
+    pub onto: Option<&'a str>,
+}
+
+/// What happened to a single worktree during [`rebase_worktrees()`]
+pub enum RebaseOutcome {
+    /// The branch was rebased
+    Rebased,
+    /// The worktree was left alone on purpose, e.g. as it has the default
+    /// branch
+    Skipped(String),
+    /// The branch could not be updated, the reason is given
+    Failed(String),
+}
+
+/// Rebases the branches of all worktrees of the setup at `root`. `on_outcome`
+/// is called for each worktree as soon as it is done. An error stops the
+/// whole rebase.
+pub fn rebase_worktrees<F>(
+    repo: &repo::RepoHandle,
+    root: &Path,
+    config: &Option<repo::WorktreeRootConfig>,
+    options: &RebaseOptions,
+    mut on_outcome: F,
+) -> Result<(), String>
+where
+    F: FnMut(&str, RebaseOutcome),
+{
+    if options.pull {
+        let fetch_defaults = config
+            .as_ref()
+            .and_then(|config| config.fetch)
+            .unwrap_or_default();
+        repo.fetchall_with_options(fetch_defaults)
+            .map_err(|error| format!("Error fetching remotes: {}", error))?;
+    }
+
+    let worktrees = repo
+        .get_worktrees()
+        .map_err(|error| format!("Error getting worktrees: {}", error))?;
+
+    if options.pull {
+        for worktree in &worktrees {
+            if let Some(warning) = worktree
+                .forward_branch(root, options.rebase, options.stash)
+                .map_err(|error| format!("Error updating worktree branch: {}", error))?
+            {
+                on_outcome(worktree.name(), RebaseOutcome::Failed(warning));
+            }
+        }
+    }
+
+    if let Some(onto) = options.onto {
+        repo.find_rev_commit(onto)
+            .map_err(|error| format!("Cannot rebase onto \"{}\": {}", onto, error))?;
+    }
+
+    for worktree in &worktrees {
+        // The default and persistent branches are what the others are based
+        // on, so they are left alone
+        if options.onto.is_some() {
+            if let Some(reason) = worktree
+                .rebase_protection(config)
+                .map_err(|error| format!("Error rebasing worktree branch: {}", error))?
+            {
+                on_outcome(worktree.name(), RebaseOutcome::Skipped(reason));
+                continue;
+            }
+        }
+        let outcome = match worktree
+            .rebase_onto(config, options.onto, options.stash)
+            .map_err(|error| format!("Error rebasing worktree branch: {}", error))?
+        {
+            Some(warning) => RebaseOutcome::Failed(warning),
+            None => RebaseOutcome::Rebased,
+        };
+        on_outcome(worktree.name(), outcome);
+    }
+    Ok(())
+}
+
+/// A pull request on GitHub or a merge request on GitLab, given by its number
+pub enum ReviewRequest {
+    Pull(u64),
+    Merge(u64),
+}
+
+impl ReviewRequest {
+    /// The ref that the forge keeps for the head of the request
+    fn head_ref(&self) -> String {
+        match self {
+            Self::Pull(number) => format!("refs/pull/{}/head", number),
+            Self::Merge(number) => format!("refs/merge-requests/{}/head", number),
+        }
+    }
+
+    /// The name of the worktree, its branch and the remote tracking branch
+    pub fn name(&self) -> String {
+        match self {
+            Self::Pull(number) => format!("pr-{}", number),
+            Self::Merge(number) => format!("mr-{}", number),
+        }
+    }
+}
+
+/// Checks out a pull or merge request in a new worktree that tracks it.
+///
+/// The head of the request is fetched into the remote tracking branch
+/// `<remote>/pr-<number>` (or `mr-<number>`). The refspec is added to the
+/// remote, so that the usual fetches keep it up to date, and `git pull` in
+/// the worktree gets new commits of the request.
+pub fn add_review_worktree(
+    directory: &Path,
+    request: &ReviewRequest,
+) -> Result<Option<Vec<String>>, String> {
+    let repo = repo::RepoHandle::open(directory, true).map_err(|error| match error.kind {
+        repo::RepoErrorKind::NotFound => {
+            String::from("Current directory does not contain a worktree setup")
+        }
+        _ => format!("Error opening repo: {}", error),
+    })?;
+
+    let config = repo::read_worktree_root_config(directory)?;
+    let remotes = repo.remotes()?;
+
+    // Requests live on the remote of the forge, usually the default remote
+    let remote_name = match config.and_then(|config| config.track) {
+        Some(track) => track.default_remote,
+        None => match repo.primary_remote()? {
+            Some(remote) => remote,
+            None if remotes.len() == 1 => remotes[0].clone(),
+            None if remotes.iter().any(|remote| remote == "origin") => String::from("origin"),
+            None => {
+                return Err(String::from(
+                    "Cannot decide which remote to fetch from, set a default remote",
+                ))
+            }
+        },
+    };
+
+    let name = request.name();
+    let refspec = format!(
+        "+{}:refs/remotes/{}/{}",
+        request.head_ref(),
+        remote_name,
+        name
+    );
+
+    repo.fetch_refspec(&remote_name, &refspec)?;
+    if repo.find_remote_branch(&remote_name, &name).is_err() {
+        return Err(format!(
+            "{} not found on remote {}",
+            request.head_ref(),
+            remote_name
+        ));
+    }
+    repo.add_fetch_refspec(&remote_name, &refspec)?;
+
+    let warnings = add_worktree(directory, &name, Some((&remote_name, &name)), false, false)?;
+
+    // The tracking branch was derived from the default refspec, which would
+    // make `git pull` look for a branch of that name on the remote
+    repo.set_local_config(&format!("branch.{}.merge", name), &request.head_ref())?;
+
+    Ok(warnings)
+}
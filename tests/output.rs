@@ -0,0 +1,30 @@
+use grm::output::{capture, print_warning};
+
+// Capturing is global, so this is the only test in its own binary. Otherwise,
+// the messages of other tests running at the same time would be captured too.
+#[test]
+fn capture_is_restored_after_panic() {
+    let (result, outer) = capture(|| {
+        print_warning("outer before");
+
+        let (_, inner) = capture(|| print_warning("inner"));
+        assert_eq!(inner, vec![String::from("inner")]);
+
+        let panicked = std::panic::catch_unwind(|| {
+            capture(|| {
+                print_warning("lost");
+                panic!("failed while capturing");
+            })
+        });
+        assert!(panicked.is_err());
+
+        print_warning("outer after");
+        42
+    });
+
+    assert_eq!(result, 42);
+    assert_eq!(
+        outer,
+        vec![String::from("outer before"), String::from("outer after")]
+    );
+}
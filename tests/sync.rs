@@ -0,0 +1,65 @@
+use grm::output::Message;
+use grm::repo::RepoHandle;
+use grm::{Config, SyncChanges, SyncEvent, SyncOptions, SyncPlan};
+
+mod helpers;
+
+use helpers::*;
+
+fn options() -> SyncOptions {
+    SyncOptions {
+        init_worktree: false,
+        clone_depth: None,
+        fetch: false,
+        fetch_options: Default::default(),
+        check: false,
+        wait_for_lock: false,
+        fail_fast: false,
+    }
+}
+
+#[test]
+fn sync_plan_and_report() -> Result<(), Box<dyn std::error::Error>> {
+    let tmpdir = init_tmpdir();
+    let root = tmpdir.path().display().to_string();
+
+    RepoHandle::init(&tmpdir.path().join("unmanaged"), false)?;
+
+    let config: Config = toml::from_str(&format!(
+        r#"
+        [[trees]]
+        root = "{}"
+
+        [[trees.repos]]
+        name = "managed"
+        worktree_setup = false
+        "#,
+        root
+    ))?;
+
+    let plan = SyncPlan::new(config, &options())?;
+    assert_eq!(plan.trees.len(), 1);
+    assert_eq!(plan.trees[0].repos.len(), 1);
+    assert_eq!(plan.trees[0].repos[0].path, tmpdir.path().join("managed"));
+
+    let mut synced = Vec::new();
+    let mut messages = Vec::new();
+    let report = plan.execute(&options(), |event| match event {
+        SyncEvent::Message(name, message) => messages.push((name.to_string(), message.clone())),
+        SyncEvent::Synced(repo) => synced.push(repo.name.clone()),
+        SyncEvent::Debug(_) => {}
+    });
+    assert_eq!(synced, vec!["managed"]);
+    assert!(messages.contains(&(
+        String::from("managed"),
+        Message::Success(String::from("Repository created"))
+    )));
+    assert!(report.success());
+    // Initialized, but not cloned
+    assert_eq!(report.repos[0].result, Ok(SyncChanges::default()));
+    assert!(tmpdir.path().join("managed").join(".git").exists());
+    assert_eq!(report.unmanaged, vec![tmpdir.path().join("unmanaged")]);
+
+    cleanup_tmpdir(tmpdir);
+    Ok(())
+}